@@ -0,0 +1,43 @@
+CREATE TABLE IF NOT EXISTS accounts (
+    id TEXT PRIMARY KEY,
+    email TEXT NOT NULL,
+    display_name TEXT NOT NULL,
+    allowed_models TEXT NOT NULL DEFAULT '[]',
+    status TEXT NOT NULL,
+    default_model TEXT,
+    max_cost_cents INTEGER,
+    guardrail_prompt TEXT,
+    req_per_day INTEGER,
+    tokens_per_day INTEGER,
+    model_price_caps TEXT NOT NULL DEFAULT '[]',
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS catalog_models (
+    key TEXT PRIMARY KEY,
+    provider TEXT NOT NULL,
+    model_id TEXT NOT NULL,
+    prompt_price_per_1k REAL NOT NULL,
+    completion_price_per_1k REAL NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS model_aliases (
+    alias TEXT PRIMARY KEY,
+    targets TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS model_fallbacks (
+    model TEXT PRIMARY KEY,
+    chain TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+-- Bumped on every router state write; replicas poll it to know when to reload.
+CREATE TABLE IF NOT EXISTS router_state (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    version INTEGER NOT NULL
+);
+
+INSERT OR IGNORE INTO router_state (id, version) VALUES (1, 0);
@@ -0,0 +1,23 @@
+CREATE INDEX IF NOT EXISTS idx_messages_user_created_at ON messages(user_id, created_at);
+
+-- Per-account daily totals, maintained in the same transaction as message inserts
+-- so limit checks don't have to aggregate the messages table.
+CREATE TABLE IF NOT EXISTS usage_rollups (
+    user_id TEXT NOT NULL,
+    day TEXT NOT NULL,
+    requests INTEGER NOT NULL DEFAULT 0,
+    tokens_input INTEGER NOT NULL DEFAULT 0,
+    tokens_output INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, day)
+);
+
+INSERT OR IGNORE INTO usage_rollups (user_id, day, requests, tokens_input, tokens_output)
+SELECT
+    user_id,
+    substr(created_at, 1, 10),
+    SUM(CASE WHEN role = 'user' THEN 1 ELSE 0 END),
+    COALESCE(SUM(tokens_input), 0),
+    COALESCE(SUM(tokens_output), 0)
+FROM messages
+WHERE user_id IS NOT NULL
+GROUP BY user_id, substr(created_at, 1, 10);
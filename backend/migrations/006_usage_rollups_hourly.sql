@@ -0,0 +1,20 @@
+-- Hour-granular totals used to rebuild the in-memory 24h usage windows on boot.
+CREATE TABLE IF NOT EXISTS usage_rollups_hourly (
+    user_id TEXT NOT NULL,
+    hour TEXT NOT NULL,
+    requests INTEGER NOT NULL DEFAULT 0,
+    tokens_input INTEGER NOT NULL DEFAULT 0,
+    tokens_output INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (user_id, hour)
+);
+
+INSERT OR IGNORE INTO usage_rollups_hourly (user_id, hour, requests, tokens_input, tokens_output)
+SELECT
+    user_id,
+    substr(created_at, 1, 13),
+    SUM(CASE WHEN role = 'user' THEN 1 ELSE 0 END),
+    COALESCE(SUM(tokens_input), 0),
+    COALESCE(SUM(tokens_output), 0)
+FROM messages
+WHERE user_id IS NOT NULL
+GROUP BY user_id, substr(created_at, 1, 13);
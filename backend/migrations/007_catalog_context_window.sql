@@ -0,0 +1,6 @@
+-- Context window per catalog model, used to trim or summarize history before it
+-- overflows the provider's limit. NULL means unknown (no trimming).
+ALTER TABLE catalog_models ADD COLUMN context_window INTEGER;
+
+UPDATE catalog_models SET context_window = 128000 WHERE model_id = 'gpt-4-turbo-preview';
+UPDATE catalog_models SET context_window = 200000 WHERE model_id LIKE 'claude-3%';
@@ -0,0 +1,9 @@
+-- Rolling per-conversation summary, refreshed in the background after each
+-- exchange and used in place of history trimmed to fit the context window.
+CREATE TABLE IF NOT EXISTS conversation_summaries (
+    conversation_id TEXT PRIMARY KEY,
+    summary TEXT NOT NULL,
+    messages_covered INTEGER NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+);
@@ -0,0 +1,2 @@
+-- Set on an assistant message when it is regenerated; points at the replacement.
+ALTER TABLE messages ADD COLUMN superseded_by TEXT;
@@ -0,0 +1,10 @@
+-- One rating per assistant message; resubmitting replaces it.
+CREATE TABLE IF NOT EXISTS message_feedback (
+    message_id TEXT PRIMARY KEY,
+    user_id TEXT,
+    rating INTEGER NOT NULL CHECK (rating BETWEEN 1 AND 5),
+    category TEXT,
+    comment TEXT,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (message_id) REFERENCES messages(id) ON DELETE CASCADE
+);
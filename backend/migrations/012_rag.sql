@@ -0,0 +1,35 @@
+ALTER TABLE catalog_models ADD COLUMN kind TEXT NOT NULL DEFAULT 'chat';
+
+CREATE TABLE IF NOT EXISTS collections (
+    id TEXT PRIMARY KEY,
+    description TEXT,
+    embedding_model TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS documents (
+    id TEXT PRIMARY KEY,
+    collection_id TEXT NOT NULL,
+    title TEXT NOT NULL,
+    source TEXT,
+    chunk_count INTEGER NOT NULL DEFAULT 0,
+    created_at TEXT NOT NULL,
+    FOREIGN KEY (collection_id) REFERENCES collections(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_documents_collection ON documents(collection_id);
+
+-- Embeddings are little-endian f32 vectors; search is a brute-force cosine scan
+-- over one collection, which is plenty for knowledge-base sized corpora.
+CREATE TABLE IF NOT EXISTS document_chunks (
+    id TEXT PRIMARY KEY,
+    document_id TEXT NOT NULL,
+    collection_id TEXT NOT NULL,
+    ordinal INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    embedding BLOB NOT NULL,
+    FOREIGN KEY (document_id) REFERENCES documents(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_document_chunks_collection ON document_chunks(collection_id);
+CREATE INDEX IF NOT EXISTS idx_document_chunks_document ON document_chunks(document_id);
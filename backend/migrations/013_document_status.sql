@@ -0,0 +1,6 @@
+-- Large documents are indexed in the background; chunks are only written once
+-- every embedding succeeded, so search never sees a partial document.
+ALTER TABLE documents ADD COLUMN content_type TEXT NOT NULL DEFAULT 'text';
+ALTER TABLE documents ADD COLUMN status TEXT NOT NULL DEFAULT 'ready';
+ALTER TABLE documents ADD COLUMN error TEXT;
+ALTER TABLE documents ADD COLUMN updated_at TEXT;
@@ -0,0 +1,4 @@
+-- sort_order is user-assigned; conversations without one follow, newest first.
+ALTER TABLE conversations ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE conversations ADD COLUMN starred INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE conversations ADD COLUMN sort_order INTEGER;
@@ -0,0 +1,9 @@
+-- Unsent user input, kept apart from messages so it never reaches a provider
+-- or the usage tallies.
+CREATE TABLE IF NOT EXISTS conversation_drafts (
+    conversation_id TEXT PRIMARY KEY,
+    user_id TEXT,
+    content TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    FOREIGN KEY (conversation_id) REFERENCES conversations(id) ON DELETE CASCADE
+);
@@ -0,0 +1,11 @@
+-- Requests turned away by rate limits, price caps or daily quotas, kept for
+-- per-account usage reports.
+CREATE TABLE IF NOT EXISTS limit_rejections (
+    id TEXT PRIMARY KEY,
+    user_id TEXT,
+    kind TEXT NOT NULL,
+    detail TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_limit_rejections_user ON limit_rejections(user_id, created_at);
@@ -0,0 +1,15 @@
+-- Routing decisions and PII redaction per message, for the admin conversation
+-- inspector.
+ALTER TABLE messages ADD COLUMN routing_trace TEXT;
+ALTER TABLE messages ADD COLUMN pii_redacted INTEGER NOT NULL DEFAULT 0;
+
+-- Every time an admin reads stored message content.
+CREATE TABLE IF NOT EXISTS admin_access_log (
+    id TEXT PRIMARY KEY,
+    actor_id TEXT NOT NULL,
+    action TEXT NOT NULL,
+    target_id TEXT NOT NULL,
+    created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_admin_access_log_target ON admin_access_log(target_id, created_at);
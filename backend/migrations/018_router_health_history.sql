@@ -0,0 +1,16 @@
+-- Periodic per-model router health, so degradation can be traced over time.
+CREATE TABLE IF NOT EXISTS router_health_history (
+    id TEXT PRIMARY KEY,
+    recorded_at TEXT NOT NULL,
+    model TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    successes INTEGER NOT NULL,
+    failures INTEGER NOT NULL,
+    success_rate REAL NOT NULL,
+    p50_ms INTEGER,
+    p95_ms INTEGER,
+    p99_ms INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS idx_router_health_history_model ON router_health_history(model, recorded_at);
+CREATE INDEX IF NOT EXISTS idx_router_health_history_recorded ON router_health_history(recorded_at);
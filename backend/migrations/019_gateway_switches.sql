@@ -0,0 +1,8 @@
+-- Operator overrides: maintenance mode and provider/model kill switches.
+CREATE TABLE IF NOT EXISTS gateway_switches (
+    kind TEXT NOT NULL,
+    target TEXT NOT NULL,
+    reason TEXT,
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (kind, target)
+);
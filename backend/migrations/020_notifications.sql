@@ -0,0 +1,17 @@
+-- Admin notification center. Repeats of an unread notification with the same
+-- dedup_key bump its occurrence count instead of adding rows.
+CREATE TABLE IF NOT EXISTS notifications (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    severity TEXT NOT NULL,
+    title TEXT NOT NULL,
+    detail TEXT NOT NULL,
+    dedup_key TEXT NOT NULL,
+    occurrences INTEGER NOT NULL DEFAULT 1,
+    created_at TEXT NOT NULL,
+    last_seen_at TEXT NOT NULL,
+    read_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_notifications_unread ON notifications(read_at, last_seen_at);
+CREATE INDEX IF NOT EXISTS idx_notifications_dedup ON notifications(dedup_key, read_at);
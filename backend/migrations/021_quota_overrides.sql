@@ -0,0 +1,16 @@
+-- Support tooling for daily quotas: temporary limit raises that lapse on their
+-- own, and the last manual reset per account (usage before it no longer counts
+-- when the in-memory counters are rebuilt).
+CREATE TABLE IF NOT EXISTS quota_overrides (
+    account_id TEXT PRIMARY KEY,
+    req_per_day INTEGER,
+    tokens_per_day INTEGER,
+    expires_at TEXT NOT NULL,
+    reason TEXT,
+    created_at TEXT NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS quota_resets (
+    account_id TEXT PRIMARY KEY,
+    reset_at TEXT NOT NULL
+);
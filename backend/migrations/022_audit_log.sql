@@ -0,0 +1,16 @@
+-- Tamper-evident audit trail: each entry carries an HMAC over its own fields
+-- and the previous entry's hash, so editing, reordering or deleting a past
+-- entry breaks the chain from that point on.
+CREATE TABLE IF NOT EXISTS audit_log (
+    seq INTEGER PRIMARY KEY,
+    created_at TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    action TEXT NOT NULL,
+    detail TEXT NOT NULL,
+    key_id TEXT NOT NULL,
+    prev_hash TEXT NOT NULL,
+    hash TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_audit_log_kind ON audit_log(kind, seq);
@@ -0,0 +1,4 @@
+-- Where each catalog model is hosted, and the region an account's prompts must
+-- stay in. NULL means unknown and unrestricted respectively.
+ALTER TABLE catalog_models ADD COLUMN region TEXT;
+ALTER TABLE accounts ADD COLUMN data_residency TEXT;
@@ -0,0 +1,15 @@
+-- Provider API keys brought by an account, encrypted with the master key
+-- (AES-256-GCM). `key_id` names the master key that sealed the row so rotation
+-- knows what still needs re-encrypting; only the last four characters are kept
+-- in the clear.
+CREATE TABLE IF NOT EXISTS provider_keys (
+    account_id TEXT NOT NULL,
+    provider TEXT NOT NULL,
+    nonce BLOB NOT NULL,
+    ciphertext BLOB NOT NULL,
+    key_id TEXT NOT NULL,
+    last4 TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (account_id, provider)
+);
@@ -0,0 +1,3 @@
+-- Web origins allowed to use an account's session, as a JSON array. Empty means
+-- any origin the global ALLOWED_ORIGINS list lets through.
+ALTER TABLE accounts ADD COLUMN allowed_origins TEXT NOT NULL DEFAULT '[]';
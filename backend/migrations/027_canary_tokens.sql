@@ -0,0 +1,12 @@
+-- Canary strings planted in account guardrail prompts. Retired tokens are no
+-- longer injected but are still watched for, since an old prompt can leak late.
+CREATE TABLE IF NOT EXISTS canary_tokens (
+    token TEXT PRIMARY KEY,
+    account_id TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    retired_at TEXT,
+    hits INTEGER NOT NULL DEFAULT 0,
+    last_seen_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_canary_tokens_account ON canary_tokens(account_id, created_at);
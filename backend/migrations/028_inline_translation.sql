@@ -0,0 +1,5 @@
+-- Inline translation: accounts opt in, and translated turns keep the English
+-- text exchanged with the model next to what the user wrote or read.
+ALTER TABLE accounts ADD COLUMN inline_translation INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE messages ADD COLUMN language TEXT;
+ALTER TABLE messages ADD COLUMN english_content TEXT;
@@ -0,0 +1,8 @@
+-- Webhook secrets are sealed under KEY_ENCRYPTION_KEY like provider keys.
+-- `secret` only holds plaintext for endpoints created before this; the
+-- provider-keys reencrypt run seals those and clears it.
+ALTER TABLE webhooks ADD COLUMN secret_nonce BLOB;
+ALTER TABLE webhooks ADD COLUMN secret_ciphertext BLOB;
+ALTER TABLE webhooks ADD COLUMN secret_key_id TEXT;
+ALTER TABLE webhooks ADD COLUMN secret_last4 TEXT NOT NULL DEFAULT '';
+UPDATE webhooks SET secret_last4 = substr(secret, -4);
@@ -0,0 +1,8 @@
+-- Summaries are keyed by the conversation and its owner, so a summary is only
+-- ever read back for the account whose turns it covers.
+ALTER TABLE conversation_summaries ADD COLUMN user_id TEXT;
+
+UPDATE conversation_summaries
+SET user_id = (
+    SELECT user_id FROM conversations WHERE conversations.id = conversation_summaries.conversation_id
+);
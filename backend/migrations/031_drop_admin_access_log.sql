@@ -0,0 +1,4 @@
+-- Admin reads of message content are recorded in the audit trail as
+-- `conversation.read` actions; the old access log is no longer written.
+DROP INDEX IF EXISTS idx_admin_access_log_target;
+DROP TABLE IF EXISTS admin_access_log;
@@ -0,0 +1,230 @@
+//! Abuse scoring. Each account's recent activity is scored on request
+//! velocity, blocked attempts (policy blocks and limit rejections) and the same
+//! prompt sent over and over. An account whose score crosses the threshold is
+//! throttled to a trickle for a while and an admin is notified, so a leaked
+//! credential stops burning quota before anyone has noticed.
+//!
+//! Activity is kept in memory per replica, like the quota counters.
+
+use crate::config::Config;
+use chrono::{DateTime, Duration, Utc};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, VecDeque},
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+/// How far back activity counts toward the score.
+const WINDOW_MINUTES: i64 = 10;
+/// Events kept per account; enough to score any rate worth throttling.
+const MAX_EVENTS: usize = 2_000;
+/// Points per request above the velocity limit within the last minute.
+const VELOCITY_POINTS: u32 = 1;
+const BLOCKED_POINTS: u32 = 5;
+/// Points per repeat of a prompt beyond `FREE_REPEATS` within the window.
+const REPEAT_POINTS: u32 = 2;
+/// Sending a prompt again after an error or to compare answers is normal.
+const FREE_REPEATS: u32 = 2;
+/// Idle accounts are swept out of memory every this many recorded events.
+const SWEEP_EVERY: u64 = 1_024;
+/// Requests a minute a throttled account still gets.
+pub const THROTTLED_PER_MINUTE: u64 = 2;
+
+#[derive(Clone, Copy)]
+pub struct AbuseSettings {
+    pub velocity_per_minute: u32,
+    pub threshold: u32,
+    pub throttle: Duration,
+}
+
+impl AbuseSettings {
+    /// `None` when detection is off.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        config.abuse_detection.then(|| Self {
+            velocity_per_minute: config.abuse_velocity_per_minute,
+            threshold: config.abuse_score_threshold,
+            throttle: Duration::seconds(config.abuse_throttle_secs as i64),
+        })
+    }
+}
+
+/// An account's score and what it is made of.
+#[derive(Clone, Debug, Serialize)]
+pub struct AccountRisk {
+    pub account_id: String,
+    pub score: u32,
+    pub requests_last_minute: u32,
+    pub blocked: u32,
+    pub repeated_prompts: u32,
+    pub throttled_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Clone, Default)]
+pub struct AbuseDetector {
+    accounts: Arc<Mutex<HashMap<String, Activity>>>,
+    recorded: Arc<AtomicU64>,
+}
+
+#[derive(Default)]
+struct Activity {
+    events: VecDeque<Event>,
+    throttled_until: Option<DateTime<Utc>>,
+}
+
+struct Event {
+    at: DateTime<Utc>,
+    /// The prompt's hash for requests; `None` for blocked attempts.
+    prompt: Option<u64>,
+}
+
+impl Activity {
+    fn push(&mut self, event: Event) {
+        self.events.push_back(event);
+        if self.events.len() > MAX_EVENTS {
+            self.events.pop_front();
+        }
+    }
+
+    fn prune(&mut self, now: DateTime<Utc>) {
+        let cutoff = now - Duration::minutes(WINDOW_MINUTES);
+        while self.events.front().is_some_and(|e| e.at < cutoff) {
+            self.events.pop_front();
+        }
+        if self.throttled_until.is_some_and(|until| until <= now) {
+            self.throttled_until = None;
+        }
+    }
+
+    fn risk(&self, account_id: &str, settings: &AbuseSettings, now: DateTime<Utc>) -> AccountRisk {
+        let minute_ago = now - Duration::minutes(1);
+        let mut requests_last_minute: u32 = 0;
+        let mut blocked = 0;
+        let mut prompts: HashMap<u64, u32> = HashMap::new();
+        for event in &self.events {
+            match event.prompt {
+                Some(prompt) => {
+                    *prompts.entry(prompt).or_default() += 1;
+                    if event.at >= minute_ago {
+                        requests_last_minute += 1;
+                    }
+                }
+                None => blocked += 1,
+            }
+        }
+        let repeated_prompts = prompts
+            .values()
+            .map(|n| n.saturating_sub(FREE_REPEATS))
+            .sum::<u32>();
+        let score = requests_last_minute.saturating_sub(settings.velocity_per_minute)
+            * VELOCITY_POINTS
+            + blocked * BLOCKED_POINTS
+            + repeated_prompts * REPEAT_POINTS;
+        AccountRisk {
+            account_id: account_id.to_string(),
+            score,
+            requests_last_minute,
+            blocked,
+            repeated_prompts,
+            throttled_until: self.throttled_until,
+        }
+    }
+}
+
+impl AbuseDetector {
+    /// When the account's throttle lifts, if it is throttled.
+    pub fn throttled_until(&self, account: &str) -> Option<DateTime<Utc>> {
+        let now = Utc::now();
+        self.lock()
+            .get(account)
+            .and_then(|a| a.throttled_until)
+            .filter(|until| *until > now)
+    }
+
+    /// Records a chat request. Returns the account's risk when this request
+    /// got it throttled.
+    pub fn record_request(
+        &self,
+        account: &str,
+        prompt: &str,
+        settings: &AbuseSettings,
+    ) -> Option<AccountRisk> {
+        let mut hasher = DefaultHasher::new();
+        prompt.trim().to_lowercase().hash(&mut hasher);
+        self.record(account, Some(hasher.finish()), settings)
+    }
+
+    /// Records a request turned away by a policy or a limit. Returns the
+    /// account's risk when this got it throttled.
+    pub fn record_blocked(&self, account: &str, settings: &AbuseSettings) -> Option<AccountRisk> {
+        self.record(account, None, settings)
+    }
+
+    /// Accounts with a score or a throttle, riskiest first.
+    pub fn snapshot(&self, settings: &AbuseSettings) -> Vec<AccountRisk> {
+        let now = Utc::now();
+        let mut accounts = self.lock();
+        sweep(&mut accounts, now);
+        let mut risks: Vec<AccountRisk> = accounts
+            .iter()
+            .map(|(id, activity)| activity.risk(id, settings, now))
+            .filter(|risk| risk.score > 0 || risk.throttled_until.is_some())
+            .collect();
+        risks.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then_with(|| a.account_id.cmp(&b.account_id))
+        });
+        risks
+    }
+
+    /// Lifts the throttle and forgets the activity behind it. Returns whether
+    /// there was anything to clear.
+    pub fn release(&self, account: &str) -> bool {
+        self.lock().remove(account).is_some()
+    }
+
+    fn record(
+        &self,
+        account: &str,
+        prompt: Option<u64>,
+        settings: &AbuseSettings,
+    ) -> Option<AccountRisk> {
+        let now = Utc::now();
+        let mut accounts = self.lock();
+        if self.recorded.fetch_add(1, Ordering::Relaxed) % SWEEP_EVERY == SWEEP_EVERY - 1 {
+            sweep(&mut accounts, now);
+        }
+        let activity = accounts.entry(account.to_string()).or_default();
+        activity.prune(now);
+        activity.push(Event { at: now, prompt });
+        if activity.throttled_until.is_some() {
+            return None;
+        }
+        let risk = activity.risk(account, settings, now);
+        if risk.score < settings.threshold {
+            return None;
+        }
+        let until = now + settings.throttle;
+        activity.throttled_until = Some(until);
+        Some(AccountRisk {
+            throttled_until: Some(until),
+            ..risk
+        })
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Activity>> {
+        self.accounts.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Drops accounts with nothing left in the window and no throttle.
+fn sweep(accounts: &mut HashMap<String, Activity>, now: DateTime<Utc>) {
+    accounts.retain(|_, activity| {
+        activity.prune(now);
+        !activity.events.is_empty() || activity.throttled_until.is_some()
+    });
+}
@@ -1,28 +1,127 @@
 use crate::{
     AppState,
-    audit::{DashboardResponse, build_dashboard},
+    abuse::{AbuseSettings, AccountRisk},
+    audit::{DashboardInputs, DashboardResponse, PageInfo, build_dashboard},
+    audit_log::AuditEvent,
+    auth::validate_token,
+    config::{self, ConfigReload},
+    db::{ActivityFilter, ErasedContent, HealthHistoryPoint, NamedCount, PurgeSummary},
     error::AppError,
-    governance::{Policy, PolicyUpsert, evaluate_policies},
-    model_router::{AccountAccess, AccountStatus, AliasTarget, CatalogEntry, ModelPriceCap},
+    governance::{Policy, PolicyHit, PolicyUpsert, evaluate_policies},
+    llm::{Provider, estimate_cost},
+    model_router::{
+        AccountAccess, AccountStatus, AliasPreview, AliasTarget, CatalogEntry, GatewaySwitches,
+        LimitOverride, ModelDeletion, ModelKind, ModelPriceCap, SwitchKind, normalize_model_list,
+        normalize_origins, normalize_region,
+    },
+    routes::{
+        chat::{current_usage, provider_from_str},
+        conversations::normalize_tag,
+    },
 };
 use axum::{
     Json,
-    extract::{Path, State},
+    extract::{Path, Query, State},
 };
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[cfg(feature = "chaos")]
+use crate::llm::{ChaosSettings, ChaosStatus};
+
+const DEFAULT_REQUEST_LIMIT: i64 = 50;
+const DEFAULT_HIT_LIMIT: i64 = 20;
+const MAX_PAGE_LIMIT: i64 = 500;
+const DEFAULT_USAGE_WINDOW: &str = "30d";
+const MAX_USAGE_WINDOW_HOURS: i64 = 365 * 24;
+const TOP_MODEL_LIMIT: usize = 5;
+const MAX_OVERRIDE_DAYS: i64 = 30;
+const DEFAULT_HISTORY_LIMIT: i64 = 1000;
+const MAX_HISTORY_LIMIT: i64 = 10_000;
+const DEFAULT_ALIAS_SAMPLES: u32 = 100;
+const MAX_ALIAS_SAMPLES: u32 = 100_000;
+
+#[derive(Debug, Deserialize)]
+pub struct DashboardQuery {
+    /// Only show recent requests from conversations carrying this tag.
+    pub tag: Option<String>,
+    /// RFC 3339 timestamp or `YYYY-MM-DD` (inclusive).
+    pub from: Option<String>,
+    /// RFC 3339 timestamp (exclusive) or `YYYY-MM-DD` (inclusive of that day).
+    pub to: Option<String>,
+    pub account_id: Option<String>,
+    pub model: Option<String>,
+    pub role: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+    pub hits_limit: Option<i64>,
+    pub hits_offset: Option<i64>,
+}
 
 pub async fn dashboard_overview(
     State(state): State<AppState>,
+    Query(query): Query<DashboardQuery>,
 ) -> Result<Json<DashboardResponse>, AppError> {
+    let role = query.role.map(|r| r.trim().to_lowercase());
+    if let Some(role) = &role
+        && !matches!(role.as_str(), "user" | "assistant")
+    {
+        return Err(AppError::BadRequest(
+            "role must be 'user' or 'assistant'".into(),
+        ));
+    }
+    let filters = ActivityFilter {
+        tag: query.tag.as_deref().map(normalize_tag).transpose()?,
+        from: query
+            .from
+            .as_deref()
+            .map(|v| parse_bound(v, false))
+            .transpose()?,
+        to: query
+            .to
+            .as_deref()
+            .map(|v| parse_bound(v, true))
+            .transpose()?,
+        user_id: non_empty(query.account_id),
+        model: non_empty(query.model),
+        role,
+    };
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_REQUEST_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+    let hits_limit = query
+        .hits_limit
+        .unwrap_or(DEFAULT_HIT_LIMIT)
+        .clamp(1, MAX_PAGE_LIMIT);
+    let hits_offset = query.hits_offset.unwrap_or(0).max(0);
+
     let counts = state.db.counts().await?;
     let models = state.db.model_usage().await?;
-    let recent = state.db.recent_messages(50).await?;
+    // One extra row tells whether another page exists.
+    let mut recent = state
+        .db
+        .recent_messages(&filters, limit + 1, offset)
+        .await?;
+    let has_more_requests = recent.len() as i64 > limit;
+    recent.truncate(limit as usize);
     let accounts = state.access.list().await;
     let policies = state.db.list_policies().await?;
-    let policy_hits = state.db.recent_policy_hits(20).await?;
+    let mut policy_hits = state
+        .db
+        .recent_policy_hits(&filters, hits_limit + 1, hits_offset)
+        .await?;
+    let has_more_policy_hits = policy_hits.len() as i64 > hits_limit;
+    policy_hits.truncate(hits_limit as usize);
     let router_health = state.access.router_health();
+    let feedback = state.db.feedback_by_model().await?;
+    let tags = state.db.tag_counts().await?;
 
-    let dashboard = build_dashboard(
+    let dashboard = build_dashboard(DashboardInputs {
         counts,
         models,
         recent,
@@ -30,14 +129,822 @@ pub async fn dashboard_overview(
         policies,
         policy_hits,
         router_health,
-    );
+        dedup: state.dedup.stats(),
+        feedback,
+        tags,
+        filters,
+        page: PageInfo {
+            limit,
+            offset,
+            has_more_requests,
+            hits_limit,
+            hits_offset,
+            has_more_policy_hits,
+        },
+    });
     Ok(Json(dashboard))
 }
 
+fn non_empty(value: Option<String>) -> Option<String> {
+    value
+        .map(|v| v.trim().to_string())
+        .filter(|v| !v.is_empty())
+}
+
+/// Normalizes a time filter to the RFC 3339 UTC form messages are stored with.
+/// A bare date as the upper bound covers that whole day.
+fn parse_bound(value: &str, upper: bool) -> Result<String, AppError> {
+    let value = value.trim();
+    if let Ok(ts) = DateTime::parse_from_rfc3339(value) {
+        return Ok(ts.with_timezone(&Utc).to_rfc3339());
+    }
+    let date = NaiveDate::parse_from_str(value, "%Y-%m-%d").map_err(|_| {
+        AppError::BadRequest(format!(
+            "invalid time '{value}'; use RFC 3339 or YYYY-MM-DD"
+        ))
+    })?;
+    let date = if upper {
+        date.succ_opt().unwrap_or(date)
+    } else {
+        date
+    };
+    Ok(date.and_time(NaiveTime::MIN).and_utc().to_rfc3339())
+}
+
 pub async fn list_accounts(State(state): State<AppState>) -> Json<Vec<AccountAccess>> {
     Json(state.access.list().await)
 }
 
+#[derive(Debug, Deserialize)]
+pub struct CreateAccountBody {
+    /// Defaults to a generated id.
+    pub id: Option<String>,
+    pub email: String,
+    pub display_name: String,
+    #[serde(default)]
+    pub allowed_models: Vec<String>,
+    pub status: Option<AccountStatus>,
+    pub default_model: Option<String>,
+    pub max_cost_cents: Option<u32>,
+    pub guardrail_prompt: Option<String>,
+    pub req_per_day: Option<u32>,
+    pub tokens_per_day: Option<u32>,
+    #[serde(default)]
+    pub model_price_caps: Vec<ModelPriceCap>,
+    pub data_residency: Option<String>,
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    #[serde(default)]
+    pub inline_translation: bool,
+}
+
+pub async fn create_account(
+    State(state): State<AppState>,
+    Json(body): Json<CreateAccountBody>,
+) -> Result<Json<AccountAccess>, AppError> {
+    let account = account_from_body(body)?;
+    Ok(Json(state.access.create_account(account).await?))
+}
+
+fn account_from_body(body: CreateAccountBody) -> Result<AccountAccess, AppError> {
+    let account = AccountAccess {
+        id: body
+            .id
+            .map(|id| id.trim().to_string())
+            .unwrap_or_else(|| Uuid::new_v4().to_string()),
+        email: body.email.trim().to_string(),
+        display_name: body.display_name.trim().to_string(),
+        allowed_models: normalize_model_list(body.allowed_models),
+        status: body.status.unwrap_or(AccountStatus::Active),
+        default_model: body.default_model,
+        max_cost_cents: body.max_cost_cents,
+        guardrail_prompt: body.guardrail_prompt,
+        req_per_day: body.req_per_day,
+        tokens_per_day: body.tokens_per_day,
+        model_price_caps: body.model_price_caps,
+        data_residency: normalize_region(body.data_residency)?,
+        allowed_origins: normalize_origins(body.allowed_origins)?,
+        inline_translation: body.inline_translation,
+    };
+    validate_account(&account)?;
+    Ok(account)
+}
+
+pub fn validate_account(account: &AccountAccess) -> Result<(), AppError> {
+    let id = &account.id;
+    if id.is_empty()
+        || id.len() > 64
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    {
+        return Err(AppError::BadRequest(
+            "account id must be 1-64 letters, digits, '-', '_' or '.'".into(),
+        ));
+    }
+    if !account.email.contains('@') {
+        return Err(AppError::BadRequest("a valid email is required".into()));
+    }
+    if account.display_name.trim().is_empty() {
+        return Err(AppError::BadRequest("display_name is required".into()));
+    }
+    if normalize_region(account.data_residency.clone())? != account.data_residency {
+        return Err(AppError::BadRequest(
+            "data_residency must be lowercase and not blank".into(),
+        ));
+    }
+    if normalize_origins(account.allowed_origins.clone())? != account.allowed_origins {
+        return Err(AppError::BadRequest(
+            "allowed_origins must be lowercase, without duplicates or trailing '/'".into(),
+        ));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeleteAccountQuery {
+    /// Also delete the account's conversations, messages and usage history.
+    #[serde(default)]
+    pub purge: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeleteAccountResponse {
+    pub account: AccountAccess,
+    pub purged: Option<PurgeSummary>,
+}
+
+/// Removes an account. Without `purge`, its conversations and usage stay in
+/// the database for auditing.
+pub async fn delete_account(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<DeleteAccountQuery>,
+) -> Result<Json<DeleteAccountResponse>, AppError> {
+    let account = state.access.delete_account(&id).await?;
+    let purged = if query.purge {
+        let summary = state.db.purge_user_data(&id).await?;
+        state.usage.forget(&id);
+        Some(summary)
+    } else {
+        None
+    };
+    Ok(Json(DeleteAccountResponse { account, purged }))
+}
+
+/// What an erasure removed and kept, as signed for compliance records.
+#[derive(Debug, Serialize)]
+pub struct ErasureReport {
+    pub erasure_id: Uuid,
+    pub user_id: String,
+    /// Stands in for the user in the notifications kept.
+    pub pseudonym: String,
+    pub erased_at: String,
+    pub requested_by: Option<String>,
+    pub removed: ErasedContent,
+    pub anonymized: AnonymizedRecords,
+    pub retained: RetainedRecords,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnonymizedRecords {
+    pub notifications: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RetainedRecords {
+    /// Audit trail entries naming the user, kept so the chain still verifies.
+    pub audit_entries: i64,
+    /// Daily and hourly usage rollups. They hold only counts and costs, and
+    /// quotas and account usage are computed from them.
+    pub usage_days: u64,
+    pub usage_hours: u64,
+    /// The account itself is removed with `DELETE /admin/accounts/:id`.
+    pub account: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SignedErasureReport {
+    pub report: ErasureReport,
+    pub key_id: String,
+    /// HMAC-SHA256 (keyed by `AUDIT_HMAC_KEY`) over `report` as serialized here.
+    pub signature: String,
+    /// The audit trail entry recording the erasure and its signature.
+    pub audit_seq: i64,
+}
+
+/// Erases a user's conversations, messages, policy hits, feedback and limit
+/// rejections. Usage rollups stay under the account id, so the daily quota,
+/// account usage and aggregate reporting are unchanged, before and after a
+/// restart.
+pub async fn erase_user_data(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<Json<SignedErasureReport>, AppError> {
+    let id = id.trim().to_string();
+    if id.is_empty() {
+        return Err(AppError::BadRequest("user id is required".into()));
+    }
+    let requested_by = validate_token(&state.config.load(), &jar).map(|claims| claims.sub);
+    let erasure_id = Uuid::new_v4();
+    let pseudonym = format!("erased-{}", erasure_id.simple());
+    let counts = state.db.erase_user_data(&id, &pseudonym).await?;
+    let report = ErasureReport {
+        erasure_id,
+        user_id: id.clone(),
+        pseudonym,
+        erased_at: Utc::now().to_rfc3339(),
+        requested_by: requested_by.clone(),
+        removed: counts.removed,
+        anonymized: AnonymizedRecords {
+            notifications: counts.notifications,
+        },
+        retained: RetainedRecords {
+            audit_entries: state.db.audit_entries_by(&id).await?,
+            usage_days: counts.usage_days,
+            usage_hours: counts.usage_hours,
+            account: state.access.account(Some(&id)).await.is_some(),
+        },
+    };
+    let serialized = serde_json::to_vec(&report).map_err(|e| AppError::Internal(e.to_string()))?;
+    let (key_id, signature) = state.audit.seal(&serialized);
+    let entry = state
+        .audit
+        .append(AuditEvent::admin_action(
+            requested_by.as_deref(),
+            "user_data.erase",
+            serde_json::json!({
+                "user_id": id,
+                "erasure_id": erasure_id,
+                "signature": signature,
+            }),
+        ))
+        .await?;
+    Ok(Json(SignedErasureReport {
+        report,
+        key_id,
+        signature,
+        audit_seq: entry.seq,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AccountUsageQuery {
+    /// Trailing window such as `30d` or `12h`; defaults to 30 days.
+    pub window: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelUsage {
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub responses: i64,
+    pub tokens_input: i64,
+    pub tokens_output: i64,
+    pub cost: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct QuotaRemaining {
+    /// Effective limits, including any active override.
+    pub req_per_day: Option<u32>,
+    pub tokens_per_day: Option<u32>,
+    pub requests_used: u64,
+    pub tokens_used: u64,
+    /// `None` when the account has no limit of that kind.
+    pub requests_remaining: Option<u64>,
+    pub tokens_remaining: Option<u64>,
+    pub limit_override: Option<LimitOverride>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AccountUsageReport {
+    pub account_id: String,
+    pub window: String,
+    pub since: String,
+    pub requests: i64,
+    pub tokens_input: i64,
+    pub tokens_output: i64,
+    /// Estimated spend in USD across models with known pricing.
+    pub cost: f64,
+    pub top_models: Vec<ModelUsage>,
+    pub policy_hits: Vec<NamedCount>,
+    pub limit_rejections: Vec<NamedCount>,
+    pub quota: QuotaRemaining,
+}
+
+pub async fn account_usage(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<AccountUsageQuery>,
+) -> Result<Json<AccountUsageReport>, AppError> {
+    let account = state
+        .access
+        .account(Some(&id))
+        .await
+        .ok_or_else(|| AppError::BadRequest(format!("account {id} not found")))?;
+    let window = non_empty(query.window).unwrap_or_else(|| DEFAULT_USAGE_WINDOW.to_string());
+    let since = Utc::now() - parse_window(&window)?;
+    let since_ts = since.to_rfc3339();
+
+    let totals = state
+        .db
+        .account_usage_totals(&account.id, &since.format("%Y-%m-%dT%H").to_string())
+        .await?;
+    let models = state.db.account_model_usage(&account.id, &since_ts).await?;
+    let policy_hits = state.db.account_policy_hits(&account.id, &since_ts).await?;
+    let limit_rejections = state
+        .db
+        .account_limit_rejections(&account.id, &since_ts)
+        .await?;
+
+    let mut models: Vec<ModelUsage> = models
+        .into_iter()
+        .map(|row| {
+            let cost = row
+                .provider
+                .as_deref()
+                .and_then(|p| provider_from_str(p).ok())
+                .zip(row.model.as_deref())
+                .and_then(|(provider, model)| {
+                    estimate_cost(
+                        provider,
+                        model,
+                        Some(row.tokens_input as u32),
+                        Some(row.tokens_output as u32),
+                    )
+                });
+            ModelUsage {
+                provider: row.provider,
+                model: row.model,
+                responses: row.responses,
+                tokens_input: row.tokens_input,
+                tokens_output: row.tokens_output,
+                cost,
+            }
+        })
+        .collect();
+    let cost = models
+        .iter()
+        .filter_map(|m| m.cost)
+        .fold(0.0, |acc, c| acc + c);
+    models.truncate(TOP_MODEL_LIMIT);
+
+    let quota = quota_remaining(&state, &account).await;
+
+    Ok(Json(AccountUsageReport {
+        account_id: account.id,
+        window,
+        since: since_ts,
+        requests: totals.requests,
+        tokens_input: totals.tokens_input,
+        tokens_output: totals.tokens_output,
+        cost,
+        top_models: models,
+        policy_hits,
+        limit_rejections,
+        quota,
+    }))
+}
+
+async fn quota_remaining(state: &AppState, account: &AccountAccess) -> QuotaRemaining {
+    let used = current_usage(state, &account.id).await;
+    let (req_per_day, tokens_per_day) = state.access.effective_limits(account).await;
+    QuotaRemaining {
+        req_per_day,
+        tokens_per_day,
+        requests_used: used.requests,
+        tokens_used: used.tokens,
+        requests_remaining: req_per_day.map(|limit| (limit as u64).saturating_sub(used.requests)),
+        tokens_remaining: tokens_per_day.map(|limit| (limit as u64).saturating_sub(used.tokens)),
+        limit_override: state.access.limit_override(&account.id).await,
+    }
+}
+
+async fn existing_account(state: &AppState, id: &str) -> Result<AccountAccess, AppError> {
+    state
+        .access
+        .account(Some(id))
+        .await
+        .ok_or_else(|| AppError::BadRequest(format!("account {id} not found")))
+}
+
+/// Zeroes the account's 24h request and token usage so it can chat again right
+/// away. Usage history and reports are kept. The shared Redis tally is cleared
+/// for every replica. Without Redis only this replica's counters are dropped;
+/// the others see the recorded reset when they rebuild their counters on
+/// restart.
+pub async fn reset_quota(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<QuotaRemaining>, AppError> {
+    let account = existing_account(&state, &id).await?;
+    state
+        .db
+        .record_quota_reset(&account.id, &Utc::now().to_rfc3339())
+        .await?;
+    state.usage.forget(&account.id);
+    state.store.reset_daily_usage(&account.id).await?;
+    Ok(Json(quota_remaining(&state, &account).await))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct LimitOverrideBody {
+    pub req_per_day: Option<u32>,
+    pub tokens_per_day: Option<u32>,
+    pub expires_at: DateTime<Utc>,
+    pub reason: Option<String>,
+}
+
+/// Temporarily raises the account's daily limits. The override lapses at
+/// `expires_at`; the account's own limits are never edited.
+pub async fn set_limit_override(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(body): Json<LimitOverrideBody>,
+) -> Result<Json<QuotaRemaining>, AppError> {
+    let account = existing_account(&state, &id).await?;
+    if body.req_per_day.is_none() && body.tokens_per_day.is_none() {
+        return Err(AppError::BadRequest(
+            "set req_per_day and/or tokens_per_day".into(),
+        ));
+    }
+    let now = Utc::now();
+    if body.expires_at <= now {
+        return Err(AppError::BadRequest(
+            "expires_at must be in the future".into(),
+        ));
+    }
+    if body.expires_at > now + Duration::days(MAX_OVERRIDE_DAYS) {
+        return Err(AppError::BadRequest(format!(
+            "overrides can last at most {MAX_OVERRIDE_DAYS} days; edit the account's limits instead"
+        )));
+    }
+    for (kind, base, raised) in [
+        ("request", account.req_per_day, body.req_per_day),
+        ("token", account.tokens_per_day, body.tokens_per_day),
+    ] {
+        match (base, raised) {
+            (None, Some(_)) => {
+                return Err(AppError::BadRequest(format!(
+                    "account has no daily {kind} limit to raise"
+                )));
+            }
+            (Some(base), Some(raised)) if raised <= base => {
+                return Err(AppError::BadRequest(format!(
+                    "the {kind} override must be above the account's limit of {base}"
+                )));
+            }
+            _ => {}
+        }
+    }
+    state
+        .access
+        .set_limit_override(LimitOverride {
+            account_id: account.id.clone(),
+            req_per_day: body.req_per_day,
+            tokens_per_day: body.tokens_per_day,
+            expires_at: body.expires_at,
+            reason: non_empty(body.reason),
+            created_at: now,
+        })
+        .await?;
+    Ok(Json(quota_remaining(&state, &account).await))
+}
+
+/// Ends an override before it expires.
+pub async fn clear_limit_override(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<QuotaRemaining>, AppError> {
+    let account = existing_account(&state, &id).await?;
+    if !state.access.clear_limit_override(&account.id).await? {
+        return Err(AppError::BadRequest(format!(
+            "account {id} has no active override"
+        )));
+    }
+    Ok(Json(quota_remaining(&state, &account).await))
+}
+
+/// Parses a trailing window like `30d` or `12h`.
+fn parse_window(value: &str) -> Result<Duration, AppError> {
+    let invalid = || AppError::BadRequest(format!("invalid window {value}: use e.g. 30d or 12h"));
+    let value = value.trim();
+    let (count, unit) = value.split_at(value.len().saturating_sub(1));
+    let count: i64 = count.parse().map_err(|_| invalid())?;
+    let hours = match unit {
+        "d" => count.checked_mul(24),
+        "h" => Some(count),
+        _ => None,
+    }
+    .filter(|h| *h > 0)
+    .ok_or_else(invalid)?;
+    if hours > MAX_USAGE_WINDOW_HOURS {
+        return Err(AppError::BadRequest("window cannot exceed 365d".into()));
+    }
+    Ok(Duration::hours(hours))
+}
+
+#[derive(Debug, Serialize)]
+pub struct InspectedMessage {
+    pub id: String,
+    pub role: String,
+    /// Only present for admin sessions; every such read is logged.
+    pub content: Option<String>,
+    /// Language of `content` for inline-translated turns.
+    pub language: Option<String>,
+    /// The English text exchanged with the model; shown like `content`.
+    pub english_content: Option<String>,
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub tokens_input: Option<i64>,
+    pub tokens_output: Option<i64>,
+    pub cost: Option<f64>,
+    pub superseded_by: Option<String>,
+    pub routing: Option<serde_json::Value>,
+    pub pii_redacted: bool,
+    pub policy_hits: Vec<PolicyHit>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ConversationInspection {
+    pub id: String,
+    pub title: Option<String>,
+    pub user_id: Option<String>,
+    pub created_at: String,
+    pub content_visible: bool,
+    pub total_cost: f64,
+    pub messages: Vec<InspectedMessage>,
+}
+
+pub async fn inspect_conversation(
+    Path(id): Path<Uuid>,
+    State(state): State<AppState>,
+    jar: CookieJar,
+) -> Result<Json<ConversationInspection>, AppError> {
+    let conversation = state
+        .db
+        .conversation(id)
+        .await?
+        .ok_or_else(|| AppError::BadRequest(format!("conversation {id} not found")))?;
+
+    let admin = validate_token(&state.config.load(), &jar)
+        .map(|claims| claims.sub)
+        .filter(|sub| state.config.load().admin_accounts.iter().any(|a| a == sub));
+    if let Some(actor) = admin.as_deref() {
+        // Log before reading so a failed audit write never leaks content. The
+        // audit trail is the only record of the read.
+        state
+            .audit
+            .append(AuditEvent::admin_action(
+                Some(actor),
+                "conversation.read",
+                serde_json::json!({ "conversation_id": conversation.id }),
+            ))
+            .await?;
+    }
+
+    let messages = state.db.conversation_messages(id).await?;
+    let mut traces: HashMap<String, _> = state
+        .db
+        .conversation_traces(id)
+        .await?
+        .into_iter()
+        .map(|t| (t.id.clone(), t))
+        .collect();
+    let mut hits: HashMap<String, Vec<PolicyHit>> = HashMap::new();
+    for hit in state.db.conversation_policy_hits(id).await? {
+        hits.entry(hit.message_id.clone()).or_default().push(hit);
+    }
+
+    let messages: Vec<InspectedMessage> = messages
+        .into_iter()
+        .map(|m| {
+            let trace = traces.remove(&m.id);
+            let cost = m
+                .provider
+                .as_deref()
+                .and_then(|p| provider_from_str(p).ok())
+                .zip(m.model.as_deref())
+                .and_then(|(provider, model)| {
+                    estimate_cost(
+                        provider,
+                        model,
+                        m.tokens_input.map(|t| t as u32),
+                        m.tokens_output.map(|t| t as u32),
+                    )
+                });
+            InspectedMessage {
+                policy_hits: hits.remove(&m.id).unwrap_or_default(),
+                content: admin.is_some().then_some(m.content),
+                english_content: admin.is_some().then_some(m.english_content).flatten(),
+                language: m.language,
+                routing: trace
+                    .as_ref()
+                    .and_then(|t| t.routing_trace.as_deref())
+                    .and_then(|raw| serde_json::from_str(raw).ok()),
+                pii_redacted: trace.is_some_and(|t| t.pii_redacted),
+                id: m.id,
+                role: m.role,
+                provider: m.provider,
+                model: m.model,
+                tokens_input: m.tokens_input,
+                tokens_output: m.tokens_output,
+                cost,
+                superseded_by: m.superseded_by,
+                created_at: m.created_at,
+            }
+        })
+        .collect();
+    let total_cost = messages
+        .iter()
+        .filter_map(|m| m.cost)
+        .fold(0.0, |acc, c| acc + c);
+
+    Ok(Json(ConversationInspection {
+        id: conversation.id,
+        title: conversation.title,
+        user_id: conversation.user_id,
+        created_at: conversation.created_at,
+        content_visible: admin.is_some(),
+        total_cost,
+        messages,
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HealthHistoryQuery {
+    pub model: Option<String>,
+    /// Same formats as the overview; defaults to the last 24 hours.
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub limit: Option<i64>,
+}
+
+pub async fn router_health_history(
+    State(state): State<AppState>,
+    Query(query): Query<HealthHistoryQuery>,
+) -> Result<Json<Vec<HealthHistoryPoint>>, AppError> {
+    let from = match non_empty(query.from) {
+        Some(from) => parse_bound(&from, false)?,
+        None => (Utc::now() - Duration::hours(24)).to_rfc3339(),
+    };
+    let to = non_empty(query.to)
+        .map(|to| parse_bound(&to, true))
+        .transpose()?;
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_HISTORY_LIMIT)
+        .clamp(1, MAX_HISTORY_LIMIT);
+    let model = non_empty(query.model);
+    let points = state
+        .db
+        .health_history(model.as_deref(), &from, to.as_deref(), limit)
+        .await?;
+    Ok(Json(points))
+}
+
+/// Re-reads the environment, `.env`, the config file and referenced secrets
+/// without dropping in-flight requests.
+pub async fn reload_config(State(state): State<AppState>) -> Result<Json<ConfigReload>, AppError> {
+    Ok(Json(config::reload(&state.config, &state.llm).await?))
+}
+
+/// Accounts the abuse detector is scoring on this replica, riskiest first,
+/// with any throttle in force.
+pub async fn list_abuse(State(state): State<AppState>) -> Json<Vec<AccountRisk>> {
+    let settings = AbuseSettings::from_config(&state.config.load());
+    Json(
+        settings
+            .map(|settings| state.abuse.snapshot(&settings))
+            .unwrap_or_default(),
+    )
+}
+
+/// Lifts an account's abuse throttle and clears the activity behind its
+/// score. Returns the remaining list.
+pub async fn release_abuse_throttle(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<AccountRisk>>, AppError> {
+    if !state.abuse.release(&id) {
+        return Err(AppError::BadRequest(format!(
+            "no abuse activity recorded for account {id}"
+        )));
+    }
+    Ok(list_abuse(State(state)).await)
+}
+
+pub async fn list_switches(State(state): State<AppState>) -> Json<GatewaySwitches> {
+    Json(state.access.switches())
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MaintenanceBody {
+    pub enabled: bool,
+    /// Shown to callers while maintenance is on; a default is used when empty.
+    pub message: Option<String>,
+}
+
+/// Puts the gateway into maintenance mode: chat and document ingestion answer
+/// 503 with the message until it is switched off again.
+pub async fn set_maintenance(
+    State(state): State<AppState>,
+    Json(body): Json<MaintenanceBody>,
+) -> Result<Json<GatewaySwitches>, AppError> {
+    let message = non_empty(body.message);
+    let switches = state
+        .access
+        .set_switch(
+            SwitchKind::Maintenance,
+            "gateway",
+            body.enabled,
+            message.as_deref(),
+        )
+        .await?;
+    Ok(Json(switches))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KillSwitchBody {
+    pub disabled: bool,
+    pub reason: Option<String>,
+}
+
+/// Takes every model of a provider out of routing, regardless of health.
+pub async fn set_provider_switch(
+    Path(provider): Path<String>,
+    State(state): State<AppState>,
+    Json(body): Json<KillSwitchBody>,
+) -> Result<Json<GatewaySwitches>, AppError> {
+    let provider = provider.to_lowercase();
+    provider_from_str(&provider)?;
+    let reason = non_empty(body.reason);
+    let switches = state
+        .access
+        .set_switch(
+            SwitchKind::Provider,
+            &provider,
+            body.disabled,
+            reason.as_deref(),
+        )
+        .await?;
+    Ok(Json(switches))
+}
+
+/// Takes one catalog model (by key or provider id) out of routing. Fallback
+/// chains skip it, and requests naming it directly get a 503.
+pub async fn set_model_switch(
+    Path(model): Path<String>,
+    State(state): State<AppState>,
+    Json(body): Json<KillSwitchBody>,
+) -> Result<Json<GatewaySwitches>, AppError> {
+    if body.disabled && state.access.model_entry(&model).is_none() {
+        return Err(AppError::BadRequest(format!("unknown model: {model}")));
+    }
+    let reason = non_empty(body.reason);
+    let switches = state
+        .access
+        .set_switch(
+            SwitchKind::Model,
+            &model.to_lowercase(),
+            body.disabled,
+            reason.as_deref(),
+        )
+        .await?;
+    Ok(Json(switches))
+}
+
+#[cfg(feature = "chaos")]
+pub async fn get_chaos(State(state): State<AppState>) -> Json<ChaosStatus> {
+    Json(state.llm.chaos().status())
+}
+
+/// Starts, retunes or stops fault injection on this replica. Refused in
+/// production, where injected failures would reach real users.
+#[cfg(feature = "chaos")]
+pub async fn set_chaos(
+    State(state): State<AppState>,
+    Json(settings): Json<ChaosSettings>,
+) -> Result<Json<ChaosStatus>, AppError> {
+    if settings.enabled && state.config.load().production {
+        return Err(AppError::BadRequest(
+            "fault injection is not allowed in production".into(),
+        ));
+    }
+    if !(0.0..=1.0).contains(&settings.rate) {
+        return Err(AppError::BadRequest("rate must be between 0 and 1".into()));
+    }
+    if settings.timeout_ms > 300_000 {
+        return Err(AppError::BadRequest(
+            "timeout_ms must be at most 300000".into(),
+        ));
+    }
+    Ok(Json(state.llm.chaos().set(settings)))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct ModelUpdateBody {
     pub models: Vec<String>,
@@ -62,6 +969,25 @@ pub struct GuardrailUpdateBody {
     pub guardrail_prompt: Option<String>,
 }
 
+#[derive(Debug, Deserialize)]
+pub struct ResidencyUpdateBody {
+    /// Region the account's prompts must stay in; `null` lifts the requirement.
+    pub data_residency: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OriginsUpdateBody {
+    /// Web origins the account's session may be used from; empty lifts the
+    /// restriction.
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TranslationUpdateBody {
+    /// Route the account's prompts and replies through `TRANSLATION_MODEL`.
+    pub inline_translation: bool,
+}
+
 #[derive(Debug, Deserialize)]
 pub struct LimitsUpdateBody {
     pub req_per_day: Option<u32>,
@@ -91,6 +1017,46 @@ pub async fn update_account_guardrail(
     Ok(Json(updated))
 }
 
+pub async fn update_account_residency(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(body): Json<ResidencyUpdateBody>,
+) -> Result<Json<AccountAccess>, AppError> {
+    let data_residency = normalize_region(body.data_residency)?;
+    let updated = state.access.set_data_residency(&id, data_residency).await?;
+    Ok(Json(updated))
+}
+
+pub async fn update_account_origins(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(body): Json<OriginsUpdateBody>,
+) -> Result<Json<AccountAccess>, AppError> {
+    let allowed_origins = normalize_origins(body.allowed_origins)?;
+    let updated = state
+        .access
+        .set_allowed_origins(&id, allowed_origins)
+        .await?;
+    Ok(Json(updated))
+}
+
+pub async fn update_account_translation(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(body): Json<TranslationUpdateBody>,
+) -> Result<Json<AccountAccess>, AppError> {
+    if body.inline_translation && state.config.load().translation_model.is_none() {
+        return Err(AppError::BadRequest(
+            "inline translation needs TRANSLATION_MODEL to be set".into(),
+        ));
+    }
+    let updated = state
+        .access
+        .set_inline_translation(&id, body.inline_translation)
+        .await?;
+    Ok(Json(updated))
+}
+
 pub async fn update_account_limits(
     Path(id): Path<String>,
     State(state): State<AppState>,
@@ -114,24 +1080,131 @@ pub struct ModelCatalogBody {
     pub provider: String,
     pub prompt_price_per_1k: f64,
     pub completion_price_per_1k: f64,
+    #[serde(default)]
+    pub context_window: Option<u32>,
+    #[serde(default)]
+    pub kind: ModelKind,
+    /// Where the provider hosts the model, e.g. `eu` or `us`.
+    #[serde(default)]
+    pub region: Option<String>,
 }
 
 pub async fn list_models(State(state): State<AppState>) -> Json<Vec<CatalogEntry>> {
     Json(state.access.list_models().await)
 }
 
+/// Whether `upsert_model` checks the id against the provider's model list.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum VerifyMode {
+    #[default]
+    Off,
+    /// Save anyway and report problems under `warnings`.
+    Warn,
+    /// Refuse ids the provider doesn't list, or that can't be checked.
+    Reject,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ModelUpsertQuery {
+    #[serde(default)]
+    pub verify: VerifyMode,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ModelUpsertResponse {
+    #[serde(flatten)]
+    pub entry: CatalogEntry,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub warnings: Vec<String>,
+}
+
 pub async fn upsert_model(
     State(state): State<AppState>,
+    Query(query): Query<ModelUpsertQuery>,
     Json(body): Json<ModelCatalogBody>,
-) -> Result<Json<CatalogEntry>, AppError> {
+) -> Result<Json<ModelUpsertResponse>, AppError> {
+    let mut warnings = Vec::new();
+    if query.verify != VerifyMode::Off {
+        let problem = upstream_model_problem(&state, &body.provider, &body.id).await;
+        match (query.verify, problem) {
+            (_, Ok(None)) => {}
+            (VerifyMode::Reject, Ok(Some(problem))) => return Err(AppError::BadRequest(problem)),
+            (VerifyMode::Reject, Err(e)) => return Err(e),
+            (_, Ok(Some(problem))) => warnings.push(problem),
+            (_, Err(e)) => warnings.push(e.to_string()),
+        }
+    }
     let entry = CatalogEntry {
         id: body.id.clone(),
         provider: body.provider.clone(),
         prompt_price_per_1k: body.prompt_price_per_1k,
         completion_price_per_1k: body.completion_price_per_1k,
+        context_window: body.context_window,
+        kind: body.kind,
+        region: normalize_region(body.region.clone())?,
     };
-    state.access.upsert_model(entry.clone()).await;
-    Ok(Json(entry))
+    state.access.upsert_model(entry.clone()).await?;
+    Ok(Json(ModelUpsertResponse { entry, warnings }))
+}
+
+/// Asks the provider for its model list and explains why `id` isn't usable,
+/// suggesting a close match for likely typos. Errors mean the check couldn't run.
+async fn upstream_model_problem(
+    state: &AppState,
+    provider: &str,
+    id: &str,
+) -> Result<Option<String>, AppError> {
+    let provider = provider_from_str(provider)?;
+    if provider == Provider::Mock {
+        // The mock provider answers for any model id.
+        return Ok(None);
+    }
+    let available =
+        state.llm.list_models(provider).await.map_err(|e| {
+            AppError::Upstream(format!("could not verify {id} with {provider}: {e}"))
+        })?;
+    if available.iter().any(|m| m == id) {
+        return Ok(None);
+    }
+    let hint = closest_model(id, &available)
+        .map(|m| format!("; did you mean {m}?"))
+        .unwrap_or_default();
+    Ok(Some(format!(
+        "{provider} does not list a model named {id}{hint}"
+    )))
+}
+
+/// The listed id nearest to `id` by edit distance, if it's close enough to
+/// be a typo.
+fn closest_model<'a>(id: &str, available: &'a [String]) -> Option<&'a str> {
+    const MAX_TYPO_DISTANCE: usize = 3;
+    let wanted: Vec<char> = id.to_lowercase().chars().collect();
+    available
+        .iter()
+        .map(|m| {
+            (
+                edit_distance(&wanted, &m.to_lowercase().chars().collect::<Vec<_>>()),
+                m,
+            )
+        })
+        .filter(|(distance, _)| *distance <= MAX_TYPO_DISTANCE)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, m)| m.as_str())
+}
+
+fn edit_distance(a: &[char], b: &[char]) -> usize {
+    let mut row: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = diagonal + usize::from(ca != cb);
+            diagonal = row[j + 1];
+            row[j + 1] = substitution.min(row[j] + 1).min(diagonal + 1);
+        }
+    }
+    row[b.len()]
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -147,10 +1220,33 @@ pub async fn set_alias(
     state
         .access
         .set_alias(body.alias.clone(), body.targets.clone())
-        .await;
+        .await?;
     Ok(Json(body))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct AliasResolveQuery {
+    pub samples: Option<u32>,
+}
+
+/// Simulates an alias's weighted pick so weights can be checked before they
+/// take traffic. Nothing is routed and router health is left untouched.
+pub async fn preview_alias(
+    Path(alias): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<AliasResolveQuery>,
+) -> Result<Json<AliasPreview>, AppError> {
+    let samples = query
+        .samples
+        .unwrap_or(DEFAULT_ALIAS_SAMPLES)
+        .clamp(1, MAX_ALIAS_SAMPLES);
+    state
+        .access
+        .preview_alias(&alias, samples)
+        .map(Json)
+        .ok_or_else(|| AppError::BadRequest(format!("unknown alias: {alias}")))
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct FallbackBody {
     pub chain: Vec<String>,
@@ -161,10 +1257,46 @@ pub async fn set_fallbacks(
     State(state): State<AppState>,
     Json(body): Json<FallbackBody>,
 ) -> Result<Json<FallbackBody>, AppError> {
-    state.access.set_fallbacks(id, body.chain.clone()).await;
+    state.access.set_fallbacks(id, body.chain.clone()).await?;
     Ok(Json(body))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct DeleteModelQuery {
+    /// Also strip the model from aliases and fallback chains that use it.
+    #[serde(default)]
+    pub force: bool,
+}
+
+pub async fn delete_model(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Query(query): Query<DeleteModelQuery>,
+) -> Result<Json<ModelDeletion>, AppError> {
+    Ok(Json(state.access.delete_model(&id, query.force).await?))
+}
+
+#[derive(Debug, Serialize)]
+pub struct Deleted {
+    pub deleted: String,
+}
+
+pub async fn delete_alias(
+    Path(alias): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Deleted>, AppError> {
+    state.access.delete_alias(&alias).await?;
+    Ok(Json(Deleted { deleted: alias }))
+}
+
+pub async fn delete_fallbacks(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Deleted>, AppError> {
+    state.access.delete_fallbacks(&id).await?;
+    Ok(Json(Deleted { deleted: id }))
+}
+
 #[derive(Debug, Deserialize)]
 pub struct PolicyInput {
     pub id: Option<String>,
@@ -186,8 +1318,23 @@ pub async fn upsert_policy(
     State(state): State<AppState>,
     Json(body): Json<PolicyInput>,
 ) -> Result<Json<Policy>, AppError> {
+    let saved = state
+        .db
+        .create_or_update_policy(policy_from_input(body)?)
+        .await?;
+    Ok(Json(saved))
+}
+
+fn policy_from_input(body: PolicyInput) -> Result<PolicyUpsert, AppError> {
+    let id = match body.id.as_deref().map(str::trim).filter(|s| !s.is_empty()) {
+        Some(raw) => Some(
+            Uuid::parse_str(raw)
+                .map_err(|_| AppError::BadRequest(format!("invalid policy id {raw}")))?,
+        ),
+        None => None,
+    };
     let upsert = PolicyUpsert {
-        id: body.id.as_ref().and_then(|s| uuid::Uuid::parse_str(s).ok()),
+        id,
         name: body.name,
         description: body.description,
         match_type: body.match_type,
@@ -196,8 +1343,131 @@ pub async fn upsert_policy(
         applies_to: body.applies_to,
         enabled: body.enabled,
     };
-    let saved = state.db.create_or_update_policy(upsert).await?;
-    Ok(Json(saved))
+    upsert.validate()?;
+    Ok(upsert)
+}
+
+const MAX_IMPORT_ITEMS: usize = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct ImportBody {
+    /// Items are decoded one by one so a malformed entry only fails itself.
+    #[serde(default)]
+    pub policies: Vec<serde_json::Value>,
+    #[serde(default)]
+    pub accounts: Vec<serde_json::Value>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ImportOutcome {
+    Created,
+    Updated,
+    Failed,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportItemResult {
+    pub index: usize,
+    pub id: Option<String>,
+    pub outcome: ImportOutcome,
+    pub error: Option<String>,
+}
+
+impl ImportItemResult {
+    fn from_result(index: usize, result: Result<(String, bool), AppError>) -> Self {
+        match result {
+            Ok((id, created)) => Self {
+                index,
+                id: Some(id),
+                outcome: if created {
+                    ImportOutcome::Created
+                } else {
+                    ImportOutcome::Updated
+                },
+                error: None,
+            },
+            Err(e) => Self {
+                index,
+                id: None,
+                outcome: ImportOutcome::Failed,
+                error: Some(e.to_string()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportResponse {
+    pub policies: Vec<ImportItemResult>,
+    pub accounts: Vec<ImportItemResult>,
+    pub failed: usize,
+}
+
+/// Upserts policies and accounts in one call. Each item is validated and saved
+/// on its own; failures are reported per item and don't stop the rest.
+pub async fn bulk_import(
+    State(state): State<AppState>,
+    Json(body): Json<ImportBody>,
+) -> Result<Json<ImportResponse>, AppError> {
+    if body.policies.len() + body.accounts.len() > MAX_IMPORT_ITEMS {
+        return Err(AppError::BadRequest(format!(
+            "at most {MAX_IMPORT_ITEMS} items per import"
+        )));
+    }
+
+    let mut known_policies: HashSet<String> = state
+        .db
+        .list_policies()
+        .await?
+        .into_iter()
+        .map(|p| p.id)
+        .collect();
+    let mut policies = Vec::with_capacity(body.policies.len());
+    for (index, item) in body.policies.into_iter().enumerate() {
+        let result = async {
+            let input: PolicyInput = serde_json::from_value(item)
+                .map_err(|e| AppError::BadRequest(format!("invalid policy: {e}")))?;
+            let saved = state
+                .db
+                .create_or_update_policy(policy_from_input(input)?)
+                .await?;
+            let created = known_policies.insert(saved.id.clone());
+            Ok((saved.id, created))
+        }
+        .await;
+        policies.push(ImportItemResult::from_result(index, result));
+    }
+
+    let mut accounts = Vec::with_capacity(body.accounts.len());
+    for (index, item) in body.accounts.into_iter().enumerate() {
+        let result = async {
+            let mut input: CreateAccountBody = serde_json::from_value(item)
+                .map_err(|e| AppError::BadRequest(format!("invalid account: {e}")))?;
+            // Without an id, re-importing the same email updates that account.
+            if input.id.is_none() {
+                input.id = state.access.account_id_for_email(input.email.trim()).await;
+            }
+            let (saved, created) = state
+                .access
+                .upsert_account(account_from_body(input)?)
+                .await?;
+            Ok((saved.id, created))
+        }
+        .await;
+        accounts.push(ImportItemResult::from_result(index, result));
+    }
+
+    let failed = policies
+        .iter()
+        .chain(&accounts)
+        .filter(|r| matches!(r.outcome, ImportOutcome::Failed))
+        .count();
+    Ok(Json(ImportResponse {
+        policies,
+        accounts,
+        failed,
+    }))
 }
 
 #[derive(Debug, Deserialize)]
@@ -224,7 +1494,7 @@ pub async fn test_policy(
         .find(|p| p.id == id)
         .ok_or_else(|| AppError::BadRequest("policy not found".into()))?;
 
-    let hits = evaluate_policies(&[policy.clone()], "user", &body.text);
+    let hits = evaluate_policies(std::slice::from_ref(&policy), "user", &body.text);
     if let Some(blocked) = hits.blocked {
         return Ok(Json(PolicyTestResult {
             matched: true,
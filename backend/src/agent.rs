@@ -0,0 +1,302 @@
+//! Agent runs: the model calls built-in tools in a loop until it answers or
+//! the run's step or cost budget is spent. The loop lives with the chat routes
+//! (`POST /api/v1/chat/agent`); this module has the tools and the budget.
+//! Tools run inside the gateway and never fetch from the network, so a prompt
+//! can't steer requests at internal hosts.
+
+use crate::{AppState, config::Config, error::AppError, llm::ToolSpec, rag::RetrievalOptions};
+use serde::Serialize;
+use serde_json::{Value, json};
+
+/// Tool output beyond this is cut before it goes back to the model.
+const MAX_TOOL_OUTPUT_CHARS: usize = 8_000;
+const MAX_EXPRESSION_CHARS: usize = 200;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tool {
+    Calculator,
+    CurrentTime,
+    SearchDocuments,
+}
+
+impl Tool {
+    pub const ALL: [Tool; 3] = [Tool::Calculator, Tool::CurrentTime, Tool::SearchDocuments];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Tool::Calculator => "calculator",
+            Tool::CurrentTime => "current_time",
+            Tool::SearchDocuments => "search_documents",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|t| t.name() == name)
+    }
+
+    pub fn spec(self) -> ToolSpec {
+        let (description, parameters) = match self {
+            Tool::Calculator => (
+                "Evaluates an arithmetic expression with + - * / % ^ and parentheses.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "expression": {"type": "string", "description": "For example (3 + 4) * 2.5"}
+                    },
+                    "required": ["expression"]
+                }),
+            ),
+            Tool::CurrentTime => (
+                "Returns the current date and time in UTC, as RFC 3339.",
+                json!({"type": "object", "properties": {}}),
+            ),
+            Tool::SearchDocuments => (
+                "Searches the document collections attached to this request and returns the closest passages, numbered for citation.",
+                json!({
+                    "type": "object",
+                    "properties": {
+                        "query": {"type": "string", "description": "What to look for"}
+                    },
+                    "required": ["query"]
+                }),
+            ),
+        };
+        ToolSpec {
+            name: self.name().into(),
+            description: description.into(),
+            parameters,
+        }
+    }
+}
+
+/// The tools a run offers: those `requested` by name, or every built-in one.
+/// Document search needs collections in the request's `retrieval`.
+pub fn select_tools(
+    requested: Option<&[String]>,
+    retrieval: Option<&RetrievalOptions>,
+) -> Result<Vec<Tool>, AppError> {
+    let searchable = retrieval.is_some_and(|r| !r.collections.is_empty());
+    let Some(requested) = requested else {
+        return Ok(Tool::ALL
+            .into_iter()
+            .filter(|t| searchable || *t != Tool::SearchDocuments)
+            .collect());
+    };
+    let mut tools = Vec::new();
+    for name in requested {
+        let tool = Tool::from_name(name).ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "unknown tool {name}; available: {}",
+                Tool::ALL.map(Tool::name).join(", ")
+            ))
+        })?;
+        if tool == Tool::SearchDocuments && !searchable {
+            return Err(AppError::BadRequest(
+                "search_documents needs retrieval.collections".into(),
+            ));
+        }
+        if !tools.contains(&tool) {
+            tools.push(tool);
+        }
+    }
+    Ok(tools)
+}
+
+/// Limits on one run: what the request asks for, within the configured
+/// `AGENT_MAX_STEPS` and `AGENT_MAX_COST`.
+#[derive(Clone, Copy, Debug, Serialize)]
+pub struct Budget {
+    /// Model calls, each of which may request several tools.
+    pub max_steps: usize,
+    /// USD of estimated provider cost.
+    pub max_cost: f64,
+}
+
+impl Budget {
+    pub fn new(
+        config: &Config,
+        max_steps: Option<usize>,
+        max_cost: Option<f64>,
+    ) -> Result<Self, AppError> {
+        if max_steps == Some(0) {
+            return Err(AppError::BadRequest("max_steps must be at least 1".into()));
+        }
+        if let Some(cost) = max_cost
+            && !(cost.is_finite() && cost > 0.0)
+        {
+            return Err(AppError::BadRequest("max_cost must be above 0".into()));
+        }
+        Ok(Self {
+            max_steps: max_steps.map_or(config.agent_max_steps, |n| n.min(config.agent_max_steps)),
+            max_cost: max_cost.map_or(config.agent_max_cost, |c| c.min(config.agent_max_cost)),
+        })
+    }
+}
+
+/// Runs one tool call. A failure is the tool's answer (`Err`), handed back
+/// to the model so it can correct its call; it doesn't end the run.
+pub async fn run_tool(
+    state: &AppState,
+    tool: Tool,
+    arguments: &Value,
+    retrieval: Option<&RetrievalOptions>,
+    residency: Option<&str>,
+) -> Result<String, String> {
+    let text_argument = |name: &str| {
+        arguments[name]
+            .as_str()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .ok_or_else(|| format!("`{name}` must be a non-empty string"))
+    };
+    let output = match tool {
+        Tool::Calculator => {
+            let value = calculate(text_argument("expression")?)?;
+            format_number(value)
+        }
+        Tool::CurrentTime => chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        Tool::SearchDocuments => {
+            let query = text_argument("query")?;
+            let Some(options) = retrieval else {
+                return Err("no collections are attached to this request".into());
+            };
+            let hits = state
+                .rag
+                .search(query, options, residency)
+                .await
+                .map_err(|e| e.to_string())?;
+            if hits.is_empty() {
+                "No matching passages.".to_string()
+            } else {
+                hits.iter()
+                    .map(|h| {
+                        format!(
+                            "[{}] {}\n{}",
+                            h.citation.index, h.citation.document_title, h.content
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n\n")
+            }
+        }
+    };
+    Ok(truncate(output))
+}
+
+fn truncate(mut output: String) -> String {
+    if let Some((idx, _)) = output.char_indices().nth(MAX_TOOL_OUTPUT_CHARS) {
+        output.truncate(idx);
+        output.push_str("\n[truncated]");
+    }
+    output
+}
+
+fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        value.to_string()
+    }
+}
+
+/// Evaluates `+ - * / % ^`, unary minus and parentheses over decimal numbers.
+fn calculate(expression: &str) -> Result<f64, String> {
+    if expression.chars().count() > MAX_EXPRESSION_CHARS {
+        return Err(format!(
+            "expression is longer than {MAX_EXPRESSION_CHARS} characters"
+        ));
+    }
+    let mut parser = Calculator {
+        chars: expression.chars().filter(|c| !c.is_whitespace()).collect(),
+        pos: 0,
+    };
+    let value = parser.sum()?;
+    if let Some(c) = parser.peek() {
+        return Err(format!("unexpected `{c}`"));
+    }
+    if !value.is_finite() {
+        return Err("the result is not a finite number".into());
+    }
+    Ok(value)
+}
+
+struct Calculator {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+impl Calculator {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let found = self.peek() == Some(c);
+        if found {
+            self.pos += 1;
+        }
+        found
+    }
+
+    fn sum(&mut self) -> Result<f64, String> {
+        let mut value = self.product()?;
+        loop {
+            if self.eat('+') {
+                value += self.product()?;
+            } else if self.eat('-') {
+                value -= self.product()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn product(&mut self) -> Result<f64, String> {
+        let mut value = self.power()?;
+        loop {
+            if self.eat('*') {
+                value *= self.power()?;
+            } else if self.eat('/') {
+                value /= self.power()?;
+            } else if self.eat('%') {
+                value %= self.power()?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    /// Right-associative, and binds tighter than unary minus: -2^2 is -4.
+    fn power(&mut self) -> Result<f64, String> {
+        if self.eat('-') {
+            return Ok(-self.power()?);
+        }
+        let base = self.atom()?;
+        if self.eat('^') {
+            return Ok(base.powf(self.power()?));
+        }
+        Ok(base)
+    }
+
+    fn atom(&mut self) -> Result<f64, String> {
+        if self.eat('(') {
+            let value = self.sum()?;
+            if !self.eat(')') {
+                return Err("missing `)`".into());
+            }
+            return Ok(value);
+        }
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit() || c == '.') {
+            self.pos += 1;
+        }
+        let number: String = self.chars[start..self.pos].iter().collect();
+        match self.peek() {
+            _ if !number.is_empty() => number
+                .parse()
+                .map_err(|_| format!("`{number}` is not a number")),
+            Some(c) => Err(format!("unexpected `{c}`")),
+            None => Err("the expression ends early".into()),
+        }
+    }
+}
@@ -1,5 +1,6 @@
 use crate::{
-    db::{Counts, MessageRecord, ModelUsage},
+    db::{ActivityFilter, Counts, MessageRecord, ModelFeedback, ModelUsage, TagCount},
+    dedup::DedupStats,
     governance::{Policy, PolicyHit},
     model_router::{AccountAccess, RouterHealthEntry},
 };
@@ -17,6 +18,38 @@ pub struct DashboardResponse {
     pub policies: Vec<Policy>,
     pub policy_hits: Vec<PolicyHit>,
     pub router_health: Vec<RouterHealthEntry>,
+    pub dedup: DedupStats,
+    pub feedback: Vec<ModelFeedback>,
+    pub tags: Vec<TagCount>,
+    pub tag_filter: Option<String>,
+    pub filters: ActivityFilter,
+    pub page: PageInfo,
+}
+
+/// Where `recent_requests` and `policy_hits` sit in the filtered results.
+#[derive(Debug, Serialize)]
+pub struct PageInfo {
+    pub limit: i64,
+    pub offset: i64,
+    pub has_more_requests: bool,
+    pub hits_limit: i64,
+    pub hits_offset: i64,
+    pub has_more_policy_hits: bool,
+}
+
+pub struct DashboardInputs {
+    pub counts: Counts,
+    pub models: Vec<ModelUsage>,
+    pub recent: Vec<MessageRecord>,
+    pub accounts: Vec<AccountAccess>,
+    pub policies: Vec<Policy>,
+    pub policy_hits: Vec<PolicyHit>,
+    pub router_health: Vec<RouterHealthEntry>,
+    pub dedup: DedupStats,
+    pub feedback: Vec<ModelFeedback>,
+    pub tags: Vec<TagCount>,
+    pub filters: ActivityFilter,
+    pub page: PageInfo,
 }
 
 #[derive(Debug, Serialize)]
@@ -55,15 +88,21 @@ pub struct AlertEntry {
     pub created_at: String,
 }
 
-pub fn build_dashboard(
-    counts: Counts,
-    models: Vec<ModelUsage>,
-    recent: Vec<MessageRecord>,
-    accounts: Vec<AccountAccess>,
-    policies: Vec<Policy>,
-    policy_hits: Vec<PolicyHit>,
-    router_health: Vec<RouterHealthEntry>,
-) -> DashboardResponse {
+pub fn build_dashboard(input: DashboardInputs) -> DashboardResponse {
+    let DashboardInputs {
+        counts,
+        models,
+        recent,
+        accounts,
+        policies,
+        policy_hits,
+        router_health,
+        dedup,
+        feedback,
+        tags,
+        filters,
+        page,
+    } = input;
     let requests = recent.iter().map(message_to_request).collect::<Vec<_>>();
 
     let alerts = requests
@@ -119,6 +158,12 @@ pub fn build_dashboard(
         policies,
         policy_hits,
         router_health,
+        dedup,
+        feedback,
+        tags,
+        tag_filter: filters.tag.clone(),
+        filters,
+        page,
     }
 }
 
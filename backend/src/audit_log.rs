@@ -0,0 +1,420 @@
+//! Tamper-evident audit trail. Admin actions, policy hits and routing events are
+//! appended as a hash chain: every entry stores the previous entry's hash and an
+//! HMAC (keyed by `AUDIT_HMAC_KEY`) over its own fields and that link. Editing,
+//! reordering or deleting a past entry breaks verification from that entry on,
+//! and without the key a forger can't re-sign the rest of the chain.
+//!
+//! Deleting entries off the end leaves a shorter chain that still verifies, so
+//! compliance should record the head (`seq` and `hash`) reported by
+//! `/admin/audit/verify` somewhere outside the database from time to time.
+
+use crate::{
+    AppState,
+    auth::validate_token,
+    config::SharedConfig,
+    db::{AuditRecord, Db},
+    error::AppError,
+};
+use axum::{
+    Json,
+    extract::{Query, Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, sync::Arc};
+use tokio::sync::{Mutex, mpsc, oneshot};
+use tracing::warn;
+
+pub const KINDS: &[&str] = &["admin_action", "policy_hit", "routing"];
+
+/// `prev_hash` of the first entry.
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+/// Another replica sharing the database may take a sequence number first.
+const APPEND_ATTEMPTS: usize = 5;
+const VERIFY_PAGE: i64 = 500;
+const DEFAULT_LIST_LIMIT: i64 = 100;
+const MAX_LIST_LIMIT: i64 = 500;
+
+/// Something worth a place in the audit trail.
+pub struct AuditEvent {
+    pub kind: &'static str,
+    pub actor: String,
+    pub action: String,
+    pub detail: Value,
+}
+
+impl AuditEvent {
+    /// A change made through the admin API, or an admin reading user content.
+    pub fn admin_action(actor: Option<&str>, action: impl Into<String>, detail: Value) -> Self {
+        Self {
+            kind: "admin_action",
+            actor: actor.unwrap_or("anonymous").to_string(),
+            action: action.into(),
+            detail,
+        }
+    }
+
+    /// A policy matched a user message; `action` is the policy's action.
+    pub fn policy_hit(actor: Option<&str>, action: &str, detail: Value) -> Self {
+        Self {
+            kind: "policy_hit",
+            actor: actor.unwrap_or("anonymous").to_string(),
+            action: action.to_string(),
+            detail,
+        }
+    }
+
+    /// Where a chat request was routed, or that every candidate failed.
+    pub fn routing(actor: Option<&str>, routed: bool, detail: Value) -> Self {
+        Self {
+            kind: "routing",
+            actor: actor.unwrap_or("anonymous").to_string(),
+            action: if routed { "routed" } else { "failed" }.to_string(),
+            detail,
+        }
+    }
+}
+
+/// Queued events waiting for the background writer.
+const QUEUE_CAPACITY: usize = 1024;
+/// Most events the writer appends in one transaction.
+const BATCH_LIMIT: usize = 256;
+
+enum Queued {
+    Event(AuditEvent),
+    /// Answered once every event queued before it has been written.
+    Flush(oneshot::Sender<()>),
+}
+
+#[derive(Clone)]
+pub struct AuditLog {
+    db: Db,
+    config: SharedConfig,
+    chain: Chain,
+    queue: mpsc::Sender<Queued>,
+}
+
+/// Writes entries to the end of the chain.
+#[derive(Clone)]
+struct Chain {
+    db: Db,
+    config: SharedConfig,
+    /// Appends from this process go one at a time so they don't race for `seq`.
+    append_lock: Arc<Mutex<()>>,
+}
+
+impl AuditLog {
+    /// Also starts the writer that appends `record`ed events in the background.
+    pub fn new(db: Db, config: SharedConfig) -> Self {
+        let chain = Chain {
+            db: db.clone(),
+            config: config.clone(),
+            append_lock: Arc::new(Mutex::new(())),
+        };
+        let (queue, pending) = mpsc::channel(QUEUE_CAPACITY);
+        tokio::spawn(chain.clone().write_queued(pending));
+        Self {
+            db,
+            config,
+            chain,
+            queue,
+        }
+    }
+
+    /// Appends an event, signed with the current key, to the end of the chain.
+    /// Admin actions go through here so the caller knows the entry is stored.
+    pub async fn append(&self, event: AuditEvent) -> Result<AuditRecord, AppError> {
+        let mut records = self.chain.append(std::slice::from_ref(&event)).await?;
+        Ok(records.remove(0))
+    }
+
+    /// Queues a routing or policy event for the background writer, so chat
+    /// requests don't wait on the chain. Failures are logged by the writer.
+    pub async fn record(&self, event: AuditEvent) {
+        let (kind, action) = (event.kind, event.action.clone());
+        if self.queue.send(Queued::Event(event)).await.is_err() {
+            warn!("audit writer has stopped; {kind} entry {action} not recorded");
+        }
+    }
+
+    /// Appends an admin action, logging rather than failing the request that
+    /// raised it.
+    pub async fn record_now(&self, event: AuditEvent) {
+        let (kind, action) = (event.kind, event.action.clone());
+        if let Err(e) = self.append(event).await {
+            warn!("failed to record {kind} audit entry {action}: {e}");
+        }
+    }
+
+    /// Waits until every event `record`ed so far is in the chain.
+    pub async fn flush(&self) {
+        let (done, written) = oneshot::channel();
+        if self.queue.send(Queued::Flush(done)).await.is_ok() {
+            let _ = written.await;
+        }
+    }
+
+    /// Signs a document (such as an erasure report) with the current key.
+    /// Returns the key id and the hex HMAC-SHA256.
+    pub fn seal(&self, document: &[u8]) -> (String, String) {
+        let key = self.config.load().audit_hmac_key.clone();
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+        mac.update(document);
+        (key_id(&key), hex::encode(mac.finalize().into_bytes()))
+    }
+
+    /// Walks the whole chain, checking links, sequence and signatures.
+    pub async fn verify(&self) -> Result<Verification, AppError> {
+        self.flush().await;
+        let config = self.config.load();
+        let keys: HashMap<String, &str> = std::iter::once(config.audit_hmac_key.as_str())
+            .chain(config.audit_hmac_key_previous.as_deref())
+            .map(|key| (key_id(key), key))
+            .collect();
+
+        let mut entries = 0;
+        let mut head: Option<ChainHead> = None;
+        loop {
+            let after = head.as_ref().map_or(0, |h| h.seq);
+            let page = self.db.audit_page(after, VERIFY_PAGE).await?;
+            if page.is_empty() {
+                break;
+            }
+            for record in page {
+                let expected_seq = head.as_ref().map_or(0, |h| h.seq) + 1;
+                let expected_prev = head
+                    .as_ref()
+                    .map(|h| h.hash.as_str())
+                    .unwrap_or(GENESIS_HASH);
+                let problem = if record.seq != expected_seq {
+                    Some(format!(
+                        "expected entry {expected_seq}; entries are missing"
+                    ))
+                } else if record.prev_hash != expected_prev {
+                    Some("does not link to the previous entry".to_string())
+                } else {
+                    match keys.get(&record.key_id) {
+                        None => Some(format!(
+                            "signed with unknown key {}; set AUDIT_HMAC_KEY_PREVIOUS if the key was rotated",
+                            record.key_id
+                        )),
+                        Some(key) if sign(key, &record) != record.hash => {
+                            Some("signature does not match; the entry was modified".to_string())
+                        }
+                        Some(_) => None,
+                    }
+                };
+                if let Some(reason) = problem {
+                    return Ok(Verification {
+                        valid: false,
+                        entries,
+                        head,
+                        problem: Some(ChainProblem {
+                            seq: record.seq,
+                            reason,
+                        }),
+                    });
+                }
+                entries += 1;
+                head = Some(ChainHead {
+                    seq: record.seq,
+                    hash: record.hash,
+                    created_at: record.created_at,
+                });
+            }
+        }
+        Ok(Verification {
+            valid: true,
+            entries,
+            head,
+            problem: None,
+        })
+    }
+}
+
+impl Chain {
+    /// Appends queued events in batches until every `AuditLog` is dropped.
+    async fn write_queued(self, mut pending: mpsc::Receiver<Queued>) {
+        let mut batch = Vec::new();
+        while pending.recv_many(&mut batch, BATCH_LIMIT).await > 0 {
+            let mut events = Vec::new();
+            let mut flushes = Vec::new();
+            for queued in batch.drain(..) {
+                match queued {
+                    Queued::Event(event) => events.push(event),
+                    Queued::Flush(done) => flushes.push(done),
+                }
+            }
+            if !events.is_empty()
+                && let Err(e) = self.append(&events).await
+            {
+                warn!("failed to record {} audit entries: {e}", events.len());
+            }
+            for done in flushes {
+                let _ = done.send(());
+            }
+        }
+    }
+
+    /// Appends events in order, in one transaction, linking each to the one
+    /// before it.
+    async fn append(&self, events: &[AuditEvent]) -> Result<Vec<AuditRecord>, AppError> {
+        let _guard = self.append_lock.lock().await;
+        let key = self.config.load().audit_hmac_key.clone();
+        for _ in 0..APPEND_ATTEMPTS {
+            let (mut seq, mut prev_hash) = self
+                .db
+                .audit_head()
+                .await?
+                .unwrap_or((0, GENESIS_HASH.to_string()));
+            let created_at = Utc::now().to_rfc3339();
+            let mut records = Vec::with_capacity(events.len());
+            for event in events {
+                seq += 1;
+                let mut record = AuditRecord {
+                    seq,
+                    created_at: created_at.clone(),
+                    kind: event.kind.to_string(),
+                    actor: event.actor.clone(),
+                    action: event.action.clone(),
+                    detail: event.detail.to_string(),
+                    key_id: key_id(&key),
+                    prev_hash,
+                    hash: String::new(),
+                };
+                record.hash = sign(&key, &record);
+                prev_hash = record.hash.clone();
+                records.push(record);
+            }
+            if self.db.insert_audit(&records).await? {
+                return Ok(records);
+            }
+        }
+        Err(AppError::Internal(
+            "audit log head kept moving; entry not recorded".into(),
+        ))
+    }
+}
+
+/// Identifies a key without revealing it, so verification can tell which key
+/// signed an entry across rotations.
+fn key_id(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))[..16].to_string()
+}
+
+/// HMAC-SHA256 over every field but the hash itself, each length-prefixed so
+/// content can't be shifted between fields.
+fn sign(key: &str, record: &AuditRecord) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(key.as_bytes()).expect("HMAC accepts any key length");
+    let seq = record.seq.to_string();
+    for field in [
+        seq.as_str(),
+        &record.created_at,
+        &record.kind,
+        &record.actor,
+        &record.action,
+        &record.detail,
+        &record.key_id,
+        &record.prev_hash,
+    ] {
+        mac.update(&(field.len() as u64).to_be_bytes());
+        mac.update(field.as_bytes());
+    }
+    hex::encode(mac.finalize().into_bytes())
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainHead {
+    pub seq: i64,
+    pub hash: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainProblem {
+    pub seq: i64,
+    pub reason: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Verification {
+    pub valid: bool,
+    /// Entries verified before the first problem (all of them when valid).
+    pub entries: i64,
+    /// The last entry that verified.
+    pub head: Option<ChainHead>,
+    pub problem: Option<ChainProblem>,
+}
+
+/// Records every state-changing admin API call once it has been answered.
+pub async fn record_admin_actions(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    request: Request,
+    next: Next,
+) -> Response {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let audited = path.starts_with("/api/v1/admin/")
+        && !matches!(method, Method::GET | Method::HEAD | Method::OPTIONS);
+    let actor = validate_token(&state.config.load(), &jar).map(|claims| claims.sub);
+    let response = next.run(request).await;
+    if audited {
+        let status = response.status().as_u16();
+        state
+            .audit
+            .record_now(AuditEvent::admin_action(
+                actor.as_deref(),
+                format!("{method} {path}"),
+                json!({ "method": method.as_str(), "path": path, "status": status }),
+            ))
+            .await;
+    }
+    response
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AuditQuery {
+    pub kind: Option<String>,
+    /// Only entries with a lower sequence number, for paging back.
+    pub before: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+pub async fn list_audit(
+    State(state): State<AppState>,
+    Query(query): Query<AuditQuery>,
+) -> Result<Json<Vec<AuditRecord>>, AppError> {
+    let kind = query.kind.filter(|k| !k.trim().is_empty());
+    if let Some(kind) = &kind
+        && !KINDS.contains(&kind.as_str())
+    {
+        return Err(AppError::BadRequest(format!(
+            "unknown audit kind {kind}; expected one of {}",
+            KINDS.join(", ")
+        )));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    state.audit.flush().await;
+    let records = state
+        .db
+        .list_audit(kind.as_deref(), query.before, limit)
+        .await?;
+    Ok(Json(records))
+}
+
+pub async fn verify_audit(State(state): State<AppState>) -> Result<Json<Verification>, AppError> {
+    Ok(Json(state.audit.verify().await?))
+}
@@ -1,4 +1,10 @@
-use axum::{Json, extract::State, response::IntoResponse};
+use axum::{
+    Json,
+    extract::{Request, State},
+    http::{HeaderMap, header::ORIGIN},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
 use axum_extra::extract::cookie::{Cookie, CookieJar, SameSite};
 use chrono::{Duration, Utc};
 use jsonwebtoken::{DecodingKey, EncodingKey, Header, Validation, decode, encode};
@@ -7,7 +13,7 @@ use time::Duration as CookieDuration;
 
 use crate::{AppState, config::Config, error::AppError};
 
-const COOKIE_NAME: &str = "auth";
+pub(crate) const COOKIE_NAME: &str = "auth";
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Claims {
@@ -30,6 +36,7 @@ pub struct LoginResponse {
 pub async fn login(
     State(state): State<AppState>,
     jar: CookieJar,
+    headers: HeaderMap,
     Json(body): Json<LoginRequest>,
 ) -> Result<(CookieJar, Json<LoginResponse>), AppError> {
     // Stub user auth: accept demo@local / demo123
@@ -37,17 +44,11 @@ pub async fn login(
         return Err(AppError::BadRequest("invalid credentials".into()));
     }
 
-    let exp = (Utc::now() + Duration::hours(24)).timestamp() as usize;
-    let claims = Claims {
-        sub: "demo-user".into(),
-        exp,
-    };
-    let token = encode(
-        &Header::default(),
-        &claims,
-        &EncodingKey::from_secret(state.config.jwt_secret.as_bytes()),
-    )
-    .map_err(|e| AppError::Internal(format!("token encode error: {e}")))?;
+    let user_id = "demo-user".to_string();
+    if let Some(origin) = headers.get(ORIGIN) {
+        check_origin(&state, &user_id, origin.as_bytes()).await?;
+    }
+    let token = issue_token(&state.config.load(), &user_id)?;
 
     let cookie = Cookie::build((COOKIE_NAME, token.clone()))
         .http_only(true)
@@ -56,13 +57,21 @@ pub async fn login(
         .max_age(CookieDuration::hours(24))
         .build();
 
-    Ok((
-        jar.add(cookie),
-        Json(LoginResponse {
-            user_id: claims.sub,
-            token,
-        }),
-    ))
+    Ok((jar.add(cookie), Json(LoginResponse { user_id, token })))
+}
+
+/// Signs a 24-hour session token for `user_id`.
+pub fn issue_token(config: &Config, user_id: &str) -> Result<String, AppError> {
+    let claims = Claims {
+        sub: user_id.to_string(),
+        exp: (Utc::now() + Duration::hours(24)).timestamp() as usize,
+    };
+    encode(
+        &Header::default(),
+        &claims,
+        &EncodingKey::from_secret(config.jwt_secret.as_bytes()),
+    )
+    .map_err(|e| AppError::Internal(format!("token encode error: {e}")))
 }
 
 pub async fn logout(jar: CookieJar) -> impl IntoResponse {
@@ -80,3 +89,36 @@ pub fn validate_token(config: &Config, jar: &CookieJar) -> Option<Claims> {
     .ok()
     .map(|d| d.claims)
 }
+
+/// Refuses requests carrying an account's session from a web origin outside the
+/// account's `allowed_origins`. Requests without an `Origin` header come from
+/// non-browser clients, which could claim any origin, so they pass.
+pub async fn enforce_account_origins(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    request: Request,
+    next: Next,
+) -> Response {
+    if let Some(origin) = request.headers().get(ORIGIN)
+        && let Some(claims) = validate_token(&state.config.load(), &jar)
+        && let Err(e) = check_origin(&state, &claims.sub, origin.as_bytes()).await
+    {
+        return e.into_response();
+    }
+    next.run(request).await
+}
+
+async fn check_origin(state: &AppState, account_id: &str, origin: &[u8]) -> Result<(), AppError> {
+    let allowed = state.access.origins_for(account_id).await;
+    if allowed.is_empty()
+        || allowed
+            .iter()
+            .any(|o| origin.eq_ignore_ascii_case(o.as_bytes()))
+    {
+        return Ok(());
+    }
+    Err(AppError::Forbidden(format!(
+        "origin {} is not allowed for account {account_id}",
+        String::from_utf8_lossy(origin)
+    )))
+}
@@ -0,0 +1,184 @@
+//! One-shot operational commands: each does its job against the configured
+//! database and exits instead of serving.
+
+use backend::{
+    auth,
+    config::Config,
+    db::Db,
+    error::AppError,
+    llm::LlmService,
+    model_router::{AccessControl, AccountAccess, AccountStatus, normalize_model_list},
+    state_export, tls, validate_account,
+};
+use clap::Args;
+use std::path::PathBuf;
+use uuid::Uuid;
+
+#[derive(Args)]
+pub struct ExportState {
+    /// Write the document to this file instead of stdout.
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Args)]
+pub struct CreateAdmin {
+    #[arg(long)]
+    email: String,
+    /// Display name; defaults to the email.
+    #[arg(long)]
+    name: Option<String>,
+    /// Account id; generated when omitted.
+    #[arg(long)]
+    id: Option<String>,
+    /// Models the account may chat with, comma-separated. None by default,
+    /// since the account is meant for the admin API.
+    #[arg(long, value_delimiter = ',')]
+    models: Vec<String>,
+    /// Print a 24-hour session token for the new account.
+    #[arg(long)]
+    token: bool,
+}
+
+/// Loads and validates the configuration, resolving secrets, building the
+/// provider clients and loading the TLS certificate, then exits without
+/// serving. Exits 1 with every problem listed if anything is wrong.
+pub async fn check_config() -> ! {
+    let checked = async {
+        let config = Config::load().await?;
+        LlmService::new(&config)?;
+        if let (Some(cert), Some(key)) = (&config.tls_cert, &config.tls_key) {
+            tls::load(cert, key).await?;
+        }
+        Ok::<_, AppError>(config)
+    }
+    .await;
+    match checked {
+        Ok(config) => {
+            if let Some(path) = &config.config_file {
+                println!("config file: {}", path.display());
+            }
+            for warning in &config.warnings {
+                println!("warning: {warning}");
+            }
+            println!("configuration OK");
+            std::process::exit(0);
+        }
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Opening the database applies any pending migrations.
+pub async fn migrate(config: &Config) -> Result<(), AppError> {
+    let db = Db::new(&config.database_url).await?;
+    let (applied, latest) = db.migration_status().await?;
+    println!(
+        "{}: {applied} migration(s) applied, latest {}",
+        config.database_url,
+        latest.map_or("none".into(), |v| format!("{v:03}"))
+    );
+    db.close().await;
+    Ok(())
+}
+
+pub async fn seed(config: &Config) -> Result<(), AppError> {
+    let db = Db::new(&config.database_url).await?;
+    let seeded = AccessControl::seed(&db, config.mock_provider).await?;
+    db.close().await;
+    if seeded {
+        println!("seeded the default accounts and model catalog");
+    } else {
+        println!("the database already has accounts or models; nothing seeded");
+    }
+    Ok(())
+}
+
+pub async fn export_state(config: &Config, args: ExportState) -> Result<(), AppError> {
+    let db = Db::new(&config.database_url).await?;
+    let yaml = state_export::export_yaml(&db).await;
+    db.close().await;
+    let yaml = yaml?;
+    match args.output {
+        Some(path) => {
+            std::fs::write(&path, yaml).map_err(|e| {
+                AppError::Internal(format!("failed to write {}: {e}", path.display()))
+            })?;
+            eprintln!("wrote {}", path.display());
+        }
+        None => print!("{yaml}"),
+    }
+    Ok(())
+}
+
+/// Creates the account; admin access itself comes from `ADMIN_ACCOUNTS`,
+/// which lives in the configuration, so that step is printed for the operator.
+pub async fn create_admin(config: &Config, args: CreateAdmin) -> Result<(), AppError> {
+    let email = args.email.trim().to_string();
+    let account = AccountAccess {
+        id: args
+            .id
+            .map(|id| id.trim().to_string())
+            .unwrap_or_else(|| Uuid::new_v4().to_string()),
+        display_name: args
+            .name
+            .map(|n| n.trim().to_string())
+            .unwrap_or_else(|| email.clone()),
+        email,
+        allowed_models: normalize_model_list(args.models),
+        status: AccountStatus::Active,
+        default_model: None,
+        max_cost_cents: None,
+        guardrail_prompt: None,
+        req_per_day: None,
+        tokens_per_day: None,
+        model_price_caps: Vec::new(),
+        data_residency: None,
+        allowed_origins: Vec::new(),
+        inline_translation: false,
+    };
+    validate_account(&account)?;
+
+    let db = Db::new(&config.database_url).await?;
+    let created = async {
+        // Seed first: a database holding only this account would never get
+        // the default catalog.
+        AccessControl::seed(&db, config.mock_provider).await?;
+        for existing in db.load_accounts().await? {
+            if existing.id == account.id {
+                return Err(AppError::BadRequest(format!(
+                    "account {} already exists",
+                    account.id
+                )));
+            }
+            if existing.email.eq_ignore_ascii_case(&account.email) {
+                return Err(AppError::BadRequest(format!(
+                    "an account with email {} already exists",
+                    account.email
+                )));
+            }
+        }
+        db.save_account(&account).await
+    }
+    .await;
+    db.close().await;
+    created?;
+
+    println!("created account {} ({})", account.id, account.email);
+    if config.admin_accounts.contains(&account.id) {
+        println!("it is already listed in ADMIN_ACCOUNTS");
+    } else {
+        let mut admins = config.admin_accounts.clone();
+        admins.push(account.id.clone());
+        println!(
+            "grant admin access by setting ADMIN_ACCOUNTS={} and reloading the configuration",
+            admins.join(",")
+        );
+    }
+    if args.token {
+        println!("session token: {}", auth::issue_token(config, &account.id)?);
+    }
+    Ok(())
+}
@@ -1,42 +1,1167 @@
-use crate::error::AppError;
-use std::env;
+use crate::{
+    error::AppError,
+    llm::{FixtureMode, LlmService},
+    secrets::{self, SECRET_VARS, SecretBackends},
+};
+use arc_swap::ArcSwap;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashMap},
+    env, fmt, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::SystemTime,
+};
+use tracing::{info, warn};
 
-#[derive(Debug, Clone)]
+/// Config files looked for in the working directory when `RACTOCHAT_CONFIG`
+/// isn't set.
+const DEFAULT_CONFIG_FILES: &[&str] = &["ractochat.toml", "ractochat.yaml", "ractochat.yml"];
+
+/// Used when `JWT_SECRET` is unset; refused in production.
+const DEV_JWT_SECRET: &str = "dev-secret-change-me";
+const MIN_JWT_SECRET_LEN: usize = 32;
+/// Used when `AUDIT_HMAC_KEY` is unset; refused in production.
+const DEV_AUDIT_HMAC_KEY: &str = "dev-audit-key-change-me";
+
+/// The live configuration. Handlers load it per request so a reload takes
+/// effect without restarting.
+pub type SharedConfig = Arc<ArcSwap<Config>>;
+
+#[derive(Debug, Clone, PartialEq)]
 pub struct Config {
+    /// `RACTOCHAT_ENV=production`: missing secrets and provider keys fail
+    /// startup instead of being warned about.
+    pub production: bool,
     pub host: String,
     pub port: u16,
     pub database_url: String,
     pub openai_api_key: Option<String>,
     pub anthropic_api_key: Option<String>,
+    /// Tried when the provider rejects the primary key, so keys can be rotated
+    /// without downtime.
+    pub openai_api_key_secondary: Option<String>,
+    pub anthropic_api_key_secondary: Option<String>,
+    /// Encrypts API keys stored in the database: 32 bytes as 64 hex characters.
+    pub key_encryption_key: Option<String>,
+    /// The master key before the last rotation, still able to decrypt keys
+    /// that haven't been re-encrypted.
+    pub key_encryption_key_previous: Option<String>,
+    /// Serve requests for `mock` catalog models locally. On by default when no
+    /// provider keys are set outside production.
+    pub mock_provider: bool,
+    pub mock_latency_ms: u64,
+    /// Share of mock calls that fail, 0.0 to 1.0.
+    pub mock_failure_rate: f64,
+    /// Record provider calls to `llm_fixtures_dir`, or replay them from it.
+    pub llm_fixtures: FixtureMode,
+    pub llm_fixtures_dir: PathBuf,
+    /// Provider API roots, for regional endpoints or gateways.
+    pub openai_base_url: String,
+    pub anthropic_base_url: String,
+    /// Proxy all provider traffic goes through.
+    pub egress_proxy: Option<String>,
+    /// PEM client certificate and PKCS#8 key presented to providers (mTLS).
+    pub egress_client_cert: Option<PathBuf>,
+    pub egress_client_key: Option<PathBuf>,
+    /// Extra PEM root certificate to trust, e.g. a TLS-inspecting proxy's CA.
+    pub egress_ca_cert: Option<PathBuf>,
     pub allowed_origins: Option<String>,
+    /// PEM certificate chain and private key; when both are set the listener
+    /// serves HTTPS.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
     pub jwt_secret: String,
+    /// Signs the audit trail's hash chain.
+    pub audit_hmac_key: String,
+    /// The key before the last rotation, so older entries still verify.
+    pub audit_hmac_key_previous: Option<String>,
+    pub redis_url: Option<String>,
+    pub rate_limit_per_minute: Option<u32>,
+    pub state_sync_secs: u64,
+    pub shutdown_drain_secs: u64,
+    /// How long startup keeps retrying unreachable providers before reporting
+    /// ready anyway; 0 skips the check.
+    pub startup_probe_secs: u64,
+    pub context_summary_model: Option<String>,
+    /// Catalog model that translates prompts and replies for accounts with
+    /// inline translation turned on.
+    pub translation_model: Option<String>,
+    pub rag_embedding_model: String,
+    pub rag_top_k: usize,
+    pub rag_max_upload_bytes: usize,
+    /// Largest JSON request body; document uploads have their own limit.
+    pub max_body_bytes: usize,
+    /// Most messages a chat request may carry.
+    pub max_messages: usize,
+    /// Longest single message, in characters.
+    pub max_message_chars: usize,
+    /// Most model calls one agent run may make.
+    pub agent_max_steps: usize,
+    /// Most an agent run may spend, in USD of estimated provider cost.
+    pub agent_max_cost: f64,
+    /// Account ids allowed to read stored message content through the admin API.
+    pub admin_accounts: Vec<String>,
+    /// Seconds between router health history snapshots; 0 disables them.
+    pub health_history_secs: u64,
+    pub health_history_days: u64,
+    /// Fixed seed for weighted alias picks, so tests route reproducibly.
+    pub routing_seed: Option<u64>,
+    /// Mask emails, phone numbers and similar in user messages before routing.
+    pub pii_redaction: bool,
+    /// Score accounts on velocity, blocked attempts and repeated prompts, and
+    /// throttle those over `abuse_score_threshold`.
+    pub abuse_detection: bool,
+    /// Requests a minute before velocity starts adding to the score.
+    pub abuse_velocity_per_minute: u32,
+    pub abuse_score_threshold: u32,
+    pub abuse_throttle_secs: u64,
+    /// `tracing` filter directive, e.g. `info` or `info,sqlx=warn`.
+    pub log_level: String,
+    /// The config file that was merged in, if any.
+    pub config_file: Option<PathBuf>,
+    /// Secret settings given as `file:`/`vault:`/`aws-sm:` references, keyed by
+    /// variable name. The resolved values live in the fields above.
+    pub secret_refs: BTreeMap<&'static str, String>,
+    pub secret_backends: SecretBackends,
+    /// Seconds between re-reads of referenced secrets; 0 disables refresh.
+    pub secrets_refresh_secs: u64,
+    /// Seconds between checks of the config file for edits; 0 disables the
+    /// watch (SIGHUP and the reload endpoint still work).
+    pub config_watch_secs: u64,
+    /// Non-fatal problems found while loading, logged at startup and reload.
+    pub warnings: Vec<String>,
 }
 
 impl Config {
-    pub fn from_env() -> Result<Self, AppError> {
-        let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".into());
-        let port = env::var("PORT")
-            .ok()
-            .and_then(|p| p.parse::<u16>().ok())
-            .unwrap_or(8000);
-
-        let database_url =
-            env::var("DATABASE_URL").unwrap_or_else(|_| "sqlite://./data/app.db".into());
-        let openai_api_key = env::var("OPENAI_API_KEY").ok();
-        let anthropic_api_key = env::var("ANTHROPIC_API_KEY").ok();
-        let allowed_origins = env::var("ALLOWED_ORIGINS")
-            .ok()
+    /// Reads the process environment, falling back to a `.env` file and then to
+    /// the config file (`RACTOCHAT_CONFIG`, or `ractochat.toml`/`.yaml` in the
+    /// working directory), and resolves secret references. Files are re-read on
+    /// every call so reloads pick up edits; variables set in the real
+    /// environment always win.
+    pub async fn load() -> Result<Self, AppError> {
+        let mut config = Self::from_env()?;
+        let values = secrets::resolve(&config.secret_refs, &config.secret_backends).await?;
+        for (var, value) in values {
+            config.set_secret(var, value);
+        }
+        Ok(config)
+    }
+
+    /// Settings as written, with secret references still unresolved. Every
+    /// problem is collected so one failed start reports all of them.
+    fn from_env() -> Result<Self, AppError> {
+        Self::from_source(EnvSource::load()?)
+    }
+
+    /// Settings taken from `vars` alone, as if they were the whole environment:
+    /// the process environment, `.env` and config files are ignored, and secret
+    /// references are left unresolved.
+    #[cfg(feature = "test_support")]
+    pub fn from_vars(vars: HashMap<String, String>) -> Result<Self, AppError> {
+        Self::from_source(EnvSource {
+            dotenv: vars,
+            file: HashMap::new(),
+            config_path: None,
+            process_env: false,
+        })
+    }
+
+    fn from_source(source: EnvSource) -> Result<Self, AppError> {
+        let mut problems = Vec::new();
+        let production = match source.text("RACTOCHAT_ENV").as_deref().map(str::trim) {
+            None | Some("development") => false,
+            Some("production") => true,
+            Some(other) => {
+                problems.push(format!(
+                    "RACTOCHAT_ENV: expected development or production, got {other:?}"
+                ));
+                false
+            }
+        };
+        let host = source.text("HOST").unwrap_or_else(|| "0.0.0.0".into());
+        let port = source.parsed("PORT", &mut problems).unwrap_or(8000);
+
+        let database_url = source
+            .text("DATABASE_URL")
+            .unwrap_or_else(|| "sqlite://./data/app.db".into());
+        let openai_api_key = source.text("OPENAI_API_KEY");
+        let anthropic_api_key = source.text("ANTHROPIC_API_KEY");
+        let openai_api_key_secondary = source.text("OPENAI_API_KEY_SECONDARY");
+        let anthropic_api_key_secondary = source.text("ANTHROPIC_API_KEY_SECONDARY");
+        let key_encryption_key = source.text("KEY_ENCRYPTION_KEY");
+        let key_encryption_key_previous = source.text("KEY_ENCRYPTION_KEY_PREVIOUS");
+        let mock_provider = source
+            .flag("MOCK_PROVIDER", &mut problems)
+            .unwrap_or(!production && openai_api_key.is_none() && anthropic_api_key.is_none());
+        let mock_latency_ms = source.parsed("MOCK_LATENCY_MS", &mut problems).unwrap_or(0);
+        let mock_failure_rate = source
+            .parsed("MOCK_FAILURE_RATE", &mut problems)
+            .unwrap_or(0.0);
+        let llm_fixtures = match source.text("LLM_FIXTURES") {
+            None => FixtureMode::Off,
+            Some(value) => FixtureMode::parse(&value).unwrap_or_else(|| {
+                problems.push(format!(
+                    "LLM_FIXTURES: expected off, record or replay, got {value:?}"
+                ));
+                FixtureMode::Off
+            }),
+        };
+        let llm_fixtures_dir = source
+            .text("LLM_FIXTURES_DIR")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| PathBuf::from("fixtures/llm"));
+        let openai_base_url = source
+            .text("OPENAI_BASE_URL")
+            .unwrap_or_else(|| "https://api.openai.com/v1".into());
+        let anthropic_base_url = source
+            .text("ANTHROPIC_BASE_URL")
+            .unwrap_or_else(|| "https://api.anthropic.com/v1".into());
+        let egress_proxy = source.text("LLM_PROXY_URL");
+        let egress_client_cert = source.text("LLM_CLIENT_CERT").map(PathBuf::from);
+        let egress_client_key = source.text("LLM_CLIENT_KEY").map(PathBuf::from);
+        let egress_ca_cert = source.text("LLM_CA_CERT").map(PathBuf::from);
+        let allowed_origins = source
+            .var("ALLOWED_ORIGINS")
             .or_else(|| Some("http://localhost:3000".to_string()));
-        let jwt_secret = env::var("JWT_SECRET").unwrap_or_else(|_| "dev-secret-change-me".into());
+        let tls_cert = source.text("TLS_CERT").map(PathBuf::from);
+        let tls_key = source.text("TLS_KEY").map(PathBuf::from);
+        let jwt_secret = source
+            .text("JWT_SECRET")
+            .unwrap_or_else(|| DEV_JWT_SECRET.into());
+        let audit_hmac_key = source
+            .text("AUDIT_HMAC_KEY")
+            .unwrap_or_else(|| DEV_AUDIT_HMAC_KEY.into());
+        let audit_hmac_key_previous = source.text("AUDIT_HMAC_KEY_PREVIOUS");
+        let redis_url = source.text("REDIS_URL");
+        let rate_limit_per_minute = source.parsed("RATE_LIMIT_PER_MINUTE", &mut problems);
+        let state_sync_secs = source.parsed("STATE_SYNC_SECS", &mut problems).unwrap_or(5);
+        let shutdown_drain_secs = source
+            .parsed("SHUTDOWN_DRAIN_SECS", &mut problems)
+            .unwrap_or(30);
+        let startup_probe_secs = source
+            .parsed("STARTUP_PROBE_SECS", &mut problems)
+            .unwrap_or(30);
+        let context_summary_model = source.text("CONTEXT_SUMMARY_MODEL");
+        let translation_model = source.text("TRANSLATION_MODEL");
+        let rag_embedding_model = source
+            .text("RAG_EMBEDDING_MODEL")
+            .unwrap_or_else(|| "text-embedding-3-small".into());
+        let rag_top_k = source.parsed("RAG_TOP_K", &mut problems).unwrap_or(4);
+        let rag_max_upload_bytes = source
+            .parsed::<usize>("RAG_MAX_UPLOAD_MB", &mut problems)
+            .unwrap_or(20)
+            * 1024
+            * 1024;
+        let max_body_bytes = source
+            .parsed::<usize>("MAX_BODY_KB", &mut problems)
+            .unwrap_or(2048)
+            * 1024;
+        let max_messages = source.parsed("MAX_MESSAGES", &mut problems).unwrap_or(500);
+        let max_message_chars = source
+            .parsed("MAX_MESSAGE_CHARS", &mut problems)
+            .unwrap_or(100_000);
+        let agent_max_steps = source.parsed("AGENT_MAX_STEPS", &mut problems).unwrap_or(8);
+        let agent_max_cost = source
+            .parsed("AGENT_MAX_COST", &mut problems)
+            .unwrap_or(0.5);
+        let admin_accounts = source
+            .var("ADMIN_ACCOUNTS")
+            .map(|v| {
+                v.split(',')
+                    .map(|id| id.trim().to_string())
+                    .filter(|id| !id.is_empty())
+                    .collect()
+            })
+            .unwrap_or_default();
 
-        Ok(Self {
+        let health_history_secs = source
+            .parsed("HEALTH_HISTORY_SECS", &mut problems)
+            .unwrap_or(60);
+        let health_history_days = source
+            .parsed("HEALTH_HISTORY_DAYS", &mut problems)
+            .unwrap_or(30);
+        let routing_seed = source.parsed("ROUTING_SEED", &mut problems);
+        let pii_redaction = source.flag("PII_REDACTION", &mut problems).unwrap_or(true);
+        let abuse_detection = source
+            .flag("ABUSE_DETECTION", &mut problems)
+            .unwrap_or(true);
+        let abuse_velocity_per_minute = source
+            .parsed("ABUSE_VELOCITY_PER_MINUTE", &mut problems)
+            .unwrap_or(30);
+        let abuse_score_threshold = source
+            .parsed("ABUSE_SCORE_THRESHOLD", &mut problems)
+            .unwrap_or(50);
+        let abuse_throttle_secs = source
+            .parsed("ABUSE_THROTTLE_SECS", &mut problems)
+            .unwrap_or(900);
+        let log_level = source.text("LOG_LEVEL").unwrap_or_else(|| "info".into());
+        let secret_refs = SECRET_VARS
+            .iter()
+            .filter_map(|&var| {
+                source
+                    .var(var)
+                    .filter(|v| secrets::is_reference(v))
+                    .map(|v| (var, v))
+            })
+            .collect();
+        let secret_backends = SecretBackends {
+            vault_addr: source.text("VAULT_ADDR"),
+            vault_token: source.text("VAULT_TOKEN"),
+            vault_namespace: source.text("VAULT_NAMESPACE"),
+            aws_region: source
+                .text("AWS_REGION")
+                .or_else(|| source.text("AWS_DEFAULT_REGION")),
+            aws_endpoint: source.text("AWS_ENDPOINT_URL_SECRETS_MANAGER"),
+            aws_access_key_id: source.text("AWS_ACCESS_KEY_ID"),
+            aws_secret_access_key: source.text("AWS_SECRET_ACCESS_KEY"),
+            aws_session_token: source.text("AWS_SESSION_TOKEN"),
+        };
+        let secrets_refresh_secs = source
+            .parsed("SECRETS_REFRESH_SECS", &mut problems)
+            .unwrap_or(300);
+        let config_watch_secs = source
+            .parsed("CONFIG_WATCH_SECS", &mut problems)
+            .unwrap_or(2);
+
+        let mut config = Self {
+            production,
             host,
             port,
             database_url,
             openai_api_key,
             anthropic_api_key,
+            openai_api_key_secondary,
+            anthropic_api_key_secondary,
+            key_encryption_key,
+            key_encryption_key_previous,
+            mock_provider,
+            mock_latency_ms,
+            mock_failure_rate,
+            llm_fixtures,
+            llm_fixtures_dir,
+            openai_base_url,
+            anthropic_base_url,
+            egress_proxy,
+            egress_client_cert,
+            egress_client_key,
+            egress_ca_cert,
             allowed_origins,
+            tls_cert,
+            tls_key,
             jwt_secret,
+            audit_hmac_key,
+            audit_hmac_key_previous,
+            redis_url,
+            rate_limit_per_minute,
+            state_sync_secs,
+            shutdown_drain_secs,
+            startup_probe_secs,
+            context_summary_model,
+            translation_model,
+            rag_embedding_model,
+            rag_top_k,
+            rag_max_upload_bytes,
+            max_body_bytes,
+            max_messages,
+            max_message_chars,
+            agent_max_steps,
+            agent_max_cost,
+            admin_accounts,
+            health_history_secs,
+            health_history_days,
+            routing_seed,
+            pii_redaction,
+            abuse_detection,
+            abuse_velocity_per_minute,
+            abuse_score_threshold,
+            abuse_throttle_secs,
+            log_level,
+            config_file: source.config_path,
+            secret_refs,
+            secret_backends,
+            secrets_refresh_secs,
+            config_watch_secs,
+            warnings: Vec::new(),
+        };
+        config.check(&mut problems);
+        if !problems.is_empty() {
+            return Err(AppError::Config(format!(
+                "{} problem(s) found:\n{}",
+                problems.len(),
+                problems
+                    .iter()
+                    .map(|p| format!("  - {p}"))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            )));
+        }
+        Ok(config)
+    }
+
+    /// Checks that need more than one setting or more than parsing. Problems
+    /// stop startup; warnings are logged. Production mode turns the
+    /// conveniences that are fine on a laptop into problems.
+    fn check(&mut self, problems: &mut Vec<String>) {
+        if !self.database_url.starts_with("sqlite:") {
+            problems.push(format!(
+                "DATABASE_URL: only sqlite URLs are supported, got {:?}",
+                self.database_url
+            ));
+        }
+        for (var, url) in [
+            ("OPENAI_BASE_URL", Some(&self.openai_base_url)),
+            ("ANTHROPIC_BASE_URL", Some(&self.anthropic_base_url)),
+            ("LLM_PROXY_URL", self.egress_proxy.as_ref()),
+        ] {
+            if let Some(url) = url
+                && let Err(e) = reqwest::Url::parse(url)
+            {
+                problems.push(format!("{var}: {url:?} is not a valid URL ({e})"));
+            }
+        }
+        for (secondary, primary, var) in [
+            (
+                &self.openai_api_key_secondary,
+                &self.openai_api_key,
+                "OPENAI_API_KEY",
+            ),
+            (
+                &self.anthropic_api_key_secondary,
+                &self.anthropic_api_key,
+                "ANTHROPIC_API_KEY",
+            ),
+        ] {
+            if secondary.is_some() && primary.is_none() {
+                problems.push(format!("{var}_SECONDARY is set without {var}"));
+            }
+        }
+        for (var, key) in [
+            ("KEY_ENCRYPTION_KEY", &self.key_encryption_key),
+            (
+                "KEY_ENCRYPTION_KEY_PREVIOUS",
+                &self.key_encryption_key_previous,
+            ),
+        ] {
+            if let Some(key) = key
+                && !self.secret_refs.contains_key(var)
+                && (key.len() != 64 || !key.chars().all(|c| c.is_ascii_hexdigit()))
+            {
+                problems.push(format!("{var} must be 64 hex characters (32 bytes)"));
+            }
+        }
+        if self.key_encryption_key_previous.is_some() && self.key_encryption_key.is_none() {
+            problems.push("KEY_ENCRYPTION_KEY_PREVIOUS is set without KEY_ENCRYPTION_KEY".into());
+        }
+        match (&self.tls_cert, &self.tls_key) {
+            (Some(_), None) => problems.push("TLS_CERT is set without TLS_KEY".into()),
+            (None, Some(_)) => problems.push("TLS_KEY is set without TLS_CERT".into()),
+            _ => {}
+        }
+        match (&self.egress_client_cert, &self.egress_client_key) {
+            (Some(_), None) => {
+                problems.push("LLM_CLIENT_CERT is set without LLM_CLIENT_KEY".into())
+            }
+            (None, Some(_)) => {
+                problems.push("LLM_CLIENT_KEY is set without LLM_CLIENT_CERT".into())
+            }
+            _ => {}
+        }
+        for (var, path) in [
+            ("TLS_CERT", &self.tls_cert),
+            ("TLS_KEY", &self.tls_key),
+            ("LLM_CLIENT_CERT", &self.egress_client_cert),
+            ("LLM_CLIENT_KEY", &self.egress_client_key),
+            ("LLM_CA_CERT", &self.egress_ca_cert),
+        ] {
+            if let Some(path) = path
+                && !path.is_file()
+            {
+                problems.push(format!("{var}: {} does not exist", path.display()));
+            }
+        }
+        if !(0.0..=1.0).contains(&self.mock_failure_rate) {
+            problems.push(format!(
+                "MOCK_FAILURE_RATE: expected a value between 0 and 1, got {}",
+                self.mock_failure_rate
+            ));
+        }
+        for (var, value) in [
+            ("MAX_BODY_KB", self.max_body_bytes),
+            ("MAX_MESSAGES", self.max_messages),
+            ("MAX_MESSAGE_CHARS", self.max_message_chars),
+            ("AGENT_MAX_STEPS", self.agent_max_steps),
+        ] {
+            if value == 0 {
+                problems.push(format!("{var}: must be greater than 0"));
+            }
+        }
+        if self.agent_max_cost.is_nan() || self.agent_max_cost <= 0.0 {
+            problems.push(format!(
+                "AGENT_MAX_COST: must be greater than 0, got {}",
+                self.agent_max_cost
+            ));
+        }
+        if self.abuse_score_threshold == 0 {
+            problems.push("ABUSE_SCORE_THRESHOLD: must be greater than 0".into());
+        }
+        if self.production && self.mock_provider {
+            problems.push("MOCK_PROVIDER is enabled in production".into());
+        }
+        if self.production && self.llm_fixtures != FixtureMode::Off {
+            problems.push("LLM_FIXTURES must be off in production".into());
+        }
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log_level) {
+            problems.push(format!(
+                "LOG_LEVEL: invalid filter {:?} ({e})",
+                self.log_level
+            ));
+        }
+
+        let mut warn_or_fail = |message: String| {
+            if self.production {
+                problems.push(message);
+            } else {
+                self.warnings.push(message);
+            }
+        };
+        if self.openai_api_key.is_none()
+            && self.anthropic_api_key.is_none()
+            && self.llm_fixtures != FixtureMode::Replay
+        {
+            warn_or_fail(if self.mock_provider {
+                "no provider keys; the mock provider is serving requests".into()
+            } else {
+                "no provider keys: set OPENAI_API_KEY or ANTHROPIC_API_KEY".into()
+            });
+        }
+        if self.jwt_secret == DEV_JWT_SECRET {
+            warn_or_fail("JWT_SECRET is not set; using the development default".into());
+        } else if !self.secret_refs.contains_key("JWT_SECRET")
+            && self.jwt_secret.len() < MIN_JWT_SECRET_LEN
+        {
+            warn_or_fail(format!(
+                "JWT_SECRET is shorter than {MIN_JWT_SECRET_LEN} characters"
+            ));
+        }
+        if self.audit_hmac_key == DEV_AUDIT_HMAC_KEY {
+            warn_or_fail("AUDIT_HMAC_KEY is not set; using the development default".into());
+        }
+        if self.production
+            && self
+                .allowed_origins
+                .as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .all(|o| o.trim().is_empty())
+        {
+            self.warnings
+                .push("ALLOWED_ORIGINS is empty; cross-origin browser requests are refused".into());
+        }
+        if self.production
+            && self
+                .allowed_origins
+                .as_deref()
+                .is_some_and(|o| o.contains("localhost"))
+        {
+            self.warnings
+                .push("ALLOWED_ORIGINS allows localhost in production".into());
+        }
+        if self.production && self.routing_seed.is_some() {
+            self.warnings
+                .push("ROUTING_SEED is set; alias splits are predictable".into());
+        }
+        if self.production && cfg!(feature = "chaos") {
+            self.warnings.push(
+                "built with the chaos feature; fault injection stays off in production".into(),
+            );
+        }
+    }
+
+    fn secret(&self, var: &str) -> Option<&str> {
+        match var {
+            "OPENAI_API_KEY" => self.openai_api_key.as_deref(),
+            "OPENAI_API_KEY_SECONDARY" => self.openai_api_key_secondary.as_deref(),
+            "ANTHROPIC_API_KEY" => self.anthropic_api_key.as_deref(),
+            "ANTHROPIC_API_KEY_SECONDARY" => self.anthropic_api_key_secondary.as_deref(),
+            "JWT_SECRET" => Some(&self.jwt_secret),
+            "AUDIT_HMAC_KEY" => Some(&self.audit_hmac_key),
+            "KEY_ENCRYPTION_KEY" => self.key_encryption_key.as_deref(),
+            "KEY_ENCRYPTION_KEY_PREVIOUS" => self.key_encryption_key_previous.as_deref(),
+            "AUDIT_HMAC_KEY_PREVIOUS" => self.audit_hmac_key_previous.as_deref(),
+            _ => None,
+        }
+    }
+
+    fn set_secret(&mut self, var: &str, value: String) {
+        match var {
+            "OPENAI_API_KEY" => self.openai_api_key = Some(value),
+            "OPENAI_API_KEY_SECONDARY" => self.openai_api_key_secondary = Some(value),
+            "ANTHROPIC_API_KEY" => self.anthropic_api_key = Some(value),
+            "ANTHROPIC_API_KEY_SECONDARY" => self.anthropic_api_key_secondary = Some(value),
+            "JWT_SECRET" => self.jwt_secret = value,
+            "AUDIT_HMAC_KEY" => self.audit_hmac_key = value,
+            "KEY_ENCRYPTION_KEY" => self.key_encryption_key = Some(value),
+            "KEY_ENCRYPTION_KEY_PREVIOUS" => self.key_encryption_key_previous = Some(value),
+            "AUDIT_HMAC_KEY_PREVIOUS" => self.audit_hmac_key_previous = Some(value),
+            _ => {}
+        }
+    }
+}
+
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "1" | "true" | "yes" | "on" => Some(true),
+        "0" | "false" | "no" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Layered settings lookup: process environment, then `.env`, then the config
+/// file. Config file values are stored under their environment variable names.
+struct EnvSource {
+    dotenv: HashMap<String, String>,
+    file: HashMap<&'static str, String>,
+    config_path: Option<PathBuf>,
+    /// Whether real environment variables are consulted (and win).
+    process_env: bool,
+}
+
+impl EnvSource {
+    fn load() -> Result<Self, AppError> {
+        let dotenv: HashMap<String, String> = dotenvy::dotenv_iter()
+            .map(|iter| iter.filter_map(Result::ok).collect())
+            .unwrap_or_default();
+        let explicit = env::var("RACTOCHAT_CONFIG")
+            .ok()
+            .or_else(|| dotenv.get("RACTOCHAT_CONFIG").cloned())
+            .filter(|p| !p.trim().is_empty());
+        let config_path = match explicit {
+            Some(path) => Some(PathBuf::from(path)),
+            None => DEFAULT_CONFIG_FILES
+                .iter()
+                .map(PathBuf::from)
+                .find(|p| p.is_file()),
+        };
+        let file = match &config_path {
+            Some(path) => FileConfig::read(path)?.into_vars(),
+            None => HashMap::new(),
+        };
+        Ok(Self {
+            dotenv,
+            file,
+            config_path,
+            process_env: true,
         })
     }
+
+    fn var(&self, key: &str) -> Option<String> {
+        self.process_env
+            .then(|| env::var(key).ok())
+            .flatten()
+            .or_else(|| self.dotenv.get(key).cloned())
+            .or_else(|| self.file.get(key).cloned())
+    }
+
+    /// A value that is set and not blank.
+    fn text(&self, key: &str) -> Option<String> {
+        self.var(key).filter(|v| !v.trim().is_empty())
+    }
+
+    /// Parses a set value, recording a problem instead of falling back to the
+    /// default when it doesn't parse.
+    fn parsed<T>(&self, key: &str, problems: &mut Vec<String>) -> Option<T>
+    where
+        T: FromStr,
+        T::Err: fmt::Display,
+    {
+        let value = self.text(key)?;
+        match value.trim().parse() {
+            Ok(parsed) => Some(parsed),
+            Err(e) => {
+                problems.push(format!("{key}: cannot parse {value:?} ({e})"));
+                None
+            }
+        }
+    }
+
+    fn flag(&self, key: &str, problems: &mut Vec<String>) -> Option<bool> {
+        let value = self.text(key)?;
+        let parsed = parse_bool(&value);
+        if parsed.is_none() {
+            problems.push(format!("{key}: expected true or false, got {value:?}"));
+        }
+        parsed
+    }
+}
+
+/// The config file layout. Every setting is optional; unknown keys are
+/// rejected so typos don't silently fall back to defaults.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FileConfig {
+    server: ServerSection,
+    database: DatabaseSection,
+    auth: AuthSection,
+    providers: ProvidersSection,
+    egress: EgressSection,
+    router: RouterSection,
+    rate_limits: RateLimitSection,
+    mock: MockSection,
+    fixtures: FixturesSection,
+    pii: PiiSection,
+    abuse: AbuseSection,
+    rag: RagSection,
+    limits: LimitsSection,
+    logging: LoggingSection,
+    secrets: SecretsSection,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ServerSection {
+    environment: Option<String>,
+    host: Option<String>,
+    port: Option<u16>,
+    allowed_origins: Option<Vec<String>>,
+    shutdown_drain_secs: Option<u64>,
+    startup_probe_secs: Option<u64>,
+    config_watch_secs: Option<u64>,
+    tls_cert: Option<String>,
+    tls_key: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct DatabaseSection {
+    url: Option<String>,
+    redis_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AuthSection {
+    jwt_secret: Option<String>,
+    admin_accounts: Option<Vec<String>>,
+    audit_hmac_key: Option<String>,
+    audit_hmac_key_previous: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ProvidersSection {
+    openai: ProviderSection,
+    anthropic: ProviderSection,
+    key_encryption_key: Option<String>,
+    key_encryption_key_previous: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct ProviderSection {
+    api_key: Option<String>,
+    secondary_api_key: Option<String>,
+    base_url: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct MockSection {
+    enabled: Option<bool>,
+    latency_ms: Option<u64>,
+    failure_rate: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct FixturesSection {
+    mode: Option<String>,
+    dir: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct EgressSection {
+    proxy: Option<String>,
+    client_cert: Option<String>,
+    client_key: Option<String>,
+    ca_cert: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RouterSection {
+    state_sync_secs: Option<u64>,
+    context_summary_model: Option<String>,
+    translation_model: Option<String>,
+    health_history_secs: Option<u64>,
+    health_history_days: Option<u64>,
+    seed: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RateLimitSection {
+    per_minute: Option<u32>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct PiiSection {
+    redaction: Option<bool>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct AbuseSection {
+    detection: Option<bool>,
+    velocity_per_minute: Option<u32>,
+    score_threshold: Option<u32>,
+    throttle_secs: Option<u64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct RagSection {
+    embedding_model: Option<String>,
+    top_k: Option<usize>,
+    max_upload_mb: Option<usize>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LimitsSection {
+    max_body_kb: Option<usize>,
+    max_messages: Option<usize>,
+    max_message_chars: Option<usize>,
+    agent_max_steps: Option<usize>,
+    agent_max_cost: Option<f64>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct LoggingSection {
+    level: Option<String>,
+}
+
+/// Backend locations only; Vault tokens and AWS credentials come from the
+/// environment.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct SecretsSection {
+    refresh_secs: Option<u64>,
+    vault_addr: Option<String>,
+    vault_namespace: Option<String>,
+    aws_region: Option<String>,
+    aws_endpoint: Option<String>,
+}
+
+impl FileConfig {
+    fn read(path: &Path) -> Result<Self, AppError> {
+        let text = fs::read_to_string(path).map_err(|e| {
+            AppError::Config(format!("cannot read config file {}: {e}", path.display()))
+        })?;
+        let parsed = match path.extension().and_then(|e| e.to_str()) {
+            Some("yaml" | "yml") => serde_yaml::from_str(&text).map_err(|e| e.to_string()),
+            _ => toml::from_str(&text).map_err(|e| e.to_string()),
+        };
+        parsed.map_err(|e| AppError::Config(format!("invalid config file {}: {e}", path.display())))
+    }
+
+    /// Flattens the file into the environment variables it stands in for.
+    fn into_vars(self) -> HashMap<&'static str, String> {
+        let mut vars = HashMap::new();
+        let mut set = |key: &'static str, value: Option<String>| {
+            if let Some(value) = value {
+                vars.insert(key, value);
+            }
+        };
+        let list = |items: Option<Vec<String>>| items.map(|v| v.join(","));
+        let num = |n: Option<u64>| n.map(|n| n.to_string());
+
+        set("RACTOCHAT_ENV", self.server.environment);
+        set("HOST", self.server.host);
+        set("PORT", self.server.port.map(|p| p.to_string()));
+        set("ALLOWED_ORIGINS", list(self.server.allowed_origins));
+        set("SHUTDOWN_DRAIN_SECS", num(self.server.shutdown_drain_secs));
+        set("STARTUP_PROBE_SECS", num(self.server.startup_probe_secs));
+        set("CONFIG_WATCH_SECS", num(self.server.config_watch_secs));
+        set("TLS_CERT", self.server.tls_cert);
+        set("TLS_KEY", self.server.tls_key);
+        set("DATABASE_URL", self.database.url);
+        set("REDIS_URL", self.database.redis_url);
+        set("JWT_SECRET", self.auth.jwt_secret);
+        set("ADMIN_ACCOUNTS", list(self.auth.admin_accounts));
+        set("AUDIT_HMAC_KEY", self.auth.audit_hmac_key);
+        set("AUDIT_HMAC_KEY_PREVIOUS", self.auth.audit_hmac_key_previous);
+        set("OPENAI_API_KEY", self.providers.openai.api_key);
+        set("ANTHROPIC_API_KEY", self.providers.anthropic.api_key);
+        set(
+            "OPENAI_API_KEY_SECONDARY",
+            self.providers.openai.secondary_api_key,
+        );
+        set(
+            "ANTHROPIC_API_KEY_SECONDARY",
+            self.providers.anthropic.secondary_api_key,
+        );
+        set("KEY_ENCRYPTION_KEY", self.providers.key_encryption_key);
+        set(
+            "KEY_ENCRYPTION_KEY_PREVIOUS",
+            self.providers.key_encryption_key_previous,
+        );
+        set("OPENAI_BASE_URL", self.providers.openai.base_url);
+        set("ANTHROPIC_BASE_URL", self.providers.anthropic.base_url);
+        set("MOCK_PROVIDER", self.mock.enabled.map(|b| b.to_string()));
+        set("MOCK_LATENCY_MS", num(self.mock.latency_ms));
+        set(
+            "MOCK_FAILURE_RATE",
+            self.mock.failure_rate.map(|r| r.to_string()),
+        );
+        set("LLM_FIXTURES", self.fixtures.mode);
+        set("LLM_FIXTURES_DIR", self.fixtures.dir);
+        set("LLM_PROXY_URL", self.egress.proxy);
+        set("LLM_CLIENT_CERT", self.egress.client_cert);
+        set("LLM_CLIENT_KEY", self.egress.client_key);
+        set("LLM_CA_CERT", self.egress.ca_cert);
+        set("STATE_SYNC_SECS", num(self.router.state_sync_secs));
+        set("CONTEXT_SUMMARY_MODEL", self.router.context_summary_model);
+        set("TRANSLATION_MODEL", self.router.translation_model);
+        set("HEALTH_HISTORY_SECS", num(self.router.health_history_secs));
+        set("HEALTH_HISTORY_DAYS", num(self.router.health_history_days));
+        set("ROUTING_SEED", num(self.router.seed));
+        set(
+            "RATE_LIMIT_PER_MINUTE",
+            self.rate_limits.per_minute.map(|n| n.to_string()),
+        );
+        set("PII_REDACTION", self.pii.redaction.map(|b| b.to_string()));
+        set(
+            "ABUSE_DETECTION",
+            self.abuse.detection.map(|b| b.to_string()),
+        );
+        set(
+            "ABUSE_VELOCITY_PER_MINUTE",
+            self.abuse.velocity_per_minute.map(|n| n.to_string()),
+        );
+        set(
+            "ABUSE_SCORE_THRESHOLD",
+            self.abuse.score_threshold.map(|n| n.to_string()),
+        );
+        set("ABUSE_THROTTLE_SECS", num(self.abuse.throttle_secs));
+        set("RAG_EMBEDDING_MODEL", self.rag.embedding_model);
+        set("RAG_TOP_K", self.rag.top_k.map(|n| n.to_string()));
+        set(
+            "RAG_MAX_UPLOAD_MB",
+            self.rag.max_upload_mb.map(|n| n.to_string()),
+        );
+        set(
+            "MAX_BODY_KB",
+            self.limits.max_body_kb.map(|n| n.to_string()),
+        );
+        set(
+            "MAX_MESSAGES",
+            self.limits.max_messages.map(|n| n.to_string()),
+        );
+        set(
+            "MAX_MESSAGE_CHARS",
+            self.limits.max_message_chars.map(|n| n.to_string()),
+        );
+        set(
+            "AGENT_MAX_STEPS",
+            self.limits.agent_max_steps.map(|n| n.to_string()),
+        );
+        set(
+            "AGENT_MAX_COST",
+            self.limits.agent_max_cost.map(|n| n.to_string()),
+        );
+        set("LOG_LEVEL", self.logging.level);
+        set("SECRETS_REFRESH_SECS", num(self.secrets.refresh_secs));
+        set("VAULT_ADDR", self.secrets.vault_addr);
+        set("VAULT_NAMESPACE", self.secrets.vault_namespace);
+        set("AWS_REGION", self.secrets.aws_region);
+        set(
+            "AWS_ENDPOINT_URL_SECRETS_MANAGER",
+            self.secrets.aws_endpoint,
+        );
+        vars
+    }
+}
+
+/// Which settings a reload changed. Names only, so secrets never leak into
+/// responses or logs.
+#[derive(Debug, Default, Serialize)]
+pub struct ConfigReload {
+    pub applied: Vec<&'static str>,
+    /// Changed, but only read at startup; the running values are kept.
+    pub restart_required: Vec<&'static str>,
+    pub warnings: Vec<String>,
+}
+
+/// Re-reads the configuration, swaps in everything that can change at runtime
+/// and rebuilds the provider clients when their keys changed. In-flight requests
+/// keep the snapshot they started with.
+pub async fn reload(config: &SharedConfig, llm: &LlmService) -> Result<ConfigReload, AppError> {
+    let current = config.load_full();
+    let mut next = Config::load().await?;
+    let mut changes = ConfigReload::default();
+
+    macro_rules! live {
+        ($($field:ident),* $(,)?) => {$(
+            if next.$field != current.$field {
+                changes.applied.push(stringify!($field));
+            }
+        )*};
+    }
+    macro_rules! startup_only {
+        ($($field:ident),* $(,)?) => {$(
+            if next.$field != current.$field {
+                changes.restart_required.push(stringify!($field));
+                next.$field = current.$field.clone();
+            }
+        )*};
+    }
+    // Switching between HTTP and HTTPS needs a new listener; new certificate
+    // paths are picked up by the running one.
+    if next.tls_cert.is_some() != current.tls_cert.is_some() {
+        changes.restart_required.push("tls");
+        next.tls_cert = current.tls_cert.clone();
+        next.tls_key = current.tls_key.clone();
+    }
+    live!(
+        production,
+        openai_api_key,
+        anthropic_api_key,
+        openai_api_key_secondary,
+        anthropic_api_key_secondary,
+        key_encryption_key,
+        key_encryption_key_previous,
+        mock_provider,
+        mock_latency_ms,
+        mock_failure_rate,
+        llm_fixtures,
+        llm_fixtures_dir,
+        openai_base_url,
+        anthropic_base_url,
+        egress_proxy,
+        egress_client_cert,
+        egress_client_key,
+        egress_ca_cert,
+        allowed_origins,
+        tls_cert,
+        tls_key,
+        jwt_secret,
+        audit_hmac_key,
+        audit_hmac_key_previous,
+        rate_limit_per_minute,
+        max_messages,
+        max_message_chars,
+        agent_max_steps,
+        agent_max_cost,
+        shutdown_drain_secs,
+        context_summary_model,
+        translation_model,
+        admin_accounts,
+        pii_redaction,
+        abuse_detection,
+        abuse_velocity_per_minute,
+        abuse_score_threshold,
+        abuse_throttle_secs,
+        config_file,
+        secret_refs,
+        secret_backends,
+    );
+    startup_only!(
+        host,
+        port,
+        database_url,
+        redis_url,
+        state_sync_secs,
+        startup_probe_secs,
+        rag_embedding_model,
+        rag_top_k,
+        rag_max_upload_bytes,
+        max_body_bytes,
+        health_history_secs,
+        health_history_days,
+        routing_seed,
+        log_level,
+        secrets_refresh_secs,
+        config_watch_secs,
+    );
+
+    // Certificate files may have been rotated in place, so provider clients
+    // are rebuilt whenever egress is configured, not only when settings change.
+    let clients_changed = next.openai_api_key != current.openai_api_key
+        || next.anthropic_api_key != current.anthropic_api_key
+        || next.openai_api_key_secondary != current.openai_api_key_secondary
+        || next.anthropic_api_key_secondary != current.anthropic_api_key_secondary
+        || next.openai_base_url != current.openai_base_url
+        || next.anthropic_base_url != current.anthropic_base_url
+        || next.egress_proxy != current.egress_proxy
+        || next.egress_client_cert != current.egress_client_cert
+        || next.egress_client_key != current.egress_client_key
+        || next.egress_ca_cert != current.egress_ca_cert
+        || next.mock_provider != current.mock_provider
+        || next.mock_latency_ms != current.mock_latency_ms
+        || next.mock_failure_rate != current.mock_failure_rate
+        || next.llm_fixtures != current.llm_fixtures
+        || next.llm_fixtures_dir != current.llm_fixtures_dir;
+    if clients_changed || next.egress_client_cert.is_some() || next.egress_ca_cert.is_some() {
+        llm.reconfigure(&next)?;
+    }
+    for warning in &next.warnings {
+        warn!("config: {warning}");
+    }
+    changes.warnings = next.warnings.clone();
+    config.store(Arc::new(next));
+    info!(
+        "configuration reloaded; applied: {:?}, restart required: {:?}",
+        changes.applied, changes.restart_required
+    );
+    Ok(changes)
+}
+
+/// Re-reads referenced secrets and swaps in the ones that changed, returning
+/// their variable names. Skips the swap if a reload replaced the configuration
+/// meanwhile, since that reload resolved the secrets itself.
+pub async fn refresh_secrets(
+    config: &SharedConfig,
+    llm: &LlmService,
+) -> Result<Vec<&'static str>, AppError> {
+    let current = config.load_full();
+    let values = secrets::resolve(&current.secret_refs, &current.secret_backends).await?;
+    let changed: Vec<&'static str> = values
+        .iter()
+        .filter(|(var, value)| current.secret(var) != Some(value.as_str()))
+        .map(|(var, _)| *var)
+        .collect();
+    if changed.is_empty() {
+        return Ok(changed);
+    }
+    let mut next = (*current).clone();
+    for (var, value) in values {
+        next.set_secret(var, value);
+    }
+    let next = Arc::new(next);
+    let previous = config.compare_and_swap(&current, next.clone());
+    if !Arc::ptr_eq(&previous, &current) {
+        return Ok(Vec::new());
+    }
+    // Clients are only rebuilt for the configuration that won the swap. If that
+    // fails, put the old secrets back so config and clients stay in step.
+    if changed.iter().any(|var| var.contains("API_KEY"))
+        && let Err(e) = llm.reconfigure(&next)
+    {
+        config.compare_and_swap(&next, current);
+        return Err(e);
+    }
+    Ok(changed)
+}
+
+/// Modification time and size, enough to notice a file being replaced or
+/// edited. `None` when the file can't be read.
+pub fn file_fingerprint(path: &Path) -> Option<(Option<SystemTime>, u64)> {
+    fs::metadata(path)
+        .ok()
+        .map(|meta| (meta.modified().ok(), meta.len()))
 }
@@ -0,0 +1,83 @@
+use crate::llm::{LlmMessage, Role};
+use serde::Serialize;
+
+/// Framing tokens providers add around each message on top of its content.
+const MESSAGE_OVERHEAD_TOKENS: u32 = 4;
+/// Completion budget held back when the request doesn't set `max_tokens`.
+const DEFAULT_COMPLETION_RESERVE: u32 = 1024;
+
+/// What context management did to a request, returned alongside the answer so
+/// clients can tell the model didn't see the whole transcript.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct ContextReport {
+    pub context_window: Option<u32>,
+    pub estimated_prompt_tokens: u32,
+    pub dropped_messages: usize,
+    pub dropped_tokens: u32,
+    pub summarized: bool,
+}
+
+/// Cheap token estimate (~4 characters per token). Deliberately pessimistic
+/// enough that trimming happens before the provider would reject the request.
+pub fn estimate_tokens(text: &str) -> u32 {
+    (text.chars().count() as u32).div_ceil(4)
+}
+
+pub fn message_tokens(message: &LlmMessage) -> u32 {
+    estimate_tokens(&message.content) + MESSAGE_OVERHEAD_TOKENS
+}
+
+pub fn prompt_tokens(messages: &[LlmMessage]) -> u32 {
+    messages.iter().map(message_tokens).sum()
+}
+
+/// Tokens available for the prompt once the completion has been reserved.
+pub fn prompt_budget(context_window: u32, max_tokens: Option<u32>) -> u32 {
+    let reserve = max_tokens
+        .unwrap_or(DEFAULT_COMPLETION_RESERVE)
+        .min(context_window / 2);
+    context_window - reserve
+}
+
+/// Removes the oldest conversational turns until the prompt fits `budget` and
+/// returns them in their original order. System messages and the latest message
+/// are always kept, and the remaining history never starts on an assistant turn.
+pub fn trim_to_budget(messages: &mut Vec<LlmMessage>, budget: u32) -> Vec<LlmMessage> {
+    let mut dropped = Vec::new();
+    let mut total = prompt_tokens(messages);
+    while let Some(idx) = messages.iter().position(|m| m.role != Role::System) {
+        if idx + 1 >= messages.len() {
+            break;
+        }
+        let leads_with_assistant = messages[idx].role == Role::Assistant;
+        if total <= budget && (dropped.is_empty() || !leads_with_assistant) {
+            break;
+        }
+        let removed = messages.remove(idx);
+        total -= message_tokens(&removed);
+        dropped.push(removed);
+    }
+    dropped
+}
+
+/// Flattens turns into a plain transcript for the summarizer, keeping the most
+/// recent `max_tokens` worth when the dropped history is itself too long.
+pub fn transcript(messages: &[LlmMessage], max_tokens: u32) -> String {
+    let mut lines = Vec::new();
+    let mut used = 0;
+    for message in messages.iter().rev() {
+        let cost = message_tokens(message);
+        if used + cost > max_tokens {
+            break;
+        }
+        used += cost;
+        let speaker = match message.role {
+            Role::System => "System",
+            Role::User => "User",
+            Role::Assistant => "Assistant",
+        };
+        lines.push(format!("{speaker}: {}", message.content));
+    }
+    lines.reverse();
+    lines.join("\n\n")
+}
@@ -1,11 +1,16 @@
 use crate::{
     error::AppError,
     governance::{Policy, PolicyHit, PolicyHitInsert, PolicyUpsert},
+    model_router::{
+        AccountAccess, AccountStatus, AliasTarget, CatalogDefinitions, CatalogEntry,
+        GatewaySwitches, HealthSample, LimitOverride, ModelKind, Switch, SwitchKind,
+    },
 };
-use chrono::Utc;
+use chrono::{DateTime, Utc};
 use serde::Serialize;
 use sqlx::SqlitePool;
 use sqlx::sqlite::{SqliteConnectOptions, SqliteJournalMode};
+use std::collections::BTreeMap;
 use std::path::Path;
 use std::str::FromStr;
 use uuid::Uuid;
@@ -17,13 +22,12 @@ pub struct Db {
 
 impl Db {
     pub async fn new(database_url: &str) -> Result<Self, AppError> {
-        if let Some(path) = database_url.strip_prefix("sqlite://") {
-            if !path.starts_with(":memory:") {
-                if let Some(parent) = Path::new(path).parent() {
-                    std::fs::create_dir_all(parent)
-                        .map_err(|e| AppError::Config(format!("failed to create db dir: {e}")))?;
-                }
-            }
+        if let Some(path) = database_url.strip_prefix("sqlite://")
+            && !path.starts_with(":memory:")
+            && let Some(parent) = Path::new(path).parent()
+        {
+            std::fs::create_dir_all(parent)
+                .map_err(|e| AppError::Config(format!("failed to create db dir: {e}")))?;
         }
 
         let options = SqliteConnectOptions::from_str(database_url)
@@ -48,50 +52,128 @@ impl Db {
         Ok(Self { pool })
     }
 
-    pub async fn ensure_conversation(
-        &self,
-        id: Uuid,
-        title: Option<&str>,
-        user_id: Option<&str>,
-    ) -> Result<(), AppError> {
-        let created_at = Utc::now().to_rfc3339();
-        let title = title.unwrap_or("Untitled");
+    #[cfg(feature = "test_support")]
+    pub(crate) fn pool(&self) -> &SqlitePool {
+        &self.pool
+    }
+
+    /// How many migrations have been applied, and the latest version.
+    pub async fn migration_status(&self) -> Result<(i64, Option<i64>), AppError> {
+        sqlx::query_as("SELECT COUNT(*), MAX(version) FROM _sqlx_migrations")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(map_db_err)
+    }
+
+    /// Folds the WAL back into the main database file and closes the pool.
+    pub async fn close(&self) {
+        if let Err(e) = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE);")
+            .execute(&self.pool)
+            .await
+        {
+            tracing::warn!("wal checkpoint failed: {e}");
+        }
+        self.pool.close().await;
+    }
+
+    /// Persists one chat exchange (conversation row, messages, policy hits and the
+    /// daily usage rollup) in a single transaction, so the hot path takes the write
+    /// lock once per request.
+    pub async fn record_exchange(&self, exchange: ExchangeInsert) -> Result<(), AppError> {
+        let now = Utc::now();
+        let created_at = now.to_rfc3339();
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+
         sqlx::query(
             r#"INSERT OR IGNORE INTO conversations (id, title, user_id, created_at)
                VALUES (?1, ?2, ?3, ?4)"#,
         )
-        .bind(id.to_string())
-        .bind(title)
-        .bind(user_id)
-        .bind(created_at)
-        .execute(&self.pool)
+        .bind(exchange.conversation_id.to_string())
+        .bind(exchange.title.as_deref().unwrap_or("Untitled"))
+        .bind(exchange.user_id.as_deref())
+        .bind(&created_at)
+        .execute(&mut *tx)
         .await
         .map_err(map_db_err)?;
-        Ok(())
+
+        let requests = exchange.requests;
+        let mut tokens_input = 0i64;
+        let mut tokens_output = 0i64;
+        for msg in exchange.messages {
+            tokens_input += msg.tokens_input.unwrap_or(0) as i64;
+            tokens_output += msg.tokens_output.unwrap_or(0) as i64;
+            sqlx::query(
+                r#"INSERT INTO messages
+                   (id, conversation_id, role, content, provider, model, tokens_input, tokens_output, created_at, user_id, routing_trace, pii_redacted, language, english_content)
+                   VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14)"#,
+            )
+            .bind(msg.id.unwrap_or_else(Uuid::new_v4).to_string())
+            .bind(msg.conversation_id.to_string())
+            .bind(msg.role)
+            .bind(msg.content)
+            .bind(msg.provider)
+            .bind(msg.model)
+            .bind(msg.tokens_input.map(|v| v as i64))
+            .bind(msg.tokens_output.map(|v| v as i64))
+            .bind(&created_at)
+            .bind(msg.user_id)
+            .bind(msg.routing_trace)
+            .bind(msg.pii_redacted)
+            .bind(msg.language)
+            .bind(msg.english_content)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        }
+
+        if let Some(sup) = exchange.supersedes {
+            sqlx::query(
+                "UPDATE messages SET superseded_by = ?1 WHERE id = ?2 AND conversation_id = ?3",
+            )
+            .bind(sup.superseded_by.to_string())
+            .bind(sup.message_id)
+            .bind(exchange.conversation_id.to_string())
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        }
+
+        for hit in exchange.policy_hits {
+            sqlx::query(
+                r#"
+                INSERT INTO policy_hits (id, message_id, policy_id, policy_name, action, created_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                "#,
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(hit.message_id)
+            .bind(hit.policy_id)
+            .bind(hit.policy_name)
+            .bind(hit.action)
+            .bind(&created_at)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        }
+
+        if let Some(user_id) = exchange.user_id.as_deref() {
+            add_usage_rollups(&mut tx, user_id, now, requests, tokens_input, tokens_output).await?;
+        }
+
+        tx.commit().await.map_err(map_db_err)
     }
 
-    pub async fn insert_message(&self, msg: MessageInsert) -> Result<Uuid, AppError> {
-        let created_at = Utc::now().to_rfc3339();
-        let id = msg.id.unwrap_or_else(Uuid::new_v4);
-        sqlx::query(
-            r#"INSERT INTO messages
-               (id, conversation_id, role, content, provider, model, tokens_input, tokens_output, created_at, user_id)
-               VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)"#,
-        )
-        .bind(id.to_string())
-        .bind(msg.conversation_id.to_string())
-        .bind(msg.role)
-        .bind(msg.content)
-        .bind(msg.provider)
-        .bind(msg.model)
-        .bind(msg.tokens_input.map(|v| v as i64))
-        .bind(msg.tokens_output.map(|v| v as i64))
-        .bind(created_at)
-        .bind(msg.user_id)
-        .execute(&self.pool)
-        .await
-        .map_err(map_db_err)?;
-        Ok(id)
+    /// Adds tokens spent outside the stored messages, such as on translation
+    /// or earlier agent steps, to the account's usage rollups.
+    pub async fn record_extra_usage(
+        &self,
+        user_id: &str,
+        tokens_input: i64,
+        tokens_output: i64,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        add_usage_rollups(&mut tx, user_id, Utc::now(), 0, tokens_input, tokens_output).await?;
+        tx.commit().await.map_err(map_db_err)
     }
 
     pub async fn counts(&self) -> Result<Counts, AppError> {
@@ -155,32 +237,9 @@ impl Db {
     pub async fn create_or_update_policy(&self, policy: PolicyUpsert) -> Result<Policy, AppError> {
         let id = policy.id.unwrap_or_else(Uuid::new_v4);
         let now = Utc::now().to_rfc3339();
-        sqlx::query(
-            r#"
-            INSERT INTO policies (id, name, description, match_type, pattern, action, applies_to, enabled, created_at)
-            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
-            ON CONFLICT(id) DO UPDATE SET
-                name=excluded.name,
-                description=excluded.description,
-                match_type=excluded.match_type,
-                pattern=excluded.pattern,
-                action=excluded.action,
-                applies_to=excluded.applies_to,
-                enabled=excluded.enabled
-            "#,
-        )
-        .bind(id.to_string())
-        .bind(policy.name.clone())
-        .bind(policy.description.clone())
-        .bind(policy.match_type.clone())
-        .bind(policy.pattern.clone())
-        .bind(policy.action.clone())
-        .bind(policy.applies_to.clone())
-        .bind(policy.enabled as i32)
-        .bind(now.clone())
-        .execute(&self.pool)
-        .await
-        .map_err(map_db_err)?;
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        write_policy(&mut tx, id, &policy, &now).await?;
+        tx.commit().await.map_err(map_db_err)?;
 
         Ok(Policy {
             id: id.to_string(),
@@ -195,50 +254,64 @@ impl Db {
         })
     }
 
-    pub async fn record_policy_hits(&self, hits: Vec<PolicyHitInsert>) -> Result<(), AppError> {
-        if hits.is_empty() {
-            return Ok(());
-        }
-        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
-        for hit in hits {
-            let created_at = Utc::now().to_rfc3339();
-            sqlx::query(
-                r#"
-                INSERT INTO policy_hits (id, message_id, policy_id, policy_name, action, created_at)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
-                "#,
-            )
-            .bind(Uuid::new_v4().to_string())
-            .bind(hit.message_id)
-            .bind(hit.policy_id)
-            .bind(hit.policy_name)
-            .bind(hit.action)
-            .bind(created_at)
-            .execute(&mut *tx)
-            .await
-            .map_err(map_db_err)?;
-        }
-        tx.commit().await.map_err(map_db_err)?;
-        Ok(())
+    /// Policy hits newest first, narrowed by the time range and account of
+    /// `filter` (message model/role/tag filters don't apply to hits).
+    pub async fn recent_policy_hits(
+        &self,
+        filter: &ActivityFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<PolicyHit>, AppError> {
+        let rows = sqlx::query_as::<_, PolicyHit>(
+            r#"
+            SELECT h.id, h.message_id, h.policy_id, h.policy_name, h.action, h.created_at
+            FROM policy_hits h
+            LEFT JOIN messages m ON m.id = h.message_id
+            WHERE (?1 IS NULL OR h.created_at >= ?1)
+              AND (?2 IS NULL OR h.created_at < ?2)
+              AND (?3 IS NULL OR m.user_id = ?3)
+            ORDER BY h.created_at DESC
+            LIMIT ?4 OFFSET ?5
+            "#,
+        )
+        .bind(filter.from.as_deref())
+        .bind(filter.to.as_deref())
+        .bind(filter.user_id.as_deref())
+        .bind(limit)
+        .bind(offset)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(rows)
     }
 
-    pub async fn recent_policy_hits(&self, limit: i64) -> Result<Vec<PolicyHit>, AppError> {
+    pub async fn conversation_policy_hits(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<PolicyHit>, AppError> {
         let rows = sqlx::query_as::<_, PolicyHit>(
             r#"
-            SELECT id, message_id, policy_id, policy_name, action, created_at
-            FROM policy_hits
-            ORDER BY created_at DESC
-            LIMIT ?1
+            SELECT h.id, h.message_id, h.policy_id, h.policy_name, h.action, h.created_at
+            FROM policy_hits h
+            JOIN messages m ON m.id = h.message_id
+            WHERE m.conversation_id = ?1
+            ORDER BY h.created_at
             "#,
         )
-        .bind(limit)
+        .bind(conversation_id.to_string())
         .fetch_all(&self.pool)
         .await
         .map_err(map_db_err)?;
         Ok(rows)
     }
 
-    pub async fn recent_messages(&self, limit: i64) -> Result<Vec<MessageRecord>, AppError> {
+    /// Messages newest first, matching every filter that is set.
+    pub async fn recent_messages(
+        &self,
+        filter: &ActivityFilter,
+        limit: i64,
+        offset: i64,
+    ) -> Result<Vec<MessageRecord>, AppError> {
         let rows = sqlx::query_as::<_, MessageRecord>(
             r#"
             SELECT
@@ -251,13 +324,31 @@ impl Db {
                 tokens_input,
                 tokens_output,
                 user_id,
-                created_at
+                superseded_by,
+                created_at,
+                language,
+                english_content
             FROM messages
+            WHERE (?1 IS NULL OR conversation_id IN (
+                    SELECT conversation_id FROM conversation_tags WHERE tag = ?1
+                ))
+              AND (?2 IS NULL OR created_at >= ?2)
+              AND (?3 IS NULL OR created_at < ?3)
+              AND (?4 IS NULL OR user_id = ?4)
+              AND (?5 IS NULL OR model = ?5)
+              AND (?6 IS NULL OR role = ?6)
             ORDER BY created_at DESC
-            LIMIT ?1
+            LIMIT ?7 OFFSET ?8
             "#,
         )
+        .bind(filter.tag.as_deref())
+        .bind(filter.from.as_deref())
+        .bind(filter.to.as_deref())
+        .bind(filter.user_id.as_deref())
+        .bind(filter.model.as_deref())
+        .bind(filter.role.as_deref())
         .bind(limit)
+        .bind(offset)
         .fetch_all(&self.pool)
         .await
         .map_err(map_db_err)?;
@@ -265,6 +356,36 @@ impl Db {
     }
 }
 
+/// Narrows admin activity views. Times are RFC 3339 UTC strings; `to` is
+/// exclusive.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ActivityFilter {
+    pub tag: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    #[serde(rename = "account_id")]
+    pub user_id: Option<String>,
+    pub model: Option<String>,
+    pub role: Option<String>,
+}
+
+pub struct ExchangeInsert {
+    pub conversation_id: Uuid,
+    pub title: Option<String>,
+    pub user_id: Option<String>,
+    /// Provider calls this exchange counts against the account's quota.
+    pub requests: i64,
+    pub messages: Vec<MessageInsert>,
+    pub policy_hits: Vec<PolicyHitInsert>,
+    pub supersedes: Option<Supersession>,
+}
+
+/// Marks an earlier assistant message as replaced by one written in the same exchange.
+pub struct Supersession {
+    pub message_id: String,
+    pub superseded_by: Uuid,
+}
+
 pub struct MessageInsert {
     pub id: Option<Uuid>,
     pub conversation_id: Uuid,
@@ -275,6 +396,13 @@ pub struct MessageInsert {
     pub tokens_input: Option<u32>,
     pub tokens_output: Option<u32>,
     pub user_id: Option<String>,
+    /// JSON-encoded routing trace for assistant replies.
+    pub routing_trace: Option<String>,
+    pub pii_redacted: bool,
+    /// Language of `content` when the turn went through inline translation.
+    pub language: Option<String>,
+    /// The English text the routed model read or wrote for a translated turn.
+    pub english_content: Option<String>,
 }
 
 #[derive(Debug, Serialize, sqlx::FromRow)]
@@ -288,7 +416,12 @@ pub struct MessageRecord {
     pub tokens_input: Option<i64>,
     pub tokens_output: Option<i64>,
     pub user_id: Option<String>,
+    pub superseded_by: Option<String>,
     pub created_at: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub english_content: Option<String>,
 }
 
 #[derive(Debug, Serialize)]
@@ -305,8 +438,10 @@ pub struct ModelUsage {
     pub count: i64,
 }
 
-#[derive(Debug, Serialize, sqlx::FromRow)]
-pub struct UsageStats {
+#[derive(Debug, sqlx::FromRow)]
+pub struct HourlyUsage {
+    pub user_id: String,
+    pub hour: String,
     pub requests: i64,
     pub tokens_input: i64,
     pub tokens_output: i64,
@@ -317,27 +452,2706 @@ fn map_db_err(e: sqlx::Error) -> AppError {
 }
 
 impl Db {
-    pub async fn usage_since(
+    /// Hourly totals since `since_hour` (formatted `%Y-%m-%dT%H`), for rebuilding
+    /// the in-memory usage windows.
+    pub async fn hourly_usage_since(&self, since_hour: &str) -> Result<Vec<HourlyUsage>, AppError> {
+        let rows = sqlx::query_as::<_, HourlyUsage>(
+            r#"
+            SELECT user_id, hour, requests, tokens_input, tokens_output
+            FROM usage_rollups_hourly
+            WHERE hour >= ?1
+            "#,
+        )
+        .bind(since_hour)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(rows)
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ConversationRecord {
+    pub id: String,
+    pub title: Option<String>,
+    pub user_id: Option<String>,
+    pub created_at: String,
+}
+
+impl Db {
+    pub async fn conversation(
         &self,
-        user_id: &str,
-        since_iso: &str,
-    ) -> Result<UsageStats, AppError> {
-        let row = sqlx::query_as::<_, UsageStats>(
+        conversation_id: Uuid,
+    ) -> Result<Option<ConversationRecord>, AppError> {
+        sqlx::query_as::<_, ConversationRecord>(
+            "SELECT id, title, user_id, created_at FROM conversations WHERE id = ?1",
+        )
+        .bind(conversation_id.to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    /// All messages of a conversation, oldest first. Messages written by the same
+    /// exchange share a timestamp, so insertion order breaks the tie.
+    pub async fn conversation_messages(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<MessageRecord>, AppError> {
+        sqlx::query_as::<_, MessageRecord>(
             r#"
             SELECT
-                COUNT(*) as requests,
-                COALESCE(SUM(tokens_input), 0) as tokens_input,
-                COALESCE(SUM(tokens_output), 0) as tokens_output
+                id,
+                conversation_id,
+                role,
+                content,
+                provider,
+                model,
+                tokens_input,
+                tokens_output,
+                user_id,
+                superseded_by,
+                created_at,
+                language,
+                english_content
             FROM messages
-            WHERE user_id = ?1
-              AND created_at >= ?2
+            WHERE conversation_id = ?1
+            ORDER BY created_at, rowid
+            "#,
+        )
+        .bind(conversation_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct PurgeSummary {
+    pub conversations: u64,
+    pub messages: u64,
+}
+
+/// Rows deleted for a user by `purge_user_data` and `erase_user_data`.
+#[derive(Debug, Default, Serialize)]
+pub struct ErasedContent {
+    pub conversations: u64,
+    pub messages: u64,
+    pub policy_hits: u64,
+    pub feedback: u64,
+    pub tags: u64,
+    pub drafts: u64,
+    pub summaries: u64,
+    pub limit_rejections: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErasureCounts {
+    pub removed: ErasedContent,
+    /// Notifications rewritten to the pseudonym.
+    pub notifications: u64,
+    /// Usage rollup rows kept under the account, which quotas and account
+    /// usage are computed from.
+    pub usage_days: u64,
+    pub usage_hours: u64,
+}
+
+/// Deletes a user's conversations (with their messages, policy hits, tags,
+/// drafts and summaries), their messages and feedback elsewhere, and their
+/// limit rejections.
+async fn delete_user_content(
+    tx: &mut SqliteTx<'_>,
+    user_id: &str,
+) -> Result<ErasedContent, AppError> {
+    let owned_messages = "SELECT id FROM messages WHERE user_id = ?1 \
+         OR conversation_id IN (SELECT id FROM conversations WHERE user_id = ?1)";
+    let owned_conversations = "SELECT id FROM conversations WHERE user_id = ?1";
+    let mut counts = Vec::new();
+    for sql in [
+        format!("DELETE FROM policy_hits WHERE message_id IN ({owned_messages})"),
+        format!(
+            "DELETE FROM message_feedback WHERE message_id IN ({owned_messages}) OR user_id = ?1"
+        ),
+        format!("DELETE FROM conversation_tags WHERE conversation_id IN ({owned_conversations})"),
+        format!(
+            "DELETE FROM conversation_drafts WHERE conversation_id IN ({owned_conversations}) OR user_id = ?1"
+        ),
+        format!(
+            "DELETE FROM conversation_summaries WHERE conversation_id IN ({owned_conversations})"
+        ),
+        format!("DELETE FROM messages WHERE id IN ({owned_messages})"),
+        "DELETE FROM conversations WHERE user_id = ?1".to_string(),
+        "DELETE FROM limit_rejections WHERE user_id = ?1".to_string(),
+    ] {
+        let affected = sqlx::query(&sql)
+            .bind(user_id)
+            .execute(&mut **tx)
+            .await
+            .map_err(map_db_err)?
+            .rows_affected();
+        counts.push(affected);
+    }
+    Ok(ErasedContent {
+        policy_hits: counts[0],
+        feedback: counts[1],
+        tags: counts[2],
+        drafts: counts[3],
+        summaries: counts[4],
+        messages: counts[5],
+        conversations: counts[6],
+        limit_rejections: counts[7],
+    })
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ConversationDraft {
+    pub conversation_id: String,
+    pub content: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ConversationListRow {
+    pub id: String,
+    pub title: Option<String>,
+    pub created_at: String,
+    pub pinned: bool,
+    pub starred: bool,
+    pub sort_order: Option<i64>,
+    /// Comma-joined; tags are validated to never contain commas.
+    pub tags: Option<String>,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct TagCount {
+    pub tag: String,
+    pub conversations: i64,
+}
+
+impl Db {
+    /// The caller's conversations, newest first, optionally only those tagged `tag`.
+    pub async fn list_conversations(
+        &self,
+        user_id: Option<&str>,
+        tag: Option<&str>,
+        starred_only: bool,
+        limit: i64,
+    ) -> Result<Vec<ConversationListRow>, AppError> {
+        let rows = sqlx::query_as::<_, ConversationListRow>(
+            r#"
+            SELECT c.id, c.title, c.created_at, c.pinned, c.starred, c.sort_order,
+                   GROUP_CONCAT(t.tag, ',') as tags
+            FROM conversations c
+            LEFT JOIN conversation_tags t ON t.conversation_id = c.id
+            WHERE c.user_id IS ?1
+              AND (?2 IS NULL OR EXISTS (
+                  SELECT 1 FROM conversation_tags x WHERE x.conversation_id = c.id AND x.tag = ?2
+              ))
+              AND (?3 = 0 OR c.starred = 1)
+            GROUP BY c.id
+            ORDER BY c.pinned DESC, c.sort_order IS NULL, c.sort_order, c.created_at DESC
+            LIMIT ?4
             "#,
         )
         .bind(user_id)
-        .bind(since_iso)
-        .fetch_one(&self.pool)
+        .bind(tag)
+        .bind(starred_only)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(rows)
+    }
+
+    pub async fn conversation_list_row(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Option<ConversationListRow>, AppError> {
+        sqlx::query_as::<_, ConversationListRow>(
+            r#"
+            SELECT c.id, c.title, c.created_at, c.pinned, c.starred, c.sort_order,
+                   GROUP_CONCAT(t.tag, ',') as tags
+            FROM conversations c
+            LEFT JOIN conversation_tags t ON t.conversation_id = c.id
+            WHERE c.id = ?1
+            GROUP BY c.id
+            "#,
+        )
+        .bind(conversation_id.to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn conversation_draft(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Option<ConversationDraft>, AppError> {
+        sqlx::query_as::<_, ConversationDraft>(
+            "SELECT conversation_id, content, updated_at FROM conversation_drafts WHERE conversation_id = ?1",
+        )
+        .bind(conversation_id.to_string())
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn save_conversation_draft(
+        &self,
+        draft: &ConversationDraft,
+        user_id: Option<&str>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO conversation_drafts (conversation_id, user_id, content, updated_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(conversation_id) DO UPDATE SET
+                user_id = excluded.user_id,
+                content = excluded.content,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&draft.conversation_id)
+        .bind(user_id)
+        .bind(&draft.content)
+        .bind(&draft.updated_at)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(())
+    }
+
+    pub async fn delete_conversation_draft(&self, conversation_id: Uuid) -> Result<bool, AppError> {
+        let deleted = sqlx::query("DELETE FROM conversation_drafts WHERE conversation_id = ?1")
+            .bind(conversation_id.to_string())
+            .execute(&self.pool)
+            .await
+            .map_err(map_db_err)?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    /// Updates whichever of the flags are given, leaving the others untouched.
+    pub async fn set_conversation_flags(
+        &self,
+        conversation_id: Uuid,
+        pinned: Option<bool>,
+        starred: Option<bool>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            UPDATE conversations
+            SET pinned = COALESCE(?2, pinned), starred = COALESCE(?3, starred)
+            WHERE id = ?1
+            "#,
+        )
+        .bind(conversation_id.to_string())
+        .bind(pinned)
+        .bind(starred)
+        .execute(&self.pool)
         .await
         .map_err(map_db_err)?;
-        Ok(row)
+        Ok(())
+    }
+
+    /// Gives the listed conversations positions 0.. in the given order and clears
+    /// the position of the user's other conversations.
+    pub async fn reorder_conversations(
+        &self,
+        user_id: Option<&str>,
+        ids: &[Uuid],
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        sqlx::query("UPDATE conversations SET sort_order = NULL WHERE user_id IS ?1")
+            .bind(user_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        for (position, id) in ids.iter().enumerate() {
+            sqlx::query("UPDATE conversations SET sort_order = ?2 WHERE id = ?1 AND user_id IS ?3")
+                .bind(id.to_string())
+                .bind(position as i64)
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(map_db_err)?;
+        }
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    pub async fn conversation_tags(&self, conversation_id: Uuid) -> Result<Vec<String>, AppError> {
+        sqlx::query_scalar::<_, String>(
+            "SELECT tag FROM conversation_tags WHERE conversation_id = ?1 ORDER BY tag",
+        )
+        .bind(conversation_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn add_conversation_tags(
+        &self,
+        conversation_id: Uuid,
+        tags: &[String],
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        write_tags(&mut tx, conversation_id, tags).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    pub async fn replace_conversation_tags(
+        &self,
+        conversation_id: Uuid,
+        tags: &[String],
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        sqlx::query("DELETE FROM conversation_tags WHERE conversation_id = ?1")
+            .bind(conversation_id.to_string())
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        write_tags(&mut tx, conversation_id, tags).await?;
+        tx.commit().await.map_err(map_db_err)
     }
+
+    pub async fn remove_conversation_tag(
+        &self,
+        conversation_id: Uuid,
+        tag: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query("DELETE FROM conversation_tags WHERE conversation_id = ?1 AND tag = ?2")
+            .bind(conversation_id.to_string())
+            .bind(tag)
+            .execute(&self.pool)
+            .await
+            .map_err(map_db_err)?;
+        Ok(())
+    }
+
+    pub async fn tag_counts(&self) -> Result<Vec<TagCount>, AppError> {
+        sqlx::query_as::<_, TagCount>(
+            r#"
+            SELECT tag, COUNT(*) as conversations
+            FROM conversation_tags
+            GROUP BY tag
+            ORDER BY conversations DESC, tag
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+}
+
+async fn write_tags(
+    tx: &mut SqliteTx<'_>,
+    conversation_id: Uuid,
+    tags: &[String],
+) -> Result<(), AppError> {
+    let now = Utc::now().to_rfc3339();
+    for tag in tags {
+        sqlx::query(
+            "INSERT OR IGNORE INTO conversation_tags (conversation_id, tag, created_at) VALUES (?1, ?2, ?3)",
+        )
+        .bind(conversation_id.to_string())
+        .bind(tag)
+        .bind(&now)
+        .execute(&mut **tx)
+        .await
+        .map_err(map_db_err)?;
+    }
+    Ok(())
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct MessageFeedback {
+    pub message_id: String,
+    pub user_id: Option<String>,
+    pub rating: i64,
+    pub category: Option<String>,
+    pub comment: Option<String>,
+    pub created_at: String,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct ModelFeedback {
+    pub provider: String,
+    pub model: String,
+    pub ratings: i64,
+    pub average_rating: f64,
+    /// Ratings of 2 or below.
+    pub negative: i64,
+}
+
+impl Db {
+    pub async fn message(&self, message_id: &str) -> Result<Option<MessageRecord>, AppError> {
+        sqlx::query_as::<_, MessageRecord>(
+            r#"
+            SELECT
+                id,
+                conversation_id,
+                role,
+                content,
+                provider,
+                model,
+                tokens_input,
+                tokens_output,
+                user_id,
+                superseded_by,
+                created_at,
+                language,
+                english_content
+            FROM messages
+            WHERE id = ?1
+            "#,
+        )
+        .bind(message_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    /// Stores feedback on a message; submitting again replaces the earlier rating.
+    pub async fn save_feedback(&self, feedback: &MessageFeedback) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO message_feedback (message_id, user_id, rating, category, comment, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(message_id) DO UPDATE SET
+                user_id=excluded.user_id,
+                rating=excluded.rating,
+                category=excluded.category,
+                comment=excluded.comment,
+                created_at=excluded.created_at
+            "#,
+        )
+        .bind(&feedback.message_id)
+        .bind(feedback.user_id.as_deref())
+        .bind(feedback.rating)
+        .bind(feedback.category.as_deref())
+        .bind(feedback.comment.as_deref())
+        .bind(&feedback.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(())
+    }
+
+    pub async fn feedback_by_model(&self) -> Result<Vec<ModelFeedback>, AppError> {
+        let rows = sqlx::query_as::<_, ModelFeedback>(
+            r#"
+            SELECT
+                COALESCE(m.provider, 'unknown') as provider,
+                COALESCE(m.model, 'unknown') as model,
+                COUNT(*) as ratings,
+                AVG(f.rating) as average_rating,
+                SUM(CASE WHEN f.rating <= 2 THEN 1 ELSE 0 END) as negative
+            FROM message_feedback f
+            JOIN messages m ON m.id = f.message_id
+            GROUP BY provider, model
+            ORDER BY ratings DESC
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(rows)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ConversationSummary {
+    pub summary: String,
+    pub messages_covered: i64,
+}
+
+impl Db {
+    pub async fn conversation_summary(
+        &self,
+        conversation_id: Uuid,
+        user_id: Option<&str>,
+    ) -> Result<Option<ConversationSummary>, AppError> {
+        sqlx::query_as::<_, ConversationSummary>(
+            r#"
+            SELECT summary, messages_covered FROM conversation_summaries
+            WHERE conversation_id = ?1 AND user_id IS ?2
+            "#,
+        )
+        .bind(conversation_id.to_string())
+        .bind(user_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    /// Stores the summary for `user_id`'s conversation. A summary kept for
+    /// another owner is left alone.
+    pub async fn save_conversation_summary(
+        &self,
+        conversation_id: Uuid,
+        user_id: Option<&str>,
+        summary: &str,
+        messages_covered: i64,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO conversation_summaries
+                (conversation_id, user_id, summary, messages_covered, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT(conversation_id) DO UPDATE SET
+                summary=excluded.summary,
+                messages_covered=excluded.messages_covered,
+                updated_at=excluded.updated_at
+            WHERE conversation_summaries.user_id IS excluded.user_id
+            "#,
+        )
+        .bind(conversation_id.to_string())
+        .bind(user_id)
+        .bind(summary)
+        .bind(messages_covered)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(())
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct AccountRow {
+    id: String,
+    email: String,
+    display_name: String,
+    allowed_models: String,
+    status: String,
+    default_model: Option<String>,
+    max_cost_cents: Option<i64>,
+    guardrail_prompt: Option<String>,
+    req_per_day: Option<i64>,
+    tokens_per_day: Option<i64>,
+    model_price_caps: String,
+    data_residency: Option<String>,
+    allowed_origins: String,
+    inline_translation: bool,
+}
+
+impl AccountRow {
+    fn into_account(self) -> AccountAccess {
+        AccountAccess {
+            id: self.id,
+            email: self.email,
+            display_name: self.display_name,
+            allowed_models: serde_json::from_str(&self.allowed_models).unwrap_or_default(),
+            status: if self.status == "suspended" {
+                AccountStatus::Suspended
+            } else {
+                AccountStatus::Active
+            },
+            default_model: self.default_model,
+            max_cost_cents: self.max_cost_cents.map(|v| v as u32),
+            guardrail_prompt: self.guardrail_prompt,
+            req_per_day: self.req_per_day.map(|v| v as u32),
+            tokens_per_day: self.tokens_per_day.map(|v| v as u32),
+            model_price_caps: serde_json::from_str(&self.model_price_caps).unwrap_or_default(),
+            data_residency: self.data_residency,
+            allowed_origins: serde_json::from_str(&self.allowed_origins).unwrap_or_default(),
+            inline_translation: self.inline_translation,
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct CatalogModelRow {
+    key: String,
+    provider: String,
+    model_id: String,
+    prompt_price_per_1k: f64,
+    completion_price_per_1k: f64,
+    context_window: Option<i64>,
+    kind: String,
+    region: Option<String>,
+}
+
+#[derive(sqlx::FromRow)]
+struct JsonListRow {
+    name: String,
+    value: String,
+}
+
+type SqliteTx<'a> = sqlx::Transaction<'a, sqlx::Sqlite>;
+
+/// Router state (accounts and catalog) shared by every replica. Each write bumps
+/// `router_state.version` so other replicas know to reload.
+impl Db {
+    pub async fn router_state_version(&self) -> Result<i64, AppError> {
+        sqlx::query_scalar::<_, i64>("SELECT version FROM router_state WHERE id = 1")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(map_db_err)
+    }
+
+    pub async fn load_accounts(&self) -> Result<Vec<AccountAccess>, AppError> {
+        let rows = sqlx::query_as::<_, AccountRow>(
+            r#"
+            SELECT id, email, display_name, allowed_models, status, default_model, max_cost_cents,
+                   guardrail_prompt, req_per_day, tokens_per_day, model_price_caps, data_residency,
+                   allowed_origins, inline_translation
+            FROM accounts
+            ORDER BY id
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(rows.into_iter().map(AccountRow::into_account).collect())
+    }
+
+    pub async fn load_catalog(&self) -> Result<CatalogDefinitions, AppError> {
+        let models = sqlx::query_as::<_, CatalogModelRow>(
+            "SELECT key, provider, model_id, prompt_price_per_1k, completion_price_per_1k, context_window, kind, region FROM catalog_models",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        let aliases = sqlx::query_as::<_, JsonListRow>(
+            "SELECT alias as name, targets as value FROM model_aliases",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        let fallbacks = sqlx::query_as::<_, JsonListRow>(
+            "SELECT model as name, chain as value FROM model_fallbacks",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+
+        Ok(CatalogDefinitions {
+            models: models
+                .into_iter()
+                .map(|m| {
+                    (
+                        m.key,
+                        CatalogEntry {
+                            provider: m.provider,
+                            id: m.model_id,
+                            prompt_price_per_1k: m.prompt_price_per_1k,
+                            completion_price_per_1k: m.completion_price_per_1k,
+                            context_window: m.context_window.map(|w| w as u32),
+                            kind: ModelKind::parse(&m.kind),
+                            region: m.region,
+                        },
+                    )
+                })
+                .collect(),
+            aliases: aliases
+                .into_iter()
+                .map(|a| (a.name, serde_json::from_str(&a.value).unwrap_or_default()))
+                .collect(),
+            fallbacks: fallbacks
+                .into_iter()
+                .map(|f| (f.name, serde_json::from_str(&f.value).unwrap_or_default()))
+                .collect(),
+        })
+    }
+
+    /// Writes the given accounts and catalog in one transaction. Used to seed an
+    /// empty database on first boot.
+    pub async fn seed_router_state(
+        &self,
+        accounts: &[AccountAccess],
+        catalog: &CatalogDefinitions,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        for account in accounts {
+            write_account(&mut tx, account).await?;
+        }
+        for (key, entry) in &catalog.models {
+            write_catalog_model(&mut tx, key, entry).await?;
+        }
+        for (alias, targets) in &catalog.aliases {
+            write_alias(&mut tx, alias, targets).await?;
+        }
+        for (model, chain) in &catalog.fallbacks {
+            write_fallbacks(&mut tx, model, chain).await?;
+        }
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    pub async fn save_account(&self, account: &AccountAccess) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        write_account(&mut tx, account).await?;
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    pub async fn delete_account(&self, id: &str) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        for sql in [
+            "DELETE FROM accounts WHERE id = ?1",
+            "DELETE FROM quota_overrides WHERE account_id = ?1",
+            "DELETE FROM quota_resets WHERE account_id = ?1",
+            "DELETE FROM provider_keys WHERE account_id = ?1",
+        ] {
+            sqlx::query(sql)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(map_db_err)?;
+        }
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    /// Deletes everything stored for a user: their conversations with all
+    /// dependent rows, any stray messages, and usage rollups.
+    pub async fn purge_user_data(&self, user_id: &str) -> Result<PurgeSummary, AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        let content = delete_user_content(&mut tx, user_id).await?;
+        for sql in [
+            "DELETE FROM usage_rollups WHERE user_id = ?1",
+            "DELETE FROM usage_rollups_hourly WHERE user_id = ?1",
+        ] {
+            sqlx::query(sql)
+                .bind(user_id)
+                .execute(&mut *tx)
+                .await
+                .map_err(map_db_err)?;
+        }
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(PurgeSummary {
+            conversations: content.conversations,
+            messages: content.messages,
+        })
+    }
+
+    /// Deletes everything a user wrote or that records what they did, like
+    /// `purge_user_data`, and rewrites notifications naming the account to
+    /// `pseudonym`. Usage rollups stay under the account id: they hold no
+    /// content, and the daily quota and account usage are computed from them.
+    pub async fn erase_user_data(
+        &self,
+        user_id: &str,
+        pseudonym: &str,
+    ) -> Result<ErasureCounts, AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        let removed = delete_user_content(&mut tx, user_id).await?;
+        let notifications = sqlx::query(
+            r#"
+            UPDATE notifications SET
+                title = replace(title, ?1, ?2),
+                detail = replace(detail, ?1, ?2),
+                dedup_key = replace(dedup_key, ?1, ?2)
+            WHERE dedup_key = 'abuse:' || ?1 OR dedup_key LIKE 'budget:' || ?1 || ':%'
+            "#,
+        )
+        .bind(user_id)
+        .bind(pseudonym)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_db_err)?
+        .rows_affected();
+        let mut retained = Vec::new();
+        for sql in [
+            "SELECT COUNT(*) FROM usage_rollups WHERE user_id = ?1",
+            "SELECT COUNT(*) FROM usage_rollups_hourly WHERE user_id = ?1",
+        ] {
+            let (count,): (i64,) = sqlx::query_as(sql)
+                .bind(user_id)
+                .fetch_one(&mut *tx)
+                .await
+                .map_err(map_db_err)?;
+            retained.push(count as u64);
+        }
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(ErasureCounts {
+            removed,
+            notifications,
+            usage_days: retained[0],
+            usage_hours: retained[1],
+        })
+    }
+
+    pub async fn save_catalog_model(
+        &self,
+        key: &str,
+        entry: &CatalogEntry,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        write_catalog_model(&mut tx, key, entry).await?;
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    pub async fn save_alias(&self, alias: &str, targets: &[AliasTarget]) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        write_alias(&mut tx, alias, targets).await?;
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    /// Deletes a catalog model and its fallback chain, and rewrites the aliases
+    /// and chains that referenced it (removing those left empty).
+    pub async fn delete_catalog_model(
+        &self,
+        key: &str,
+        model_id: &str,
+        aliases: &BTreeMap<String, Vec<AliasTarget>>,
+        fallbacks: &BTreeMap<String, Vec<String>>,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        delete_row(&mut tx, "DELETE FROM catalog_models WHERE key = ?1", key).await?;
+        for model in [key, model_id] {
+            delete_row(
+                &mut tx,
+                "DELETE FROM model_fallbacks WHERE model = ?1",
+                model,
+            )
+            .await?;
+        }
+        for (alias, targets) in aliases {
+            if targets.is_empty() {
+                delete_row(&mut tx, "DELETE FROM model_aliases WHERE alias = ?1", alias).await?;
+            } else {
+                write_alias(&mut tx, alias, targets).await?;
+            }
+        }
+        for (model, chain) in fallbacks {
+            if chain.is_empty() {
+                delete_row(
+                    &mut tx,
+                    "DELETE FROM model_fallbacks WHERE model = ?1",
+                    model,
+                )
+                .await?;
+            } else {
+                write_fallbacks(&mut tx, model, chain).await?;
+            }
+        }
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    pub async fn delete_alias(&self, alias: &str) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        let deleted =
+            delete_row(&mut tx, "DELETE FROM model_aliases WHERE alias = ?1", alias).await?;
+        if deleted {
+            bump_router_version(&mut tx).await?;
+        }
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(deleted)
+    }
+
+    pub async fn delete_fallbacks(&self, model: &str) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        let deleted = delete_row(
+            &mut tx,
+            "DELETE FROM model_fallbacks WHERE model = ?1",
+            model,
+        )
+        .await?;
+        if deleted {
+            bump_router_version(&mut tx).await?;
+        }
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(deleted)
+    }
+
+    pub async fn save_fallbacks(&self, model: &str, chain: &[String]) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        write_fallbacks(&mut tx, model, chain).await?;
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+}
+
+async fn bump_router_version(tx: &mut SqliteTx<'_>) -> Result<(), AppError> {
+    sqlx::query("UPDATE router_state SET version = version + 1 WHERE id = 1")
+        .execute(&mut **tx)
+        .await
+        .map_err(map_db_err)?;
+    Ok(())
+}
+
+async fn write_policy(
+    tx: &mut SqliteTx<'_>,
+    id: Uuid,
+    policy: &PolicyUpsert,
+    now: &str,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO policies (id, name, description, match_type, pattern, action, applies_to, enabled, created_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+        ON CONFLICT(id) DO UPDATE SET
+            name=excluded.name,
+            description=excluded.description,
+            match_type=excluded.match_type,
+            pattern=excluded.pattern,
+            action=excluded.action,
+            applies_to=excluded.applies_to,
+            enabled=excluded.enabled
+        "#,
+    )
+    .bind(id.to_string())
+    .bind(&policy.name)
+    .bind(&policy.description)
+    .bind(&policy.match_type)
+    .bind(&policy.pattern)
+    .bind(&policy.action)
+    .bind(&policy.applies_to)
+    .bind(policy.enabled as i32)
+    .bind(now)
+    .execute(&mut **tx)
+    .await
+    .map_err(map_db_err)?;
+    Ok(())
+}
+
+/// Adds to the account's daily and hourly usage rollups.
+async fn add_usage_rollups(
+    tx: &mut SqliteTx<'_>,
+    user_id: &str,
+    now: chrono::DateTime<Utc>,
+    requests: i64,
+    tokens_input: i64,
+    tokens_output: i64,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO usage_rollups (user_id, day, requests, tokens_input, tokens_output)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT(user_id, day) DO UPDATE SET
+            requests = requests + excluded.requests,
+            tokens_input = tokens_input + excluded.tokens_input,
+            tokens_output = tokens_output + excluded.tokens_output
+        "#,
+    )
+    .bind(user_id)
+    .bind(now.format("%Y-%m-%d").to_string())
+    .bind(requests)
+    .bind(tokens_input)
+    .bind(tokens_output)
+    .execute(&mut **tx)
+    .await
+    .map_err(map_db_err)?;
+    sqlx::query(
+        r#"
+        INSERT INTO usage_rollups_hourly (user_id, hour, requests, tokens_input, tokens_output)
+        VALUES (?1, ?2, ?3, ?4, ?5)
+        ON CONFLICT(user_id, hour) DO UPDATE SET
+            requests = requests + excluded.requests,
+            tokens_input = tokens_input + excluded.tokens_input,
+            tokens_output = tokens_output + excluded.tokens_output
+        "#,
+    )
+    .bind(user_id)
+    .bind(now.format("%Y-%m-%dT%H").to_string())
+    .bind(requests)
+    .bind(tokens_input)
+    .bind(tokens_output)
+    .execute(&mut **tx)
+    .await
+    .map_err(map_db_err)?;
+    Ok(())
+}
+
+async fn delete_row(tx: &mut SqliteTx<'_>, sql: &str, key: &str) -> Result<bool, AppError> {
+    let result = sqlx::query(sql)
+        .bind(key)
+        .execute(&mut **tx)
+        .await
+        .map_err(map_db_err)?;
+    Ok(result.rows_affected() > 0)
+}
+
+async fn write_account(tx: &mut SqliteTx<'_>, account: &AccountAccess) -> Result<(), AppError> {
+    let status = match account.status {
+        AccountStatus::Active => "active",
+        AccountStatus::Suspended => "suspended",
+    };
+    sqlx::query(
+        r#"
+        INSERT INTO accounts
+            (id, email, display_name, allowed_models, status, default_model, max_cost_cents,
+             guardrail_prompt, req_per_day, tokens_per_day, model_price_caps, data_residency,
+             allowed_origins, inline_translation, updated_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+        ON CONFLICT(id) DO UPDATE SET
+            email=excluded.email,
+            display_name=excluded.display_name,
+            allowed_models=excluded.allowed_models,
+            status=excluded.status,
+            default_model=excluded.default_model,
+            max_cost_cents=excluded.max_cost_cents,
+            guardrail_prompt=excluded.guardrail_prompt,
+            req_per_day=excluded.req_per_day,
+            tokens_per_day=excluded.tokens_per_day,
+            model_price_caps=excluded.model_price_caps,
+            data_residency=excluded.data_residency,
+            allowed_origins=excluded.allowed_origins,
+            inline_translation=excluded.inline_translation,
+            updated_at=excluded.updated_at
+        "#,
+    )
+    .bind(&account.id)
+    .bind(&account.email)
+    .bind(&account.display_name)
+    .bind(serde_json::to_string(&account.allowed_models).unwrap_or_else(|_| "[]".into()))
+    .bind(status)
+    .bind(&account.default_model)
+    .bind(account.max_cost_cents.map(|v| v as i64))
+    .bind(&account.guardrail_prompt)
+    .bind(account.req_per_day.map(|v| v as i64))
+    .bind(account.tokens_per_day.map(|v| v as i64))
+    .bind(serde_json::to_string(&account.model_price_caps).unwrap_or_else(|_| "[]".into()))
+    .bind(&account.data_residency)
+    .bind(serde_json::to_string(&account.allowed_origins).unwrap_or_else(|_| "[]".into()))
+    .bind(account.inline_translation)
+    .bind(Utc::now().to_rfc3339())
+    .execute(&mut **tx)
+    .await
+    .map_err(map_db_err)?;
+    Ok(())
+}
+
+async fn write_catalog_model(
+    tx: &mut SqliteTx<'_>,
+    key: &str,
+    entry: &CatalogEntry,
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO catalog_models (key, provider, model_id, prompt_price_per_1k, completion_price_per_1k, context_window, kind, region, updated_at)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+        ON CONFLICT(key) DO UPDATE SET
+            provider=excluded.provider,
+            model_id=excluded.model_id,
+            prompt_price_per_1k=excluded.prompt_price_per_1k,
+            completion_price_per_1k=excluded.completion_price_per_1k,
+            context_window=excluded.context_window,
+            kind=excluded.kind,
+            region=excluded.region,
+            updated_at=excluded.updated_at
+        "#,
+    )
+    .bind(key)
+    .bind(&entry.provider)
+    .bind(&entry.id)
+    .bind(entry.prompt_price_per_1k)
+    .bind(entry.completion_price_per_1k)
+    .bind(entry.context_window.map(|w| w as i64))
+    .bind(entry.kind.as_str())
+    .bind(&entry.region)
+    .bind(Utc::now().to_rfc3339())
+    .execute(&mut **tx)
+    .await
+    .map_err(map_db_err)?;
+    Ok(())
+}
+
+async fn write_alias(
+    tx: &mut SqliteTx<'_>,
+    alias: &str,
+    targets: &[AliasTarget],
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO model_aliases (alias, targets, updated_at)
+        VALUES (?1, ?2, ?3)
+        ON CONFLICT(alias) DO UPDATE SET targets=excluded.targets, updated_at=excluded.updated_at
+        "#,
+    )
+    .bind(alias)
+    .bind(serde_json::to_string(targets).unwrap_or_else(|_| "[]".into()))
+    .bind(Utc::now().to_rfc3339())
+    .execute(&mut **tx)
+    .await
+    .map_err(map_db_err)?;
+    Ok(())
+}
+
+async fn write_fallbacks(
+    tx: &mut SqliteTx<'_>,
+    model: &str,
+    chain: &[String],
+) -> Result<(), AppError> {
+    sqlx::query(
+        r#"
+        INSERT INTO model_fallbacks (model, chain, updated_at)
+        VALUES (?1, ?2, ?3)
+        ON CONFLICT(model) DO UPDATE SET chain=excluded.chain, updated_at=excluded.updated_at
+        "#,
+    )
+    .bind(model)
+    .bind(serde_json::to_string(chain).unwrap_or_else(|_| "[]".into()))
+    .bind(Utc::now().to_rfc3339())
+    .execute(&mut **tx)
+    .await
+    .map_err(map_db_err)?;
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct CollectionRecord {
+    pub id: String,
+    pub description: Option<String>,
+    pub embedding_model: String,
+    pub created_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct DocumentRecord {
+    pub id: String,
+    pub collection_id: String,
+    pub title: String,
+    pub source: Option<String>,
+    pub content_type: String,
+    pub chunk_count: i64,
+    /// `processing`, `ready` or `failed`.
+    pub status: String,
+    pub error: Option<String>,
+    pub created_at: String,
+    pub updated_at: Option<String>,
+}
+
+pub struct ChunkInsert {
+    pub ordinal: i64,
+    pub content: String,
+    pub embedding: Vec<u8>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct ChunkRecord {
+    pub id: String,
+    pub document_id: String,
+    pub document_title: String,
+    pub collection_id: String,
+    pub content: String,
+    pub embedding: Vec<u8>,
+}
+
+/// Retrieval collections, their documents and embedded chunks.
+impl Db {
+    pub async fn list_collections(&self) -> Result<Vec<CollectionRecord>, AppError> {
+        sqlx::query_as::<_, CollectionRecord>(
+            "SELECT id, description, embedding_model, created_at FROM collections ORDER BY id",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn collections_using_model(&self, model: &str) -> Result<Vec<String>, AppError> {
+        sqlx::query_scalar::<_, String>(
+            "SELECT id FROM collections WHERE embedding_model = ?1 ORDER BY id",
+        )
+        .bind(model)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn collection(&self, id: &str) -> Result<Option<CollectionRecord>, AppError> {
+        sqlx::query_as::<_, CollectionRecord>(
+            "SELECT id, description, embedding_model, created_at FROM collections WHERE id = ?1",
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn create_collection(&self, collection: &CollectionRecord) -> Result<(), AppError> {
+        let inserted = sqlx::query(
+            r#"
+            INSERT OR IGNORE INTO collections (id, description, embedding_model, created_at)
+            VALUES (?1, ?2, ?3, ?4)
+            "#,
+        )
+        .bind(&collection.id)
+        .bind(collection.description.as_deref())
+        .bind(&collection.embedding_model)
+        .bind(&collection.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        if inserted.rows_affected() == 0 {
+            return Err(AppError::BadRequest(format!(
+                "collection {} already exists",
+                collection.id
+            )));
+        }
+        Ok(())
+    }
+
+    pub async fn delete_collection(&self, id: &str) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        for sql in [
+            "DELETE FROM document_chunks WHERE collection_id = ?1",
+            "DELETE FROM documents WHERE collection_id = ?1",
+        ] {
+            sqlx::query(sql)
+                .bind(id)
+                .execute(&mut *tx)
+                .await
+                .map_err(map_db_err)?;
+        }
+        let deleted = sqlx::query("DELETE FROM collections WHERE id = ?1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    pub async fn insert_document(&self, document: &DocumentRecord) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO documents (id, collection_id, title, source, content_type, chunk_count, status, error, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+            "#,
+        )
+        .bind(&document.id)
+        .bind(&document.collection_id)
+        .bind(&document.title)
+        .bind(document.source.as_deref())
+        .bind(&document.content_type)
+        .bind(document.chunk_count)
+        .bind(&document.status)
+        .bind(document.error.as_deref())
+        .bind(&document.created_at)
+        .bind(document.updated_at.as_deref())
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(())
+    }
+
+    /// Writes all of a document's chunks and marks it ready in one transaction,
+    /// so a search never sees a half-indexed document.
+    pub async fn complete_document(
+        &self,
+        document_id: &str,
+        collection_id: &str,
+        chunks: Vec<ChunkInsert>,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        for chunk in chunks {
+            sqlx::query(
+                r#"
+                INSERT INTO document_chunks (id, document_id, collection_id, ordinal, content, embedding)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+                "#,
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(document_id)
+            .bind(collection_id)
+            .bind(chunk.ordinal)
+            .bind(chunk.content)
+            .bind(chunk.embedding)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        }
+        sqlx::query(
+            "UPDATE documents SET status = 'ready', error = NULL, updated_at = ?2 WHERE id = ?1",
+        )
+        .bind(document_id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await
+        .map_err(map_db_err)?;
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    pub async fn fail_document(&self, document_id: &str, error: &str) -> Result<(), AppError> {
+        sqlx::query(
+            "UPDATE documents SET status = 'failed', error = ?2, updated_at = ?3 WHERE id = ?1",
+        )
+        .bind(document_id)
+        .bind(error)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(())
+    }
+
+    /// Background ingests don't survive a restart; marks the ones that were cut
+    /// off as failed so clients know to resubmit.
+    pub async fn fail_interrupted_ingests(&self) -> Result<u64, AppError> {
+        let updated = sqlx::query(
+            r#"
+            UPDATE documents
+            SET status = 'failed', error = 'interrupted by server restart', updated_at = ?1
+            WHERE status = 'processing'
+            "#,
+        )
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(updated.rows_affected())
+    }
+
+    pub async fn document(
+        &self,
+        collection_id: &str,
+        document_id: &str,
+    ) -> Result<Option<DocumentRecord>, AppError> {
+        sqlx::query_as::<_, DocumentRecord>(
+            r#"
+            SELECT id, collection_id, title, source, content_type, chunk_count, status, error, created_at, updated_at
+            FROM documents
+            WHERE id = ?1 AND collection_id = ?2
+            "#,
+        )
+        .bind(document_id)
+        .bind(collection_id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn list_documents(
+        &self,
+        collection_id: &str,
+    ) -> Result<Vec<DocumentRecord>, AppError> {
+        sqlx::query_as::<_, DocumentRecord>(
+            r#"
+            SELECT id, collection_id, title, source, content_type, chunk_count, status, error, created_at, updated_at
+            FROM documents
+            WHERE collection_id = ?1
+            ORDER BY created_at DESC
+            "#,
+        )
+        .bind(collection_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn delete_document(
+        &self,
+        collection_id: &str,
+        document_id: &str,
+    ) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        sqlx::query("DELETE FROM document_chunks WHERE document_id = ?1 AND collection_id = ?2")
+            .bind(document_id)
+            .bind(collection_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        let deleted = sqlx::query("DELETE FROM documents WHERE id = ?1 AND collection_id = ?2")
+            .bind(document_id)
+            .bind(collection_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(deleted.rows_affected() > 0)
+    }
+
+    pub async fn collection_chunks(
+        &self,
+        collection_id: &str,
+    ) -> Result<Vec<ChunkRecord>, AppError> {
+        sqlx::query_as::<_, ChunkRecord>(
+            r#"
+            SELECT c.id, c.document_id, d.title as document_title, c.collection_id, c.content, c.embedding
+            FROM document_chunks c
+            JOIN documents d ON d.id = c.document_id
+            WHERE c.collection_id = ?1 AND d.status = 'ready'
+            "#,
+        )
+        .bind(collection_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+}
+
+#[derive(Debug, Default, sqlx::FromRow)]
+pub struct UsageTotals {
+    pub requests: i64,
+    pub tokens_input: i64,
+    pub tokens_output: i64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct AccountModelUsage {
+    pub provider: Option<String>,
+    pub model: Option<String>,
+    pub responses: i64,
+    pub tokens_input: i64,
+    pub tokens_output: i64,
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct NamedCount {
+    pub name: String,
+    pub count: i64,
+}
+
+/// Per-account usage reports.
+impl Db {
+    pub async fn account_usage_totals(
+        &self,
+        user_id: &str,
+        since_hour: &str,
+    ) -> Result<UsageTotals, AppError> {
+        sqlx::query_as::<_, UsageTotals>(
+            r#"
+            SELECT COALESCE(SUM(requests), 0) as requests,
+                   COALESCE(SUM(tokens_input), 0) as tokens_input,
+                   COALESCE(SUM(tokens_output), 0) as tokens_output
+            FROM usage_rollups_hourly
+            WHERE user_id = ?1 AND hour >= ?2
+            "#,
+        )
+        .bind(user_id)
+        .bind(since_hour)
+        .fetch_one(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    /// Answered requests per model, busiest first.
+    pub async fn account_model_usage(
+        &self,
+        user_id: &str,
+        since: &str,
+    ) -> Result<Vec<AccountModelUsage>, AppError> {
+        sqlx::query_as::<_, AccountModelUsage>(
+            r#"
+            SELECT provider, model, COUNT(*) as responses,
+                   COALESCE(SUM(tokens_input), 0) as tokens_input,
+                   COALESCE(SUM(tokens_output), 0) as tokens_output
+            FROM messages
+            WHERE user_id = ?1 AND role = 'assistant' AND created_at >= ?2
+            GROUP BY provider, model
+            ORDER BY responses DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn account_policy_hits(
+        &self,
+        user_id: &str,
+        since: &str,
+    ) -> Result<Vec<NamedCount>, AppError> {
+        sqlx::query_as::<_, NamedCount>(
+            r#"
+            SELECT h.policy_name as name, COUNT(*) as count
+            FROM policy_hits h
+            JOIN messages m ON m.id = h.message_id
+            WHERE m.user_id = ?1 AND h.created_at >= ?2
+            GROUP BY h.policy_name
+            ORDER BY count DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn record_limit_rejection(
+        &self,
+        user_id: Option<&str>,
+        kind: &str,
+        detail: &str,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO limit_rejections (id, user_id, kind, detail, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5)
+            "#,
+        )
+        .bind(Uuid::new_v4().to_string())
+        .bind(user_id)
+        .bind(kind)
+        .bind(detail)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(())
+    }
+
+    pub async fn account_limit_rejections(
+        &self,
+        user_id: &str,
+        since: &str,
+    ) -> Result<Vec<NamedCount>, AppError> {
+        sqlx::query_as::<_, NamedCount>(
+            r#"
+            SELECT kind as name, COUNT(*) as count
+            FROM limit_rejections
+            WHERE user_id = ?1 AND created_at >= ?2
+            GROUP BY kind
+            ORDER BY count DESC
+            "#,
+        )
+        .bind(user_id)
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub struct MessageTrace {
+    pub id: String,
+    pub routing_trace: Option<String>,
+    pub pii_redacted: bool,
+}
+
+/// Admin conversation inspector.
+impl Db {
+    /// Routing traces and redaction flags, keyed by message id.
+    pub async fn conversation_traces(
+        &self,
+        conversation_id: Uuid,
+    ) -> Result<Vec<MessageTrace>, AppError> {
+        sqlx::query_as::<_, MessageTrace>(
+            "SELECT id, routing_trace, pii_redacted FROM messages WHERE conversation_id = ?1",
+        )
+        .bind(conversation_id.to_string())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct HealthHistoryPoint {
+    pub recorded_at: String,
+    pub model: String,
+    pub provider: String,
+    pub successes: i64,
+    pub failures: i64,
+    pub success_rate: f64,
+    pub p50_ms: Option<i64>,
+    pub p95_ms: Option<i64>,
+    pub p99_ms: Option<i64>,
+}
+
+/// Router health history.
+impl Db {
+    pub async fn insert_health_samples(
+        &self,
+        recorded_at: &str,
+        samples: &[HealthSample],
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        for sample in samples {
+            sqlx::query(
+                r#"
+                INSERT INTO router_health_history
+                    (id, recorded_at, model, provider, successes, failures, success_rate, p50_ms, p95_ms, p99_ms)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                "#,
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(recorded_at)
+            .bind(&sample.model)
+            .bind(&sample.provider)
+            .bind(sample.successes as i64)
+            .bind(sample.failures as i64)
+            .bind(sample.success_rate)
+            .bind(sample.p50_ms.map(|v| v as i64))
+            .bind(sample.p95_ms.map(|v| v as i64))
+            .bind(sample.p99_ms.map(|v| v as i64))
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        }
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    pub async fn prune_health_history(&self, before: &str) -> Result<u64, AppError> {
+        let result = sqlx::query("DELETE FROM router_health_history WHERE recorded_at < ?1")
+            .bind(before)
+            .execute(&self.pool)
+            .await
+            .map_err(map_db_err)?;
+        Ok(result.rows_affected())
+    }
+
+    /// Points oldest first within `[from, to)`, optionally for one model.
+    pub async fn health_history(
+        &self,
+        model: Option<&str>,
+        from: &str,
+        to: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<HealthHistoryPoint>, AppError> {
+        sqlx::query_as::<_, HealthHistoryPoint>(
+            r#"
+            SELECT recorded_at, model, provider, successes, failures, success_rate,
+                   p50_ms, p95_ms, p99_ms
+            FROM router_health_history
+            WHERE recorded_at >= ?1
+              AND (?2 IS NULL OR recorded_at < ?2)
+              AND (?3 IS NULL OR model = ?3)
+            ORDER BY recorded_at, model
+            LIMIT ?4
+            "#,
+        )
+        .bind(from)
+        .bind(to)
+        .bind(model)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+}
+
+/// A full router state import: everything listed is upserted and the named
+/// rows are deleted, all in one transaction.
+pub struct StateWrite<'a> {
+    pub accounts: &'a [AccountAccess],
+    pub catalog: &'a CatalogDefinitions,
+    pub policies: &'a [PolicyUpsert],
+    pub delete_accounts: &'a [String],
+    pub delete_models: &'a [String],
+    pub delete_aliases: &'a [String],
+    pub delete_fallbacks: &'a [String],
+    pub delete_policies: &'a [String],
+}
+
+impl Db {
+    pub async fn apply_state(&self, write: StateWrite<'_>) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        for (sql, keys) in [
+            ("DELETE FROM accounts WHERE id = ?1", write.delete_accounts),
+            (
+                "DELETE FROM catalog_models WHERE key = ?1",
+                write.delete_models,
+            ),
+            (
+                "DELETE FROM model_aliases WHERE alias = ?1",
+                write.delete_aliases,
+            ),
+            (
+                "DELETE FROM model_fallbacks WHERE model = ?1",
+                write.delete_fallbacks,
+            ),
+            ("DELETE FROM policies WHERE id = ?1", write.delete_policies),
+        ] {
+            for key in keys {
+                delete_row(&mut tx, sql, key).await?;
+            }
+        }
+        for account in write.accounts {
+            write_account(&mut tx, account).await?;
+        }
+        for (key, entry) in &write.catalog.models {
+            write_catalog_model(&mut tx, key, entry).await?;
+        }
+        for (alias, targets) in &write.catalog.aliases {
+            write_alias(&mut tx, alias, targets).await?;
+        }
+        for (model, chain) in &write.catalog.fallbacks {
+            write_fallbacks(&mut tx, model, chain).await?;
+        }
+        let now = Utc::now().to_rfc3339();
+        for policy in write.policies {
+            let id = policy.id.unwrap_or_else(Uuid::new_v4);
+            write_policy(&mut tx, id, policy, &now).await?;
+        }
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct SwitchRow {
+    kind: String,
+    target: String,
+    reason: Option<String>,
+    created_at: String,
+}
+
+/// Maintenance mode and kill switches. Changes bump the router version so
+/// other replicas pick them up on their next sync.
+impl Db {
+    pub async fn load_switches(&self) -> Result<GatewaySwitches, AppError> {
+        let rows = sqlx::query_as::<_, SwitchRow>(
+            "SELECT kind, target, reason, created_at FROM gateway_switches",
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        let mut switches = GatewaySwitches::default();
+        for row in rows {
+            let switch = Switch {
+                reason: row.reason,
+                since: row.created_at,
+            };
+            match SwitchKind::parse(&row.kind) {
+                Some(SwitchKind::Maintenance) => switches.maintenance = Some(switch),
+                Some(SwitchKind::Provider) => {
+                    switches.providers.insert(row.target, switch);
+                }
+                Some(SwitchKind::Model) => {
+                    switches.models.insert(row.target, switch);
+                }
+                None => {}
+            }
+        }
+        Ok(switches)
+    }
+
+    /// Turns a switch on. Re-enabling an active switch only updates its reason.
+    pub async fn enable_switch(
+        &self,
+        kind: SwitchKind,
+        target: &str,
+        reason: Option<&str>,
+    ) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        sqlx::query(
+            r#"
+            INSERT INTO gateway_switches (kind, target, reason, created_at)
+            VALUES (?1, ?2, ?3, ?4)
+            ON CONFLICT(kind, target) DO UPDATE SET reason = excluded.reason
+            "#,
+        )
+        .bind(kind.as_str())
+        .bind(target)
+        .bind(reason)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&mut *tx)
+        .await
+        .map_err(map_db_err)?;
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    pub async fn clear_switch(&self, kind: SwitchKind, target: &str) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        sqlx::query("DELETE FROM gateway_switches WHERE kind = ?1 AND target = ?2")
+            .bind(kind.as_str())
+            .bind(target)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+}
+
+#[derive(Debug, Serialize, sqlx::FromRow)]
+pub struct Notification {
+    pub id: String,
+    pub kind: String,
+    pub severity: String,
+    pub title: String,
+    pub detail: String,
+    pub occurrences: i64,
+    pub created_at: String,
+    pub last_seen_at: String,
+    pub read_at: Option<String>,
+}
+
+pub struct NotificationInsert<'a> {
+    pub kind: &'a str,
+    pub severity: &'a str,
+    pub title: &'a str,
+    pub detail: &'a str,
+    pub dedup_key: &'a str,
+}
+
+/// Admin notifications.
+impl Db {
+    /// Adds a notification, or folds it into the unread one with the same
+    /// dedup key so a recurring problem shows up once with a count.
+    /// Records a notification, or bumps the unread one sharing its `dedup_key`.
+    /// Returns whether a new notification was created.
+    pub async fn record_notification(&self, n: NotificationInsert<'_>) -> Result<bool, AppError> {
+        let now = Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        let bumped = sqlx::query(
+            r#"
+            UPDATE notifications
+            SET occurrences = occurrences + 1, last_seen_at = ?1, detail = ?2,
+                severity = ?3, title = ?4
+            WHERE dedup_key = ?5 AND read_at IS NULL
+            "#,
+        )
+        .bind(&now)
+        .bind(n.detail)
+        .bind(n.severity)
+        .bind(n.title)
+        .bind(n.dedup_key)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_db_err)?
+        .rows_affected();
+        if bumped == 0 {
+            sqlx::query(
+                r#"
+                INSERT INTO notifications
+                    (id, kind, severity, title, detail, dedup_key, created_at, last_seen_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?7)
+                "#,
+            )
+            .bind(Uuid::new_v4().to_string())
+            .bind(n.kind)
+            .bind(n.severity)
+            .bind(n.title)
+            .bind(n.detail)
+            .bind(n.dedup_key)
+            .bind(&now)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        }
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(bumped == 0)
+    }
+
+    pub async fn list_notifications(
+        &self,
+        unread_only: bool,
+        kind: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<Notification>, AppError> {
+        sqlx::query_as::<_, Notification>(
+            r#"
+            SELECT id, kind, severity, title, detail, occurrences, created_at,
+                   last_seen_at, read_at
+            FROM notifications
+            WHERE (?1 = 0 OR read_at IS NULL)
+              AND (?2 IS NULL OR kind = ?2)
+            ORDER BY last_seen_at DESC
+            LIMIT ?3
+            "#,
+        )
+        .bind(unread_only)
+        .bind(kind)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn unread_notification_count(&self) -> Result<i64, AppError> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM notifications WHERE read_at IS NULL")
+            .fetch_one(&self.pool)
+            .await
+            .map_err(map_db_err)
+    }
+
+    /// Marks the given notifications (all of them when `ids` is `None`) read or
+    /// unread. Returns how many changed.
+    pub async fn mark_notifications(
+        &self,
+        ids: Option<&[String]>,
+        read: bool,
+    ) -> Result<u64, AppError> {
+        let read_at = read.then(|| Utc::now().to_rfc3339());
+        // Only touch rows whose state actually changes, so the count is honest
+        // and earlier read times are kept.
+        let state_filter = if read {
+            "read_at IS NULL"
+        } else {
+            "read_at IS NOT NULL"
+        };
+        let Some(ids) = ids else {
+            return sqlx::query(&format!(
+                "UPDATE notifications SET read_at = ?1 WHERE {state_filter}"
+            ))
+            .bind(&read_at)
+            .execute(&self.pool)
+            .await
+            .map(|r| r.rows_affected())
+            .map_err(map_db_err);
+        };
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        let mut changed = 0;
+        for id in ids {
+            changed += sqlx::query(&format!(
+                "UPDATE notifications SET read_at = ?1 WHERE id = ?2 AND {state_filter}"
+            ))
+            .bind(&read_at)
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?
+            .rows_affected();
+        }
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(changed)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+struct QuotaOverrideRow {
+    account_id: String,
+    req_per_day: Option<i64>,
+    tokens_per_day: Option<i64>,
+    expires_at: String,
+    reason: Option<String>,
+    created_at: String,
+}
+
+impl QuotaOverrideRow {
+    fn into_override(self) -> Option<LimitOverride> {
+        let parse = |ts: &str| {
+            DateTime::parse_from_rfc3339(ts)
+                .ok()
+                .map(|t| t.with_timezone(&Utc))
+        };
+        Some(LimitOverride {
+            req_per_day: self.req_per_day.map(|v| v as u32),
+            tokens_per_day: self.tokens_per_day.map(|v| v as u32),
+            expires_at: parse(&self.expires_at)?,
+            created_at: parse(&self.created_at)?,
+            account_id: self.account_id,
+            reason: self.reason,
+        })
+    }
+}
+
+/// Quota overrides and resets. Overrides are part of the router state so
+/// other replicas pick them up on their next sync.
+impl Db {
+    /// Overrides that haven't expired yet.
+    pub async fn load_quota_overrides(&self) -> Result<Vec<LimitOverride>, AppError> {
+        let rows = sqlx::query_as::<_, QuotaOverrideRow>(
+            r#"
+            SELECT account_id, req_per_day, tokens_per_day, expires_at, reason, created_at
+            FROM quota_overrides
+            WHERE expires_at > ?1
+            "#,
+        )
+        .bind(Utc::now().to_rfc3339())
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(rows
+            .into_iter()
+            .filter_map(QuotaOverrideRow::into_override)
+            .collect())
+    }
+
+    pub async fn save_quota_override(&self, limit: &LimitOverride) -> Result<(), AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        sqlx::query(
+            r#"
+            INSERT INTO quota_overrides
+                (account_id, req_per_day, tokens_per_day, expires_at, reason, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT(account_id) DO UPDATE SET
+                req_per_day = excluded.req_per_day,
+                tokens_per_day = excluded.tokens_per_day,
+                expires_at = excluded.expires_at,
+                reason = excluded.reason,
+                created_at = excluded.created_at
+            "#,
+        )
+        .bind(&limit.account_id)
+        .bind(limit.req_per_day.map(|v| v as i64))
+        .bind(limit.tokens_per_day.map(|v| v as i64))
+        .bind(limit.expires_at.to_rfc3339())
+        .bind(&limit.reason)
+        .bind(limit.created_at.to_rfc3339())
+        .execute(&mut *tx)
+        .await
+        .map_err(map_db_err)?;
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)
+    }
+
+    /// Returns whether an unexpired override was removed.
+    pub async fn delete_quota_override(&self, account_id: &str) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        let active: Option<bool> =
+            sqlx::query_scalar("SELECT expires_at > ?2 FROM quota_overrides WHERE account_id = ?1")
+                .bind(account_id)
+                .bind(Utc::now().to_rfc3339())
+                .fetch_optional(&mut *tx)
+                .await
+                .map_err(map_db_err)?;
+        sqlx::query("DELETE FROM quota_overrides WHERE account_id = ?1")
+            .bind(account_id)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        bump_router_version(&mut tx).await?;
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(active.unwrap_or(false))
+    }
+
+    pub async fn record_quota_reset(&self, account_id: &str, at: &str) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO quota_resets (account_id, reset_at) VALUES (?1, ?2)
+            ON CONFLICT(account_id) DO UPDATE SET reset_at = excluded.reset_at
+            "#,
+        )
+        .bind(account_id)
+        .bind(at)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(())
+    }
+
+    /// Last manual quota reset per account.
+    pub async fn quota_resets(&self) -> Result<Vec<(String, String)>, AppError> {
+        sqlx::query_as("SELECT account_id, reset_at FROM quota_resets")
+            .fetch_all(&self.pool)
+            .await
+            .map_err(map_db_err)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AuditRecord {
+    pub seq: i64,
+    pub created_at: String,
+    pub kind: String,
+    pub actor: String,
+    pub action: String,
+    /// JSON object.
+    pub detail: String,
+    pub key_id: String,
+    pub prev_hash: String,
+    pub hash: String,
+}
+
+/// The hash-chained audit trail.
+impl Db {
+    /// The last entry's sequence number and hash.
+    pub async fn audit_head(&self) -> Result<Option<(i64, String)>, AppError> {
+        sqlx::query_as("SELECT seq, hash FROM audit_log ORDER BY seq DESC LIMIT 1")
+            .fetch_optional(&self.pool)
+            .await
+            .map_err(map_db_err)
+    }
+
+    /// Inserts consecutive entries together. Returns false, storing none of
+    /// them, when a sequence number was taken by another writer.
+    pub async fn insert_audit(&self, records: &[AuditRecord]) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        for record in records {
+            let inserted = sqlx::query(
+                r#"
+                INSERT OR IGNORE INTO audit_log
+                    (seq, created_at, kind, actor, action, detail, key_id, prev_hash, hash)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)
+                "#,
+            )
+            .bind(record.seq)
+            .bind(&record.created_at)
+            .bind(&record.kind)
+            .bind(&record.actor)
+            .bind(&record.action)
+            .bind(&record.detail)
+            .bind(&record.key_id)
+            .bind(&record.prev_hash)
+            .bind(&record.hash)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+            if inserted.rows_affected() != 1 {
+                return Ok(false);
+            }
+        }
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(true)
+    }
+
+    /// Entries after `after_seq` in chain order, for verification.
+    pub async fn audit_page(
+        &self,
+        after_seq: i64,
+        limit: i64,
+    ) -> Result<Vec<AuditRecord>, AppError> {
+        sqlx::query_as::<_, AuditRecord>(
+            r#"
+            SELECT seq, created_at, kind, actor, action, detail, key_id, prev_hash, hash
+            FROM audit_log WHERE seq > ?1 ORDER BY seq LIMIT ?2
+            "#,
+        )
+        .bind(after_seq)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    /// Entries attributed to `actor`; they stay put when the actor's data is
+    /// erased, since rewriting them would break the chain.
+    pub async fn audit_entries_by(&self, actor: &str) -> Result<i64, AppError> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM audit_log WHERE actor = ?1")
+            .bind(actor)
+            .fetch_one(&self.pool)
+            .await
+            .map_err(map_db_err)
+    }
+
+    /// Newest entries first, optionally of one kind and before a sequence number.
+    pub async fn list_audit(
+        &self,
+        kind: Option<&str>,
+        before_seq: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<AuditRecord>, AppError> {
+        sqlx::query_as::<_, AuditRecord>(
+            r#"
+            SELECT seq, created_at, kind, actor, action, detail, key_id, prev_hash, hash
+            FROM audit_log
+            WHERE (?1 IS NULL OR kind = ?1) AND (?2 IS NULL OR seq < ?2)
+            ORDER BY seq DESC LIMIT ?3
+            "#,
+        )
+        .bind(kind)
+        .bind(before_seq)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ProviderKeyRecord {
+    pub account_id: String,
+    pub provider: String,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+    pub key_id: String,
+    pub last4: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// Encrypted provider API keys.
+impl Db {
+    pub async fn provider_keys(
+        &self,
+        account_id: Option<&str>,
+    ) -> Result<Vec<ProviderKeyRecord>, AppError> {
+        sqlx::query_as::<_, ProviderKeyRecord>(
+            r#"
+            SELECT account_id, provider, nonce, ciphertext, key_id, last4, created_at, updated_at
+            FROM provider_keys
+            WHERE ?1 IS NULL OR account_id = ?1
+            ORDER BY account_id, provider
+            "#,
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    /// Inserts or replaces a key; `created_at` is kept across replacements.
+    pub async fn save_provider_key(&self, record: &ProviderKeyRecord) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO provider_keys
+                (account_id, provider, nonce, ciphertext, key_id, last4, created_at, updated_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            ON CONFLICT(account_id, provider) DO UPDATE SET
+                nonce = excluded.nonce,
+                ciphertext = excluded.ciphertext,
+                key_id = excluded.key_id,
+                last4 = excluded.last4,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&record.account_id)
+        .bind(&record.provider)
+        .bind(&record.nonce)
+        .bind(&record.ciphertext)
+        .bind(&record.key_id)
+        .bind(&record.last4)
+        .bind(&record.created_at)
+        .bind(&record.updated_at)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(())
+    }
+
+    /// Swaps in a re-encrypted key, unless the row changed since it was read.
+    pub async fn reseal_provider_key(
+        &self,
+        record: &ProviderKeyRecord,
+        previous_key_id: &str,
+    ) -> Result<bool, AppError> {
+        let updated = sqlx::query(
+            r#"
+            UPDATE provider_keys SET nonce = ?3, ciphertext = ?4, key_id = ?5
+            WHERE account_id = ?1 AND provider = ?2 AND key_id = ?6
+            "#,
+        )
+        .bind(&record.account_id)
+        .bind(&record.provider)
+        .bind(&record.nonce)
+        .bind(&record.ciphertext)
+        .bind(&record.key_id)
+        .bind(previous_key_id)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(updated.rows_affected() == 1)
+    }
+
+    pub async fn delete_provider_key(
+        &self,
+        account_id: &str,
+        provider: &str,
+    ) -> Result<bool, AppError> {
+        let deleted =
+            sqlx::query("DELETE FROM provider_keys WHERE account_id = ?1 AND provider = ?2")
+                .bind(account_id)
+                .bind(provider)
+                .execute(&self.pool)
+                .await
+                .map_err(map_db_err)?;
+        Ok(deleted.rows_affected() == 1)
+    }
+}
+
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct WebhookRecord {
+    pub id: String,
+    pub url: String,
+    /// Plaintext secret of an endpoint created before secrets were sealed;
+    /// empty once sealed.
+    pub secret: String,
+    pub secret_nonce: Option<Vec<u8>>,
+    pub secret_ciphertext: Option<Vec<u8>>,
+    /// The master key the secret is sealed with.
+    pub secret_key_id: Option<String>,
+    pub secret_last4: String,
+    /// JSON array of subscribed events; empty means every event.
+    pub events: String,
+    pub enabled: bool,
+    pub description: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct WebhookDelivery {
+    pub id: String,
+    pub webhook_id: String,
+    pub event: String,
+    pub payload: String,
+    pub status: String,
+    pub attempts: i64,
+    pub next_attempt_at: String,
+    pub last_status: Option<i64>,
+    pub last_error: Option<String>,
+    pub created_at: String,
+    pub delivered_at: Option<String>,
+}
+
+/// A pending delivery that is due, with what's needed to send it.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct DueWebhookDelivery {
+    pub id: String,
+    pub event: String,
+    pub payload: String,
+    pub attempts: i64,
+    pub next_attempt_at: String,
+    pub webhook_id: String,
+    pub url: String,
+    pub secret: String,
+    pub secret_nonce: Option<Vec<u8>>,
+    pub secret_ciphertext: Option<Vec<u8>>,
+    pub secret_key_id: Option<String>,
+}
+
+/// Where a delivery attempt left the delivery.
+pub struct WebhookAttempt<'a> {
+    pub status: &'a str,
+    pub attempts: i64,
+    pub next_attempt_at: &'a str,
+    pub last_status: Option<i64>,
+    pub last_error: Option<&'a str>,
+    pub delivered_at: Option<&'a str>,
+}
+
+/// Webhook endpoints and their delivery queue.
+impl Db {
+    pub async fn list_webhooks(&self) -> Result<Vec<WebhookRecord>, AppError> {
+        sqlx::query_as::<_, WebhookRecord>(
+            r#"
+            SELECT id, url, secret, secret_nonce, secret_ciphertext, secret_key_id, secret_last4,
+                   events, enabled, description, created_at, updated_at
+            FROM webhooks
+            ORDER BY created_at
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    pub async fn webhook(&self, id: &str) -> Result<Option<WebhookRecord>, AppError> {
+        sqlx::query_as::<_, WebhookRecord>(
+            r#"
+            SELECT id, url, secret, secret_nonce, secret_ciphertext, secret_key_id, secret_last4,
+                   events, enabled, description, created_at, updated_at
+            FROM webhooks
+            WHERE id = ?1
+            "#,
+        )
+        .bind(id)
+        .fetch_optional(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    /// Inserts or replaces an endpoint; `created_at` is kept across replacements.
+    pub async fn save_webhook(&self, record: &WebhookRecord) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO webhooks
+                (id, url, secret, events, enabled, description, created_at, updated_at,
+                 secret_nonce, secret_ciphertext, secret_key_id, secret_last4)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)
+            ON CONFLICT(id) DO UPDATE SET
+                url = excluded.url,
+                secret = excluded.secret,
+                secret_nonce = excluded.secret_nonce,
+                secret_ciphertext = excluded.secret_ciphertext,
+                secret_key_id = excluded.secret_key_id,
+                secret_last4 = excluded.secret_last4,
+                events = excluded.events,
+                enabled = excluded.enabled,
+                description = excluded.description,
+                updated_at = excluded.updated_at
+            "#,
+        )
+        .bind(&record.id)
+        .bind(&record.url)
+        .bind(&record.secret)
+        .bind(&record.events)
+        .bind(record.enabled)
+        .bind(&record.description)
+        .bind(&record.created_at)
+        .bind(&record.updated_at)
+        .bind(&record.secret_nonce)
+        .bind(&record.secret_ciphertext)
+        .bind(&record.secret_key_id)
+        .bind(&record.secret_last4)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(())
+    }
+
+    /// Swaps in a secret sealed under the current master key, clearing any
+    /// plaintext. False when the secret changed since it was read.
+    pub async fn reseal_webhook_secret(
+        &self,
+        record: &WebhookRecord,
+        previous_key_id: Option<&str>,
+    ) -> Result<bool, AppError> {
+        let updated = sqlx::query(
+            r#"
+            UPDATE webhooks
+            SET secret = '', secret_nonce = ?2, secret_ciphertext = ?3, secret_key_id = ?4
+            WHERE id = ?1 AND secret_key_id IS ?5
+            "#,
+        )
+        .bind(&record.id)
+        .bind(&record.secret_nonce)
+        .bind(&record.secret_ciphertext)
+        .bind(&record.secret_key_id)
+        .bind(previous_key_id)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(updated.rows_affected() == 1)
+    }
+
+    /// Deletes an endpoint along with its queued and dead deliveries.
+    pub async fn delete_webhook(&self, id: &str) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        sqlx::query("DELETE FROM webhook_deliveries WHERE webhook_id = ?1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?;
+        let deleted = sqlx::query("DELETE FROM webhooks WHERE id = ?1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await
+            .map_err(map_db_err)?
+            .rows_affected();
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(deleted == 1)
+    }
+
+    pub async fn insert_webhook_delivery(
+        &self,
+        delivery: &WebhookDelivery,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            INSERT INTO webhook_deliveries
+                (id, webhook_id, event, payload, status, attempts, next_attempt_at, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "#,
+        )
+        .bind(&delivery.id)
+        .bind(&delivery.webhook_id)
+        .bind(&delivery.event)
+        .bind(&delivery.payload)
+        .bind(&delivery.status)
+        .bind(delivery.attempts)
+        .bind(&delivery.next_attempt_at)
+        .bind(&delivery.created_at)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(())
+    }
+
+    /// Pending deliveries due by `now` on enabled endpoints, oldest first.
+    pub async fn due_webhook_deliveries(
+        &self,
+        now: &str,
+        limit: i64,
+    ) -> Result<Vec<DueWebhookDelivery>, AppError> {
+        sqlx::query_as::<_, DueWebhookDelivery>(
+            r#"
+            SELECT d.id, d.event, d.payload, d.attempts, d.next_attempt_at, d.webhook_id,
+                   w.url, w.secret, w.secret_nonce, w.secret_ciphertext, w.secret_key_id
+            FROM webhook_deliveries d
+            JOIN webhooks w ON w.id = d.webhook_id
+            WHERE d.status = 'pending' AND d.next_attempt_at <= ?1 AND w.enabled = 1
+            ORDER BY d.next_attempt_at
+            LIMIT ?2
+            "#,
+        )
+        .bind(now)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    /// Takes a due delivery by pushing its next attempt out to `lease_until`.
+    /// False when another replica took it first.
+    pub async fn claim_webhook_delivery(
+        &self,
+        id: &str,
+        due_at: &str,
+        lease_until: &str,
+    ) -> Result<bool, AppError> {
+        let claimed = sqlx::query(
+            r#"
+            UPDATE webhook_deliveries SET next_attempt_at = ?3
+            WHERE id = ?1 AND status = 'pending' AND next_attempt_at = ?2
+            "#,
+        )
+        .bind(id)
+        .bind(due_at)
+        .bind(lease_until)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(claimed.rows_affected() == 1)
+    }
+
+    pub async fn record_webhook_attempt(
+        &self,
+        id: &str,
+        attempt: WebhookAttempt<'_>,
+    ) -> Result<(), AppError> {
+        sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = ?2, attempts = ?3, next_attempt_at = ?4, last_status = ?5,
+                last_error = ?6, delivered_at = ?7
+            WHERE id = ?1
+            "#,
+        )
+        .bind(id)
+        .bind(attempt.status)
+        .bind(attempt.attempts)
+        .bind(attempt.next_attempt_at)
+        .bind(attempt.last_status)
+        .bind(attempt.last_error)
+        .bind(attempt.delivered_at)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(())
+    }
+
+    pub async fn list_webhook_deliveries(
+        &self,
+        status: Option<&str>,
+        webhook_id: Option<&str>,
+        limit: i64,
+    ) -> Result<Vec<WebhookDelivery>, AppError> {
+        sqlx::query_as::<_, WebhookDelivery>(
+            r#"
+            SELECT id, webhook_id, event, payload, status, attempts, next_attempt_at,
+                   last_status, last_error, created_at, delivered_at
+            FROM webhook_deliveries
+            WHERE (?1 IS NULL OR status = ?1)
+              AND (?2 IS NULL OR webhook_id = ?2)
+            ORDER BY created_at DESC
+            LIMIT ?3
+            "#,
+        )
+        .bind(status)
+        .bind(webhook_id)
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    /// Puts a dead delivery back in the queue with a fresh set of attempts.
+    pub async fn requeue_webhook_delivery(&self, id: &str) -> Result<bool, AppError> {
+        let requeued = sqlx::query(
+            r#"
+            UPDATE webhook_deliveries
+            SET status = 'pending', attempts = 0, next_attempt_at = ?2
+            WHERE id = ?1 AND status = 'dead'
+            "#,
+        )
+        .bind(id)
+        .bind(Utc::now().to_rfc3339())
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(requeued.rows_affected() == 1)
+    }
+
+    /// Drops delivered deliveries older than `cutoff`; dead ones are kept.
+    pub async fn prune_webhook_deliveries(&self, cutoff: &str) -> Result<u64, AppError> {
+        let pruned = sqlx::query(
+            "DELETE FROM webhook_deliveries WHERE status = 'delivered' AND created_at < ?1",
+        )
+        .bind(cutoff)
+        .execute(&self.pool)
+        .await
+        .map_err(map_db_err)?;
+        Ok(pruned.rows_affected())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct CanaryToken {
+    pub token: String,
+    pub account_id: String,
+    pub created_at: String,
+    /// Set once the token stops being injected; it is still watched for.
+    pub retired_at: Option<String>,
+    /// Times the token turned up in a prompt or a reply.
+    pub hits: i64,
+    pub last_seen_at: Option<String>,
+}
+
+/// Canary tokens planted in guardrail prompts.
+impl Db {
+    pub async fn canary_tokens(
+        &self,
+        account_id: Option<&str>,
+    ) -> Result<Vec<CanaryToken>, AppError> {
+        sqlx::query_as::<_, CanaryToken>(
+            r#"
+            SELECT token, account_id, created_at, retired_at, hits, last_seen_at
+            FROM canary_tokens
+            WHERE ?1 IS NULL OR account_id = ?1
+            ORDER BY account_id, created_at DESC
+            "#,
+        )
+        .bind(account_id)
+        .fetch_all(&self.pool)
+        .await
+        .map_err(map_db_err)
+    }
+
+    /// Retires the account's active token, if any, and makes `token` the one
+    /// injected from now on.
+    pub async fn rotate_canary_token(
+        &self,
+        account_id: &str,
+        token: &str,
+    ) -> Result<CanaryToken, AppError> {
+        let now = Utc::now().to_rfc3339();
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        retire_canary(&mut tx, account_id, &now).await?;
+        sqlx::query(
+            "INSERT INTO canary_tokens (token, account_id, created_at) VALUES (?1, ?2, ?3)",
+        )
+        .bind(token)
+        .bind(account_id)
+        .bind(&now)
+        .execute(&mut *tx)
+        .await
+        .map_err(map_db_err)?;
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(CanaryToken {
+            token: token.to_string(),
+            account_id: account_id.to_string(),
+            created_at: now,
+            retired_at: None,
+            hits: 0,
+            last_seen_at: None,
+        })
+    }
+
+    /// Stops injecting the account's active token. False when it had none.
+    pub async fn retire_canary_token(&self, account_id: &str) -> Result<bool, AppError> {
+        let mut tx = self.pool.begin().await.map_err(map_db_err)?;
+        let retired = retire_canary(&mut tx, account_id, &Utc::now().to_rfc3339()).await?;
+        tx.commit().await.map_err(map_db_err)?;
+        Ok(retired > 0)
+    }
+
+    pub async fn record_canary_hit(&self, token: &str) -> Result<(), AppError> {
+        sqlx::query("UPDATE canary_tokens SET hits = hits + 1, last_seen_at = ?2 WHERE token = ?1")
+            .bind(token)
+            .bind(Utc::now().to_rfc3339())
+            .execute(&self.pool)
+            .await
+            .map_err(map_db_err)?;
+        Ok(())
+    }
+}
+
+async fn retire_canary(
+    tx: &mut SqliteTx<'_>,
+    account_id: &str,
+    now: &str,
+) -> Result<u64, AppError> {
+    let retired = sqlx::query(
+        "UPDATE canary_tokens SET retired_at = ?2 WHERE account_id = ?1 AND retired_at IS NULL",
+    )
+    .bind(account_id)
+    .bind(now)
+    .execute(&mut **tx)
+    .await
+    .map_err(map_db_err)?;
+    Ok(retired.rows_affected())
 }
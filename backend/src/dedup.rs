@@ -0,0 +1,161 @@
+use crate::error::AppError;
+use futures_util::future::{BoxFuture, FutureExt, Shared};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    future::Future,
+    hash::{DefaultHasher, Hash, Hasher},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+type SharedCall<T> = Shared<BoxFuture<'static, Result<T, AppError>>>;
+
+/// An in-flight call and how many requests are waiting on it.
+struct Entry<T> {
+    /// Tells this call apart from a later one under the same key.
+    id: u64,
+    call: SharedCall<T>,
+    waiters: usize,
+}
+
+type Registry<T> = Arc<Mutex<HashMap<u64, Entry<T>>>>;
+
+/// Held by every request waiting on a call. When the last one goes away before
+/// the call finishes (clients disconnected), the call is dropped from the map.
+struct Waiter<T> {
+    registry: Registry<T>,
+    key: u64,
+    id: u64,
+}
+
+impl<T> Drop for Waiter<T> {
+    fn drop(&mut self) {
+        let mut inflight = self.registry.lock().unwrap_or_else(|e| e.into_inner());
+        if let Some(entry) = inflight.get_mut(&self.key)
+            && entry.id == self.id
+        {
+            entry.waiters -= 1;
+            if entry.waiters == 0 {
+                inflight.remove(&self.key);
+            }
+        }
+    }
+}
+
+/// Coalesces identical concurrent calls so only the first one does the work
+/// and every other caller waits on (and receives a clone of) its result.
+#[derive(Clone)]
+pub struct InflightDedup<T: Clone> {
+    inflight: Registry<T>,
+    next_id: Arc<AtomicU64>,
+    stats: Arc<Mutex<DedupStats>>,
+}
+
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct DedupStats {
+    pub leaders: u64,
+    pub coalesced: u64,
+    pub tokens_saved: u64,
+    pub cost_saved: f64,
+    pub inflight: usize,
+}
+
+impl<T> InflightDedup<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    pub fn new() -> Self {
+        Self {
+            inflight: Arc::new(Mutex::new(HashMap::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+            stats: Arc::new(Mutex::new(DedupStats::default())),
+        }
+    }
+
+    pub fn key(scope: &str, payload: &impl Serialize) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        scope.hash(&mut hasher);
+        serde_json::to_string(payload)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Runs `call` unless an identical call is already in flight, in which case
+    /// the existing result is awaited instead. Returns the result and whether it
+    /// was shared from another caller.
+    pub async fn run<F>(&self, key: u64, call: F) -> (Result<T, AppError>, bool)
+    where
+        F: Future<Output = Result<T, AppError>> + Send + 'static,
+    {
+        let (shared, leader, waiter) = {
+            let mut inflight = self.inflight.lock().unwrap_or_else(|e| e.into_inner());
+            let leader = !inflight.contains_key(&key);
+            let entry = inflight.entry(key).or_insert_with(|| {
+                let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+                // The entry removes itself on completion so later requests
+                // never get a stale result.
+                let registry = self.inflight.clone();
+                let call = async move {
+                    let res = call.await;
+                    let mut inflight = registry.lock().unwrap_or_else(|e| e.into_inner());
+                    if inflight.get(&key).is_some_and(|e| e.id == id) {
+                        inflight.remove(&key);
+                    }
+                    res
+                }
+                .boxed()
+                .shared();
+                Entry {
+                    id,
+                    call,
+                    waiters: 0,
+                }
+            });
+            entry.waiters += 1;
+            let waiter = Waiter {
+                registry: self.inflight.clone(),
+                key,
+                id: entry.id,
+            };
+            (entry.call.clone(), leader, waiter)
+        };
+
+        if let Ok(mut stats) = self.stats.lock() {
+            if leader {
+                stats.leaders += 1;
+            } else {
+                stats.coalesced += 1;
+            }
+        }
+
+        let res = shared.await;
+        drop(waiter);
+        (res, !leader)
+    }
+
+    pub fn record_saved(&self, tokens: u64, cost: f64) {
+        if let Ok(mut stats) = self.stats.lock() {
+            stats.tokens_saved += tokens;
+            stats.cost_saved += cost;
+        }
+    }
+
+    pub fn stats(&self) -> DedupStats {
+        let mut stats = self.stats.lock().map(|s| s.clone()).unwrap_or_default();
+        stats.inflight = self.inflight.lock().map(|m| m.len()).unwrap_or(0);
+        stats
+    }
+}
+
+impl<T> Default for InflightDedup<T>
+where
+    T: Clone + Send + Sync + 'static,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -1,17 +1,26 @@
 use axum::{
     Json,
+    extract::rejection::JsonRejection,
     http::StatusCode,
     response::{IntoResponse, Response},
 };
 use serde::Serialize;
 use thiserror::Error;
 
-#[derive(Debug, Error)]
+#[derive(Debug, Clone, Error)]
 pub enum AppError {
     #[error("bad request: {0}")]
     BadRequest(String),
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    #[error("payload too large: {0}")]
+    PayloadTooLarge(String),
+    #[error("rate limited: {0}")]
+    RateLimited(String),
     #[error("configuration error: {0}")]
     Config(String),
+    #[error("service unavailable: {0}")]
+    Unavailable(String),
     #[error("upstream error: {0}")]
     Upstream(String),
     #[error("internal error: {0}")]
@@ -22,7 +31,11 @@ impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let status = match self {
             AppError::BadRequest(_) => StatusCode::BAD_REQUEST,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::PayloadTooLarge(_) => StatusCode::PAYLOAD_TOO_LARGE,
+            AppError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
             AppError::Config(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::Unavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
             AppError::Upstream(_) => StatusCode::BAD_GATEWAY,
             AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
         };
@@ -50,3 +63,15 @@ impl From<crate::llm::LlmError> for AppError {
         }
     }
 }
+
+/// Malformed or oversized JSON bodies get the usual JSON error instead of
+/// axum's plain-text rejection.
+impl From<JsonRejection> for AppError {
+    fn from(value: JsonRejection) -> Self {
+        if value.status() == StatusCode::PAYLOAD_TOO_LARGE {
+            AppError::PayloadTooLarge("request body exceeds the size limit".into())
+        } else {
+            AppError::BadRequest(value.body_text())
+        }
+    }
+}
@@ -0,0 +1,307 @@
+use crate::{
+    db::{ConversationRecord, MessageRecord},
+    governance::PolicyHit,
+};
+use chrono::Utc;
+use std::fmt::Write as _;
+
+const REDACTION_MARKER: &str = "[REDACTED]";
+
+/// Everything an export needs, loaded once by the handler.
+pub struct Transcript {
+    pub conversation: ConversationRecord,
+    pub tags: Vec<String>,
+    pub messages: Vec<MessageRecord>,
+    pub policy_hits: Vec<PolicyHit>,
+}
+
+/// One rendered block of the transcript, shared by the Markdown and PDF output
+/// so both show the same metadata.
+struct Block {
+    heading: String,
+    notes: Vec<String>,
+    body: String,
+}
+
+impl Transcript {
+    fn title(&self) -> &str {
+        self.conversation
+            .title
+            .as_deref()
+            .filter(|t| !t.trim().is_empty())
+            .unwrap_or("Untitled conversation")
+    }
+
+    fn header_lines(&self) -> Vec<String> {
+        let mut lines = vec![
+            format!("Conversation: {}", self.conversation.id),
+            format!("Started: {}", self.conversation.created_at),
+        ];
+        if !self.tags.is_empty() {
+            lines.push(format!("Tags: {}", self.tags.join(", ")));
+        }
+        lines.push(format!("Exported: {}", Utc::now().to_rfc3339()));
+        lines
+    }
+
+    fn blocks(&self) -> Vec<Block> {
+        self.messages
+            .iter()
+            .map(|m| {
+                let speaker = match m.role.as_str() {
+                    "user" => "User".to_string(),
+                    "assistant" => "Assistant".to_string(),
+                    other => other.to_string(),
+                };
+                let mut heading = speaker;
+                if m.role == "assistant"
+                    && let Some(model) = &m.model
+                {
+                    heading.push_str(&format!(" · {model}"));
+                    if let Some(provider) = &m.provider {
+                        heading.push_str(&format!(" ({provider})"));
+                    }
+                }
+                heading.push_str(&format!(" · {}", m.created_at));
+
+                let mut notes = Vec::new();
+                for hit in self.policy_hits.iter().filter(|h| h.message_id == m.id) {
+                    notes.push(format!("Policy \"{}\" ({})", hit.policy_name, hit.action));
+                }
+                let redactions = m.content.matches(REDACTION_MARKER).count();
+                if redactions > 0 {
+                    notes.push(format!(
+                        "{redactions} value(s) redacted before storage, shown as {REDACTION_MARKER}"
+                    ));
+                }
+                if m.superseded_by.is_some() {
+                    notes.push("Superseded by a regenerated answer".into());
+                }
+
+                Block {
+                    heading,
+                    notes,
+                    body: m.content.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+pub fn to_markdown(transcript: &Transcript) -> String {
+    let mut out = String::new();
+    writeln!(out, "# {}\n", transcript.title()).ok();
+    for line in transcript.header_lines() {
+        writeln!(out, "- {line}").ok();
+    }
+    out.push_str("\n---\n");
+    for block in transcript.blocks() {
+        writeln!(out, "\n### {}\n", block.heading).ok();
+        for note in &block.notes {
+            writeln!(out, "> {note}").ok();
+        }
+        if !block.notes.is_empty() {
+            out.push('\n');
+        }
+        writeln!(out, "{}", block.body.trim_end()).ok();
+    }
+    out
+}
+
+pub fn to_pdf(transcript: &Transcript) -> Vec<u8> {
+    let mut lines = vec![PdfLine::heading(transcript.title())];
+    for line in transcript.header_lines() {
+        lines.extend(wrap(&line, Style::Meta));
+    }
+    for block in transcript.blocks() {
+        lines.push(PdfLine::blank());
+        lines.push(PdfLine::heading(&block.heading));
+        for note in &block.notes {
+            lines.extend(wrap(&format!("> {note}"), Style::Meta));
+        }
+        for paragraph in block.body.lines() {
+            lines.extend(wrap(paragraph, Style::Body));
+        }
+    }
+    pdf::render(&lines)
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Style {
+    Heading,
+    Meta,
+    Body,
+}
+
+struct PdfLine {
+    text: String,
+    style: Style,
+}
+
+impl PdfLine {
+    fn heading(text: &str) -> Self {
+        Self {
+            text: text.to_string(),
+            style: Style::Heading,
+        }
+    }
+
+    fn blank() -> Self {
+        Self {
+            text: String::new(),
+            style: Style::Body,
+        }
+    }
+}
+
+/// Characters per line at 10pt Helvetica on a Letter page with 50pt margins.
+const WRAP_COLUMNS: usize = 95;
+
+fn wrap(text: &str, style: Style) -> Vec<PdfLine> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+    for word in text.split_whitespace() {
+        let mut word = word.to_string();
+        while word.chars().count() > WRAP_COLUMNS {
+            if !current.is_empty() {
+                out.push(std::mem::take(&mut current));
+            }
+            let split: String = word.chars().take(WRAP_COLUMNS).collect();
+            word = word.chars().skip(WRAP_COLUMNS).collect();
+            out.push(split);
+        }
+        if !current.is_empty() && current.chars().count() + 1 + word.chars().count() > WRAP_COLUMNS
+        {
+            out.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push(' ');
+        }
+        current.push_str(&word);
+    }
+    out.push(current);
+    out.into_iter()
+        .map(|text| PdfLine { text, style })
+        .collect()
+}
+
+/// Minimal single-column PDF writer using the built-in Helvetica fonts, so
+/// exports need no font files or PDF dependency.
+mod pdf {
+    use super::{PdfLine, Style};
+    use std::fmt::Write as _;
+
+    const PAGE_WIDTH: u32 = 612;
+    const PAGE_HEIGHT: u32 = 792;
+    const MARGIN: u32 = 50;
+    const LEADING: u32 = 14;
+
+    pub(super) fn render(lines: &[PdfLine]) -> Vec<u8> {
+        let per_page = ((PAGE_HEIGHT - 2 * MARGIN) / LEADING) as usize;
+        let pages: Vec<&[PdfLine]> = if lines.is_empty() {
+            vec![&[]]
+        } else {
+            lines.chunks(per_page).collect()
+        };
+
+        // Objects 1-4 are fixed; each page then takes a page and a content object.
+        let mut objects: Vec<Vec<u8>> = Vec::new();
+        let kids: Vec<String> = (0..pages.len())
+            .map(|i| format!("{} 0 R", 5 + i * 2))
+            .collect();
+        objects.push(b"<< /Type /Catalog /Pages 2 0 R >>".to_vec());
+        objects.push(
+            format!(
+                "<< /Type /Pages /Kids [{}] /Count {} >>",
+                kids.join(" "),
+                pages.len()
+            )
+            .into_bytes(),
+        );
+        objects.push(
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+                .to_vec(),
+        );
+        objects.push(
+            b"<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+                .to_vec(),
+        );
+        for (i, page) in pages.iter().enumerate() {
+            let content_id = 6 + i * 2;
+            objects.push(
+                format!(
+                    "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PAGE_WIDTH} {PAGE_HEIGHT}] \
+                     /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {content_id} 0 R >>"
+                )
+                .into_bytes(),
+            );
+            let stream = page_stream(page);
+            let mut obj = format!("<< /Length {} >>\nstream\n", stream.len()).into_bytes();
+            obj.extend_from_slice(&stream);
+            obj.extend_from_slice(b"\nendstream");
+            objects.push(obj);
+        }
+
+        let mut out = b"%PDF-1.4\n".to_vec();
+        let mut offsets = Vec::with_capacity(objects.len());
+        for (i, obj) in objects.iter().enumerate() {
+            offsets.push(out.len());
+            out.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+            out.extend_from_slice(obj);
+            out.extend_from_slice(b"\nendobj\n");
+        }
+        let xref_at = out.len();
+        let mut xref = format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1);
+        for offset in offsets {
+            writeln!(xref, "{offset:010} 00000 n ").ok();
+        }
+        write!(
+            xref,
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref_at}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .ok();
+        out.extend_from_slice(xref.as_bytes());
+        out
+    }
+
+    fn page_stream(lines: &[PdfLine]) -> Vec<u8> {
+        let mut stream = format!(
+            "BT\n{LEADING} TL\n{MARGIN} {} Td\n",
+            PAGE_HEIGHT - MARGIN - LEADING
+        )
+        .into_bytes();
+        for line in lines {
+            let font = match line.style {
+                Style::Heading => "/F2 11 Tf",
+                Style::Meta => "/F1 9 Tf",
+                Style::Body => "/F1 10 Tf",
+            };
+            stream.extend_from_slice(format!("{font}\n(").as_bytes());
+            stream.extend_from_slice(&escape(&line.text));
+            stream.extend_from_slice(b") Tj T*\n");
+        }
+        stream.extend_from_slice(b"ET");
+        stream
+    }
+
+    /// Encodes text as WinAnsi bytes, escaping PDF string delimiters. Characters
+    /// outside Latin-1 have no glyph in the base fonts and become `?`.
+    fn escape(text: &str) -> Vec<u8> {
+        let mut out = Vec::with_capacity(text.len());
+        for c in text.chars() {
+            match c {
+                '(' | ')' | '\\' => {
+                    out.push(b'\\');
+                    out.push(c as u8);
+                }
+                '·' => out.push(0xB7),
+                c if (' '..='~').contains(&c) => out.push(c as u8),
+                c if ('\u{A0}'..='\u{FF}').contains(&c) => out.push(c as u32 as u8),
+                '\t' => out.push(b' '),
+                _ => out.push(b'?'),
+            }
+        }
+        out
+    }
+}
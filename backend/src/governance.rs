@@ -1,3 +1,4 @@
+use crate::error::AppError;
 use regex::Regex;
 use serde::Serialize;
 
@@ -26,6 +27,39 @@ pub struct PolicyUpsert {
     pub enabled: bool,
 }
 
+pub const MATCH_TYPES: &[&str] = &["contains_any", "contains_all", "regex"];
+pub const ACTIONS: &[&str] = &["flag", "redact", "block"];
+pub const APPLIES_TO: &[&str] = &["user", "assistant", "any"];
+
+impl PolicyUpsert {
+    pub fn validate(&self) -> Result<(), AppError> {
+        if self.name.trim().is_empty() {
+            return Err(AppError::BadRequest("policy name is required".into()));
+        }
+        if self.pattern.trim().is_empty() {
+            return Err(AppError::BadRequest("policy pattern is required".into()));
+        }
+        for (field, value, allowed) in [
+            ("match_type", &self.match_type, MATCH_TYPES),
+            ("action", &self.action, ACTIONS),
+            ("applies_to", &self.applies_to, APPLIES_TO),
+        ] {
+            if !allowed.contains(&value.as_str()) {
+                return Err(AppError::BadRequest(format!(
+                    "{field} must be one of {}",
+                    allowed.join(", ")
+                )));
+            }
+        }
+        if self.match_type == "regex"
+            && let Err(e) = Regex::new(&self.pattern)
+        {
+            return Err(AppError::BadRequest(format!("invalid regex pattern: {e}")));
+        }
+        Ok(())
+    }
+}
+
 #[derive(Debug, Serialize, sqlx::FromRow)]
 pub struct PolicyHit {
     pub id: String,
@@ -0,0 +1,474 @@
+//! The gateway as a library: application state and the HTTP router, shared by
+//! the server binary and, with the `test_support` feature, integration tests.
+
+mod abuse;
+mod admin;
+mod agent;
+mod audit;
+mod audit_log;
+pub mod auth;
+mod canary;
+pub mod config;
+mod context;
+pub mod db;
+mod dedup;
+pub mod error;
+mod export;
+mod governance;
+pub mod lifecycle;
+pub mod llm;
+pub mod model_router;
+mod notifications;
+mod pii;
+mod provider_keys;
+mod quota;
+mod rag;
+mod routes;
+mod secrets;
+mod shared_store;
+pub mod startup;
+pub mod state_export;
+#[cfg(feature = "test_support")]
+pub mod test_support;
+pub mod tls;
+mod translation;
+pub mod webhooks;
+
+use crate::abuse::AbuseDetector;
+use crate::admin::{
+    account_usage, bulk_import, clear_limit_override, create_account, dashboard_overview,
+    delete_account, delete_alias, delete_fallbacks, delete_model, erase_user_data,
+    inspect_conversation, list_abuse, list_accounts, list_models, list_policies, list_switches,
+    preview_alias, release_abuse_throttle, reload_config, reset_quota, router_health_history,
+    set_alias, set_fallbacks, set_limit_override, set_maintenance, set_model_switch,
+    set_provider_switch, test_policy, update_account_guardrail, update_account_limits,
+    update_account_models, update_account_origins, update_account_residency, update_account_status,
+    update_account_translation, upsert_model, upsert_policy,
+};
+use crate::audit_log::{AuditLog, list_audit, record_admin_actions, verify_audit};
+use crate::auth::{enforce_account_origins, login, logout};
+use crate::canary::{CanaryCache, list_canaries, retire_canary, rotate_canary};
+use crate::config::{Config, SharedConfig};
+use crate::db::Db;
+use crate::dedup::InflightDedup;
+use crate::error::AppError;
+use crate::lifecycle::Lifecycle;
+use crate::llm::LlmService;
+use crate::model_router::AccessControl;
+use crate::notifications::{list_notifications, mark_notifications};
+use crate::provider_keys::{
+    ProviderKeys, delete_provider_key, list_provider_keys, reencrypt_provider_keys,
+    set_provider_key,
+};
+use crate::quota::UsageCounters;
+use crate::rag::Rag;
+use crate::routes::chat::{RoutedResult, agent, chat, chat_stream, regenerate};
+use crate::routes::collections::{
+    add_document, create_collection, delete_collection, delete_document, get_document,
+    list_collections, list_documents,
+};
+use crate::routes::conversations::{
+    add_tags, delete_draft, export_conversation, get_draft, get_tags, list_conversations,
+    remove_tag, save_draft, set_flags, set_order, set_tags,
+};
+use crate::routes::messages::submit_feedback;
+use crate::shared_store::SharedStore;
+use crate::startup::{MIGRATIONS, ROUTER_STATE, StageState};
+use crate::state_export::{export_state, import_state};
+use crate::webhooks::{
+    create_webhook, delete_webhook, list_dead_letters, list_webhook_deliveries, list_webhooks,
+    retry_webhook_delivery, rotate_webhook_secret, test_webhook, update_webhook,
+};
+use arc_swap::ArcSwap;
+use axum::{
+    Json, Router,
+    extract::{DefaultBodyLimit, Request, State},
+    http::{HeaderValue, Method, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{delete, get, post, put},
+};
+use serde_json::json;
+use std::{
+    convert::Infallible,
+    sync::{Arc, OnceLock},
+};
+use tower::{ServiceExt, service_fn};
+use tower_http::{
+    compression::{
+        CompressionLayer,
+        predicate::{NotForContentType, Predicate, SizeAbove},
+    },
+    cors::{AllowOrigin, CorsLayer},
+};
+use tracing::warn;
+
+pub use crate::admin::validate_account;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub llm: LlmService,
+    pub db: Db,
+    pub config: SharedConfig,
+    pub access: AccessControl,
+    pub dedup: InflightDedup<RoutedResult>,
+    pub store: SharedStore,
+    pub lifecycle: Lifecycle,
+    pub usage: UsageCounters,
+    pub abuse: AbuseDetector,
+    pub audit: AuditLog,
+    pub canaries: CanaryCache,
+    pub provider_keys: ProviderKeys,
+    pub rag: Rag,
+}
+
+impl AppState {
+    /// Opens the database (running migrations), builds the provider clients and
+    /// loads the router state, seeding accounts and catalog on first boot, and
+    /// records those stages in `lifecycle`. Checking the providers and starting
+    /// background tasks are left to the caller.
+    pub async fn new(config: Config, lifecycle: Lifecycle) -> Result<Self, AppError> {
+        let startup = lifecycle.startup().clone();
+        startup.begin(MIGRATIONS);
+        let db = Db::new(&config.database_url).await?;
+        startup.finish(MIGRATIONS, StageState::Done, None);
+        startup.begin(ROUTER_STATE);
+        let llm = LlmService::new(&config)?.with_notifications(db.clone());
+        let usage = UsageCounters::rebuild(&db).await?;
+        let store = SharedStore::connect(&config).await;
+        let access = AccessControl::load(
+            db.clone(),
+            store.clone(),
+            config.mock_provider,
+            config.routing_seed,
+        )
+        .await?;
+        let interrupted = db.fail_interrupted_ingests().await?;
+        if interrupted > 0 {
+            warn!("marked {interrupted} interrupted document ingest(s) as failed");
+        }
+        let rag = Rag::new(
+            db.clone(),
+            llm.clone(),
+            access.clone(),
+            config.rag_embedding_model.clone(),
+            config.rag_top_k,
+        );
+        startup.finish(ROUTER_STATE, StageState::Done, None);
+        let config = Arc::new(ArcSwap::from_pointee(config));
+        Ok(Self {
+            llm,
+            audit: AuditLog::new(db.clone(), config.clone()),
+            canaries: CanaryCache::new(db.clone()),
+            provider_keys: ProviderKeys::new(db.clone(), config.clone()),
+            db,
+            config,
+            access,
+            dedup: InflightDedup::new(),
+            store,
+            lifecycle,
+            usage,
+            abuse: AbuseDetector::default(),
+            rag,
+        })
+    }
+}
+
+/// Every route, with compression and CORS applied.
+pub fn router(state: AppState) -> Router {
+    let cors = build_cors(state.config.clone());
+    let (upload_limit, body_limit) = {
+        let config = state.config.load();
+        (config.rag_max_upload_bytes, config.max_body_bytes)
+    };
+    let health = health_routes(state.lifecycle.clone());
+    Router::new()
+        .route("/api/v1/chat", post(chat))
+        .route("/api/v1/chat/stream", post(chat_stream))
+        .route("/api/v1/chat/agent", post(agent))
+        .route("/api/v1/conversations", get(list_conversations))
+        .route("/api/v1/conversations/order", put(set_order))
+        .route("/api/v1/conversations/:id/flags", put(set_flags))
+        .route(
+            "/api/v1/conversations/:id/draft",
+            get(get_draft).put(save_draft).delete(delete_draft),
+        )
+        .route(
+            "/api/v1/conversations/:id/tags",
+            get(get_tags).put(set_tags).post(add_tags),
+        )
+        .route("/api/v1/conversations/:id/tags/:tag", delete(remove_tag))
+        .route("/api/v1/conversations/:id/export", get(export_conversation))
+        .route("/api/v1/conversations/:id/regenerate", post(regenerate))
+        .route("/api/v1/messages/:id/feedback", post(submit_feedback))
+        .route(
+            "/api/v1/collections",
+            get(list_collections).post(create_collection),
+        )
+        .route("/api/v1/collections/:id", delete(delete_collection))
+        .route(
+            "/api/v1/collections/:id/documents",
+            get(list_documents)
+                .post(add_document)
+                .layer(DefaultBodyLimit::max(upload_limit)),
+        )
+        .route(
+            "/api/v1/collections/:id/documents/:doc_id",
+            get(get_document).delete(delete_document),
+        )
+        .route("/api/v1/auth/login", post(login))
+        .route("/api/v1/auth/logout", post(logout))
+        .route("/api/v1/admin/overview", get(dashboard_overview))
+        .route(
+            "/api/v1/admin/accounts",
+            get(list_accounts).post(create_account),
+        )
+        .route("/api/v1/admin/accounts/:id", delete(delete_account))
+        .route("/api/v1/admin/users/:id/data", delete(erase_user_data))
+        .route("/api/v1/admin/accounts/:id/usage", get(account_usage))
+        .route("/api/v1/admin/accounts/:id/quota/reset", post(reset_quota))
+        .route(
+            "/api/v1/admin/accounts/:id/quota/override",
+            post(set_limit_override).delete(clear_limit_override),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/models",
+            post(update_account_models),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/status",
+            post(update_account_status),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/guardrail",
+            post(update_account_guardrail),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/residency",
+            post(update_account_residency),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/canary",
+            post(rotate_canary).delete(retire_canary),
+        )
+        .route("/api/v1/admin/canaries", get(list_canaries))
+        .route(
+            "/api/v1/admin/accounts/:id/origins",
+            post(update_account_origins),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/translation",
+            post(update_account_translation),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/limits",
+            post(update_account_limits),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/provider-keys",
+            get(list_provider_keys),
+        )
+        .route(
+            "/api/v1/admin/accounts/:id/provider-keys/:provider",
+            put(set_provider_key).delete(delete_provider_key),
+        )
+        .route(
+            "/api/v1/admin/provider-keys/reencrypt",
+            post(reencrypt_provider_keys),
+        )
+        .route("/api/v1/admin/conversations/:id", get(inspect_conversation))
+        .route("/api/v1/admin/import", post(bulk_import))
+        .route("/api/v1/admin/config/reload", post(reload_config))
+        .route("/api/v1/admin/switches", get(list_switches))
+        .route("/api/v1/admin/abuse", get(list_abuse))
+        .route("/api/v1/admin/abuse/:id", delete(release_abuse_throttle))
+        .route("/api/v1/admin/notifications", get(list_notifications))
+        .route("/api/v1/admin/notifications/read", post(mark_notifications))
+        .route(
+            "/api/v1/admin/webhooks",
+            get(list_webhooks).post(create_webhook),
+        )
+        .route(
+            "/api/v1/admin/webhooks/:id",
+            put(update_webhook).delete(delete_webhook),
+        )
+        .route(
+            "/api/v1/admin/webhooks/:id/secret",
+            post(rotate_webhook_secret),
+        )
+        .route("/api/v1/admin/webhooks/:id/test", post(test_webhook))
+        .route(
+            "/api/v1/admin/webhooks/deliveries",
+            get(list_webhook_deliveries),
+        )
+        .route(
+            "/api/v1/admin/webhooks/deliveries/:id/retry",
+            post(retry_webhook_delivery),
+        )
+        .route(
+            "/api/v1/admin/webhooks/dead-letters",
+            get(list_dead_letters),
+        )
+        .route("/api/v1/admin/audit", get(list_audit))
+        .route("/api/v1/admin/audit/verify", get(verify_audit))
+        .route("/api/v1/admin/switches/maintenance", put(set_maintenance))
+        .route(
+            "/api/v1/admin/switches/providers/:provider",
+            put(set_provider_switch),
+        )
+        .route(
+            "/api/v1/admin/switches/models/:model",
+            put(set_model_switch),
+        )
+        .route("/api/v1/admin/state/export", get(export_state))
+        .route("/api/v1/admin/state/import", post(import_state))
+        .route(
+            "/api/v1/admin/router/health/history",
+            get(router_health_history),
+        )
+        .route(
+            "/api/v1/admin/policies",
+            get(list_policies).post(upsert_policy),
+        )
+        .route("/api/v1/admin/policies/:id", post(upsert_policy))
+        .route("/api/v1/admin/policies/:id/test", post(test_policy))
+        .route("/api/v1/admin/models", get(list_models).post(upsert_model))
+        .route("/api/v1/admin/models/:id", delete(delete_model))
+        .route("/api/v1/admin/models/aliases", post(set_alias))
+        .route("/api/v1/admin/models/aliases/:alias", delete(delete_alias))
+        .route(
+            "/api/v1/admin/models/aliases/:alias/resolve",
+            get(preview_alias),
+        )
+        .route(
+            "/api/v1/admin/models/:id/fallbacks",
+            post(set_fallbacks).delete(delete_fallbacks),
+        )
+        .merge(chaos_routes())
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            record_admin_actions,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            enforce_account_origins,
+        ))
+        .with_state(state)
+        .merge(health)
+        .layer(DefaultBodyLimit::max(body_limit))
+        .layer(compression_layer())
+        .layer(cors)
+}
+
+/// Fault injection controls, only in builds with the `chaos` feature.
+#[cfg(feature = "chaos")]
+fn chaos_routes() -> Router<AppState> {
+    use crate::admin::{get_chaos, set_chaos};
+    Router::new().route("/api/v1/admin/chaos", get(get_chaos).put(set_chaos))
+}
+
+#[cfg(not(feature = "chaos"))]
+fn chaos_routes() -> Router<AppState> {
+    Router::new()
+}
+
+/// Gzip/brotli for JSON payloads such as the admin overview. SSE responses are
+/// excluded: compressors buffer output, which would stall token streaming.
+fn compression_layer() -> CompressionLayer<impl Predicate> {
+    let predicate = SizeAbove::new(1024)
+        .and(NotForContentType::SSE)
+        .and(NotForContentType::IMAGES);
+    CompressionLayer::new()
+        .gzip(true)
+        .br(true)
+        .compress_when(predicate)
+}
+
+fn build_cors(config: SharedConfig) -> CorsLayer {
+    CorsLayer::new()
+        .allow_methods([
+            Method::GET,
+            Method::POST,
+            Method::PUT,
+            Method::DELETE,
+            Method::OPTIONS,
+        ])
+        .allow_headers([axum::http::header::CONTENT_TYPE])
+        .allow_credentials(true)
+        // Checked per request against the live config so reloads apply.
+        .allow_origin(AllowOrigin::predicate(move |origin: &HeaderValue, _| {
+            let config = config.load();
+            let allowed: Vec<&str> = config
+                .allowed_origins
+                .as_deref()
+                .unwrap_or_default()
+                .split(',')
+                .map(str::trim)
+                .filter(|o| !o.is_empty())
+                .collect();
+            // Mirror the request origin when none are configured so local dev
+            // hosts work without ALLOWED_ORIGINS. Production never mirrors.
+            if allowed.is_empty() {
+                return !config.production;
+            }
+            allowed.iter().any(|o| origin.as_bytes() == o.as_bytes())
+        }))
+}
+
+/// What the server runs from the moment it listens: the health endpoints, and
+/// `app` for everything else once startup has set it. Until then other
+/// requests are refused with 503.
+pub fn startup_router(lifecycle: Lifecycle, app: Arc<OnceLock<Router>>) -> Router {
+    health_routes(lifecycle).fallback_service(service_fn(move |request: Request| {
+        let app = app.get().cloned();
+        async move {
+            let response: Response = match app {
+                Some(app) => match app.oneshot(request).await {
+                    Ok(response) => response,
+                    Err(never) => match never {},
+                },
+                None => {
+                    AppError::Unavailable("the server is still starting".into()).into_response()
+                }
+            };
+            Ok::<_, Infallible>(response)
+        }
+    }))
+}
+
+/// `/health` is the readiness check load balancers already use; `/health/live`
+/// only says the process is up, and `/health/ready` shows startup progress.
+fn health_routes(lifecycle: Lifecycle) -> Router {
+    Router::new()
+        .route("/health", get(health))
+        .route("/health/live", get(|| async { "ok" }))
+        .route("/health/ready", get(ready))
+        .with_state(lifecycle)
+}
+
+/// Why the replica shouldn't get traffic, if it shouldn't.
+fn not_ready(lifecycle: &Lifecycle) -> Option<&'static str> {
+    if lifecycle.is_draining() {
+        Some("draining")
+    } else if !lifecycle.startup().is_complete() {
+        Some("starting")
+    } else {
+        None
+    }
+}
+
+async fn health(State(lifecycle): State<Lifecycle>) -> impl IntoResponse {
+    match not_ready(&lifecycle) {
+        Some(reason) => (StatusCode::SERVICE_UNAVAILABLE, reason),
+        None => (StatusCode::OK, "ok"),
+    }
+}
+
+async fn ready(State(lifecycle): State<Lifecycle>) -> impl IntoResponse {
+    let (status, code) = match not_ready(&lifecycle) {
+        Some(reason) => (reason, StatusCode::SERVICE_UNAVAILABLE),
+        None => ("ready", StatusCode::OK),
+    };
+    let body = json!({ "status": status, "stages": lifecycle.startup().stages() });
+    (code, Json(body))
+}
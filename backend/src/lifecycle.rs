@@ -0,0 +1,108 @@
+use crate::startup::Startup;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::watch;
+use tokio_util::task::TaskTracker;
+use tracing::{info, warn};
+
+/// Process lifecycle shared with handlers: how far startup has got, whether we
+/// are draining, and the background tasks (SSE producers and their DB writes)
+/// that must finish before exit.
+#[derive(Clone)]
+pub struct Lifecycle {
+    startup: Startup,
+    draining: Arc<AtomicBool>,
+    tasks: TaskTracker,
+    signal: watch::Sender<bool>,
+}
+
+impl Lifecycle {
+    pub fn new() -> Self {
+        let (signal, _) = watch::channel(false);
+        Self {
+            startup: Startup::new(),
+            draining: Arc::new(AtomicBool::new(false)),
+            tasks: TaskTracker::new(),
+            signal,
+        }
+    }
+
+    pub fn startup(&self) -> &Startup {
+        &self.startup
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    pub fn spawn<F>(&self, task: F)
+    where
+        F: std::future::Future<Output = ()> + Send + 'static,
+    {
+        self.tasks.spawn(task);
+    }
+
+    pub fn begin_drain(&self) {
+        if !self.draining.swap(true, Ordering::SeqCst) {
+            let _ = self.signal.send(true);
+        }
+    }
+
+    /// Resolves once draining has started.
+    pub async fn drained(&self) {
+        let mut rx = self.signal.subscribe();
+        let _ = rx.wait_for(|draining| *draining).await;
+    }
+
+    /// Waits for tracked background tasks, giving up after `timeout`.
+    pub async fn wait_for_tasks(&self, timeout: Duration) {
+        self.tasks.close();
+        if tokio::time::timeout(timeout, self.tasks.wait())
+            .await
+            .is_err()
+        {
+            warn!(
+                "{} background task(s) still running after drain window",
+                self.tasks.len()
+            );
+        }
+    }
+}
+
+impl Default for Lifecycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Resolves on SIGTERM or Ctrl-C.
+pub async fn shutdown_signal() {
+    let ctrl_c = async {
+        let _ = tokio::signal::ctrl_c().await;
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        match tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()) {
+            Ok(mut sig) => {
+                sig.recv().await;
+            }
+            Err(e) => {
+                warn!("failed to install SIGTERM handler: {e}");
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    tokio::select! {
+        _ = ctrl_c => info!("received Ctrl-C, draining"),
+        _ = terminate => info!("received SIGTERM, draining"),
+    }
+}
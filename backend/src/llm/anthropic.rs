@@ -1,16 +1,121 @@
-use super::{LlmClient, LlmError, LlmMessage, LlmRequest, LlmResponse, Provider, Role};
+use super::{
+    LlmClient, LlmError, LlmMessage, LlmRequest, LlmResponse, Provider, Role, ToolCall,
+    ToolRequest, ToolResponse, ToolTurn,
+};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone)]
 pub struct AnthropicClient {
     api_key: String,
+    base_url: String,
     http: reqwest::Client,
 }
 
 impl AnthropicClient {
-    pub fn new(api_key: String, http: reqwest::Client) -> Self {
-        Self { api_key, http }
+    pub fn new(api_key: String, base_url: &str, http: reqwest::Client) -> Self {
+        Self {
+            api_key,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http,
+        }
+    }
+
+    /// One turn of a tool-calling loop over the messages API.
+    pub async fn chat_tools(&self, req: ToolRequest) -> Result<ToolResponse, LlmError> {
+        let (system, messages) = map_tool_turns(&req.messages)?;
+        let payload = AnthropicToolChatRequest {
+            model: req.model.clone(),
+            system,
+            messages,
+            tools: req
+                .tools
+                .iter()
+                .map(|t| AnthropicTool {
+                    name: t.name.clone(),
+                    description: t.description.clone(),
+                    input_schema: t.parameters.clone(),
+                })
+                .collect(),
+            max_tokens: req.max_tokens.unwrap_or(512),
+            temperature: req.temperature,
+        };
+
+        let response = self
+            .http
+            .post(format!("{}/messages", self.base_url))
+            .header("x-api-key", &self.api_key)
+            .header("anthropic-version", "2023-06-01")
+            .json(&payload)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(LlmError::UnexpectedStatus(status, body));
+        }
+
+        let body: AnthropicChatResponse = response.json().await?;
+        let mut content = String::new();
+        let mut calls = Vec::new();
+        for block in body.content {
+            match block.kind.as_str() {
+                "text" => content.push_str(&block.text.unwrap_or_default()),
+                "tool_use" => calls.push(ToolCall {
+                    id: block.id.unwrap_or_default(),
+                    name: block.name.unwrap_or_default(),
+                    arguments: block.input.unwrap_or_default(),
+                }),
+                _ => {}
+            }
+        }
+
+        let tokens_input = body.usage.as_ref().map(|u| u.input_tokens);
+        let tokens_output = body.usage.as_ref().map(|u| u.output_tokens);
+        let cost =
+            super::estimate_cost(Provider::Anthropic, &req.model, tokens_input, tokens_output);
+
+        Ok(ToolResponse {
+            provider: Provider::Anthropic,
+            model: req.model,
+            content,
+            calls,
+            tokens_input,
+            tokens_output,
+            cost,
+        })
+    }
+
+    /// Ids of every model the key can use, following pagination.
+    pub async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        let mut ids = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            let mut request = self
+                .http
+                .get(format!("{}/models", self.base_url))
+                .header("x-api-key", &self.api_key)
+                .header("anthropic-version", "2023-06-01")
+                .query(&[("limit", "1000")]);
+            if let Some(after_id) = &after {
+                request = request.query(&[("after_id", after_id)]);
+            }
+            let response = request.send().await?;
+
+            if !response.status().is_success() {
+                let status = response.status();
+                let body = response.text().await.unwrap_or_default();
+                return Err(LlmError::UnexpectedStatus(status, body));
+            }
+
+            let page: AnthropicModelList = response.json().await?;
+            ids.extend(page.data.into_iter().map(|m| m.id));
+            match page.last_id {
+                Some(last) if page.has_more => after = Some(last),
+                _ => return Ok(ids),
+            }
+        }
     }
 }
 
@@ -31,7 +136,7 @@ impl LlmClient for AnthropicClient {
 
         let response = self
             .http
-            .post("https://api.anthropic.com/v1/messages")
+            .post(format!("{}/messages", self.base_url))
             .header("x-api-key", &self.api_key)
             .header("anthropic-version", "2023-06-01")
             .json(&payload)
@@ -102,6 +207,65 @@ fn map_messages(messages: &[LlmMessage]) -> Result<Vec<AnthropicMessage>, LlmErr
     Ok(mapped)
 }
 
+/// Splits out the system prompt and maps the rest to content blocks. Results
+/// for one turn's calls share a user message, as the API requires.
+fn map_tool_turns(
+    turns: &[ToolTurn],
+) -> Result<(Option<String>, Vec<AnthropicToolMessage>), LlmError> {
+    let mut system_parts = Vec::new();
+    let mut mapped: Vec<AnthropicToolMessage> = Vec::new();
+    for turn in turns {
+        let (role, block) = match turn {
+            ToolTurn::Message(m) if m.role == Role::System => {
+                system_parts.push(m.content.clone());
+                continue;
+            }
+            ToolTurn::Message(m) => (
+                m.role.as_anthropic()?,
+                AnthropicBlock::Text {
+                    text: m.content.clone(),
+                },
+            ),
+            ToolTurn::Calls { content, calls } => {
+                let mut blocks = Vec::with_capacity(calls.len() + 1);
+                if !content.is_empty() {
+                    blocks.push(AnthropicBlock::Text {
+                        text: content.clone(),
+                    });
+                }
+                blocks.extend(calls.iter().map(|c| AnthropicBlock::ToolUse {
+                    id: c.id.clone(),
+                    name: c.name.clone(),
+                    input: c.arguments.clone(),
+                }));
+                mapped.push(AnthropicToolMessage {
+                    role: "assistant".into(),
+                    content: blocks,
+                });
+                continue;
+            }
+            ToolTurn::Result { call_id, content } => (
+                "user",
+                AnthropicBlock::ToolResult {
+                    tool_use_id: call_id.clone(),
+                    content: content.clone(),
+                },
+            ),
+        };
+        match mapped.last_mut() {
+            Some(last) if last.role == role && matches!(turn, ToolTurn::Result { .. }) => {
+                last.content.push(block)
+            }
+            _ => mapped.push(AnthropicToolMessage {
+                role: role.to_string(),
+                content: vec![block],
+            }),
+        }
+    }
+    let system = (!system_parts.is_empty()).then(|| system_parts.join("\n"));
+    Ok((system, mapped))
+}
+
 #[derive(Debug, Serialize)]
 struct AnthropicChatRequest {
     model: String,
@@ -133,11 +297,60 @@ struct AnthropicChatResponse {
     usage: Option<AnthropicUsage>,
 }
 
+#[derive(Debug, Serialize)]
+struct AnthropicToolChatRequest {
+    model: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    system: Option<String>,
+    messages: Vec<AnthropicToolMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<AnthropicTool>,
+    max_tokens: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicToolMessage {
+    role: String,
+    content: Vec<AnthropicBlock>,
+}
+
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum AnthropicBlock {
+    Text {
+        text: String,
+    },
+    ToolUse {
+        id: String,
+        name: String,
+        input: serde_json::Value,
+    },
+    ToolResult {
+        tool_use_id: String,
+        content: String,
+    },
+}
+
+#[derive(Debug, Serialize)]
+struct AnthropicTool {
+    name: String,
+    description: String,
+    input_schema: serde_json::Value,
+}
+
 #[derive(Debug, Deserialize)]
 struct AnthropicContent {
     #[serde(rename = "type")]
-    _type: String,
+    kind: String,
     text: Option<String>,
+    #[serde(default)]
+    id: Option<String>,
+    #[serde(default)]
+    name: Option<String>,
+    #[serde(default)]
+    input: Option<serde_json::Value>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -145,3 +358,17 @@ struct AnthropicUsage {
     input_tokens: u32,
     output_tokens: u32,
 }
+
+#[derive(Debug, Deserialize)]
+struct AnthropicModelList {
+    data: Vec<AnthropicModel>,
+    #[serde(default)]
+    has_more: bool,
+    #[serde(default)]
+    last_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AnthropicModel {
+    id: String,
+}
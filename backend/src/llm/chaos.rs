@@ -0,0 +1,130 @@
+//! Fault injection for resilience testing (feature `chaos`). While enabled, a
+//! share of provider calls fail the way real providers do, so retries,
+//! fallbacks, health tracking and stream error handling can be watched under
+//! failure. Off at startup and held per replica; the admin API toggles it.
+
+use super::{LlmError, Provider};
+use arc_swap::ArcSwap;
+use rand::{Rng, seq::SliceRandom};
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+const ALL_FAULTS: &[Fault] = &[Fault::Timeout, Fault::RateLimited, Fault::Malformed];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Fault {
+    /// Hangs for `timeout_ms`, then fails like a request that timed out.
+    Timeout,
+    /// A 429 from the provider.
+    RateLimited,
+    /// A success status with a body that doesn't parse.
+    Malformed,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ChaosSettings {
+    pub enabled: bool,
+    /// Share of provider calls that fail, from 0 to 1.
+    pub rate: f64,
+    /// Faults to draw from; every kind when empty.
+    #[serde(default)]
+    pub faults: Vec<Fault>,
+    /// Providers whose calls fail; every provider when empty.
+    #[serde(default)]
+    pub providers: Vec<Provider>,
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+}
+
+fn default_timeout_ms() -> u64 {
+    5_000
+}
+
+impl Default for ChaosSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            rate: 0.0,
+            faults: Vec::new(),
+            providers: Vec::new(),
+            timeout_ms: default_timeout_ms(),
+        }
+    }
+}
+
+/// The settings and how many faults of each kind were injected since they
+/// were last changed.
+#[derive(Debug, Serialize)]
+pub struct ChaosStatus {
+    #[serde(flatten)]
+    pub settings: ChaosSettings,
+    pub injected: BTreeMap<Fault, u64>,
+}
+
+#[derive(Clone, Default)]
+pub struct Chaos {
+    settings: Arc<ArcSwap<ChaosSettings>>,
+    injected: Arc<Mutex<BTreeMap<Fault, u64>>>,
+}
+
+impl Chaos {
+    pub fn status(&self) -> ChaosStatus {
+        ChaosStatus {
+            settings: ChaosSettings::clone(&self.settings.load()),
+            injected: self.counts().clone(),
+        }
+    }
+
+    /// Replaces the settings and resets the counts.
+    pub fn set(&self, settings: ChaosSettings) -> ChaosStatus {
+        self.settings.store(Arc::new(settings));
+        self.counts().clear();
+        self.status()
+    }
+
+    /// The error this call should fail with, if it was picked to fail.
+    pub(super) async fn inject(&self, provider: Provider) -> Option<LlmError> {
+        let settings = self.settings.load_full();
+        if !settings.enabled
+            || (!settings.providers.is_empty() && !settings.providers.contains(&provider))
+        {
+            return None;
+        }
+        let fault = {
+            let mut rng = rand::thread_rng();
+            if !rng.gen_bool(settings.rate.clamp(0.0, 1.0)) {
+                return None;
+            }
+            let faults = if settings.faults.is_empty() {
+                ALL_FAULTS
+            } else {
+                &settings.faults
+            };
+            *faults.choose(&mut rng)?
+        };
+        *self.counts().entry(fault).or_default() += 1;
+        Some(match fault {
+            Fault::Timeout => {
+                tokio::time::sleep(Duration::from_millis(settings.timeout_ms)).await;
+                LlmError::Provider(format!("{provider} request timed out (injected)"))
+            }
+            Fault::RateLimited => LlmError::UnexpectedStatus(
+                StatusCode::TOO_MANY_REQUESTS,
+                format!("{provider} rate limit exceeded (injected)"),
+            ),
+            Fault::Malformed => LlmError::Provider(format!(
+                "error decoding {provider} response body (injected)"
+            )),
+        })
+    }
+
+    fn counts(&self) -> std::sync::MutexGuard<'_, BTreeMap<Fault, u64>> {
+        self.injected.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
@@ -0,0 +1,294 @@
+use super::{Embeddings, LlmError, LlmMessage, LlmRequest, LlmResponse, Provider};
+use crate::pii::redact;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+use tracing::{debug, warn};
+
+/// Whether provider calls are captured to disk or served from it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum FixtureMode {
+    #[default]
+    Off,
+    /// Call the provider and save each request/response pair.
+    Record,
+    /// Answer from saved pairs only; nothing reaches a provider.
+    Replay,
+}
+
+impl FixtureMode {
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "off" => Some(FixtureMode::Off),
+            "record" => Some(FixtureMode::Record),
+            "replay" => Some(FixtureMode::Replay),
+            _ => None,
+        }
+    }
+}
+
+/// Recorded provider interactions, one JSON file per distinct request. Files
+/// are named by a hash of the request so the same prompt always maps to the
+/// same fixture; the stored copies are PII-scrubbed.
+pub struct Fixtures {
+    pub mode: FixtureMode,
+    dir: PathBuf,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Fixture<Req, Resp> {
+    kind: String,
+    provider: Provider,
+    model: String,
+    request: Req,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    response: Option<Resp>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<RecordedError>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct ChatRequestRecord {
+    messages: Vec<LlmMessage>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct EmbedRequestRecord {
+    inputs: Vec<String>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct RecordedError {
+    /// Upstream HTTP status; absent for transport failures.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    status: Option<u16>,
+    message: String,
+}
+
+impl Fixtures {
+    pub fn new(mode: FixtureMode, dir: &Path) -> Self {
+        Self {
+            mode,
+            dir: dir.to_path_buf(),
+        }
+    }
+
+    pub async fn replay_chat(&self, req: &LlmRequest) -> Result<LlmResponse, LlmError> {
+        let fixture: Fixture<ChatRequestRecord, LlmResponse> =
+            self.read(&chat_path(self, req)).await?;
+        replayed(fixture)
+    }
+
+    pub async fn replay_embed(
+        &self,
+        provider: Provider,
+        model: &str,
+        inputs: &[String],
+    ) -> Result<Embeddings, LlmError> {
+        let fixture: Fixture<EmbedRequestRecord, Embeddings> = self
+            .read(&embed_path(self, provider, model, inputs))
+            .await?;
+        replayed(fixture)
+    }
+
+    pub async fn record_chat(&self, req: &LlmRequest, result: &Result<LlmResponse, LlmError>) {
+        let Some(outcome) = outcome(result) else {
+            return;
+        };
+        let fixture = Fixture {
+            kind: "chat".into(),
+            provider: req.provider,
+            model: req.model.clone(),
+            request: ChatRequestRecord {
+                messages: req
+                    .messages
+                    .iter()
+                    .map(|m| LlmMessage {
+                        role: m.role,
+                        content: scrub(&m.content),
+                    })
+                    .collect(),
+                max_tokens: req.max_tokens,
+                temperature: req.temperature,
+            },
+            response: outcome.as_ref().ok().map(|resp| LlmResponse {
+                content: scrub(&resp.content),
+                ..(*resp).clone()
+            }),
+            error: outcome.err(),
+        };
+        self.write(&chat_path(self, req), &fixture).await;
+    }
+
+    pub async fn record_embed(
+        &self,
+        provider: Provider,
+        model: &str,
+        inputs: &[String],
+        result: &Result<Embeddings, LlmError>,
+    ) {
+        let Some(outcome) = outcome(result) else {
+            return;
+        };
+        let fixture = Fixture {
+            kind: "embed".into(),
+            provider,
+            model: model.to_string(),
+            request: EmbedRequestRecord {
+                inputs: inputs.iter().map(|i| scrub(i)).collect(),
+            },
+            response: outcome.as_ref().ok().map(|e| (*e).clone()),
+            error: outcome.err(),
+        };
+        self.write(&embed_path(self, provider, model, inputs), &fixture)
+            .await;
+    }
+
+    async fn read<T: for<'de> Deserialize<'de>>(&self, path: &Path) -> Result<T, LlmError> {
+        let text = tokio::fs::read_to_string(path).await.map_err(|e| {
+            LlmError::Provider(format!("no recorded fixture {} ({e})", path.display()))
+        })?;
+        serde_json::from_str(&text)
+            .map_err(|e| LlmError::Provider(format!("unreadable fixture {}: {e}", path.display())))
+    }
+
+    /// Saves a fixture, warning when it replaces one with a different outcome.
+    /// Recording never fails the request.
+    async fn write<Req, Resp>(&self, path: &Path, fixture: &Fixture<Req, Resp>)
+    where
+        Req: Serialize,
+        Resp: Serialize + for<'de> Deserialize<'de>,
+    {
+        if let Ok(previous) = self.read::<Fixture<serde_json::Value, Resp>>(path).await {
+            let drift = drift(&previous, fixture);
+            if !drift.is_empty() {
+                warn!("fixture {} drifted: {}", path.display(), drift.join("; "));
+            }
+        }
+        let result = async {
+            tokio::fs::create_dir_all(&self.dir).await?;
+            let json = serde_json::to_vec_pretty(fixture).map_err(std::io::Error::other)?;
+            let tmp = path.with_extension("json.tmp");
+            tokio::fs::write(&tmp, json).await?;
+            tokio::fs::rename(&tmp, path).await
+        }
+        .await;
+        match result {
+            Ok(()) => debug!("recorded fixture {}", path.display()),
+            Err(e) => warn!("failed to record fixture {}: {e}", path.display()),
+        }
+    }
+}
+
+/// Differences a re-recording shouldn't show. Reply text is expected to vary
+/// between runs, so only the shape of the outcome is compared.
+fn drift<A, B, R>(old: &Fixture<A, R>, new: &Fixture<B, R>) -> Vec<String>
+where
+    R: Serialize,
+{
+    let describe = |error: &Option<RecordedError>| match error.as_ref().map(|e| e.status) {
+        Some(Some(status)) => format!("error {status}"),
+        Some(None) => "error".to_string(),
+        None => "ok".to_string(),
+    };
+    let mut changes = Vec::new();
+    let (was, now) = (describe(&old.error), describe(&new.error));
+    if was != now {
+        changes.push(format!("outcome {was} -> {now}"));
+    }
+    if let (Some(old), Some(new)) = (&old.response, &new.response) {
+        let fields = |r: &R| serde_json::to_value(r).unwrap_or_default();
+        let (old, new) = (fields(old), fields(new));
+        for field in ["model", "provider"] {
+            if old.get(field) != new.get(field) {
+                changes.push(format!("{field} {} -> {}", old[field], new[field]));
+            }
+        }
+        for field in ["tokens_input", "tokens_output"] {
+            if old.get(field).is_some_and(|v| !v.is_null())
+                != new.get(field).is_some_and(|v| !v.is_null())
+            {
+                changes.push(format!("{field} reporting changed"));
+            }
+        }
+    }
+    changes
+}
+
+/// The outcome worth saving: provider answers and provider errors, not local
+/// problems like a missing key.
+fn outcome<T>(result: &Result<T, LlmError>) -> Option<Result<&T, RecordedError>> {
+    match result {
+        Ok(value) => Some(Ok(value)),
+        Err(LlmError::UnexpectedStatus(status, body)) => Some(Err(RecordedError {
+            status: Some(status.as_u16()),
+            message: scrub(body),
+        })),
+        Err(LlmError::Http(e)) => Some(Err(RecordedError {
+            status: None,
+            message: scrub(&e.to_string()),
+        })),
+        Err(LlmError::Provider(msg)) => Some(Err(RecordedError {
+            status: None,
+            message: scrub(msg),
+        })),
+        Err(LlmError::MissingApiKey(_) | LlmError::InvalidRequest(_)) => None,
+    }
+}
+
+fn replayed<Req, Resp>(fixture: Fixture<Req, Resp>) -> Result<Resp, LlmError> {
+    match (fixture.response, fixture.error) {
+        (
+            _,
+            Some(RecordedError {
+                status: Some(status),
+                message,
+            }),
+        ) => Err(LlmError::UnexpectedStatus(
+            StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY),
+            message,
+        )),
+        (_, Some(RecordedError { message, .. })) => Err(LlmError::Provider(message)),
+        (Some(response), None) => Ok(response),
+        (None, None) => Err(LlmError::Provider(
+            "fixture has neither a response nor an error".into(),
+        )),
+    }
+}
+
+fn scrub(text: &str) -> String {
+    redact(text).0
+}
+
+fn chat_path(fixtures: &Fixtures, req: &LlmRequest) -> PathBuf {
+    let key = fixture_key(&serde_json::json!({
+        "provider": req.provider,
+        "model": req.model,
+        "messages": req.messages,
+        "max_tokens": req.max_tokens,
+        "temperature": req.temperature,
+    }));
+    fixtures
+        .dir
+        .join(format!("chat-{}-{key}.json", req.provider))
+}
+
+fn embed_path(fixtures: &Fixtures, provider: Provider, model: &str, inputs: &[String]) -> PathBuf {
+    let key = fixture_key(&serde_json::json!({
+        "provider": provider,
+        "model": model,
+        "inputs": inputs,
+    }));
+    fixtures.dir.join(format!("embed-{provider}-{key}.json"))
+}
+
+/// Hash of the unscrubbed request, so lookups match exactly what was sent.
+fn fixture_key(request: &serde_json::Value) -> String {
+    let digest = Sha256::digest(request.to_string());
+    hex::encode(&digest[..8])
+}
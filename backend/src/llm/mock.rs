@@ -0,0 +1,225 @@
+use super::{
+    Embeddings, LlmClient, LlmError, LlmRequest, LlmResponse, Provider, Role, ToolCall,
+    ToolRequest, ToolResponse, ToolTurn,
+};
+use async_trait::async_trait;
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+/// Dimensions of the vectors `embed` returns.
+const EMBEDDING_DIMS: usize = 256;
+
+/// Answers without a network call so the whole pipeline runs without API keys.
+/// Replies echo the last user message, and any model id is accepted. Failures
+/// are spread evenly across calls instead of drawn at random, so a run fails
+/// the same calls every time.
+#[derive(Clone)]
+pub struct MockClient {
+    latency: Duration,
+    failure_rate: f64,
+    calls: Arc<AtomicU64>,
+}
+
+impl MockClient {
+    pub fn new(latency_ms: u64, failure_rate: f64) -> Self {
+        Self {
+            latency: Duration::from_millis(latency_ms),
+            failure_rate: failure_rate.clamp(0.0, 1.0),
+            calls: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    /// Waits out the configured latency and decides whether this call fails:
+    /// call `n` fails when the running count of expected failures ticks over,
+    /// so exactly `failure_rate` of calls fail.
+    async fn simulate(&self, what: &str) -> Result<(), LlmError> {
+        if !self.latency.is_zero() {
+            tokio::time::sleep(self.latency).await;
+        }
+        let n = self.calls.fetch_add(1, Ordering::Relaxed) as f64;
+        if (n * self.failure_rate).floor() != ((n + 1.0) * self.failure_rate).floor() {
+            return Err(LlmError::Provider(format!(
+                "mock provider: simulated {what} failure"
+            )));
+        }
+        Ok(())
+    }
+
+    /// Calls the offered tools named in the last user message, one per turn in
+    /// the order they appear there, then answers with the last tool result.
+    /// A tool's first required argument gets the text after its name, up to
+    /// the end of the line or a `;`.
+    pub async fn chat_tools(&self, req: ToolRequest) -> Result<ToolResponse, LlmError> {
+        self.simulate("chat").await?;
+        let last_user = req
+            .messages
+            .iter()
+            .rposition(|t| matches!(t, ToolTurn::Message(m) if m.role == Role::User));
+        let prompt = match last_user.map(|i| &req.messages[i]) {
+            Some(ToolTurn::Message(m)) => m.content.as_str(),
+            _ => "",
+        };
+        let since = &req.messages[last_user.map_or(0, |i| i + 1)..];
+        let called: Vec<&str> = since
+            .iter()
+            .flat_map(|t| match t {
+                ToolTurn::Calls { calls, .. } => calls.iter().map(|c| c.name.as_str()).collect(),
+                _ => Vec::new(),
+            })
+            .collect();
+        let mut named: Vec<(usize, &super::ToolSpec)> = req
+            .tools
+            .iter()
+            .filter_map(|t| prompt.find(&t.name).map(|at| (at, t)))
+            .collect();
+        named.sort_by_key(|(at, _)| *at);
+        let next = named
+            .into_iter()
+            .find(|(_, t)| !called.contains(&t.name.as_str()));
+
+        let tokens_input = Some(
+            req.messages
+                .iter()
+                .map(|t| match t {
+                    ToolTurn::Message(m) => estimate_tokens(&m.content),
+                    ToolTurn::Calls { content, calls } => {
+                        estimate_tokens(content)
+                            + calls
+                                .iter()
+                                .map(|c| estimate_tokens(&c.arguments.to_string()))
+                                .sum::<u32>()
+                    }
+                    ToolTurn::Result { content, .. } => estimate_tokens(content),
+                })
+                .sum(),
+        );
+        let (content, calls) = match next {
+            Some((at, tool)) => {
+                let rest = prompt[at + tool.name.len()..].trim_start_matches([':', ' ']);
+                let input = rest.split(['\n', ';']).next().unwrap_or_default().trim();
+                let mut arguments = serde_json::Map::new();
+                if let Some(param) = tool.parameters["required"][0].as_str() {
+                    arguments.insert(param.to_string(), input.into());
+                }
+                let call = ToolCall {
+                    id: format!("call_{}", called.len() + 1),
+                    name: tool.name.clone(),
+                    arguments: arguments.into(),
+                };
+                (String::new(), vec![call])
+            }
+            None => {
+                let result = since.iter().rev().find_map(|t| match t {
+                    ToolTurn::Result { content, .. } => Some(content.as_str()),
+                    _ => None,
+                });
+                (
+                    format!("[mock {}] {}", req.model, result.unwrap_or(prompt)),
+                    Vec::new(),
+                )
+            }
+        };
+        let tokens_output = Some(
+            estimate_tokens(&content)
+                + calls
+                    .iter()
+                    .map(|c| estimate_tokens(&c.arguments.to_string()))
+                    .sum::<u32>(),
+        );
+        let cost = super::estimate_cost(Provider::Mock, &req.model, tokens_input, tokens_output);
+
+        Ok(ToolResponse {
+            provider: Provider::Mock,
+            model: req.model,
+            content,
+            calls,
+            tokens_input,
+            tokens_output,
+            cost,
+        })
+    }
+
+    /// Hashed bag of words: texts sharing words get similar vectors, which is
+    /// enough for retrieval to return sensible chunks.
+    pub async fn embed(&self, _model: &str, inputs: Vec<String>) -> Result<Embeddings, LlmError> {
+        self.simulate("embedding").await?;
+        let tokens: u32 = inputs.iter().map(|i| estimate_tokens(i)).sum();
+        let vectors = inputs
+            .iter()
+            .map(|input| {
+                let mut vector = vec![0f32; EMBEDDING_DIMS];
+                for word in input.split(|c: char| !c.is_alphanumeric()) {
+                    if !word.is_empty() {
+                        let hash = fnv1a(&word.to_lowercase());
+                        vector[hash as usize % EMBEDDING_DIMS] += 1.0;
+                    }
+                }
+                let norm = vector.iter().map(|v| v * v).sum::<f32>().sqrt();
+                if norm > 0.0 {
+                    vector.iter_mut().for_each(|v| *v /= norm);
+                }
+                vector
+            })
+            .collect();
+        Ok(Embeddings {
+            vectors,
+            tokens_input: Some(tokens),
+        })
+    }
+}
+
+#[async_trait]
+impl LlmClient for MockClient {
+    async fn chat(&self, req: LlmRequest) -> Result<LlmResponse, LlmError> {
+        self.simulate("chat").await?;
+        let prompt = req
+            .messages
+            .iter()
+            .rev()
+            .find(|m| m.role == Role::User)
+            .map(|m| m.content.as_str())
+            .unwrap_or_default();
+        let mut content = format!("[mock {}] {prompt}", req.model);
+        if let Some(max) = req.max_tokens {
+            // Roughly honour the token limit, cutting on a char boundary.
+            let limit = max as usize * 4;
+            if let Some((idx, _)) = content.char_indices().nth(limit) {
+                content.truncate(idx);
+            }
+        }
+
+        let tokens_input = Some(
+            req.messages
+                .iter()
+                .map(|m| estimate_tokens(&m.content))
+                .sum(),
+        );
+        let tokens_output = Some(estimate_tokens(&content));
+        let cost = super::estimate_cost(Provider::Mock, &req.model, tokens_input, tokens_output);
+
+        Ok(LlmResponse {
+            provider: Provider::Mock,
+            model: req.model,
+            content,
+            tokens_input,
+            tokens_output,
+            cost,
+        })
+    }
+}
+
+/// About four characters per token, like the providers' English average.
+fn estimate_tokens(text: &str) -> u32 {
+    text.chars().count().div_ceil(4).max(1) as u32
+}
+
+fn fnv1a(text: &str) -> u64 {
+    text.bytes().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
@@ -1,21 +1,47 @@
 mod anthropic;
+#[cfg(feature = "chaos")]
+mod chaos;
+mod fixtures;
+mod mock;
 mod openai;
 
-use crate::config::Config;
+use crate::{
+    config::Config,
+    db::Db,
+    error::AppError,
+    notifications::{self, Notice},
+    rag::RetrievalOptions,
+};
+use arc_swap::ArcSwap;
 use async_trait::async_trait;
+use futures_util::future::BoxFuture;
 use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
-use std::fmt;
+use std::{
+    fmt, fs,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
 use thiserror::Error;
+use tracing::warn;
 
 pub use anthropic::AnthropicClient;
+#[cfg(feature = "chaos")]
+pub use chaos::{Chaos, ChaosSettings, ChaosStatus, Fault};
+pub use fixtures::{FixtureMode, Fixtures};
+pub use mock::MockClient;
 pub use openai::OpenAiClient;
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq, Hash)]
 #[serde(rename_all = "lowercase")]
 pub enum Provider {
     Openai,
     Anthropic,
+    /// Built-in canned responses for development and CI.
+    Mock,
 }
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
@@ -61,6 +87,9 @@ pub struct LlmRequest {
     pub messages: Vec<LlmMessage>,
     pub max_tokens: Option<u32>,
     pub temperature: Option<f32>,
+    /// Collections to search; matching chunks are added to the prompt as sources.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retrieval: Option<RetrievalOptions>,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -73,6 +102,67 @@ pub struct LlmResponse {
     pub cost: Option<f64>,
 }
 
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Embeddings {
+    /// One vector per input, in input order.
+    pub vectors: Vec<Vec<f32>>,
+    pub tokens_input: Option<u32>,
+}
+
+/// A function the model may call, with a JSON Schema for its arguments.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ToolSpec {
+    pub name: String,
+    pub description: String,
+    pub parameters: serde_json::Value,
+}
+
+/// A call the model asked for. `id` pairs it with its result.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: serde_json::Value,
+}
+
+/// One entry of a tool-calling transcript.
+#[derive(Clone, Debug)]
+pub enum ToolTurn {
+    Message(LlmMessage),
+    /// An assistant turn asking for tools, with any text it wrote alongside.
+    Calls {
+        content: String,
+        calls: Vec<ToolCall>,
+    },
+    /// What a tool returned for the call with id `call_id`.
+    Result {
+        call_id: String,
+        content: String,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct ToolRequest {
+    pub provider: Provider,
+    pub model: String,
+    pub messages: Vec<ToolTurn>,
+    pub tools: Vec<ToolSpec>,
+    pub max_tokens: Option<u32>,
+    pub temperature: Option<f32>,
+}
+
+/// A model turn that either answers (`calls` empty) or asks for tools.
+#[derive(Clone, Debug, Serialize)]
+pub struct ToolResponse {
+    pub provider: Provider,
+    pub model: String,
+    pub content: String,
+    pub calls: Vec<ToolCall>,
+    pub tokens_input: Option<u32>,
+    pub tokens_output: Option<u32>,
+    pub cost: Option<f64>,
+}
+
 #[derive(Debug, Error)]
 pub enum LlmError {
     #[error("missing API key: {0}")]
@@ -83,11 +173,20 @@ pub enum LlmError {
     Http(#[from] reqwest::Error),
     #[error("upstream error {0}: {1}")]
     UnexpectedStatus(StatusCode, String),
-    #[allow(dead_code)]
     #[error("provider error: {0}")]
     Provider(String),
 }
 
+impl LlmError {
+    /// The provider refused the API key itself.
+    fn is_auth_failure(&self) -> bool {
+        matches!(
+            self,
+            LlmError::UnexpectedStatus(StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN, _)
+        )
+    }
+}
+
 #[async_trait]
 pub trait LlmClient: Send + Sync {
     async fn chat(&self, req: LlmRequest) -> Result<LlmResponse, LlmError>;
@@ -95,47 +194,412 @@ pub trait LlmClient: Send + Sync {
 
 #[derive(Clone)]
 pub struct LlmService {
-    openai: Option<OpenAiClient>,
-    anthropic: Option<AnthropicClient>,
+    clients: Arc<ArcSwap<ProviderClients>>,
+    /// Where key rotation alerts go; unset for one-off uses like config checks.
+    notify: Option<Db>,
+    /// Kept across reconfiguration, unlike the clients.
+    #[cfg(feature = "chaos")]
+    chaos: Chaos,
 }
 
-impl LlmService {
-    pub fn new(config: &Config) -> Self {
-        let http = reqwest::Client::builder()
-            .build()
-            .expect("failed to build http client");
+struct ProviderClients {
+    openai: Option<KeyPair<OpenAiClient>>,
+    anthropic: Option<KeyPair<AnthropicClient>>,
+    mock: Option<MockClient>,
+    fixtures: Fixtures,
+    /// For clients built per call around an account's own key.
+    http: reqwest::Client,
+    openai_base_url: String,
+    anthropic_base_url: String,
+}
 
-        let openai = config
-            .openai_api_key
-            .as_ref()
-            .map(|key| OpenAiClient::new(key.clone(), http.clone()));
+/// A provider's client for its primary key and, during a rotation, one for the
+/// secondary key that's tried when the primary is rejected.
+struct KeyPair<C> {
+    primary: C,
+    secondary: Option<C>,
+    /// Set on the first rejection so the alert fires once per configuration.
+    primary_rejected: AtomicBool,
+}
+
+impl<C> KeyPair<C> {
+    fn new(primary: String, secondary: Option<&String>, build: impl Fn(String) -> C) -> Self {
+        Self {
+            primary: build(primary),
+            secondary: secondary.cloned().map(&build),
+            primary_rejected: AtomicBool::new(false),
+        }
+    }
+}
 
-        let anthropic = config
-            .anthropic_api_key
+impl ProviderClients {
+    fn mock(&self) -> Result<&MockClient, LlmError> {
+        self.mock
             .as_ref()
-            .map(|key| AnthropicClient::new(key.clone(), http));
+            .ok_or_else(|| LlmError::MissingApiKey("mock provider disabled (MOCK_PROVIDER)".into()))
+    }
+
+    fn new(config: &Config) -> Result<Self, AppError> {
+        let http = egress_client(config)?;
+        let openai = config.openai_api_key.clone().map(|key| {
+            KeyPair::new(key, config.openai_api_key_secondary.as_ref(), |key| {
+                OpenAiClient::new(key, &config.openai_base_url, http.clone())
+            })
+        });
+        let anthropic = config.anthropic_api_key.clone().map(|key| {
+            KeyPair::new(key, config.anthropic_api_key_secondary.as_ref(), |key| {
+                AnthropicClient::new(key, &config.anthropic_base_url, http.clone())
+            })
+        });
+
+        let mock = config
+            .mock_provider
+            .then(|| MockClient::new(config.mock_latency_ms, config.mock_failure_rate));
+
+        Ok(Self {
+            openai,
+            anthropic,
+            mock,
+            fixtures: Fixtures::new(config.llm_fixtures, &config.llm_fixtures_dir),
+            http,
+            openai_base_url: config.openai_base_url.clone(),
+            anthropic_base_url: config.anthropic_base_url.clone(),
+        })
+    }
+}
+
+/// The HTTP client for provider traffic, routed through the egress proxy and
+/// presenting the client certificate when those are configured.
+fn egress_client(config: &Config) -> Result<reqwest::Client, AppError> {
+    let read = |path: &Path, what: &str| {
+        fs::read(path)
+            .map_err(|e| AppError::Config(format!("cannot read {what} {}: {e}", path.display())))
+    };
+    let mut builder = reqwest::Client::builder();
+    if let Some(proxy) = &config.egress_proxy {
+        let proxy = reqwest::Proxy::all(proxy)
+            .map_err(|e| AppError::Config(format!("invalid LLM_PROXY_URL: {e}")))?;
+        builder = builder.proxy(proxy);
+    }
+    if let Some(path) = &config.egress_ca_cert {
+        let cert = reqwest::Certificate::from_pem(&read(path, "CA certificate")?)
+            .map_err(|e| AppError::Config(format!("invalid LLM_CA_CERT: {e}")))?;
+        builder = builder.add_root_certificate(cert);
+    }
+    match (&config.egress_client_cert, &config.egress_client_key) {
+        (Some(cert), Some(key)) => {
+            let identity = reqwest::Identity::from_pkcs8_pem(
+                &read(cert, "client certificate")?,
+                &read(key, "client key")?,
+            )
+            .map_err(|e| AppError::Config(format!("invalid client certificate or key: {e}")))?;
+            builder = builder.identity(identity);
+        }
+        (None, None) => {}
+        _ => {
+            return Err(AppError::Config(
+                "LLM_CLIENT_CERT and LLM_CLIENT_KEY must be set together".into(),
+            ));
+        }
+    }
+    builder
+        .build()
+        .map_err(|e| AppError::Config(format!("failed to build http client: {e}")))
+}
+
+impl LlmService {
+    pub fn new(config: &Config) -> Result<Self, AppError> {
+        let clients = ProviderClients::new(config)?;
+        Ok(Self {
+            clients: Arc::new(ArcSwap::from_pointee(clients)),
+            notify: None,
+            #[cfg(feature = "chaos")]
+            chaos: Chaos::default(),
+        })
+    }
+
+    #[cfg(feature = "chaos")]
+    pub fn chaos(&self) -> &Chaos {
+        &self.chaos
+    }
+
+    /// Publishes a notification when a provider rejects its primary key.
+    pub fn with_notifications(mut self, db: Db) -> Self {
+        self.notify = Some(db);
+        self
+    }
+
+    /// Runs `call` with the primary key, retrying once with the secondary key
+    /// when the provider rejects the primary.
+    async fn with_key<C, T>(
+        &self,
+        provider: Provider,
+        keys: &KeyPair<C>,
+        call: impl for<'a> Fn(&'a C) -> BoxFuture<'a, Result<T, LlmError>>,
+    ) -> Result<T, LlmError> {
+        let err = match call(&keys.primary).await {
+            Err(err) if err.is_auth_failure() => err,
+            result => return result,
+        };
+        let Some(secondary) = &keys.secondary else {
+            return Err(err);
+        };
+        if !keys.primary_rejected.swap(true, Ordering::Relaxed) {
+            warn!("{provider} rejected the primary API key ({err}); using the secondary key");
+            if let Some(db) = self.notify.clone() {
+                let notice = Notice::key_rotation(&provider.to_string(), &err.to_string());
+                tokio::spawn(async move { notifications::publish(&db, notice).await });
+            }
+        }
+        call(secondary).await
+    }
 
-        Self { openai, anthropic }
+    /// Rebuilds the provider clients from `config`. Requests already in flight
+    /// finish with the clients they started with; on error the current clients
+    /// stay in place.
+    pub fn reconfigure(&self, config: &Config) -> Result<(), AppError> {
+        self.clients.store(Arc::new(ProviderClients::new(config)?));
+        Ok(())
     }
 
     pub async fn chat(&self, req: LlmRequest) -> Result<LlmResponse, LlmError> {
+        self.chat_with_key(req, None).await
+    }
+
+    /// Like `chat`, but with `api_key` (an account's own key) in place of the
+    /// gateway's key for the provider. There's no secondary to fall back to.
+    pub async fn chat_with_key(
+        &self,
+        req: LlmRequest,
+        api_key: Option<&str>,
+    ) -> Result<LlmResponse, LlmError> {
+        // Ahead of fixtures, so injected faults are never recorded.
+        #[cfg(feature = "chaos")]
+        if let Some(err) = self.chaos.inject(req.provider).await {
+            return Err(err);
+        }
+        let clients = self.clients.load_full();
+        let fixtures = &clients.fixtures;
+        if fixtures.mode == FixtureMode::Replay {
+            return fixtures.replay_chat(&req).await;
+        }
+        let recording = (fixtures.mode == FixtureMode::Record).then(|| req.clone());
+        let result = match api_key {
+            Some(key) => Self::call_chat_with_key(&clients, req, key).await,
+            None => self.call_chat(&clients, req).await,
+        };
+        if let Some(req) = recording {
+            fixtures.record_chat(&req, &result).await;
+        }
+        result
+    }
+
+    async fn call_chat(
+        &self,
+        clients: &ProviderClients,
+        req: LlmRequest,
+    ) -> Result<LlmResponse, LlmError> {
         match req.provider {
+            Provider::Mock => clients.mock()?.chat(req).await,
             Provider::Openai => {
-                let client = self
+                let keys = clients
                     .openai
                     .as_ref()
                     .ok_or_else(|| LlmError::MissingApiKey("OPENAI_API_KEY not set".into()))?;
-                client.chat(req).await
+                self.with_key(req.provider, keys, |c| Box::pin(c.chat(req.clone())))
+                    .await
+            }
+            Provider::Anthropic => {
+                let keys = clients
+                    .anthropic
+                    .as_ref()
+                    .ok_or_else(|| LlmError::MissingApiKey("ANTHROPIC_API_KEY not set".into()))?;
+                self.with_key(req.provider, keys, |c| Box::pin(c.chat(req.clone())))
+                    .await
+            }
+        }
+    }
+
+    async fn call_chat_with_key(
+        clients: &ProviderClients,
+        req: LlmRequest,
+        key: &str,
+    ) -> Result<LlmResponse, LlmError> {
+        match req.provider {
+            Provider::Mock => clients.mock()?.chat(req).await,
+            Provider::Openai => {
+                OpenAiClient::new(
+                    key.to_string(),
+                    &clients.openai_base_url,
+                    clients.http.clone(),
+                )
+                .chat(req)
+                .await
             }
             Provider::Anthropic => {
-                let client = self
+                AnthropicClient::new(
+                    key.to_string(),
+                    &clients.anthropic_base_url,
+                    clients.http.clone(),
+                )
+                .chat(req)
+                .await
+            }
+        }
+    }
+
+    /// One model turn of a tool-calling loop, with `api_key` in place of the
+    /// gateway's key like `chat_with_key`. Fixtures don't cover tool calls:
+    /// recording passes them through and replaying refuses them.
+    pub async fn chat_tools(
+        &self,
+        req: ToolRequest,
+        api_key: Option<&str>,
+    ) -> Result<ToolResponse, LlmError> {
+        #[cfg(feature = "chaos")]
+        if let Some(err) = self.chaos.inject(req.provider).await {
+            return Err(err);
+        }
+        let clients = self.clients.load_full();
+        if clients.fixtures.mode == FixtureMode::Replay {
+            return Err(LlmError::InvalidRequest(
+                "LLM fixtures have no tool calls to replay".into(),
+            ));
+        }
+        match (req.provider, api_key) {
+            (Provider::Mock, _) => clients.mock()?.chat_tools(req).await,
+            (Provider::Openai, Some(key)) => {
+                OpenAiClient::new(
+                    key.to_string(),
+                    &clients.openai_base_url,
+                    clients.http.clone(),
+                )
+                .chat_tools(req)
+                .await
+            }
+            (Provider::Anthropic, Some(key)) => {
+                AnthropicClient::new(
+                    key.to_string(),
+                    &clients.anthropic_base_url,
+                    clients.http.clone(),
+                )
+                .chat_tools(req)
+                .await
+            }
+            (Provider::Openai, None) => {
+                let keys = clients
+                    .openai
+                    .as_ref()
+                    .ok_or_else(|| LlmError::MissingApiKey("OPENAI_API_KEY not set".into()))?;
+                self.with_key(req.provider, keys, |c| Box::pin(c.chat_tools(req.clone())))
+                    .await
+            }
+            (Provider::Anthropic, None) => {
+                let keys = clients
                     .anthropic
                     .as_ref()
                     .ok_or_else(|| LlmError::MissingApiKey("ANTHROPIC_API_KEY not set".into()))?;
-                client.chat(req).await
+                self.with_key(req.provider, keys, |c| Box::pin(c.chat_tools(req.clone())))
+                    .await
             }
         }
     }
+
+    pub async fn embed(
+        &self,
+        provider: Provider,
+        model: &str,
+        inputs: Vec<String>,
+    ) -> Result<Embeddings, LlmError> {
+        #[cfg(feature = "chaos")]
+        if let Some(err) = self.chaos.inject(provider).await {
+            return Err(err);
+        }
+        let clients = self.clients.load_full();
+        let fixtures = &clients.fixtures;
+        match fixtures.mode {
+            FixtureMode::Replay => fixtures.replay_embed(provider, model, &inputs).await,
+            FixtureMode::Record => {
+                let result = self
+                    .call_embed(&clients, provider, model, inputs.clone())
+                    .await;
+                fixtures
+                    .record_embed(provider, model, &inputs, &result)
+                    .await;
+                result
+            }
+            FixtureMode::Off => self.call_embed(&clients, provider, model, inputs).await,
+        }
+    }
+
+    async fn call_embed(
+        &self,
+        clients: &ProviderClients,
+        provider: Provider,
+        model: &str,
+        inputs: Vec<String>,
+    ) -> Result<Embeddings, LlmError> {
+        match provider {
+            Provider::Openai => {
+                let keys = clients
+                    .openai
+                    .as_ref()
+                    .ok_or_else(|| LlmError::MissingApiKey("OPENAI_API_KEY not set".into()))?;
+                self.with_key(provider, keys, |c| {
+                    let (model, inputs) = (model.to_string(), inputs.clone());
+                    Box::pin(async move { c.embed(&model, inputs).await })
+                })
+                .await
+            }
+            Provider::Anthropic => Err(LlmError::InvalidRequest(
+                "anthropic does not offer an embeddings API".into(),
+            )),
+            Provider::Mock => clients.mock()?.embed(model, inputs).await,
+        }
+    }
+
+    /// Providers reached over the network: those with a key, unless fixtures
+    /// are replaying, in which case nothing leaves the process.
+    pub fn remote_providers(&self) -> Vec<Provider> {
+        let clients = self.clients.load();
+        if clients.fixtures.mode == FixtureMode::Replay {
+            return Vec::new();
+        }
+        let mut providers = Vec::new();
+        if clients.openai.is_some() {
+            providers.push(Provider::Openai);
+        }
+        if clients.anthropic.is_some() {
+            providers.push(Provider::Anthropic);
+        }
+        providers
+    }
+
+    /// Model ids the provider reports as available to the configured key.
+    pub async fn list_models(&self, provider: Provider) -> Result<Vec<String>, LlmError> {
+        let clients = self.clients.load_full();
+        match provider {
+            Provider::Openai => {
+                let keys = clients
+                    .openai
+                    .as_ref()
+                    .ok_or_else(|| LlmError::MissingApiKey("OPENAI_API_KEY not set".into()))?;
+                self.with_key(provider, keys, |c| Box::pin(c.list_models()))
+                    .await
+            }
+            Provider::Anthropic => {
+                let keys = clients
+                    .anthropic
+                    .as_ref()
+                    .ok_or_else(|| LlmError::MissingApiKey("ANTHROPIC_API_KEY not set".into()))?;
+                self.with_key(provider, keys, |c| Box::pin(c.list_models()))
+                    .await
+            }
+            Provider::Mock => Err(LlmError::InvalidRequest(
+                "the mock provider serves any model id".into(),
+            )),
+        }
+    }
 }
 
 pub fn estimate_cost(
@@ -155,6 +619,7 @@ pub fn estimate_cost(
             m if m.contains("haiku") => (0.000001, 0.000003),
             _ => (0.000004, 0.000016),
         },
+        Provider::Mock => (0.000001, 0.000003),
     };
 
     let tin = tokens_in.unwrap_or(0) as f64;
@@ -167,6 +632,7 @@ impl fmt::Display for Provider {
         match self {
             Provider::Openai => write!(f, "openai"),
             Provider::Anthropic => write!(f, "anthropic"),
+            Provider::Mock => write!(f, "mock"),
         }
     }
 }
@@ -1,16 +1,24 @@
-use super::{LlmClient, LlmError, LlmMessage, LlmRequest, LlmResponse, Provider, Role};
+use super::{
+    Embeddings, LlmClient, LlmError, LlmMessage, LlmRequest, LlmResponse, Provider, ToolCall,
+    ToolRequest, ToolResponse, ToolTurn,
+};
 use async_trait::async_trait;
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone)]
 pub struct OpenAiClient {
     api_key: String,
+    base_url: String,
     http: reqwest::Client,
 }
 
 impl OpenAiClient {
-    pub fn new(api_key: String, http: reqwest::Client) -> Self {
-        Self { api_key, http }
+    pub fn new(api_key: String, base_url: &str, http: reqwest::Client) -> Self {
+        Self {
+            api_key,
+            base_url: base_url.trim_end_matches('/').to_string(),
+            http,
+        }
     }
 
     fn map_messages(messages: &[LlmMessage]) -> Vec<OpenAiMessage> {
@@ -22,6 +30,162 @@ impl OpenAiClient {
             })
             .collect()
     }
+
+    fn map_tool_turns(turns: &[ToolTurn]) -> Vec<OpenAiToolMessage> {
+        turns
+            .iter()
+            .map(|turn| match turn {
+                ToolTurn::Message(m) => OpenAiToolMessage {
+                    role: m.role.as_openai().to_string(),
+                    content: Some(m.content.clone()),
+                    tool_calls: Vec::new(),
+                    tool_call_id: None,
+                },
+                ToolTurn::Calls { content, calls } => OpenAiToolMessage {
+                    role: "assistant".into(),
+                    content: (!content.is_empty()).then(|| content.clone()),
+                    tool_calls: calls
+                        .iter()
+                        .map(|c| OpenAiToolCall {
+                            id: c.id.clone(),
+                            kind: "function".into(),
+                            function: OpenAiFunctionCall {
+                                name: c.name.clone(),
+                                arguments: c.arguments.to_string(),
+                            },
+                        })
+                        .collect(),
+                    tool_call_id: None,
+                },
+                ToolTurn::Result { call_id, content } => OpenAiToolMessage {
+                    role: "tool".into(),
+                    content: Some(content.clone()),
+                    tool_calls: Vec::new(),
+                    tool_call_id: Some(call_id.clone()),
+                },
+            })
+            .collect()
+    }
+
+    /// One turn of a tool-calling loop over the chat completions API.
+    pub async fn chat_tools(&self, req: ToolRequest) -> Result<ToolResponse, LlmError> {
+        let payload = OpenAiToolChatRequest {
+            model: req.model.clone(),
+            messages: Self::map_tool_turns(&req.messages),
+            tools: req
+                .tools
+                .iter()
+                .map(|t| OpenAiTool {
+                    kind: "function".into(),
+                    function: OpenAiFunction {
+                        name: t.name.clone(),
+                        description: t.description.clone(),
+                        parameters: t.parameters.clone(),
+                    },
+                })
+                .collect(),
+            temperature: req.temperature,
+            max_tokens: req.max_tokens,
+        };
+
+        let response = self
+            .http
+            .post(format!("{}/chat/completions", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&payload)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(LlmError::UnexpectedStatus(status, body));
+        }
+
+        let body: OpenAiChatResponse = response.json().await?;
+        let message = body.choices.into_iter().next().map(|c| c.message);
+        let (content, calls) = match message {
+            Some(m) => (
+                m.content.unwrap_or_default(),
+                m.tool_calls
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|c| ToolCall {
+                        id: c.id,
+                        name: c.function.name,
+                        // Models occasionally emit malformed JSON; the tool
+                        // gets the raw text and reports the problem.
+                        arguments: serde_json::from_str(&c.function.arguments)
+                            .unwrap_or(serde_json::Value::String(c.function.arguments)),
+                    })
+                    .collect(),
+            ),
+            None => (String::new(), Vec::new()),
+        };
+
+        let (tokens_input, tokens_output) = body
+            .usage
+            .map(|u| (Some(u.prompt_tokens), Some(u.completion_tokens)))
+            .unwrap_or((None, None));
+        let cost = super::estimate_cost(Provider::Openai, &req.model, tokens_input, tokens_output);
+
+        Ok(ToolResponse {
+            provider: Provider::Openai,
+            model: req.model,
+            content,
+            calls,
+            tokens_input,
+            tokens_output,
+            cost,
+        })
+    }
+
+    pub async fn embed(&self, model: &str, inputs: Vec<String>) -> Result<Embeddings, LlmError> {
+        let payload = OpenAiEmbeddingRequest {
+            model: model.to_string(),
+            input: inputs,
+        };
+
+        let response = self
+            .http
+            .post(format!("{}/embeddings", self.base_url))
+            .bearer_auth(&self.api_key)
+            .json(&payload)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(LlmError::UnexpectedStatus(status, body));
+        }
+
+        let mut body: OpenAiEmbeddingResponse = response.json().await?;
+        body.data.sort_by_key(|d| d.index);
+        Ok(Embeddings {
+            vectors: body.data.into_iter().map(|d| d.embedding).collect(),
+            tokens_input: body.usage.map(|u| u.prompt_tokens),
+        })
+    }
+
+    /// Ids of every model the key can use.
+    pub async fn list_models(&self) -> Result<Vec<String>, LlmError> {
+        let response = self
+            .http
+            .get(format!("{}/models", self.base_url))
+            .bearer_auth(&self.api_key)
+            .send()
+            .await?;
+
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            return Err(LlmError::UnexpectedStatus(status, body));
+        }
+
+        let body: OpenAiModelList = response.json().await?;
+        Ok(body.data.into_iter().map(|m| m.id).collect())
+    }
 }
 
 #[async_trait]
@@ -36,7 +200,7 @@ impl LlmClient for OpenAiClient {
 
         let response = self
             .http
-            .post("https://api.openai.com/v1/chat/completions")
+            .post(format!("{}/chat/completions", self.base_url))
             .bearer_auth(&self.api_key)
             .json(&payload)
             .send()
@@ -106,6 +270,59 @@ struct OpenAiChatMessage {
     #[serde(rename = "role")]
     _role: String,
     content: Option<String>,
+    #[serde(default)]
+    tool_calls: Option<Vec<OpenAiToolCall>>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiToolChatRequest {
+    model: String,
+    messages: Vec<OpenAiToolMessage>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tools: Vec<OpenAiTool>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    temperature: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    max_tokens: Option<u32>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiToolMessage {
+    role: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tool_calls: Vec<OpenAiToolCall>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    tool_call_id: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiTool {
+    #[serde(rename = "type")]
+    kind: String,
+    function: OpenAiFunction,
+}
+
+#[derive(Debug, Serialize)]
+struct OpenAiFunction {
+    name: String,
+    description: String,
+    parameters: serde_json::Value,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAiToolCall {
+    id: String,
+    #[serde(rename = "type", default)]
+    kind: String,
+    function: OpenAiFunctionCall,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct OpenAiFunctionCall {
+    name: String,
+    arguments: String,
 }
 
 #[derive(Debug, Deserialize)]
@@ -113,3 +330,37 @@ struct OpenAiUsage {
     prompt_tokens: u32,
     completion_tokens: u32,
 }
+
+#[derive(Debug, Serialize)]
+struct OpenAiEmbeddingRequest {
+    model: String,
+    input: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiEmbeddingResponse {
+    data: Vec<OpenAiEmbedding>,
+    #[serde(default)]
+    usage: Option<OpenAiEmbeddingUsage>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiEmbedding {
+    index: usize,
+    embedding: Vec<f32>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiEmbeddingUsage {
+    prompt_tokens: u32,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiModelList {
+    data: Vec<OpenAiModel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OpenAiModel {
+    id: String,
+}
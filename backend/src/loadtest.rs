@@ -0,0 +1,374 @@
+//! `loadtest`: fires concurrent chat requests at a running instance and reports
+//! latency percentiles, error rates and how often routing fell back. Meant to
+//! run against a server on the mock provider, so a benchmark costs nothing and
+//! measures the gateway (routing, policies, persistence) rather than a vendor.
+
+use clap::Args;
+use futures_util::StreamExt;
+use reqwest::{StatusCode, header};
+use serde_json::{Value, json};
+use std::{
+    collections::BTreeMap,
+    sync::{
+        Arc,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+#[derive(Args)]
+pub struct Options {
+    /// Base URL of the instance under test.
+    #[arg(long, default_value = "http://localhost:8000")]
+    url: String,
+    /// Total requests to send.
+    #[arg(long, default_value_t = 200, value_parser = positive)]
+    requests: usize,
+    /// Requests in flight at once.
+    #[arg(long, default_value_t = 10, value_parser = positive)]
+    concurrency: usize,
+    /// Use the SSE endpoint and also report time to the first chunk.
+    #[arg(long)]
+    stream: bool,
+    #[arg(long, default_value = "gpt-4.1")]
+    model: String,
+    #[arg(long, default_value = "mock")]
+    provider: String,
+    #[arg(
+        long,
+        default_value = "Give me three tips for writing a good load test."
+    )]
+    prompt: String,
+    /// Log in as this account; requests are anonymous otherwise.
+    #[arg(long, requires = "password")]
+    email: Option<String>,
+    #[arg(long, requires = "email")]
+    password: Option<String>,
+    /// Per-request timeout in seconds.
+    #[arg(long, default_value_t = 60)]
+    timeout: u64,
+    /// Accept self-signed TLS certificates.
+    #[arg(long)]
+    insecure: bool,
+}
+
+fn positive(value: &str) -> Result<usize, String> {
+    match value.parse() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err("expected a positive number".into()),
+    }
+}
+
+/// What one request did.
+struct Outcome {
+    latency: Duration,
+    /// Streams only: time until the first content chunk.
+    first_token: Option<Duration>,
+    result: Result<Routed, String>,
+}
+
+struct Routed {
+    model: String,
+    attempts: usize,
+    /// Distinct models tried; more than one means the fallback chain was used.
+    models_tried: usize,
+}
+
+pub async fn run(mut options: Options) -> ! {
+    options.url = options.url.trim_end_matches('/').to_string();
+    let (requests, concurrency) = (options.requests, options.concurrency);
+    let http = match reqwest::Client::builder()
+        .timeout(Duration::from_secs(options.timeout))
+        .danger_accept_invalid_certs(options.insecure)
+        .build()
+    {
+        Ok(http) => http,
+        Err(e) => {
+            eprintln!("failed to build HTTP client: {e}");
+            std::process::exit(1);
+        }
+    };
+    let cookie = match (&options.email, &options.password) {
+        (Some(email), Some(password)) => match login(&http, &options.url, email, password).await {
+            Ok(cookie) => Some(cookie),
+            Err(e) => {
+                eprintln!("login failed: {e}");
+                std::process::exit(1);
+            }
+        },
+        _ => None,
+    };
+
+    println!(
+        "loadtest: {} {} request(s) to {} (concurrency {}, model {}, provider {}, {})",
+        requests,
+        if options.stream { "stream" } else { "chat" },
+        options.url,
+        concurrency,
+        options.model,
+        options.provider,
+        options.email.as_deref().unwrap_or("anonymous")
+    );
+    let options = Arc::new(options);
+    let next = Arc::new(AtomicUsize::new(0));
+    let started = Instant::now();
+    let workers: Vec<_> = (0..concurrency.min(requests))
+        .map(|_| {
+            let (http, cookie, options, next) =
+                (http.clone(), cookie.clone(), options.clone(), next.clone());
+            tokio::spawn(async move {
+                let mut outcomes = Vec::new();
+                loop {
+                    let n = next.fetch_add(1, Ordering::Relaxed);
+                    if n >= requests {
+                        break outcomes;
+                    }
+                    outcomes.push(send(&http, cookie.as_deref(), &options, n).await);
+                }
+            })
+        })
+        .collect();
+    let mut outcomes = Vec::with_capacity(requests);
+    for worker in workers {
+        match worker.await {
+            Ok(done) => outcomes.extend(done),
+            Err(e) => eprintln!("worker failed: {e}"),
+        }
+    }
+    report(&outcomes, started.elapsed());
+    let failed = outcomes.iter().all(|o| o.result.is_err());
+    std::process::exit(if failed { 1 } else { 0 });
+}
+
+async fn login(
+    http: &reqwest::Client,
+    url: &str,
+    email: &str,
+    password: &str,
+) -> Result<String, String> {
+    let resp = http
+        .post(format!("{url}/api/v1/auth/login"))
+        .json(&json!({ "email": email, "password": password }))
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    if !resp.status().is_success() {
+        return Err(format!("server returned {}", resp.status()));
+    }
+    resp.headers()
+        .get_all(header::SET_COOKIE)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .find(|v| v.starts_with("auth="))
+        .and_then(|v| v.split(';').next())
+        .map(str::to_string)
+        .ok_or_else(|| "no auth cookie in the login response".into())
+}
+
+async fn send(
+    http: &reqwest::Client,
+    cookie: Option<&str>,
+    options: &Options,
+    n: usize,
+) -> Outcome {
+    let path = if options.stream {
+        "chat/stream"
+    } else {
+        "chat"
+    };
+    // Numbered so identical in-flight prompts aren't collapsed into one call.
+    let body = json!({
+        "provider": options.provider,
+        "model": options.model,
+        "messages": [{ "role": "user", "content": format!("{} (request {n})", options.prompt) }],
+    });
+    let mut req = http
+        .post(format!("{}/api/v1/{path}", options.url))
+        .json(&body);
+    if let Some(cookie) = cookie {
+        req = req.header(header::COOKIE, cookie);
+    }
+    let started = Instant::now();
+    let mut first_token = None;
+    let result = async {
+        let resp = req.send().await.map_err(transport_error)?;
+        let status = resp.status();
+        if !status.is_success() {
+            return Err(status_label(status));
+        }
+        if !options.stream {
+            let body: Value = resp.json().await.map_err(transport_error)?;
+            return Ok(routed(&body["routing"]));
+        }
+        let mut chunks = resp.bytes_stream();
+        let mut buffer = String::new();
+        let mut event = String::new();
+        while let Some(chunk) = chunks.next().await {
+            buffer.push_str(&String::from_utf8_lossy(&chunk.map_err(transport_error)?));
+            while let Some(end) = buffer.find('\n') {
+                let line: String = buffer.drain(..=end).collect();
+                let line = line.trim_end_matches(['\r', '\n']);
+                if line.is_empty() {
+                    event.clear();
+                } else if let Some(name) = line.strip_prefix("event:") {
+                    event = name.trim().to_string();
+                } else if let Some(data) = line.strip_prefix("data:") {
+                    let data = data.strip_prefix(' ').unwrap_or(data);
+                    if event == "done" {
+                        let meta: Value = serde_json::from_str(data)
+                            .map_err(|_| "unreadable done event".to_string())?;
+                        return Ok(routed(&meta["routing"]));
+                    }
+                    if data.starts_with("Error: ") {
+                        return Err("stream error".to_string());
+                    }
+                    first_token.get_or_insert_with(|| started.elapsed());
+                }
+            }
+        }
+        Err("stream ended early".to_string())
+    }
+    .await;
+    Outcome {
+        latency: started.elapsed(),
+        first_token,
+        result,
+    }
+}
+
+/// Reads the routing trace. Attempts are recorded as `model#try`, and the
+/// server's `used_fallback` flag also covers same-model retries, so the two
+/// are told apart here.
+fn routed(routing: &Value) -> Routed {
+    let attempts: Vec<&str> = routing["attempts"]
+        .as_array()
+        .map(|a| a.iter().filter_map(Value::as_str).collect())
+        .unwrap_or_default();
+    let mut models: Vec<&str> = attempts
+        .iter()
+        .map(|a| a.rsplit_once('#').map_or(*a, |(model, _)| model))
+        .collect();
+    models.dedup();
+    Routed {
+        model: routing["selected_model"]
+            .as_str()
+            .unwrap_or("unknown")
+            .to_string(),
+        attempts: attempts.len().max(1),
+        models_tried: models.len().max(1),
+    }
+}
+
+fn transport_error(e: reqwest::Error) -> String {
+    if e.is_timeout() {
+        "timeout".into()
+    } else if e.is_connect() {
+        "connection failed".into()
+    } else {
+        "transport error".into()
+    }
+}
+
+fn status_label(status: StatusCode) -> String {
+    match status.canonical_reason() {
+        Some(reason) => format!("HTTP {} {reason}", status.as_u16()),
+        None => format!("HTTP {}", status.as_u16()),
+    }
+}
+
+fn report(outcomes: &[Outcome], elapsed: Duration) {
+    let total = outcomes.len();
+    let succeeded: Vec<&Routed> = outcomes
+        .iter()
+        .filter_map(|o| o.result.as_ref().ok())
+        .collect();
+    let mut errors: BTreeMap<&str, usize> = BTreeMap::new();
+    for outcome in outcomes {
+        if let Err(e) = &outcome.result {
+            *errors.entry(e.as_str()).or_default() += 1;
+        }
+    }
+    let share = |n: usize, of: usize| {
+        if of == 0 {
+            0.0
+        } else {
+            n as f64 * 100.0 / of as f64
+        }
+    };
+
+    println!(
+        "completed in {:.2}s ({:.1} req/s)",
+        elapsed.as_secs_f64(),
+        total as f64 / elapsed.as_secs_f64().max(f64::EPSILON)
+    );
+    println!(
+        "succeeded    {} ({:.1}%)",
+        succeeded.len(),
+        share(succeeded.len(), total)
+    );
+    println!(
+        "failed       {} ({:.1}%)",
+        total - succeeded.len(),
+        share(total - succeeded.len(), total)
+    );
+    for (error, count) in &errors {
+        println!("  {error:<28} {count}");
+    }
+    // Failures often return early (rate limits, bad requests), so only
+    // successful requests count toward the latency figures.
+    let latencies = outcomes
+        .iter()
+        .filter(|o| o.result.is_ok())
+        .map(|o| o.latency)
+        .collect();
+    println!("latency      {}", percentiles(latencies));
+    let first_tokens: Vec<Duration> = outcomes
+        .iter()
+        .filter(|o| o.result.is_ok())
+        .filter_map(|o| o.first_token)
+        .collect();
+    if !first_tokens.is_empty() {
+        println!("first token  {}", percentiles(first_tokens));
+    }
+    let retried = succeeded.iter().filter(|r| r.attempts > 1).count();
+    println!(
+        "retried      {retried} ({:.1}% of successes needed more than one attempt)",
+        share(retried, succeeded.len())
+    );
+    let fallbacks = succeeded.iter().filter(|r| r.models_tried > 1).count();
+    println!(
+        "fallbacks    {fallbacks} ({:.1}% of successes were answered by another model)",
+        share(fallbacks, succeeded.len())
+    );
+    let mut models: BTreeMap<&str, usize> = BTreeMap::new();
+    for routed in &succeeded {
+        *models.entry(routed.model.as_str()).or_default() += 1;
+    }
+    if !models.is_empty() {
+        println!("models");
+        for (model, count) in models {
+            println!("  {model:<28} {count}");
+        }
+    }
+}
+
+/// Nearest-rank percentiles.
+fn percentiles(mut samples: Vec<Duration>) -> String {
+    if samples.is_empty() {
+        return "n/a".into();
+    }
+    samples.sort();
+    let at = |p: f64| {
+        let rank = ((p / 100.0) * samples.len() as f64).ceil() as usize;
+        samples[rank.clamp(1, samples.len()) - 1]
+    };
+    let ms = |d: Duration| format!("{:.1}ms", d.as_secs_f64() * 1000.0);
+    format!(
+        "p50 {}  p90 {}  p95 {}  p99 {}  max {}",
+        ms(at(50.0)),
+        ms(at(90.0)),
+        ms(at(95.0)),
+        ms(at(99.0)),
+        ms(samples[samples.len() - 1])
+    )
+}
@@ -1,145 +1,433 @@
-mod admin;
-mod audit;
-mod auth;
-mod config;
-mod db;
-mod error;
-mod governance;
-mod llm;
-mod model_router;
-mod pii;
-mod routes;
-
-use crate::admin::{
-    dashboard_overview, list_accounts, list_models, list_policies, set_alias, set_fallbacks,
-    test_policy, update_account_guardrail, update_account_limits, update_account_models,
-    update_account_status, upsert_model, upsert_policy,
-};
-use crate::auth::{login, logout};
-use crate::config::Config;
-use crate::db::Db;
-use crate::error::AppError;
-use crate::llm::LlmService;
-use crate::model_router::{AccessControl, seeded_accounts};
-use crate::routes::chat::{chat, chat_stream};
-use axum::{
-    Router,
-    http::{HeaderValue, Method},
-    response::IntoResponse,
-    routing::{get, post},
+mod commands;
+mod loadtest;
+
+use axum::Router;
+use axum_server::tls_rustls::RustlsConfig;
+use backend::{
+    AppState,
+    config::{self, Config, SharedConfig},
+    db::Db,
+    error::AppError,
+    lifecycle::{Lifecycle, shutdown_signal},
+    llm::LlmService,
+    model_router::AccessControl,
+    router, startup, startup_router, tls, webhooks,
 };
-use tower_http::cors::{AllowOrigin, CorsLayer};
-use tracing::{Level, info};
+use clap::{Parser, Subcommand};
+use futures_util::future::BoxFuture;
+use std::sync::{Arc, OnceLock};
+use tracing::{info, warn};
+use tracing_subscriber::EnvFilter;
+
+#[derive(Parser)]
+#[command(version, about = "Multi-model chat gateway")]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Same as the check-config command, for existing scripts.
+    #[arg(long, hide = true)]
+    check_config: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Run the API server (the default when no command is given).
+    Serve,
+    /// Apply pending database migrations, then exit.
+    Migrate,
+    /// Write the default accounts and model catalog into an empty database.
+    Seed,
+    /// Validate the configuration, resolve secrets and load certificates
+    /// without serving.
+    CheckConfig,
+    /// Print the catalog, aliases, fallbacks, accounts and policies as a YAML
+    /// state document.
+    ExportState(commands::ExportState),
+    /// Create an account for an operator and show how to make it an admin.
+    CreateAdmin(commands::CreateAdmin),
+    /// Send concurrent chat requests to a running instance and report
+    /// latency, errors and fallbacks.
+    Loadtest(loadtest::Options),
+}
 
 #[tokio::main]
-async fn main() -> Result<(), AppError> {
-    dotenvy::dotenv().ok();
-    init_tracing();
-
-    let config = Config::from_env()?;
-    let db = Db::new(&config.database_url).await?;
-    let llm = LlmService::new(&config);
-    let access = AccessControl::new(seeded_accounts());
-    let state = AppState {
-        llm,
-        db,
-        config,
-        access,
+async fn main() {
+    let cli = Cli::parse();
+    let command = match cli.command {
+        Some(command) => command,
+        None if cli.check_config => Command::CheckConfig,
+        None => Command::Serve,
+    };
+    let result = match command {
+        Command::Serve => serve(load_config(Logs::Stdout).await).await,
+        Command::CheckConfig => commands::check_config().await,
+        Command::Loadtest(options) => loadtest::run(options).await,
+        Command::Migrate => commands::migrate(&load_config(Logs::Stderr).await).await,
+        Command::Seed => commands::seed(&load_config(Logs::Stderr).await).await,
+        Command::ExportState(args) => {
+            commands::export_state(&load_config(Logs::Stderr).await, args).await
+        }
+        Command::CreateAdmin(args) => {
+            commands::create_admin(&load_config(Logs::Stderr).await, args).await
+        }
     };
-    let shared_state = state.clone();
-
-    let cors = build_cors(&state.config);
-
-    let app = Router::new()
-        .route("/health", get(health))
-        .route("/api/v1/chat", post(chat))
-        .route("/api/v1/chat/stream", post(chat_stream))
-        .route("/api/v1/auth/login", post(login))
-        .route("/api/v1/auth/logout", post(logout))
-        .route("/api/v1/admin/overview", get(dashboard_overview))
-        .route("/api/v1/admin/accounts", get(list_accounts))
-        .route(
-            "/api/v1/admin/accounts/:id/models",
-            post(update_account_models),
-        )
-        .route(
-            "/api/v1/admin/accounts/:id/status",
-            post(update_account_status),
-        )
-        .route(
-            "/api/v1/admin/accounts/:id/guardrail",
-            post(update_account_guardrail),
-        )
-        .route(
-            "/api/v1/admin/accounts/:id/limits",
-            post(update_account_limits),
-        )
-        .route(
-            "/api/v1/admin/policies",
-            get(list_policies).post(upsert_policy),
-        )
-        .route("/api/v1/admin/policies/:id", post(upsert_policy))
-        .route("/api/v1/admin/policies/:id/test", post(test_policy))
-        .route("/api/v1/admin/models", get(list_models).post(upsert_model))
-        .route("/api/v1/admin/models/aliases", post(set_alias))
-        .route("/api/v1/admin/models/:id/fallbacks", post(set_fallbacks))
-        .with_state(shared_state)
-        .layer(cors);
-
-    let addr = format!("{}:{}", state.config.host, state.config.port);
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+/// Where logs go. One-shot commands keep stdout for their own output, so
+/// `export-state > state.yaml` gets only the document.
+#[derive(Clone, Copy)]
+enum Logs {
+    Stdout,
+    Stderr,
+}
+
+/// Loads the configuration and starts logging, or exits with every problem
+/// listed.
+async fn load_config(logs: Logs) -> Config {
+    let config = match Config::load().await {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    init_tracing(&config.log_level, logs);
+    if let Some(path) = &config.config_file {
+        info!("merged config file {}", path.display());
+    }
+    for warning in &config.warnings {
+        warn!("config: {warning}");
+    }
+    config
+}
+
+async fn serve(config: Config) -> Result<(), AppError> {
+    let tls = match (&config.tls_cert, &config.tls_key) {
+        (Some(cert), Some(key)) => Some(tls::load(cert, key).await?),
+        _ => None,
+    };
+    // Listen before migrating so liveness checks pass during a long upgrade;
+    // `/health` reports 503 until `start` has finished.
+    let addr = format!("{}:{}", config.host, config.port);
     let listener = tokio::net::TcpListener::bind(&addr)
         .await
         .map_err(|e| AppError::Internal(format!("failed to bind {addr}: {e}")))?;
     info!(
-        "listening on http://{}",
+        "listening on {}://{}",
+        if tls.is_some() { "https" } else { "http" },
         listener
             .local_addr()
             .map(|a| a.to_string())
             .unwrap_or(addr.clone())
     );
 
-    axum::serve(listener, app.into_make_service())
-        .await
-        .map_err(|e| AppError::Internal(format!("server error: {e}")))
+    let lifecycle = Lifecycle::new();
+    let started = Arc::new(OnceLock::new());
+    let app = Arc::new(OnceLock::new());
+    let initial_drain_secs = config.shutdown_drain_secs;
+    tokio::spawn(start(
+        config,
+        lifecycle.clone(),
+        tls.clone(),
+        started.clone(),
+        app.clone(),
+    ));
+    let server_app = startup_router(lifecycle.clone(), app);
+
+    // Read when shutdown starts so a reloaded drain window applies.
+    let drain_window = || {
+        let secs = started
+            .get()
+            .map_or(initial_drain_secs, |state: &AppState| {
+                state.config.load().shutdown_drain_secs
+            });
+        std::time::Duration::from_secs(secs)
+    };
+    tokio::spawn({
+        let lifecycle = lifecycle.clone();
+        async move {
+            shutdown_signal().await;
+            lifecycle.begin_drain();
+        }
+    });
+
+    let server: BoxFuture<'static, std::io::Result<()>> = match tls {
+        None => Box::pin(
+            axum::serve(listener, server_app.into_make_service())
+                .with_graceful_shutdown({
+                    let lifecycle = lifecycle.clone();
+                    async move { lifecycle.drained().await }
+                })
+                .into_future(),
+        ),
+        Some(tls) => {
+            let handle = axum_server::Handle::new();
+            tokio::spawn({
+                let (lifecycle, handle) = (lifecycle.clone(), handle.clone());
+                async move {
+                    lifecycle.drained().await;
+                    // The drain deadline below bounds how long this waits.
+                    handle.graceful_shutdown(None);
+                }
+            });
+            let listener = listener
+                .into_std()
+                .map_err(|e| AppError::Internal(format!("failed to hand over listener: {e}")))?;
+            Box::pin(
+                axum_server::from_tcp_rustls(listener, tls)
+                    .handle(handle)
+                    .serve(server_app.into_make_service()),
+            )
+        }
+    };
+    let drain_deadline = async {
+        lifecycle.drained().await;
+        tokio::time::sleep(drain_window()).await;
+    };
+    tokio::select! {
+        res = server => {
+            res.map_err(|e| AppError::Internal(format!("server error: {e}")))?;
+        }
+        _ = drain_deadline => {
+            warn!("drain window elapsed; closing remaining connections");
+        }
+    }
+
+    // Streams that already answered may still be persisting their messages.
+    lifecycle.wait_for_tasks(drain_window()).await;
+    if let Some(state) = started.get() {
+        state.db.close().await;
+    }
+    info!("shutdown complete");
+    Ok(())
+}
+
+/// Migrates, loads the router state and checks the providers behind the
+/// listening server, then hands it the app. Exits the process if the app
+/// can't be built, since the replica would never become ready.
+async fn start(
+    config: Config,
+    lifecycle: Lifecycle,
+    tls: Option<RustlsConfig>,
+    started: Arc<OnceLock<AppState>>,
+    app: Arc<OnceLock<Router>>,
+) {
+    let state = match AppState::new(config, lifecycle.clone()).await {
+        Ok(state) => state,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    spawn_router_sync(
+        state.access.clone(),
+        state.store.is_distributed(),
+        state.config.load().state_sync_secs,
+    );
+    let (history_secs, history_days) = {
+        let config = state.config.load();
+        (config.health_history_secs, config.health_history_days)
+    };
+    if history_secs > 0 {
+        spawn_health_history(
+            state.access.clone(),
+            state.db.clone(),
+            history_secs,
+            history_days,
+        );
+    }
+    spawn_webhook_delivery(state.clone());
+    #[cfg(unix)]
+    spawn_sighup_reload(state.config.clone(), state.llm.clone());
+    let watch_secs = state.config.load().config_watch_secs;
+    if watch_secs > 0 {
+        spawn_config_watch(state.config.clone(), state.llm.clone(), watch_secs);
+    }
+    let refresh_secs = state.config.load().secrets_refresh_secs;
+    if refresh_secs > 0 {
+        spawn_secret_refresh(state.config.clone(), state.llm.clone(), refresh_secs);
+    }
+    if let Some(tls) = tls {
+        tls::spawn_cert_reload(state.config.clone(), tls);
+    }
+    let _ = started.set(state.clone());
+
+    let probe_secs = state.config.load().startup_probe_secs;
+    startup::probe_providers(
+        lifecycle.startup(),
+        &state.llm,
+        std::time::Duration::from_secs(probe_secs),
+    )
+    .await;
+    let _ = app.set(router(state));
+    info!("ready for traffic");
 }
 
-fn init_tracing() {
-    tracing_subscriber::fmt()
-        .with_max_level(Level::INFO)
+fn init_tracing(level: &str, logs: Logs) {
+    let filter = EnvFilter::try_new(level).unwrap_or_else(|e| {
+        eprintln!("invalid LOG_LEVEL {level:?} ({e}); using info");
+        EnvFilter::new("info")
+    });
+    let logger = tracing_subscriber::fmt()
+        .with_env_filter(filter)
         .with_target(false)
-        .without_time()
-        .init();
+        .without_time();
+    match logs {
+        Logs::Stdout => logger.init(),
+        Logs::Stderr => logger.with_writer(std::io::stderr).init(),
+    }
 }
 
-fn build_cors(config: &Config) -> CorsLayer {
-    let mut layer = CorsLayer::new()
-        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-        .allow_headers([axum::http::header::CONTENT_TYPE])
-        .allow_credentials(true);
-
-    if let Some(origins) = config.allowed_origins.clone() {
-        let origins: Vec<_> = origins
-            .split(',')
-            .filter_map(|o| HeaderValue::from_str(o.trim()).ok())
-            .collect();
-        if !origins.is_empty() {
-            layer = layer.allow_origin(origins);
-            return layer;
+/// Keeps this replica's router in step with the others: reloads accounts and
+/// catalog when the database version moves, and pulls shared health when Redis
+/// is configured.
+fn spawn_router_sync(access: AccessControl, sync_health: bool, every_secs: u64) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(every_secs.max(1)));
+        loop {
+            tick.tick().await;
+            match access.refresh_if_changed().await {
+                Ok(true) => info!("reloaded router state from database"),
+                Ok(false) => {}
+                Err(e) => warn!("router state sync failed: {e}"),
+            }
+            if sync_health && let Err(e) = access.sync_health().await {
+                warn!("router health sync failed: {e}");
+            }
         }
-    }
+    });
+}
 
-    // Mirror request origin so local dev hosts work without configuring ALLOWED_ORIGINS.
-    layer.allow_origin(AllowOrigin::mirror_request())
+/// Persists this replica's per-model router health every `every_secs` and drops
+/// history older than `keep_days`.
+fn spawn_health_history(access: AccessControl, db: Db, every_secs: u64, keep_days: u64) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(every_secs));
+        // The first tick fires immediately, before any traffic.
+        tick.tick().await;
+        loop {
+            tick.tick().await;
+            let now = chrono::Utc::now();
+            let samples = access.take_health_window();
+            if !samples.is_empty()
+                && let Err(e) = db.insert_health_samples(&now.to_rfc3339(), &samples).await
+            {
+                warn!("router health snapshot failed: {e}");
+            }
+            let cutoff = now - chrono::Duration::days(keep_days as i64);
+            if let Err(e) = db.prune_health_history(&cutoff.to_rfc3339()).await {
+                warn!("router health history prune failed: {e}");
+            }
+        }
+    });
 }
 
-async fn health() -> impl IntoResponse {
-    "ok"
+/// Sends queued webhook deliveries as they come due, and drops delivered ones
+/// after a week. Every replica runs this; deliveries are claimed one at a time.
+fn spawn_webhook_delivery(state: AppState) {
+    tokio::spawn(async move {
+        let http = webhooks::client();
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(5));
+        let mut last_prune = std::time::Instant::now();
+        loop {
+            tick.tick().await;
+            if let Err(e) = webhooks::deliver_due(&state, &http).await {
+                warn!("webhook delivery run failed: {e}");
+            }
+            if last_prune.elapsed() >= std::time::Duration::from_secs(3600) {
+                last_prune = std::time::Instant::now();
+                let cutoff = chrono::Utc::now() - chrono::Duration::days(7);
+                if let Err(e) = state
+                    .db
+                    .prune_webhook_deliveries(&cutoff.to_rfc3339())
+                    .await
+                {
+                    warn!("webhook delivery prune failed: {e}");
+                }
+            }
+        }
+    });
 }
 
-#[derive(Clone)]
-pub struct AppState {
-    llm: LlmService,
-    db: Db,
-    config: Config,
-    access: AccessControl,
+/// Re-reads `file:`/`vault:`/`aws-sm:` secrets every `every_secs` so rotated
+/// provider keys and JWT secrets apply without a reload. A failed read keeps
+/// the current values.
+fn spawn_secret_refresh(config: SharedConfig, llm: LlmService, every_secs: u64) {
+    tokio::spawn(async move {
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(every_secs));
+        // The first tick fires immediately, right after startup resolved them.
+        tick.tick().await;
+        loop {
+            tick.tick().await;
+            if config.load().secret_refs.is_empty() {
+                continue;
+            }
+            match config::refresh_secrets(&config, &llm).await {
+                Ok(changed) if !changed.is_empty() => info!("refreshed secrets: {changed:?}"),
+                Ok(_) => {}
+                Err(e) => warn!("secret refresh failed: {e}"),
+            }
+        }
+    });
+}
+
+/// Re-reads the configuration on SIGHUP, like `POST /api/v1/admin/config/reload`.
+#[cfg(unix)]
+fn spawn_sighup_reload(config: SharedConfig, llm: LlmService) {
+    use tokio::signal::unix::{SignalKind, signal};
+    tokio::spawn(async move {
+        let mut hangups = match signal(SignalKind::hangup()) {
+            Ok(sig) => sig,
+            Err(e) => {
+                warn!("failed to install SIGHUP handler: {e}");
+                return;
+            }
+        };
+        while hangups.recv().await.is_some() {
+            if let Err(e) = config::reload(&config, &llm).await {
+                warn!("configuration reload failed: {e}");
+            }
+        }
+    });
+}
+
+/// Reloads the configuration when the config file in use is edited. Checks the
+/// file's modification time and size every `every_secs`; a file that fails to
+/// parse is reported once and the running configuration is kept.
+fn spawn_config_watch(config: SharedConfig, llm: LlmService, every_secs: u64) {
+    let fingerprint = config::file_fingerprint;
+    tokio::spawn(async move {
+        let mut watched = config
+            .load()
+            .config_file
+            .clone()
+            .map(|path| (fingerprint(&path), path));
+        let mut tick = tokio::time::interval(std::time::Duration::from_secs(every_secs));
+        loop {
+            tick.tick().await;
+            // A reload may have switched files via RACTOCHAT_CONFIG; the new one
+            // is already loaded, so it's only watched from here on.
+            let Some(path) = config.load().config_file.clone() else {
+                watched = None;
+                continue;
+            };
+            let current = fingerprint(&path);
+            let changed = matches!(
+                &watched,
+                Some((seen, seen_path)) if *seen_path == path && *seen != current
+            );
+            watched = Some((current, path.clone()));
+            if !changed {
+                continue;
+            }
+            info!("config file {} changed; reloading", path.display());
+            if let Err(e) = config::reload(&config, &llm).await {
+                warn!("configuration reload failed: {e}");
+            }
+        }
+    });
 }
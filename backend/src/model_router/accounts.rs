@@ -1,9 +1,24 @@
-use crate::error::AppError;
+use crate::{
+    db::Db,
+    error::AppError,
+    notifications::{self, Notice},
+    shared_store::SharedStore,
+};
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{
+    Arc,
+    atomic::{AtomicI64, Ordering},
+};
 use tokio::sync::RwLock;
+use tracing::info;
 
-use super::catalog::{AliasTarget, Catalog, CatalogEntry, RoutedModel, RouterHealthEntry};
+use super::catalog::{
+    AliasPreview, AliasTarget, Catalog, CatalogDefinitions, CatalogEntry, HealthSample, ModelKind,
+    RoutedModel, RouterHealthEntry,
+};
+use super::switches::{GatewaySwitches, SwitchKind};
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
@@ -18,6 +33,24 @@ pub struct ModelPriceCap {
     pub max_cents: u32,
 }
 
+/// A temporary raise of an account's daily limits. It stops applying at
+/// `expires_at` without anyone having to remove it.
+#[derive(Clone, Debug, Serialize)]
+pub struct LimitOverride {
+    pub account_id: String,
+    pub req_per_day: Option<u32>,
+    pub tokens_per_day: Option<u32>,
+    pub expires_at: DateTime<Utc>,
+    pub reason: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl LimitOverride {
+    pub fn is_active(&self) -> bool {
+        self.expires_at > Utc::now()
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct AccountAccess {
     pub id: String,
@@ -31,20 +64,214 @@ pub struct AccountAccess {
     pub req_per_day: Option<u32>,
     pub tokens_per_day: Option<u32>,
     pub model_price_caps: Vec<ModelPriceCap>,
+    /// Region the account's prompts must stay in (e.g. `eu`); only models
+    /// tagged with that region are routed to.
+    #[serde(default)]
+    pub data_residency: Option<String>,
+    /// Web origins (`https://app.example.com`) the account's session may be
+    /// used from. Empty leaves it to the global `ALLOWED_ORIGINS`.
+    #[serde(default)]
+    pub allowed_origins: Vec<String>,
+    /// Translate prompts into English for the routed model and replies back,
+    /// through `TRANSLATION_MODEL`.
+    #[serde(default)]
+    pub inline_translation: bool,
+}
+
+/// What a model delete changed besides the model itself.
+#[derive(Clone, Debug, Serialize)]
+pub struct ModelDeletion {
+    pub model: String,
+    pub updated_aliases: Vec<String>,
+    pub updated_fallbacks: Vec<String>,
 }
 
 #[derive(Clone)]
 pub struct AccessControl {
     accounts: Arc<RwLock<Vec<AccountAccess>>>,
     catalog: Catalog,
+    store: SharedStore,
+    db: Db,
+    version: Arc<AtomicI64>,
+    overrides: Arc<RwLock<HashMap<String, LimitOverride>>>,
 }
 
 impl AccessControl {
-    pub fn new(seed: Vec<AccountAccess>) -> Self {
-        Self {
-            accounts: Arc::new(RwLock::new(seed)),
-            catalog: Catalog::seed(),
+    /// Loads accounts and catalog from the database, seeding both on first boot.
+    /// With `seed_mock`, the seeded models are served by the mock provider.
+    /// `routing_seed` makes weighted alias picks reproducible.
+    pub async fn load(
+        db: Db,
+        store: SharedStore,
+        seed_mock: bool,
+        routing_seed: Option<u64>,
+    ) -> Result<Self, AppError> {
+        Self::seed(&db, seed_mock).await?;
+        let accounts = db.load_accounts().await?;
+        let defs = db.load_catalog().await?;
+        let version = db.router_state_version().await?;
+        let catalog = Catalog::from_definitions(defs, routing_seed);
+        catalog.set_switches(db.load_switches().await?);
+        let overrides = db.load_quota_overrides().await?;
+        Ok(Self {
+            accounts: Arc::new(RwLock::new(accounts)),
+            catalog,
+            store,
+            db,
+            version: Arc::new(AtomicI64::new(version)),
+            overrides: Arc::new(RwLock::new(by_account(overrides))),
+        })
+    }
+
+    /// Writes the default accounts and catalog into a database that has
+    /// neither. Returns whether anything was seeded.
+    pub async fn seed(db: &Db, seed_mock: bool) -> Result<bool, AppError> {
+        if !db.load_accounts().await?.is_empty() || !db.load_catalog().await?.models.is_empty() {
+            return Ok(false);
+        }
+        let defs = if seed_mock {
+            CatalogDefinitions::seed_mock()
+        } else {
+            CatalogDefinitions::seed()
+        };
+        db.seed_router_state(&seeded_accounts(), &defs).await?;
+        if seed_mock {
+            info!("seeded router state into database (models served by the mock provider)");
+        } else {
+            info!("seeded router state into database");
+        }
+        Ok(true)
+    }
+
+    /// Reloads accounts and catalog if another replica (or this one) changed them
+    /// since the last load. Returns whether anything was reloaded.
+    pub async fn refresh_if_changed(&self) -> Result<bool, AppError> {
+        let current = self.db.router_state_version().await?;
+        if current == self.version.load(Ordering::SeqCst) {
+            return Ok(false);
         }
+        let accounts = self.db.load_accounts().await?;
+        let defs = self.db.load_catalog().await?;
+        let switches = self.db.load_switches().await?;
+        let overrides = self.db.load_quota_overrides().await?;
+        *self.accounts.write().await = accounts;
+        *self.overrides.write().await = by_account(overrides);
+        self.catalog.replace_definitions(defs);
+        self.catalog.set_switches(switches);
+        self.version.store(current, Ordering::SeqCst);
+        Ok(true)
+    }
+
+    async fn update_account<F>(&self, id: &str, apply: F) -> Result<AccountAccess, AppError>
+    where
+        F: FnOnce(&mut AccountAccess),
+    {
+        // The change only reaches memory once it is stored. The lock is held
+        // across the write so concurrent updates to one account don't undo
+        // each other.
+        let mut accounts = self.accounts.write().await;
+        let account = accounts
+            .iter_mut()
+            .find(|a| a.id == id)
+            .ok_or_else(|| AppError::BadRequest(format!("account {id} not found")))?;
+        let mut updated = account.clone();
+        apply(&mut updated);
+        self.db.save_account(&updated).await?;
+        *account = updated.clone();
+        Ok(updated)
+    }
+
+    /// Adds a new account. Ids and emails must be unique.
+    pub async fn create_account(&self, account: AccountAccess) -> Result<AccountAccess, AppError> {
+        {
+            let mut accounts = self.accounts.write().await;
+            if accounts.iter().any(|a| a.id == account.id) {
+                return Err(AppError::BadRequest(format!(
+                    "account {} already exists",
+                    account.id
+                )));
+            }
+            if accounts
+                .iter()
+                .any(|a| a.email.eq_ignore_ascii_case(&account.email))
+            {
+                return Err(AppError::BadRequest(format!(
+                    "an account with email {} already exists",
+                    account.email
+                )));
+            }
+            accounts.push(account.clone());
+        }
+        if let Err(e) = self.db.save_account(&account).await {
+            self.accounts.write().await.retain(|a| a.id != account.id);
+            return Err(e);
+        }
+        Ok(account)
+    }
+
+    /// Creates the account, or replaces an existing one with the same id.
+    /// Returns the saved account and whether it was newly created.
+    pub async fn upsert_account(
+        &self,
+        account: AccountAccess,
+    ) -> Result<(AccountAccess, bool), AppError> {
+        let previous = {
+            let mut accounts = self.accounts.write().await;
+            if accounts
+                .iter()
+                .any(|a| a.id != account.id && a.email.eq_ignore_ascii_case(&account.email))
+            {
+                return Err(AppError::BadRequest(format!(
+                    "an account with email {} already exists",
+                    account.email
+                )));
+            }
+            match accounts.iter_mut().find(|a| a.id == account.id) {
+                Some(existing) => Some(std::mem::replace(existing, account.clone())),
+                None => {
+                    accounts.push(account.clone());
+                    None
+                }
+            }
+        };
+        if let Err(e) = self.db.save_account(&account).await {
+            let mut accounts = self.accounts.write().await;
+            match previous {
+                Some(prev) => {
+                    if let Some(slot) = accounts.iter_mut().find(|a| a.id == account.id) {
+                        *slot = prev;
+                    }
+                }
+                None => accounts.retain(|a| a.id != account.id),
+            }
+            return Err(e);
+        }
+        let created = previous.is_none();
+        Ok((account, created))
+    }
+
+    /// Id of the account registered under `email`, if any.
+    pub async fn account_id_for_email(&self, email: &str) -> Option<String> {
+        let accounts = self.accounts.read().await;
+        accounts
+            .iter()
+            .find(|a| a.email.eq_ignore_ascii_case(email))
+            .map(|a| a.id.clone())
+    }
+
+    pub async fn delete_account(&self, id: &str) -> Result<AccountAccess, AppError> {
+        let removed = {
+            let accounts = self.accounts.read().await;
+            accounts
+                .iter()
+                .find(|a| a.id == id)
+                .cloned()
+                .ok_or_else(|| AppError::BadRequest(format!("account {id} not found")))?
+        };
+        self.db.delete_account(id).await?;
+        self.accounts.write().await.retain(|a| a.id != id);
+        self.overrides.write().await.remove(id);
+        Ok(removed)
     }
 
     pub async fn list(&self) -> Vec<AccountAccess> {
@@ -58,39 +285,62 @@ impl AccessControl {
     ) -> Result<RoutedModel, AppError> {
         let accounts = self.accounts.read().await;
         let account = user_id.and_then(|uid| accounts.iter().find(|a| a.id == uid));
+        // A signed-in user whose account was deleted must not fall back to the
+        // anonymous allowlist.
+        if let Some(uid) = user_id
+            && account.is_none()
+        {
+            return Err(AppError::BadRequest(format!("account {uid} not found")));
+        }
         let allowlist = account
             .map(|a| a.allowed_models.clone())
             .unwrap_or_else(|| self.catalog.all_aliases());
 
         // HACK: Allow 'gpt-4.1' alias for frontend compatibility
         let mut allowlist = allowlist;
-        if allowlist.iter().any(|m| m == "gpt-4-turbo-preview") && !allowlist.contains(&"gpt-4.1".to_string()) {
+        if allowlist.iter().any(|m| m == "gpt-4-turbo-preview")
+            && !allowlist.contains(&"gpt-4.1".to_string())
+        {
             allowlist.push("gpt-4.1".to_string());
         }
-        if allowlist.iter().any(|m| m == "claude-3-5-sonnet-20240620") && !allowlist.contains(&"claude-3.5-sonnet".to_string()) {
-             allowlist.push("claude-3.5-sonnet".to_string());
+        if allowlist.iter().any(|m| m == "claude-3-5-sonnet-20240620")
+            && !allowlist.contains(&"claude-3.5-sonnet".to_string())
+        {
+            allowlist.push("claude-3.5-sonnet".to_string());
         }
-        if allowlist.iter().any(|m| m == "claude-3-haiku-20240307") && !allowlist.contains(&"claude-3-haiku".to_string()) {
-             allowlist.push("claude-3-haiku".to_string());
+        if allowlist.iter().any(|m| m == "claude-3-haiku-20240307")
+            && !allowlist.contains(&"claude-3-haiku".to_string())
+        {
+            allowlist.push("claude-3-haiku".to_string());
         }
 
-        let picked = self.catalog.resolve(requested, &allowlist).ok_or_else(|| {
-            AppError::BadRequest(format!(
-                "model '{}' not allowed or not available",
-                requested
-            ))
-        })?;
+        let residency = account.and_then(|a| a.data_residency.as_deref());
+        let picked = self
+            .catalog
+            .resolve(requested, &allowlist, residency)
+            .ok_or_else(|| match self.catalog.resolve_blocked(requested) {
+                Some(reason) => AppError::Unavailable(reason),
+                None => match residency {
+                    Some(region) => AppError::BadRequest(format!(
+                        "model '{requested}' is not allowed, not available or not hosted in {region}, as this account's data residency requires"
+                    )),
+                    None => AppError::BadRequest(format!(
+                        "model '{}' not allowed or not available",
+                        requested
+                    )),
+                },
+            })?;
 
         if let Some(acct) = account {
             if acct.status != AccountStatus::Active {
                 return Err(AppError::BadRequest("account suspended".into()));
             }
-            if let Some(max_cents) = acct.max_cost_cents {
-                if picked.estimate_cents > max_cents as f64 {
-                    return Err(AppError::BadRequest(
-                        "requested model exceeds account cost limit".into(),
-                    ));
-                }
+            if let Some(max_cents) = acct.max_cost_cents
+                && picked.estimate_cents > max_cents as f64
+            {
+                return Err(AppError::BadRequest(
+                    "requested model exceeds account cost limit".into(),
+                ));
             }
         }
 
@@ -103,14 +353,22 @@ impl AccessControl {
         requested: &str,
     ) -> Result<Vec<RoutedModel>, AppError> {
         let routed = self.resolve_model(user_id, requested).await?;
+        let residency = self.residency_for(user_id).await;
         let mut plan = vec![routed.clone()];
         for fb in &routed.fallback_chain {
-            if let Some(entry) = self.catalog.entry(fb) {
+            // The chain was filtered by region already; checked again so no
+            // later change to resolution can let a fallback leave the region.
+            if let Some(entry) = self.catalog.entry(fb)
+                && entry.kind == ModelKind::Chat
+                && entry.satisfies(residency.as_deref())
+                && self.catalog.disabled_reason(fb).is_none()
+            {
                 plan.push(RoutedModel {
                     request_label: requested.to_string(),
                     resolved_model: entry.id.clone(),
                     provider: entry.provider.clone(),
                     estimate_cents: entry.estimate_cents(),
+                    context_window: entry.context_window,
                     fallback_chain: Vec::new(),
                 });
             }
@@ -118,42 +376,240 @@ impl AccessControl {
         Ok(plan)
     }
 
+    /// Looks a model up by catalog key or provider model id, ignoring account
+    /// allowlists. Used for gateway-internal calls such as context summaries.
+    pub fn model_entry(&self, model: &str) -> Option<CatalogEntry> {
+        self.catalog.entry(model).or_else(|| {
+            self.catalog
+                .list_models()
+                .into_iter()
+                .find(|m| m.id.eq_ignore_ascii_case(model))
+        })
+    }
+
     pub async fn list_models(&self) -> Vec<CatalogEntry> {
         self.catalog.list_models()
     }
 
-    pub async fn upsert_model(&self, entry: CatalogEntry) {
+    pub async fn upsert_model(&self, entry: CatalogEntry) -> Result<(), AppError> {
+        self.db.save_catalog_model(&entry.id, &entry).await?;
         self.catalog.upsert_model(entry).await;
+        Ok(())
     }
 
-    pub async fn set_alias(&self, alias: String, targets: Vec<AliasTarget>) {
+    pub async fn set_alias(
+        &self,
+        alias: String,
+        targets: Vec<AliasTarget>,
+    ) -> Result<(), AppError> {
+        self.db.save_alias(&alias, &targets).await?;
         self.catalog.set_alias(alias, targets).await;
+        Ok(())
     }
 
-    pub async fn set_fallbacks(&self, model: String, chain: Vec<String>) {
+    /// Removes a catalog model. Aliases, fallback chains and collections that
+    /// still point at it block the delete unless `force` is set, in which case
+    /// the model is stripped from aliases and chains (collections always block).
+    pub async fn delete_model(&self, id: &str, force: bool) -> Result<ModelDeletion, AppError> {
+        let mut defs = self.catalog.definitions();
+        let entry = defs
+            .models
+            .get(id)
+            .cloned()
+            .ok_or_else(|| AppError::BadRequest(format!("model {id} not found")))?;
+        // Aliases and chains may name a model by catalog key or by provider id.
+        let names = [id, entry.id.as_str()];
+        let collections = self.db.collections_using_model(&entry.id).await?;
+        if !collections.is_empty() {
+            return Err(AppError::BadRequest(format!(
+                "model {id} is the embedding model of collections: {}",
+                collections.join(", ")
+            )));
+        }
+        let aliases: BTreeMap<String, Vec<AliasTarget>> = defs
+            .aliases
+            .iter()
+            .filter(|(_, targets)| targets.iter().any(|t| names.contains(&t.model.as_str())))
+            .map(|(alias, targets)| {
+                let kept = targets
+                    .iter()
+                    .filter(|t| !names.contains(&t.model.as_str()))
+                    .cloned()
+                    .collect();
+                (alias.clone(), kept)
+            })
+            .collect();
+        let fallbacks: BTreeMap<String, Vec<String>> = defs
+            .fallbacks
+            .iter()
+            .filter(|(model, chain)| {
+                !names.contains(&model.as_str())
+                    && chain.iter().any(|m| names.contains(&m.as_str()))
+            })
+            .map(|(model, chain)| {
+                let kept = chain
+                    .iter()
+                    .filter(|m| !names.contains(&m.as_str()))
+                    .cloned()
+                    .collect();
+                (model.clone(), kept)
+            })
+            .collect();
+        if !force && (!aliases.is_empty() || !fallbacks.is_empty()) {
+            let mut refs: Vec<String> = aliases.keys().map(|a| format!("alias {a}")).collect();
+            refs.extend(fallbacks.keys().map(|m| format!("fallbacks of {m}")));
+            return Err(AppError::BadRequest(format!(
+                "model {id} is still referenced by {}; pass force=true to remove those references too",
+                refs.join(", ")
+            )));
+        }
+
+        self.db
+            .delete_catalog_model(id, &entry.id, &aliases, &fallbacks)
+            .await?;
+        defs.models.remove(id);
+        defs.fallbacks.remove(id);
+        defs.fallbacks.remove(&entry.id);
+        for (alias, targets) in &aliases {
+            if targets.is_empty() {
+                defs.aliases.remove(alias);
+            } else {
+                defs.aliases.insert(alias.clone(), targets.clone());
+            }
+        }
+        for (model, chain) in &fallbacks {
+            if chain.is_empty() {
+                defs.fallbacks.remove(model);
+            } else {
+                defs.fallbacks.insert(model.clone(), chain.clone());
+            }
+        }
+        self.catalog.replace_definitions(defs);
+        Ok(ModelDeletion {
+            model: id.to_string(),
+            updated_aliases: aliases.into_keys().collect(),
+            updated_fallbacks: fallbacks.into_keys().collect(),
+        })
+    }
+
+    pub async fn delete_alias(&self, alias: &str) -> Result<(), AppError> {
+        let mut defs = self.catalog.definitions();
+        if defs.aliases.remove(alias).is_none() {
+            return Err(AppError::BadRequest(format!("alias {alias} not found")));
+        }
+        self.db.delete_alias(alias).await?;
+        self.catalog.replace_definitions(defs);
+        Ok(())
+    }
+
+    pub async fn delete_fallbacks(&self, model: &str) -> Result<(), AppError> {
+        let mut defs = self.catalog.definitions();
+        if defs.fallbacks.remove(model).is_none() {
+            return Err(AppError::BadRequest(format!(
+                "model {model} has no fallback chain"
+            )));
+        }
+        self.db.delete_fallbacks(model).await?;
+        self.catalog.replace_definitions(defs);
+        Ok(())
+    }
+
+    pub async fn set_fallbacks(&self, model: String, chain: Vec<String>) -> Result<(), AppError> {
+        self.db.save_fallbacks(&model, &chain).await?;
         self.catalog.set_fallbacks(model, chain).await;
+        Ok(())
+    }
+
+    /// Records a routed call; `error` is set when it failed. A model that
+    /// starts failing raises a notification.
+    pub fn record_health(&self, model: &str, latency_ms: u128, error: Option<&str>) {
+        let ok = error.is_none();
+        let started_failing = self.catalog.record_health(model, ok, latency_ms);
+        if started_failing {
+            let db = self.db.clone();
+            let notice = Notice::model_failing(model, error.unwrap_or_default());
+            tokio::spawn(async move { notifications::publish(&db, notice).await });
+        }
+        if self.store.is_distributed() {
+            let store = self.store.clone();
+            let model = model.to_string();
+            tokio::spawn(async move {
+                if let Err(e) = store.record_health(&model, ok, latency_ms).await {
+                    tracing::warn!("failed to publish router health for {model}: {e}");
+                }
+            });
+        }
     }
 
-    pub fn record_health(&self, model: &str, ok: bool, latency_ms: u128) {
-        self.catalog.record_health(model, ok, latency_ms);
+    /// Pulls the shared router health into the local catalog.
+    pub async fn sync_health(&self) -> Result<(), AppError> {
+        let models: Vec<String> = self
+            .catalog
+            .list_models()
+            .into_iter()
+            .map(|m| m.id)
+            .collect();
+        for (model, health) in self.store.health(&models).await? {
+            self.catalog.apply_shared_health(&model, &health);
+        }
+        Ok(())
     }
 
     pub fn router_health(&self) -> Vec<RouterHealthEntry> {
         self.catalog.health_snapshot()
     }
 
+    pub fn preview_alias(&self, alias: &str, samples: u32) -> Option<AliasPreview> {
+        self.catalog.preview_alias(alias, samples)
+    }
+
+    pub fn switches(&self) -> GatewaySwitches {
+        self.catalog.switches()
+    }
+
+    /// The message to reject chat with while maintenance mode is on.
+    pub fn maintenance(&self) -> Option<String> {
+        self.catalog.switches().maintenance_message()
+    }
+
+    /// Why `model` is switched off, if it is.
+    pub fn disabled_reason(&self, model: &str) -> Option<String> {
+        self.catalog.disabled_reason(model)
+    }
+
+    /// Turns a switch on (`Some(reason)`, where the reason may be empty) or off,
+    /// and applies it locally right away.
+    pub async fn set_switch(
+        &self,
+        kind: SwitchKind,
+        target: &str,
+        enabled: bool,
+        reason: Option<&str>,
+    ) -> Result<GatewaySwitches, AppError> {
+        if enabled {
+            self.db.enable_switch(kind, target, reason).await?;
+        } else {
+            self.db.clear_switch(kind, target).await?;
+        }
+        self.catalog.set_switches(self.db.load_switches().await?);
+        Ok(self.catalog.switches())
+    }
+
+    pub fn catalog_definitions(&self) -> CatalogDefinitions {
+        self.catalog.definitions()
+    }
+
+    pub fn take_health_window(&self) -> Vec<HealthSample> {
+        self.catalog.take_health_window()
+    }
+
     pub async fn set_guardrail(
         &self,
         id: &str,
         guardrail_prompt: Option<String>,
     ) -> Result<AccountAccess, AppError> {
-        let mut accounts = self.accounts.write().await;
-        let account = accounts
-            .iter_mut()
-            .find(|a| a.id == id)
-            .ok_or_else(|| AppError::BadRequest(format!("account {id} not found")))?;
-        account.guardrail_prompt = guardrail_prompt;
-        Ok(account.clone())
+        self.update_account(id, |account| account.guardrail_prompt = guardrail_prompt)
+            .await
     }
 
     pub async fn guardrail_for(&self, id: Option<&str>) -> Option<String> {
@@ -162,6 +618,53 @@ impl AccessControl {
         account.and_then(|a| a.guardrail_prompt.clone())
     }
 
+    pub async fn set_data_residency(
+        &self,
+        id: &str,
+        data_residency: Option<String>,
+    ) -> Result<AccountAccess, AppError> {
+        self.update_account(id, |account| account.data_residency = data_residency)
+            .await
+    }
+
+    /// The region an account's prompts must stay in, if any.
+    pub async fn residency_for(&self, id: Option<&str>) -> Option<String> {
+        let accounts = self.accounts.read().await;
+        let account = id.and_then(|uid| accounts.iter().find(|a| a.id == uid));
+        account.and_then(|a| a.data_residency.clone())
+    }
+
+    pub async fn set_allowed_origins(
+        &self,
+        id: &str,
+        allowed_origins: Vec<String>,
+    ) -> Result<AccountAccess, AppError> {
+        self.update_account(id, |account| account.allowed_origins = allowed_origins)
+            .await
+    }
+
+    pub async fn set_inline_translation(
+        &self,
+        id: &str,
+        inline_translation: bool,
+    ) -> Result<AccountAccess, AppError> {
+        self.update_account(id, |account| {
+            account.inline_translation = inline_translation
+        })
+        .await
+    }
+
+    /// The web origins an account's session is restricted to; empty when it
+    /// isn't restricted.
+    pub async fn origins_for(&self, id: &str) -> Vec<String> {
+        let accounts = self.accounts.read().await;
+        accounts
+            .iter()
+            .find(|a| a.id == id)
+            .map(|a| a.allowed_origins.clone())
+            .unwrap_or_default()
+    }
+
     pub async fn account(&self, id: Option<&str>) -> Option<AccountAccess> {
         let accounts = self.accounts.read().await;
         id.and_then(|uid| accounts.iter().find(|a| a.id == uid).cloned())
@@ -174,15 +677,59 @@ impl AccessControl {
         tokens_per_day: Option<u32>,
         caps: Vec<ModelPriceCap>,
     ) -> Result<AccountAccess, AppError> {
-        let mut accounts = self.accounts.write().await;
-        let account = accounts
-            .iter_mut()
-            .find(|a| a.id == id)
-            .ok_or_else(|| AppError::BadRequest(format!("account {id} not found")))?;
-        account.req_per_day = req_per_day;
-        account.tokens_per_day = tokens_per_day;
-        account.model_price_caps = caps;
-        Ok(account.clone())
+        self.update_account(id, |account| {
+            account.req_per_day = req_per_day;
+            account.tokens_per_day = tokens_per_day;
+            account.model_price_caps = caps;
+        })
+        .await
+    }
+
+    /// The account's daily limits with any active override applied. Overrides
+    /// only ever raise a limit; an unlimited kind stays unlimited.
+    pub async fn effective_limits(&self, account: &AccountAccess) -> (Option<u32>, Option<u32>) {
+        let overrides = self.overrides.read().await;
+        let active = overrides.get(&account.id).filter(|o| o.is_active());
+        let raise = |base: Option<u32>, raised: Option<u32>| match (base, raised) {
+            (Some(base), Some(raised)) => Some(base.max(raised)),
+            (base, _) => base,
+        };
+        (
+            raise(account.req_per_day, active.and_then(|o| o.req_per_day)),
+            raise(
+                account.tokens_per_day,
+                active.and_then(|o| o.tokens_per_day),
+            ),
+        )
+    }
+
+    /// The account's unexpired override, if any.
+    pub async fn limit_override(&self, account_id: &str) -> Option<LimitOverride> {
+        let overrides = self.overrides.read().await;
+        overrides.get(account_id).filter(|o| o.is_active()).cloned()
+    }
+
+    /// Replaces the account's override.
+    pub async fn set_limit_override(&self, limit: LimitOverride) -> Result<(), AppError> {
+        if self.account(Some(&limit.account_id)).await.is_none() {
+            return Err(AppError::BadRequest(format!(
+                "account {} not found",
+                limit.account_id
+            )));
+        }
+        self.db.save_quota_override(&limit).await?;
+        self.overrides
+            .write()
+            .await
+            .insert(limit.account_id.clone(), limit);
+        Ok(())
+    }
+
+    /// Ends an override early. Returns whether there was one.
+    pub async fn clear_limit_override(&self, account_id: &str) -> Result<bool, AppError> {
+        let removed = self.db.delete_quota_override(account_id).await?;
+        self.overrides.write().await.remove(account_id);
+        Ok(removed)
     }
 
     pub async fn update_models(
@@ -190,19 +737,9 @@ impl AccessControl {
         id: &str,
         models: Vec<String>,
     ) -> Result<AccountAccess, AppError> {
-        let mut accounts = self.accounts.write().await;
-        let account = accounts
-            .iter_mut()
-            .find(|a| a.id == id)
-            .ok_or_else(|| AppError::BadRequest(format!("account {id} not found")))?;
-        let mut filtered = models
-            .into_iter()
-            .filter(|m| !m.trim().is_empty())
-            .collect::<Vec<_>>();
-        filtered.sort();
-        filtered.dedup();
-        account.allowed_models = filtered;
-        Ok(account.clone())
+        let filtered = normalize_model_list(models);
+        self.update_account(id, |account| account.allowed_models = filtered)
+            .await
     }
 
     pub async fn update_status(
@@ -210,13 +747,8 @@ impl AccessControl {
         id: &str,
         status: AccountStatus,
     ) -> Result<AccountAccess, AppError> {
-        let mut accounts = self.accounts.write().await;
-        let account = accounts
-            .iter_mut()
-            .find(|a| a.id == id)
-            .ok_or_else(|| AppError::BadRequest(format!("account {id} not found")))?;
-        account.status = status;
-        Ok(account.clone())
+        self.update_account(id, |account| account.status = status)
+            .await
     }
 
     #[allow(dead_code)]
@@ -225,13 +757,8 @@ impl AccessControl {
         id: &str,
         model: Option<String>,
     ) -> Result<AccountAccess, AppError> {
-        let mut accounts = self.accounts.write().await;
-        let account = accounts
-            .iter_mut()
-            .find(|a| a.id == id)
-            .ok_or_else(|| AppError::BadRequest(format!("account {id} not found")))?;
-        account.default_model = model;
-        Ok(account.clone())
+        self.update_account(id, |account| account.default_model = model)
+            .await
     }
 
     #[allow(dead_code)]
@@ -240,17 +767,80 @@ impl AccessControl {
         id: &str,
         max_cost_cents: Option<u32>,
     ) -> Result<AccountAccess, AppError> {
-        let mut accounts = self.accounts.write().await;
-        let account = accounts
-            .iter_mut()
-            .find(|a| a.id == id)
-            .ok_or_else(|| AppError::BadRequest(format!("account {id} not found")))?;
-        account.max_cost_cents = max_cost_cents;
-        Ok(account.clone())
+        self.update_account(id, |account| account.max_cost_cents = max_cost_cents)
+            .await
+    }
+}
+
+pub fn normalize_model_list(models: Vec<String>) -> Vec<String> {
+    let mut filtered = models
+        .into_iter()
+        .filter(|m| !m.trim().is_empty())
+        .collect::<Vec<_>>();
+    filtered.sort();
+    filtered.dedup();
+    filtered
+}
+
+/// Lowercases a hosting region or residency requirement, treating blank as
+/// unset. Regions are short labels such as `eu`, `us` or `eu-west`.
+pub fn normalize_region(region: Option<String>) -> Result<Option<String>, AppError> {
+    let Some(region) = region.map(|r| r.trim().to_lowercase()) else {
+        return Ok(None);
+    };
+    if region.is_empty() {
+        return Ok(None);
+    }
+    if region.len() > 32
+        || !region
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return Err(AppError::BadRequest(format!(
+            "region {region} must be up to 32 letters, digits or '-'"
+        )));
+    }
+    Ok(Some(region))
+}
+
+/// Lowercases and de-duplicates web origins, dropping a trailing `/`. Each must
+/// be a bare `http(s)://host[:port]`, as browsers send it in `Origin`.
+pub fn normalize_origins(origins: Vec<String>) -> Result<Vec<String>, AppError> {
+    let mut normalized: Vec<String> = Vec::new();
+    for origin in origins {
+        let origin = origin.trim().trim_end_matches('/').to_lowercase();
+        if origin.is_empty() {
+            continue;
+        }
+        let host = origin
+            .strip_prefix("https://")
+            .or_else(|| origin.strip_prefix("http://"));
+        let valid = host.is_some_and(|host| {
+            !host.is_empty()
+                && host
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | ':' | '[' | ']'))
+        });
+        if !valid {
+            return Err(AppError::BadRequest(format!(
+                "origin {origin} must look like https://host or https://host:port, with no path"
+            )));
+        }
+        if !normalized.contains(&origin) {
+            normalized.push(origin);
+        }
     }
+    Ok(normalized)
+}
+
+fn by_account(overrides: Vec<LimitOverride>) -> HashMap<String, LimitOverride> {
+    overrides
+        .into_iter()
+        .map(|o| (o.account_id.clone(), o))
+        .collect()
 }
 
-    pub fn seeded_accounts() -> Vec<AccountAccess> {
+pub fn seeded_accounts() -> Vec<AccountAccess> {
     vec![
         AccountAccess {
             id: "demo-user".into(),
@@ -277,6 +867,9 @@ impl AccessControl {
                     max_cents: 30,
                 },
             ],
+            data_residency: None,
+            allowed_origins: Vec::new(),
+            inline_translation: false,
         },
         AccountAccess {
             id: "ops-team".into(),
@@ -294,6 +887,9 @@ impl AccessControl {
             req_per_day: Some(2000),
             tokens_per_day: Some(2_000_000),
             model_price_caps: vec![],
+            data_residency: None,
+            allowed_origins: Vec::new(),
+            inline_translation: false,
         },
         AccountAccess {
             id: "guest".into(),
@@ -312,6 +908,9 @@ impl AccessControl {
                     max_cents: 5,
                 },
             ],
+            data_residency: None,
+            allowed_origins: Vec::new(),
+            inline_translation: false,
         },
     ]
 }
@@ -1,17 +1,52 @@
-use rand::{Rng, thread_rng};
+use super::switches::GatewaySwitches;
+use crate::shared_store::SharedHealth;
+use rand::{Rng, SeedableRng, rngs::StdRng};
 use serde::{Deserialize, Serialize};
 use std::{
-    collections::HashMap,
-    sync::{Arc, RwLock as StdRwLock},
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, Mutex as StdMutex, RwLock as StdRwLock},
     time::SystemTime,
 };
 
+/// What a catalog model is used for. Only chat models are routing candidates.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ModelKind {
+    #[default]
+    Chat,
+    Embedding,
+}
+
+impl ModelKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ModelKind::Chat => "chat",
+            ModelKind::Embedding => "embedding",
+        }
+    }
+
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "embedding" => ModelKind::Embedding,
+            _ => ModelKind::Chat,
+        }
+    }
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct CatalogEntry {
     pub provider: String,
     pub id: String,
     pub prompt_price_per_1k: f64,
     pub completion_price_per_1k: f64,
+    /// Maximum prompt + completion tokens the model accepts, when known.
+    #[serde(default)]
+    pub context_window: Option<u32>,
+    #[serde(default)]
+    pub kind: ModelKind,
+    /// Where the model is hosted (e.g. `eu`, `us`); unknown when unset.
+    #[serde(default)]
+    pub region: Option<String>,
 }
 
 impl CatalogEntry {
@@ -20,18 +55,35 @@ impl CatalogEntry {
         id: &str,
         prompt_price_cents: f64,
         completion_price_cents: f64,
+        context_window: Option<u32>,
     ) -> Self {
         Self {
             provider: provider.into(),
             id: id.into(),
             prompt_price_per_1k: prompt_price_cents,
             completion_price_per_1k: completion_price_cents,
+            context_window,
+            kind: ModelKind::Chat,
+            region: None,
+        }
+    }
+
+    pub fn embedding(provider: &str, id: &str, price_cents: f64, context_window: u32) -> Self {
+        Self {
+            kind: ModelKind::Embedding,
+            ..Self::new(provider, id, price_cents, 0.0, Some(context_window))
         }
     }
 
     pub fn estimate_cents(&self) -> f64 {
         self.prompt_price_per_1k + self.completion_price_per_1k
     }
+
+    /// Whether prompts bound to `residency` may be sent to this model. A model
+    /// with no region never satisfies a residency requirement.
+    pub fn satisfies(&self, residency: Option<&str>) -> bool {
+        residency.is_none_or(|r| self.region.as_deref() == Some(r))
+    }
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -55,6 +107,7 @@ pub struct RoutedModel {
     pub resolved_model: String,
     pub provider: String,
     pub estimate_cents: f64,
+    pub context_window: Option<u32>,
     pub fallback_chain: Vec<String>,
 }
 
@@ -69,9 +122,60 @@ pub struct RouterHealthEntry {
     pub updated_at: Option<SystemTime>,
 }
 
+/// One model's traffic over a history interval, as seen by this replica.
+#[derive(Clone, Debug, Serialize)]
+pub struct HealthSample {
+    pub model: String,
+    pub provider: String,
+    pub successes: u64,
+    pub failures: u64,
+    pub success_rate: f64,
+    pub p50_ms: Option<u64>,
+    pub p95_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+}
+
+/// A dry run of an alias's weighted pick.
+#[derive(Clone, Debug, Serialize)]
+pub struct AliasPreview {
+    pub alias: String,
+    pub samples: u32,
+    pub targets: Vec<AliasTargetPreview>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct AliasTargetPreview {
+    pub model: String,
+    pub weight: u32,
+    /// The target's share of the total weight.
+    pub expected_share: f64,
+    pub picks: u32,
+    pub observed_share: f64,
+    /// The catalog entry (provider, prices, kind); `None` when the target is
+    /// not in the catalog and requests picking it will fail.
+    pub entry: Option<CatalogEntry>,
+    /// `None` until the model has served traffic.
+    pub health: Option<RouterHealthEntry>,
+    /// Why a kill switch keeps the target out of routing, if one does.
+    pub disabled: Option<String>,
+}
+
+/// The admin-editable part of the catalog, keyed the same way as the router's
+/// lookup tables.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct CatalogDefinitions {
+    pub models: BTreeMap<String, CatalogEntry>,
+    pub aliases: BTreeMap<String, Vec<AliasTarget>>,
+    pub fallbacks: BTreeMap<String, Vec<String>>,
+}
+
 #[derive(Clone)]
 pub struct Catalog {
     state: Arc<StdRwLock<CatalogState>>,
+    /// Drives weighted alias picks. With a routing seed the same sequence of
+    /// requests resolves to the same sequence of models on every run.
+    rng: Arc<StdMutex<StdRng>>,
+    seed: Option<u64>,
 }
 
 #[derive(Clone)]
@@ -80,51 +184,59 @@ struct CatalogState {
     aliases: HashMap<String, AliasRule>,
     fallbacks: HashMap<String, Vec<String>>,
     health: HashMap<String, HealthStat>,
+    window: HashMap<String, HealthWindow>,
+    switches: GatewaySwitches,
 }
 
-impl Catalog {
+impl CatalogDefinitions {
     pub fn seed() -> Self {
-        let mut models: HashMap<String, CatalogEntry> = HashMap::new();
+        let mut models = BTreeMap::new();
         models.insert(
             "gpt-4-turbo-preview".into(),
-            CatalogEntry::new("openai", "gpt-4-turbo-preview", 0.5, 4.0),
+            CatalogEntry::new("openai", "gpt-4-turbo-preview", 0.5, 4.0, Some(128_000)),
         );
         models.insert(
             "claude-3.5-sonnet".into(),
-            CatalogEntry::new("anthropic", "claude-3-5-sonnet-20240620", 0.3, 3.5),
+            CatalogEntry::new(
+                "anthropic",
+                "claude-3-5-sonnet-20240620",
+                0.3,
+                3.5,
+                Some(200_000),
+            ),
         );
         models.insert(
             "claude-3-haiku".into(),
-            CatalogEntry::new("anthropic", "claude-3-haiku-20240307", 0.08, 3.0),
+            CatalogEntry::new(
+                "anthropic",
+                "claude-3-haiku-20240307",
+                0.08,
+                3.0,
+                Some(200_000),
+            ),
+        );
+
+        models.insert(
+            "text-embedding-3-small".into(),
+            CatalogEntry::embedding("openai", "text-embedding-3-small", 0.002, 8191),
         );
 
-        let mut aliases = HashMap::new();
+        let mut aliases = BTreeMap::new();
         aliases.insert(
             "gpt-4.1".into(),
-            AliasRule {
-                targets: vec![AliasTarget::new("gpt-4-turbo-preview", 100)],
-            },
+            vec![AliasTarget::new("gpt-4-turbo-preview", 100)],
         );
         aliases.insert(
             "gpt-latest".into(),
-            AliasRule {
-                targets: vec![AliasTarget::new("gpt-4-turbo-preview", 100)],
-            },
-        );
-        aliases.insert(
-            "cheap".into(),
-            AliasRule {
-                targets: vec![AliasTarget::new("gpt-4o-mini", 100)],
-            },
+            vec![AliasTarget::new("gpt-4-turbo-preview", 100)],
         );
+        aliases.insert("cheap".into(), vec![AliasTarget::new("gpt-4o-mini", 100)]);
         aliases.insert(
             "ops-fast".into(),
-            AliasRule {
-                targets: vec![AliasTarget::new("claude-3-haiku-20240307", 100)],
-            },
+            vec![AliasTarget::new("claude-3-haiku-20240307", 100)],
         );
 
-        let mut fallbacks = HashMap::new();
+        let mut fallbacks = BTreeMap::new();
         fallbacks.insert(
             "gpt-4-turbo-preview".into(),
             vec!["gpt-4o-mini".into(), "claude-3-5-sonnet-20240620".into()],
@@ -134,39 +246,122 @@ impl Catalog {
             vec!["claude-3-haiku-20240307".into(), "gpt-4o-mini".into()],
         );
 
-        let mut health = HashMap::new();
-        for key in models.keys() {
-            health.insert(key.clone(), HealthStat::default());
+        Self {
+            models,
+            aliases,
+            fallbacks,
         }
+    }
+}
 
-        Self {
+impl CatalogDefinitions {
+    /// The default catalog with every model served by the mock provider, for
+    /// a first boot without API keys. Model names stay the same so aliases,
+    /// accounts and the frontend work unchanged.
+    pub fn seed_mock() -> Self {
+        let mut defs = Self::seed();
+        for entry in defs.models.values_mut() {
+            entry.provider = "mock".into();
+        }
+        defs
+    }
+}
+
+impl Catalog {
+    pub fn from_definitions(defs: CatalogDefinitions, seed: Option<u64>) -> Self {
+        let catalog = Self {
             state: Arc::new(StdRwLock::new(CatalogState {
-                models,
-                aliases,
-                fallbacks,
-                health,
+                models: HashMap::new(),
+                aliases: HashMap::new(),
+                fallbacks: HashMap::new(),
+                health: HashMap::new(),
+                window: HashMap::new(),
+                switches: GatewaySwitches::default(),
             })),
+            rng: Arc::new(StdMutex::new(new_rng(seed))),
+            seed,
+        };
+        catalog.replace_definitions(defs);
+        catalog
+    }
+
+    /// Swaps in new models/aliases/fallbacks while keeping accumulated health.
+    pub fn replace_definitions(&self, defs: CatalogDefinitions) {
+        if let Ok(mut state) = self.state.write() {
+            for key in defs.models.keys() {
+                state.health.entry(key.clone()).or_default();
+            }
+            state.models = defs.models.into_iter().collect();
+            state.aliases = defs
+                .aliases
+                .into_iter()
+                .map(|(alias, targets)| (alias, AliasRule { targets }))
+                .collect();
+            state.fallbacks = defs.fallbacks.into_iter().collect();
         }
     }
 
-    pub fn resolve(&self, requested: &str, allowlist: &[String]) -> Option<RoutedModel> {
+    /// Snapshot of the admin-editable definitions, as stored in the database.
+    pub fn definitions(&self) -> CatalogDefinitions {
+        let Ok(state) = self.state.read() else {
+            return CatalogDefinitions::default();
+        };
+        CatalogDefinitions {
+            models: state
+                .models
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+            aliases: state
+                .aliases
+                .iter()
+                .map(|(k, v)| (k.clone(), v.targets.clone()))
+                .collect(),
+            fallbacks: state
+                .fallbacks
+                .iter()
+                .map(|(k, v)| (k.clone(), v.clone()))
+                .collect(),
+        }
+    }
+
+    /// With `residency` set, only models hosted in that region are picked,
+    /// whether directly, through an alias or as a fallback.
+    pub fn resolve(
+        &self,
+        requested: &str,
+        allowlist: &[String],
+        residency: Option<&str>,
+    ) -> Option<RoutedModel> {
         let state = self.state.read().ok()?;
-        let target = state
-            .pick_alias(requested)
-            .unwrap_or_else(|| requested.to_string());
+        let picked = {
+            let mut rng = self.rng.lock().ok()?;
+            state.pick_alias(requested, &mut *rng, residency)
+        };
+        let target = picked.unwrap_or_else(|| requested.to_string());
 
         let allow_lower: Vec<String> = allowlist.iter().map(|m| m.to_lowercase()).collect();
         let mut candidates: Vec<&CatalogEntry> = Vec::new();
-        if allow_lower.iter().any(|m| m == &target.to_lowercase()) {
-            if let Some(entry) = state.models.get(&target) {
-                candidates.push(entry);
-            }
+        if allow_lower.iter().any(|m| m == &target.to_lowercase())
+            && let Some(entry) = state.models.get(&target)
+            && entry.kind == ModelKind::Chat
+            && entry.satisfies(residency)
+            && state.switches.blocking(&target, entry).is_none()
+        {
+            candidates.push(entry);
         }
 
         let mut chain = state.fallbacks.get(&target).cloned().unwrap_or_default();
-        chain.retain(|m| allow_lower.iter().any(|al| al == &m.to_lowercase()));
+        chain.retain(|m| {
+            allow_lower.iter().any(|al| al == &m.to_lowercase())
+                && (residency.is_none()
+                    || state.models.get(m).is_some_and(|e| e.satisfies(residency)))
+        });
         for fb in &chain {
-            if let Some(entry) = state.models.get(fb) {
+            if let Some(entry) = state.models.get(fb)
+                && entry.kind == ModelKind::Chat
+                && state.switches.blocking(fb, entry).is_none()
+            {
                 candidates.push(entry);
             }
         }
@@ -185,10 +380,100 @@ impl Catalog {
             resolved_model: entry.id.clone(),
             provider: entry.provider.clone(),
             estimate_cents: entry.estimate_cents(),
+            context_window: entry.context_window,
             fallback_chain: remaining,
         })
     }
 
+    pub fn set_switches(&self, switches: GatewaySwitches) {
+        if let Ok(mut state) = self.state.write() {
+            state.switches = switches;
+        }
+    }
+
+    pub fn switches(&self) -> GatewaySwitches {
+        self.state
+            .read()
+            .map(|s| s.switches.clone())
+            .unwrap_or_default()
+    }
+
+    /// Why `model` (a catalog key or provider id) is switched off, if it is.
+    pub fn disabled_reason(&self, model: &str) -> Option<String> {
+        let state = self.state.read().ok()?;
+        let (key, entry) = state.lookup(model)?;
+        let switch = state.switches.blocking(key, entry)?;
+        Some(match &switch.reason {
+            Some(reason) => format!("model {model} is disabled: {reason}"),
+            None => format!("model {model} is disabled"),
+        })
+    }
+
+    /// Explains a failed resolve when kill switches, rather than the allowlist,
+    /// removed every candidate for `requested`.
+    pub fn resolve_blocked(&self, requested: &str) -> Option<String> {
+        let targets: Vec<String> = {
+            let state = self.state.read().ok()?;
+            match state.aliases.get(&requested.to_lowercase()) {
+                Some(rule) => rule.targets.iter().map(|t| t.model.clone()).collect(),
+                None => vec![requested.to_string()],
+            }
+        };
+        let mut reason = None;
+        for target in targets {
+            match self.disabled_reason(&target) {
+                Some(why) => {
+                    reason.get_or_insert(why);
+                }
+                None => return None,
+            }
+        }
+        reason
+    }
+
+    /// Runs the alias's weighted pick `samples` times without routing anything,
+    /// and describes each target as the router currently sees it.
+    pub fn preview_alias(&self, alias: &str, samples: u32) -> Option<AliasPreview> {
+        let state = self.state.read().ok()?;
+        let rule = state.aliases.get(&alias.to_lowercase())?;
+        // A preview gets its own generator so it never shifts live routing;
+        // with a seed it is reproducible too.
+        let mut rng = new_rng(self.seed);
+        let mut picks: HashMap<String, u32> = HashMap::new();
+        for _ in 0..samples {
+            if let Some(model) = rule.pick(&mut rng) {
+                *picks.entry(model).or_default() += 1;
+            }
+        }
+        let total_weight: u32 = rule.targets.iter().map(|t| t.weight).sum();
+        let share = |n: u32, of: u32| if of == 0 { 0.0 } else { n as f64 / of as f64 };
+        let targets = rule
+            .targets
+            .iter()
+            .map(|target| {
+                let found = state.lookup(&target.model);
+                let picked = picks.get(&target.model).copied().unwrap_or(0);
+                AliasTargetPreview {
+                    model: target.model.clone(),
+                    weight: target.weight,
+                    expected_share: share(target.weight, total_weight),
+                    picks: picked,
+                    observed_share: share(picked, samples),
+                    entry: found.map(|(_, e)| e.clone()),
+                    health: found.and_then(|(_, e)| state.health_entry(e)),
+                    disabled: found
+                        .and_then(|(key, e)| state.switches.blocking(key, e))
+                        .map(|s| s.reason.clone().unwrap_or_else(|| "disabled".into())),
+                }
+            })
+            .collect();
+        Some(AliasPreview {
+            alias: alias.to_lowercase(),
+            samples,
+            targets,
+        })
+    }
+
     pub fn all_aliases(&self) -> Vec<String> {
         if let Ok(state) = self.state.read() {
             let mut keys: Vec<String> = state
@@ -235,9 +520,12 @@ impl Catalog {
             .unwrap_or_default()
     }
 
-    pub fn record_health(&self, model: &str, ok: bool, latency_ms: u128) {
+    /// Records a call outcome. Returns true when a model that was answering
+    /// (or had no calls yet) has just failed.
+    pub fn record_health(&self, model: &str, ok: bool, latency_ms: u128) -> bool {
         if let Ok(mut state) = self.state.write() {
             let entry = state.health.entry(model.to_string()).or_default();
+            let was_ok = entry.updated_at.is_none() || entry.last_ok;
             entry.last_ok = ok;
             entry.last_latency_ms = Some(latency_ms);
             entry.updated_at = Some(SystemTime::now());
@@ -246,6 +534,26 @@ impl Catalog {
             } else {
                 entry.failures += 1;
             }
+            state
+                .window
+                .entry(model.to_string())
+                .or_default()
+                .record(ok, latency_ms);
+            return was_ok && !ok;
+        }
+        false
+    }
+
+    /// Overwrites local health with the cluster-wide view so every replica ranks
+    /// candidates the same way.
+    pub fn apply_shared_health(&self, model: &str, shared: &SharedHealth) {
+        if let Ok(mut state) = self.state.write() {
+            let entry = state.health.entry(model.to_string()).or_default();
+            entry.last_ok = shared.last_ok;
+            entry.last_latency_ms = shared.last_latency_ms;
+            entry.successes = shared.successes;
+            entry.failures = shared.failures;
+            entry.updated_at = shared.updated_at;
         }
     }
 
@@ -270,13 +578,93 @@ impl Catalog {
             Vec::new()
         }
     }
+
+    /// Drains the attempts recorded since the previous call into one sample per
+    /// model that saw traffic.
+    pub fn take_health_window(&self) -> Vec<HealthSample> {
+        let Ok(mut state) = self.state.write() else {
+            return Vec::new();
+        };
+        let window = std::mem::take(&mut state.window);
+        window
+            .into_iter()
+            .map(|(model, mut w)| {
+                let provider = state
+                    .models
+                    .get(&model)
+                    .map(|m| m.provider.clone())
+                    .unwrap_or_default();
+                w.latencies_ms.sort_unstable();
+                let total = w.successes + w.failures;
+                HealthSample {
+                    provider,
+                    successes: w.successes,
+                    failures: w.failures,
+                    success_rate: if total == 0 {
+                        0.0
+                    } else {
+                        w.successes as f64 / total as f64
+                    },
+                    p50_ms: percentile(&w.latencies_ms, 50),
+                    p95_ms: percentile(&w.latencies_ms, 95),
+                    p99_ms: percentile(&w.latencies_ms, 99),
+                    model,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Nearest-rank percentile of an ascending slice.
+fn percentile(sorted: &[u64], pct: usize) -> Option<u64> {
+    if sorted.is_empty() {
+        return None;
+    }
+    let rank = (sorted.len() * pct).div_ceil(100).max(1);
+    sorted.get(rank - 1).copied()
 }
 
 impl CatalogState {
-    fn pick_alias(&self, alias: &str) -> Option<String> {
-        self.aliases
-            .get(&alias.to_lowercase())
-            .and_then(|rule| rule.pick())
+    fn pick_alias(
+        &self,
+        alias: &str,
+        rng: &mut impl Rng,
+        residency: Option<&str>,
+    ) -> Option<String> {
+        self.aliases.get(&alias.to_lowercase()).and_then(|rule| {
+            rule.pick_where(rng, |model| {
+                residency.is_none()
+                    || self
+                        .models
+                        .get(model)
+                        .is_some_and(|e| e.satisfies(residency))
+            })
+        })
+    }
+
+    /// Finds a model by catalog key, or failing that by provider model id.
+    fn lookup(&self, model: &str) -> Option<(&String, &CatalogEntry)> {
+        self.models.get_key_value(model).or_else(|| {
+            self.models
+                .iter()
+                .find(|(_, e)| e.id.eq_ignore_ascii_case(model))
+        })
+    }
+
+    fn health_entry(&self, entry: &CatalogEntry) -> Option<RouterHealthEntry> {
+        let stat = self
+            .health
+            .get(&entry.id)
+            .filter(|s| s.updated_at.is_some())?;
+        Some(RouterHealthEntry {
+            model: entry.id.clone(),
+            provider: entry.provider.clone(),
+            last_ok: stat.last_ok,
+            last_latency_ms: stat.last_latency_ms,
+            successes: stat.successes,
+            failures: stat.failures,
+            updated_at: stat.updated_at,
+        })
     }
 }
 
@@ -286,14 +674,20 @@ struct AliasRule {
 }
 
 impl AliasRule {
-    fn pick(&self) -> Option<String> {
-        let total: u32 = self.targets.iter().map(|t| t.weight).sum();
+    fn pick(&self, rng: &mut impl Rng) -> Option<String> {
+        self.pick_where(rng, |_| true)
+    }
+
+    /// A weighted pick among the targets `eligible` accepts.
+    fn pick_where(&self, rng: &mut impl Rng, eligible: impl Fn(&str) -> bool) -> Option<String> {
+        let targets: Vec<&AliasTarget> =
+            self.targets.iter().filter(|t| eligible(&t.model)).collect();
+        let total: u32 = targets.iter().map(|t| t.weight).sum();
         if total == 0 {
             return None;
         }
-        let mut rng = thread_rng();
         let mut roll = rng.gen_range(0..total);
-        for target in &self.targets {
+        for target in targets {
             if roll < target.weight {
                 return Some(target.model.clone());
             }
@@ -303,6 +697,37 @@ impl AliasRule {
     }
 }
 
+fn new_rng(seed: Option<u64>) -> StdRng {
+    match seed {
+        Some(seed) => StdRng::seed_from_u64(seed),
+        None => StdRng::from_entropy(),
+    }
+}
+
+/// Latency samples kept per model between history snapshots.
+const MAX_WINDOW_SAMPLES: usize = 10_000;
+
+#[derive(Clone, Debug, Default)]
+struct HealthWindow {
+    successes: u64,
+    failures: u64,
+    latencies_ms: Vec<u64>,
+}
+
+impl HealthWindow {
+    fn record(&mut self, ok: bool, latency_ms: u128) {
+        if ok {
+            self.successes += 1;
+        } else {
+            self.failures += 1;
+        }
+        if self.latencies_ms.len() < MAX_WINDOW_SAMPLES {
+            self.latencies_ms
+                .push(u64::try_from(latency_ms).unwrap_or(u64::MAX));
+        }
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 struct HealthStat {
     last_latency_ms: Option<u128>,
@@ -1,5 +1,13 @@
 mod accounts;
 mod catalog;
+mod switches;
 
-pub use accounts::{AccessControl, AccountAccess, AccountStatus, ModelPriceCap, seeded_accounts};
-pub use catalog::{AliasTarget, CatalogEntry, RoutedModel, RouterHealthEntry};
+pub use accounts::{
+    AccessControl, AccountAccess, AccountStatus, LimitOverride, ModelDeletion, ModelPriceCap,
+    normalize_model_list, normalize_origins, normalize_region,
+};
+pub use catalog::{
+    AliasPreview, AliasTarget, CatalogDefinitions, CatalogEntry, HealthSample, ModelKind,
+    RoutedModel, RouterHealthEntry,
+};
+pub use switches::{GatewaySwitches, Switch, SwitchKind};
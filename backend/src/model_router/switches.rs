@@ -0,0 +1,70 @@
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+use super::catalog::CatalogEntry;
+
+pub const DEFAULT_MAINTENANCE_MESSAGE: &str =
+    "The assistant is down for maintenance. Please try again shortly.";
+
+/// Operator overrides: maintenance mode and providers or models taken out of
+/// routing regardless of their health.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct GatewaySwitches {
+    pub maintenance: Option<Switch>,
+    /// Keyed by lowercase provider name.
+    pub providers: BTreeMap<String, Switch>,
+    /// Keyed by catalog key or provider model id, lowercase.
+    pub models: BTreeMap<String, Switch>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Switch {
+    /// Shown to callers for maintenance; operator notes for kill switches.
+    pub reason: Option<String>,
+    pub since: String,
+}
+
+/// Which kind of switch a stored row is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwitchKind {
+    Maintenance,
+    Provider,
+    Model,
+}
+
+impl SwitchKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SwitchKind::Maintenance => "maintenance",
+            SwitchKind::Provider => "provider",
+            SwitchKind::Model => "model",
+        }
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "maintenance" => Some(SwitchKind::Maintenance),
+            "provider" => Some(SwitchKind::Provider),
+            "model" => Some(SwitchKind::Model),
+            _ => None,
+        }
+    }
+}
+
+impl GatewaySwitches {
+    /// The switch that takes `entry` (stored under `key`) out of routing, if any.
+    pub fn blocking(&self, key: &str, entry: &CatalogEntry) -> Option<&Switch> {
+        self.providers
+            .get(&entry.provider.to_lowercase())
+            .or_else(|| self.models.get(&key.to_lowercase()))
+            .or_else(|| self.models.get(&entry.id.to_lowercase()))
+    }
+
+    pub fn maintenance_message(&self) -> Option<String> {
+        self.maintenance.as_ref().map(|m| {
+            m.reason
+                .clone()
+                .unwrap_or_else(|| DEFAULT_MAINTENANCE_MESSAGE.to_string())
+        })
+    }
+}
@@ -0,0 +1,227 @@
+//! Admin notification center: events worth an operator's attention (budget
+//! breaches, models that start failing, flagged messages awaiting review) kept
+//! with read/unread state instead of scrolling by in the overview alerts.
+
+use crate::{
+    AppState,
+    abuse::AccountRisk,
+    db::{Db, Notification, NotificationInsert},
+    error::AppError,
+    webhooks,
+};
+use axum::{
+    Json,
+    extract::{Query, State},
+};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::warn;
+
+const DEFAULT_LIST_LIMIT: i64 = 100;
+const MAX_LIST_LIMIT: i64 = 500;
+
+pub const KINDS: &[&str] = &[
+    "budget_breach",
+    "model_failing",
+    "review_pending",
+    "key_rotation",
+    "abuse_suspected",
+    "canary_leak",
+];
+
+/// An event to record. Unread notices sharing a `dedup_key` collapse into one.
+pub struct Notice {
+    pub kind: &'static str,
+    pub severity: &'static str,
+    pub title: String,
+    pub detail: String,
+    pub dedup_key: String,
+}
+
+impl Notice {
+    /// A request turned away by a daily quota or price cap.
+    pub fn budget_breach(user_id: Option<&str>, limit: &str, detail: &str) -> Self {
+        let account = user_id.unwrap_or("anonymous");
+        Self {
+            kind: "budget_breach",
+            severity: "warning",
+            title: format!(
+                "Account {account} hit its {} limit",
+                limit.replace('_', " ")
+            ),
+            detail: detail.to_string(),
+            dedup_key: format!("budget:{account}:{limit}"),
+        }
+    }
+
+    /// A model that was answering started failing.
+    pub fn model_failing(model: &str, error: &str) -> Self {
+        Self {
+            kind: "model_failing",
+            severity: "critical",
+            title: format!("Model {model} is failing"),
+            detail: error.to_string(),
+            dedup_key: format!("model:{model}"),
+        }
+    }
+
+    /// A provider rejected its primary API key and requests fell back to the
+    /// secondary one.
+    pub fn key_rotation(provider: &str, error: &str) -> Self {
+        Self {
+            kind: "key_rotation",
+            severity: "critical",
+            title: format!("{provider} rejected its primary API key"),
+            detail: format!(
+                "Requests are using the secondary key; promote it to the primary and issue a new secondary. {error}"
+            ),
+            dedup_key: format!("key_rotation:{provider}"),
+        }
+    }
+
+    /// An account's activity scored as abuse and it was throttled.
+    pub fn abuse_suspected(risk: &AccountRisk) -> Self {
+        let until = risk
+            .throttled_until
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default();
+        Self {
+            kind: "abuse_suspected",
+            severity: "critical",
+            title: format!("Account {} throttled for unusual activity", risk.account_id),
+            detail: format!(
+                "Score {}: {} request(s) in the last minute, {} blocked attempt(s) and {} repeated prompt(s) in the last 10 minutes. Throttled until {until}; check for a leaked credential.",
+                risk.score, risk.requests_last_minute, risk.blocked, risk.repeated_prompts
+            ),
+            dedup_key: format!("abuse:{}", risk.account_id),
+        }
+    }
+
+    /// An account's guardrail canary showed up in a reply or a user message,
+    /// so its guardrail prompt has most likely been extracted.
+    pub fn canary_leak(account_id: &str, seen_in: &str, seen_by: Option<&str>) -> Self {
+        let seen_by = seen_by.unwrap_or("anonymous");
+        let place = if seen_in == "output" {
+            format!("a model reply to {seen_by}")
+        } else {
+            format!("a message sent by {seen_by}")
+        };
+        Self {
+            kind: "canary_leak",
+            severity: "critical",
+            title: format!("Guardrail prompt of account {account_id} leaked"),
+            detail: format!(
+                "Its canary token appeared in {place}. Look for prompt injection in that conversation, then rotate the canary."
+            ),
+            dedup_key: format!("canary:{account_id}:{seen_in}:{seen_by}"),
+        }
+    }
+
+    /// A message matched a `flag` policy and should be looked at.
+    pub fn review_pending(policy_name: &str, policy_id: &str, message_id: &str) -> Self {
+        Self {
+            kind: "review_pending",
+            severity: "info",
+            title: format!("Policy {policy_name} flagged a message"),
+            detail: format!("message {message_id}"),
+            dedup_key: format!("review:{policy_id}"),
+        }
+    }
+}
+
+/// Records a notice and, when it's new rather than a repeat of an unread one,
+/// sends it to subscribed webhooks. Notifications are best effort and never
+/// fail the request that raised them.
+pub async fn publish(db: &Db, notice: Notice) {
+    let result = db
+        .record_notification(NotificationInsert {
+            kind: notice.kind,
+            severity: notice.severity,
+            title: &notice.title,
+            detail: &notice.detail,
+            dedup_key: &notice.dedup_key,
+        })
+        .await;
+    match result {
+        Ok(true) => {
+            let data = json!({
+                "kind": notice.kind,
+                "severity": notice.severity,
+                "title": notice.title,
+                "detail": notice.detail,
+            });
+            webhooks::enqueue(db, notice.kind, data).await;
+        }
+        Ok(false) => {}
+        Err(e) => warn!("failed to record {} notification: {e}", notice.kind),
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NotificationQuery {
+    #[serde(default)]
+    pub unread: bool,
+    pub kind: Option<String>,
+    pub limit: Option<i64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct NotificationList {
+    pub unread: i64,
+    pub items: Vec<Notification>,
+}
+
+pub async fn list_notifications(
+    State(state): State<AppState>,
+    Query(query): Query<NotificationQuery>,
+) -> Result<Json<NotificationList>, AppError> {
+    let kind = query.kind.filter(|k| !k.trim().is_empty());
+    if let Some(kind) = &kind
+        && !KINDS.contains(&kind.as_str())
+    {
+        return Err(AppError::BadRequest(format!(
+            "unknown notification kind {kind}; expected one of {}",
+            KINDS.join(", ")
+        )));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let items = state
+        .db
+        .list_notifications(query.unread, kind.as_deref(), limit)
+        .await?;
+    let unread = state.db.unread_notification_count().await?;
+    Ok(Json(NotificationList { unread, items }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MarkNotificationsBody {
+    /// Notifications to update; every notification when omitted.
+    pub ids: Option<Vec<String>>,
+    #[serde(default = "read_default")]
+    pub read: bool,
+}
+
+fn read_default() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize)]
+pub struct MarkNotificationsResponse {
+    pub updated: u64,
+    pub unread: i64,
+}
+
+pub async fn mark_notifications(
+    State(state): State<AppState>,
+    Json(body): Json<MarkNotificationsBody>,
+) -> Result<Json<MarkNotificationsResponse>, AppError> {
+    let updated = state
+        .db
+        .mark_notifications(body.ids.as_deref(), body.read)
+        .await?;
+    let unread = state.db.unread_notification_count().await?;
+    Ok(Json(MarkNotificationsResponse { updated, unread }))
+}
@@ -0,0 +1,485 @@
+//! Provider API keys brought by accounts. A stored key is used instead of the
+//! gateway's own key whenever that account's requests go to the provider.
+//!
+//! Keys are sealed with AES-256-GCM under `KEY_ENCRYPTION_KEY`, which can come
+//! from a secret manager like any other secret. The account and provider are
+//! bound in as associated data, so a sealed key copied onto another row won't
+//! open. The API only ever shows the last four characters. After rotating the
+//! master key, keep the old one as `KEY_ENCRYPTION_KEY_PREVIOUS` until
+//! `/admin/provider-keys/reencrypt` has moved everything off it.
+//!
+//! The same master key seals webhook signing secrets, and the reencrypt run
+//! covers those too.
+
+use crate::{
+    AppState,
+    config::{Config, SharedConfig},
+    db::{Db, ProviderKeyRecord, WebhookRecord},
+    error::AppError,
+    llm::Provider,
+    routes::chat::provider_from_str,
+};
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use chrono::Utc;
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use tracing::warn;
+
+/// Shorter than any real provider key; guards against pasting the wrong field.
+const MIN_KEY_LEN: usize = 16;
+
+/// A master key and the id stored next to everything it sealed.
+struct MasterKey {
+    id: String,
+    key: LessSafeKey,
+}
+
+impl MasterKey {
+    fn parse(var: &str, raw: &str) -> Result<Self, AppError> {
+        let bytes = hex::decode(raw.trim())
+            .ok()
+            .filter(|b| b.len() == 32)
+            .ok_or_else(|| AppError::Config(format!("{var} must be 64 hex characters")))?;
+        let key = UnboundKey::new(&AES_256_GCM, &bytes)
+            .map_err(|_| AppError::Config(format!("{var} is not a valid AES-256 key")))?;
+        Ok(Self {
+            id: hex::encode(Sha256::digest(&bytes))[..16].to_string(),
+            key: LessSafeKey::new(key),
+        })
+    }
+
+    fn seal(&self, aad: &str, plaintext: &str) -> Result<(Vec<u8>, Vec<u8>), AppError> {
+        let mut nonce = [0u8; NONCE_LEN];
+        SystemRandom::new()
+            .fill(&mut nonce)
+            .map_err(|_| AppError::Internal("no randomness available for a nonce".into()))?;
+        let mut sealed = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(aad.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| AppError::Internal("failed to encrypt provider key".into()))?;
+        Ok((nonce.to_vec(), sealed))
+    }
+
+    fn open(&self, aad: &str, nonce: &[u8], sealed: &[u8]) -> Option<String> {
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut buf = sealed.to_vec();
+        let plain = self
+            .key
+            .open_in_place(nonce, Aad::from(aad.as_bytes()), &mut buf)
+            .ok()?;
+        String::from_utf8(plain.to_vec()).ok()
+    }
+}
+
+/// The current master key and, during a rotation, the previous one.
+struct Keyring {
+    current: MasterKey,
+    previous: Option<MasterKey>,
+}
+
+impl Keyring {
+    fn from_config(config: &Config) -> Result<Self, AppError> {
+        let current = config.key_encryption_key.as_deref().ok_or_else(|| {
+            AppError::Config("stored provider keys need KEY_ENCRYPTION_KEY to be set".into())
+        })?;
+        Ok(Self {
+            current: MasterKey::parse("KEY_ENCRYPTION_KEY", current)?,
+            previous: config
+                .key_encryption_key_previous
+                .as_deref()
+                .map(|raw| MasterKey::parse("KEY_ENCRYPTION_KEY_PREVIOUS", raw))
+                .transpose()?,
+        })
+    }
+
+    fn by_id(&self, id: &str) -> Option<&MasterKey> {
+        std::iter::once(&self.current)
+            .chain(self.previous.as_ref())
+            .find(|k| k.id == id)
+    }
+
+    /// Opens something sealed under either master key; `what` names it in errors.
+    fn open_sealed(
+        &self,
+        what: &str,
+        key_id: &str,
+        aad: &str,
+        nonce: &[u8],
+        sealed: &[u8],
+    ) -> Result<String, AppError> {
+        let master = self.by_id(key_id).ok_or_else(|| {
+            AppError::Config(format!(
+                "{what} was sealed with master key {key_id}, which is neither KEY_ENCRYPTION_KEY nor KEY_ENCRYPTION_KEY_PREVIOUS"
+            ))
+        })?;
+        master
+            .open(aad, nonce, sealed)
+            .ok_or_else(|| AppError::Config(format!("{what} failed to decrypt")))
+    }
+
+    fn open(&self, record: &ProviderKeyRecord) -> Result<String, AppError> {
+        self.open_sealed(
+            &format!(
+                "the {} key for account {}",
+                record.provider, record.account_id
+            ),
+            &record.key_id,
+            &associated_data(&record.account_id, &record.provider),
+            &record.nonce,
+            &record.ciphertext,
+        )
+    }
+
+    /// A webhook's signing secret: sealed, or plaintext from before sealing.
+    fn open_webhook_secret(&self, record: &WebhookSecret<'_>) -> Result<String, AppError> {
+        match (record.key_id, record.nonce, record.ciphertext) {
+            (Some(key_id), Some(nonce), Some(ciphertext)) => self.open_sealed(
+                &format!("the secret of webhook {}", record.webhook_id),
+                key_id,
+                &webhook_associated_data(record.webhook_id),
+                nonce,
+                ciphertext,
+            ),
+            _ if !record.plaintext.is_empty() => Ok(record.plaintext.to_string()),
+            _ => Err(AppError::Config(format!(
+                "webhook {} has no secret",
+                record.webhook_id
+            ))),
+        }
+    }
+}
+
+fn associated_data(account_id: &str, provider: &str) -> String {
+    format!("{account_id}\n{provider}")
+}
+
+fn webhook_associated_data(webhook_id: &str) -> String {
+    format!("webhook\n{webhook_id}")
+}
+
+/// The stored form of a webhook's signing secret.
+pub struct WebhookSecret<'a> {
+    pub webhook_id: &'a str,
+    pub plaintext: &'a str,
+    pub nonce: Option<&'a [u8]>,
+    pub ciphertext: Option<&'a [u8]>,
+    pub key_id: Option<&'a str>,
+}
+
+impl<'a> From<&'a WebhookRecord> for WebhookSecret<'a> {
+    fn from(record: &'a WebhookRecord) -> Self {
+        Self {
+            webhook_id: &record.id,
+            plaintext: &record.secret,
+            nonce: record.secret_nonce.as_deref(),
+            ciphertext: record.secret_ciphertext.as_deref(),
+            key_id: record.secret_key_id.as_deref(),
+        }
+    }
+}
+
+/// A webhook secret sealed under the current master key.
+pub struct SealedSecret {
+    pub key_id: String,
+    pub nonce: Vec<u8>,
+    pub ciphertext: Vec<u8>,
+}
+
+/// A stored key as the API shows it.
+#[derive(Debug, Serialize)]
+pub struct ProviderKeyView {
+    pub account_id: String,
+    pub provider: String,
+    /// `…` and the last four characters.
+    pub masked_key: String,
+    /// The master key it's sealed with.
+    pub key_id: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl From<ProviderKeyRecord> for ProviderKeyView {
+    fn from(record: ProviderKeyRecord) -> Self {
+        Self {
+            masked_key: format!("…{}", record.last4),
+            account_id: record.account_id,
+            provider: record.provider,
+            key_id: record.key_id,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct Reencryption {
+    pub key_id: String,
+    pub reencrypted: usize,
+    pub already_current: usize,
+    /// Webhook secrets moved onto the current master key, including ones
+    /// stored in plaintext before secrets were sealed.
+    pub webhook_secrets: usize,
+    /// Keys that couldn't be opened with either master key, and why.
+    pub failed: Vec<String>,
+}
+
+#[derive(Clone)]
+pub struct ProviderKeys {
+    db: Db,
+    config: SharedConfig,
+}
+
+impl ProviderKeys {
+    pub fn new(db: Db, config: SharedConfig) -> Self {
+        Self { db, config }
+    }
+
+    fn keyring(&self) -> Result<Keyring, AppError> {
+        Keyring::from_config(&self.config.load())
+    }
+
+    pub async fn set(
+        &self,
+        account_id: &str,
+        provider: Provider,
+        api_key: &str,
+    ) -> Result<ProviderKeyView, AppError> {
+        let api_key = api_key.trim();
+        // Provider keys are printable ASCII, which also keeps the last-4 hint
+        // on a character boundary.
+        if api_key.len() < MIN_KEY_LEN || !api_key.chars().all(|c| c.is_ascii_graphic()) {
+            return Err(AppError::BadRequest(
+                "api_key doesn't look like a provider API key".into(),
+            ));
+        }
+        let keyring = self.keyring()?;
+        let provider = provider.to_string();
+        let (nonce, ciphertext) = keyring
+            .current
+            .seal(&associated_data(account_id, &provider), api_key)?;
+        let now = Utc::now().to_rfc3339();
+        let record = ProviderKeyRecord {
+            account_id: account_id.to_string(),
+            provider,
+            nonce,
+            ciphertext,
+            key_id: keyring.current.id.clone(),
+            last4: api_key[api_key.len() - 4..].to_string(),
+            created_at: now.clone(),
+            updated_at: now,
+        };
+        self.db.save_provider_key(&record).await?;
+        let saved = self
+            .db
+            .provider_keys(Some(account_id))
+            .await?
+            .into_iter()
+            .find(|r| r.provider == record.provider)
+            .unwrap_or(record);
+        Ok(saved.into())
+    }
+
+    /// Seals a webhook's signing secret under the current master key.
+    pub fn seal_webhook_secret(
+        &self,
+        webhook_id: &str,
+        secret: &str,
+    ) -> Result<SealedSecret, AppError> {
+        let keyring = self.keyring()?;
+        let (nonce, ciphertext) = keyring
+            .current
+            .seal(&webhook_associated_data(webhook_id), secret)?;
+        Ok(SealedSecret {
+            key_id: keyring.current.id.clone(),
+            nonce,
+            ciphertext,
+        })
+    }
+
+    pub fn open_webhook_secret(&self, secret: &WebhookSecret<'_>) -> Result<String, AppError> {
+        // Endpoints from before sealing still work without a master key.
+        if secret.key_id.is_none() && !secret.plaintext.is_empty() {
+            return Ok(secret.plaintext.to_string());
+        }
+        self.keyring()?.open_webhook_secret(secret)
+    }
+
+    pub async fn list(&self, account_id: &str) -> Result<Vec<ProviderKeyView>, AppError> {
+        let records = self.db.provider_keys(Some(account_id)).await?;
+        Ok(records.into_iter().map(ProviderKeyView::from).collect())
+    }
+
+    /// The account's own keys, decrypted, for routing its requests. Accounts
+    /// without stored keys don't need a master key configured.
+    pub async fn for_account(
+        &self,
+        account_id: Option<&str>,
+    ) -> Result<HashMap<Provider, String>, AppError> {
+        let Some(account_id) = account_id else {
+            return Ok(HashMap::new());
+        };
+        let records = self.db.provider_keys(Some(account_id)).await?;
+        if records.is_empty() {
+            return Ok(HashMap::new());
+        }
+        let keyring = self.keyring()?;
+        let mut keys = HashMap::new();
+        for record in records {
+            let provider = provider_from_str(&record.provider)?;
+            keys.insert(provider, keyring.open(&record)?);
+        }
+        Ok(keys)
+    }
+
+    /// Re-seals every key not under the current master key.
+    pub async fn reencrypt(&self) -> Result<Reencryption, AppError> {
+        let keyring = self.keyring()?;
+        let mut outcome = Reencryption {
+            key_id: keyring.current.id.clone(),
+            ..Reencryption::default()
+        };
+        for record in self.db.provider_keys(None).await? {
+            if record.key_id == keyring.current.id {
+                outcome.already_current += 1;
+                continue;
+            }
+            let plain = match keyring.open(&record) {
+                Ok(plain) => plain,
+                Err(e) => {
+                    warn!("{e}");
+                    outcome.failed.push(e.to_string());
+                    continue;
+                }
+            };
+            let (nonce, ciphertext) = keyring.current.seal(
+                &associated_data(&record.account_id, &record.provider),
+                &plain,
+            )?;
+            let resealed = ProviderKeyRecord {
+                nonce,
+                ciphertext,
+                key_id: keyring.current.id.clone(),
+                ..record.clone()
+            };
+            // A key replaced in the meantime is already under the current key.
+            if self
+                .db
+                .reseal_provider_key(&resealed, &record.key_id)
+                .await?
+            {
+                outcome.reencrypted += 1;
+            } else {
+                outcome.already_current += 1;
+            }
+        }
+        for record in self.db.list_webhooks().await? {
+            if record.secret_key_id.as_deref() == Some(keyring.current.id.as_str()) {
+                continue;
+            }
+            let plain = match keyring.open_webhook_secret(&WebhookSecret::from(&record)) {
+                Ok(plain) => plain,
+                Err(e) => {
+                    warn!("{e}");
+                    outcome.failed.push(e.to_string());
+                    continue;
+                }
+            };
+            let (nonce, ciphertext) = keyring
+                .current
+                .seal(&webhook_associated_data(&record.id), &plain)?;
+            let resealed = WebhookRecord {
+                secret: String::new(),
+                secret_nonce: Some(nonce),
+                secret_ciphertext: Some(ciphertext),
+                secret_key_id: Some(keyring.current.id.clone()),
+                ..record.clone()
+            };
+            if self
+                .db
+                .reseal_webhook_secret(&resealed, record.secret_key_id.as_deref())
+                .await?
+            {
+                outcome.webhook_secrets += 1;
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+fn stored_provider(provider: &str) -> Result<Provider, AppError> {
+    match provider_from_str(provider)? {
+        Provider::Mock => Err(AppError::BadRequest(
+            "the mock provider doesn't take an API key".into(),
+        )),
+        provider => Ok(provider),
+    }
+}
+
+async fn existing_account(state: &AppState, id: &str) -> Result<(), AppError> {
+    match state.access.account(Some(id)).await {
+        Some(_) => Ok(()),
+        None => Err(AppError::BadRequest(format!("account {id} not found"))),
+    }
+}
+
+pub async fn list_provider_keys(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ProviderKeyView>>, AppError> {
+    existing_account(&state, &id).await?;
+    Ok(Json(state.provider_keys.list(&id).await?))
+}
+
+#[derive(Deserialize)]
+pub struct ProviderKeyBody {
+    pub api_key: String,
+}
+
+pub async fn set_provider_key(
+    Path((id, provider)): Path<(String, String)>,
+    State(state): State<AppState>,
+    Json(body): Json<ProviderKeyBody>,
+) -> Result<Json<ProviderKeyView>, AppError> {
+    existing_account(&state, &id).await?;
+    let provider = stored_provider(&provider)?;
+    let view = state
+        .provider_keys
+        .set(&id, provider, &body.api_key)
+        .await?;
+    Ok(Json(view))
+}
+
+pub async fn delete_provider_key(
+    Path((id, provider)): Path<(String, String)>,
+    State(state): State<AppState>,
+) -> Result<Json<Vec<ProviderKeyView>>, AppError> {
+    let provider = stored_provider(&provider)?;
+    if !state
+        .db
+        .delete_provider_key(&id, &provider.to_string())
+        .await?
+    {
+        return Err(AppError::BadRequest(format!(
+            "account {id} has no stored {provider} key"
+        )));
+    }
+    Ok(Json(state.provider_keys.list(&id).await?))
+}
+
+pub async fn reencrypt_provider_keys(
+    State(state): State<AppState>,
+) -> Result<Json<Reencryption>, AppError> {
+    Ok(Json(state.provider_keys.reencrypt().await?))
+}
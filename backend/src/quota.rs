@@ -0,0 +1,133 @@
+use crate::{db::Db, error::AppError};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+/// Quotas cover the current hour and the ones before it, this many in all.
+pub(crate) const WINDOW_HOURS: usize = 24;
+
+/// Per-account rolling 24h request/token counters kept in memory so quota checks
+/// never touch the database. Every exchange is also written to the hourly rollup
+/// table in its persistence transaction, which is what the counters are rebuilt
+/// from on boot.
+#[derive(Clone, Default)]
+pub struct UsageCounters {
+    accounts: Arc<Mutex<HashMap<String, UsageWindow>>>,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct WindowTotals {
+    pub requests: u64,
+    pub tokens: u64,
+}
+
+#[derive(Clone, Copy, Default)]
+struct Bucket {
+    hour: i64,
+    requests: u64,
+    tokens: u64,
+}
+
+#[derive(Default)]
+struct UsageWindow {
+    buckets: [Bucket; WINDOW_HOURS],
+}
+
+impl UsageWindow {
+    fn add(&mut self, hour: i64, requests: u64, tokens: u64) {
+        let slot = &mut self.buckets[hour.rem_euclid(WINDOW_HOURS as i64) as usize];
+        if slot.hour != hour {
+            *slot = Bucket {
+                hour,
+                ..Bucket::default()
+            };
+        }
+        slot.requests += requests;
+        slot.tokens += tokens;
+    }
+
+    fn totals(&self, now_hour: i64) -> WindowTotals {
+        self.buckets
+            .iter()
+            .filter(|b| now_hour - b.hour < WINDOW_HOURS as i64 && b.hour <= now_hour)
+            .fold(WindowTotals::default(), |acc, b| WindowTotals {
+                requests: acc.requests + b.requests,
+                tokens: acc.tokens + b.tokens,
+            })
+    }
+}
+
+impl UsageCounters {
+    /// Rebuilds the windows from the last 24h of hourly rollups, leaving out
+    /// usage from before an account's last quota reset.
+    pub async fn rebuild(db: &Db) -> Result<Self, AppError> {
+        let counters = Self::default();
+        let since = Utc::now() - chrono::Duration::hours(WINDOW_HOURS as i64);
+        let rows = db
+            .hourly_usage_since(&since.format("%Y-%m-%dT%H").to_string())
+            .await?;
+        // Rollups are hourly, so the whole hour of a reset is dropped; the
+        // account may get a little extra headroom after a restart, never less.
+        let resets: HashMap<String, i64> = db
+            .quota_resets()
+            .await?
+            .into_iter()
+            .filter_map(|(account, at)| {
+                let at = DateTime::parse_from_rfc3339(&at).ok()?;
+                Some((account, at.timestamp().div_euclid(3600)))
+            })
+            .collect();
+        {
+            let mut accounts = counters.accounts.lock().unwrap_or_else(|e| e.into_inner());
+            for row in rows {
+                let Some(hour) = parse_hour(&row.hour) else {
+                    continue;
+                };
+                if resets.get(&row.user_id).is_some_and(|reset| hour <= *reset) {
+                    continue;
+                }
+                accounts.entry(row.user_id).or_default().add(
+                    hour,
+                    row.requests.max(0) as u64,
+                    (row.tokens_input + row.tokens_output).max(0) as u64,
+                );
+            }
+        }
+        Ok(counters)
+    }
+
+    pub fn record(&self, account: &str, requests: u64, tokens: u64) {
+        let mut accounts = self.accounts.lock().unwrap_or_else(|e| e.into_inner());
+        accounts
+            .entry(account.to_string())
+            .or_default()
+            .add(current_hour(), requests, tokens);
+    }
+
+    /// Drops an account's in-memory window, for deleted accounts and quota
+    /// resets.
+    pub fn forget(&self, account: &str) {
+        let mut accounts = self.accounts.lock().unwrap_or_else(|e| e.into_inner());
+        accounts.remove(account);
+    }
+
+    pub fn totals(&self, account: &str) -> WindowTotals {
+        let accounts = self.accounts.lock().unwrap_or_else(|e| e.into_inner());
+        accounts
+            .get(account)
+            .map(|w| w.totals(current_hour()))
+            .unwrap_or_default()
+    }
+}
+
+fn current_hour() -> i64 {
+    Utc::now().timestamp().div_euclid(3600)
+}
+
+fn parse_hour(hour: &str) -> Option<i64> {
+    NaiveDateTime::parse_from_str(&format!("{hour}:00:00"), "%Y-%m-%dT%H:%M:%S")
+        .ok()
+        .map(|dt| dt.and_utc().timestamp().div_euclid(3600))
+}
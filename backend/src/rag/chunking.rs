@@ -0,0 +1,64 @@
+use serde::Deserialize;
+
+/// Chunk sizes are measured in characters; ~4 characters make a token. Overlap
+/// is capped at half the chunk size.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct ChunkOptions {
+    pub size: usize,
+    pub overlap: usize,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self {
+            size: 1200,
+            overlap: 200,
+        }
+    }
+}
+
+/// Splits text into overlapping chunks, preferring to break at paragraph, then
+/// sentence, then word boundaries in the back half of each window.
+pub fn chunk_text(text: &str, opts: ChunkOptions) -> Vec<String> {
+    let size = opts.size.max(1);
+    let overlap = opts.overlap.min(size / 2);
+    let chars: Vec<char> = text.chars().collect();
+    let mut chunks = Vec::new();
+    let mut start = 0;
+
+    while start < chars.len() {
+        let hard_end = (start + size).min(chars.len());
+        let end = if hard_end == chars.len() {
+            hard_end
+        } else {
+            break_point(&chars[start..hard_end])
+                .map(|offset| start + offset)
+                .unwrap_or(hard_end)
+        };
+        let chunk: String = chars[start..end].iter().collect();
+        let trimmed = chunk.trim();
+        if !trimmed.is_empty() {
+            chunks.push(trimmed.to_string());
+        }
+        if end == chars.len() {
+            break;
+        }
+        start = end.saturating_sub(overlap).max(start + 1);
+    }
+    chunks
+}
+
+fn break_point(window: &[char]) -> Option<usize> {
+    let min = window.len() / 2;
+    let find_last = |pred: &dyn Fn(usize) -> bool| (min..window.len()).rev().find(|&i| pred(i));
+
+    find_last(&|i| i > 0 && window[i] == '\n' && window[i - 1] == '\n')
+        .or_else(|| {
+            find_last(&|i| {
+                i > 0 && window[i].is_whitespace() && matches!(window[i - 1], '.' | '!' | '?')
+            })
+        })
+        .or_else(|| find_last(&|i| window[i].is_whitespace()))
+        .map(|i| i + 1)
+}
@@ -0,0 +1,183 @@
+use serde::{Deserialize, Serialize};
+
+use crate::AppError;
+
+/// What an uploaded document was before it became plain text.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SourceFormat {
+    #[default]
+    Text,
+    Html,
+    Pdf,
+}
+
+impl SourceFormat {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SourceFormat::Text => "text",
+            SourceFormat::Html => "html",
+            SourceFormat::Pdf => "pdf",
+        }
+    }
+
+    /// Maps an upload's `Content-Type`, ignoring parameters such as `charset`.
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        let mime = content_type
+            .split(';')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_ascii_lowercase();
+        match mime.as_str() {
+            "text/plain" | "text/markdown" => Some(SourceFormat::Text),
+            "text/html" | "application/xhtml+xml" => Some(SourceFormat::Html),
+            "application/pdf" => Some(SourceFormat::Pdf),
+            _ => None,
+        }
+    }
+}
+
+/// Turns an uploaded document into plain text. PDF parsing is CPU-bound and can
+/// panic on malformed files, so it runs on the blocking pool.
+pub async fn extract(format: SourceFormat, bytes: Vec<u8>) -> Result<String, AppError> {
+    let text = match format {
+        SourceFormat::Text => String::from_utf8(bytes)
+            .map_err(|_| AppError::BadRequest("text documents must be UTF-8".into()))?,
+        SourceFormat::Html => {
+            let html = String::from_utf8_lossy(&bytes);
+            html_to_text(&html)
+        }
+        SourceFormat::Pdf => tokio::task::spawn_blocking(move || {
+            pdf_extract::extract_text_from_mem(&bytes).map_err(|e| e.to_string())
+        })
+        .await
+        .map_err(|_| AppError::BadRequest("could not read PDF".into()))?
+        .map_err(|e| AppError::BadRequest(format!("could not read PDF: {e}")))?,
+    };
+    Ok(normalize_whitespace(&text))
+}
+
+/// Elements whose content is never document text.
+const SKIPPED_ELEMENTS: [&str; 5] = ["script", "style", "head", "noscript", "template"];
+/// Elements that end a paragraph, so chunking can break between them.
+const BLOCK_ELEMENTS: [&str; 18] = [
+    "p", "div", "br", "li", "ul", "ol", "tr", "table", "section", "article", "header", "footer",
+    "h1", "h2", "h3", "h4", "h5", "h6",
+];
+
+/// Strips tags and decodes common entities, keeping block structure as blank lines.
+fn html_to_text(html: &str) -> String {
+    let mut out = String::with_capacity(html.len() / 2);
+    let mut rest = html;
+    let mut skipping: Option<String> = None;
+
+    while let Some(open) = rest.find('<') {
+        if skipping.is_none() {
+            out.push_str(&decode_entities(&rest[..open]));
+        }
+        rest = &rest[open..];
+        if rest.starts_with("<!--") {
+            rest = rest.find("-->").map(|end| &rest[end + 3..]).unwrap_or("");
+            continue;
+        }
+        let Some(close) = rest.find('>') else {
+            rest = "";
+            break;
+        };
+        let tag = &rest[1..close];
+        rest = &rest[close + 1..];
+
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_ascii_lowercase();
+        match &skipping {
+            Some(skipped) => {
+                if closing && *skipped == name {
+                    skipping = None;
+                }
+            }
+            None => {
+                if !closing && SKIPPED_ELEMENTS.contains(&name.as_str()) && !tag.ends_with('/') {
+                    skipping = Some(name);
+                } else if BLOCK_ELEMENTS.contains(&name.as_str()) {
+                    out.push_str("\n\n");
+                } else {
+                    out.push(' ');
+                }
+            }
+        }
+    }
+    if skipping.is_none() {
+        out.push_str(&decode_entities(rest));
+    }
+    out
+}
+
+fn decode_entities(text: &str) -> String {
+    if !text.contains('&') {
+        return text.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        rest = &rest[amp..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" | "#39" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => entity
+                    .strip_prefix("#x")
+                    .or_else(|| entity.strip_prefix("#X"))
+                    .and_then(|hex| u32::from_str_radix(hex, 16).ok())
+                    .or_else(|| entity.strip_prefix('#').and_then(|d| d.parse().ok()))
+                    .and_then(char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Collapses runs of spaces inside lines and of blank lines between paragraphs,
+/// which extracted PDF and HTML text is full of.
+fn normalize_whitespace(text: &str) -> String {
+    let mut paragraphs = Vec::new();
+    let mut current: Vec<String> = Vec::new();
+    for line in text.lines() {
+        let line = line.split_whitespace().collect::<Vec<_>>().join(" ");
+        if line.is_empty() {
+            if !current.is_empty() {
+                paragraphs.push(current.join("\n"));
+                current.clear();
+            }
+        } else {
+            current.push(line);
+        }
+    }
+    if !current.is_empty() {
+        paragraphs.push(current.join("\n"));
+    }
+    paragraphs.join("\n\n")
+}
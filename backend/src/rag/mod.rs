@@ -0,0 +1,342 @@
+pub mod chunking;
+pub mod extract;
+pub mod vector;
+
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    AppError,
+    db::{ChunkInsert, CollectionRecord, Db, DocumentRecord},
+    llm::{LlmMessage, LlmService, Role},
+    model_router::{AccessControl, CatalogEntry, ModelKind},
+    routes::chat::provider_from_str,
+};
+use chunking::{ChunkOptions, chunk_text};
+use extract::SourceFormat;
+
+/// Inputs per embeddings call; keeps request bodies well under provider limits.
+const EMBED_BATCH: usize = 64;
+const MAX_TOP_K: usize = 20;
+const SNIPPET_CHARS: usize = 240;
+const MIN_CHUNK_SIZE: usize = 100;
+const MAX_CHUNK_SIZE: usize = 8000;
+
+/// Per-request retrieval settings, sent as `retrieval` on a chat request.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RetrievalOptions {
+    pub collections: Vec<String>,
+    #[serde(default)]
+    pub top_k: Option<usize>,
+    #[serde(default)]
+    pub min_score: Option<f32>,
+}
+
+/// A retrieved chunk the answer may cite as `[index]`.
+#[derive(Clone, Debug, Serialize)]
+pub struct Citation {
+    pub index: usize,
+    pub collection_id: String,
+    pub document_id: String,
+    pub document_title: String,
+    pub chunk_id: String,
+    pub score: f32,
+    pub snippet: String,
+}
+
+/// A document's extracted text plus how to store and chunk it.
+pub struct DocumentUpload {
+    pub title: String,
+    pub source: Option<String>,
+    pub format: SourceFormat,
+    pub text: String,
+    pub chunking: ChunkOptions,
+}
+
+/// A document recorded as `processing` whose chunks still need embedding.
+pub struct PendingDocument {
+    pub document: DocumentRecord,
+    entry: CatalogEntry,
+    chunks: Vec<String>,
+}
+
+pub(crate) struct Hit {
+    pub(crate) citation: Citation,
+    pub(crate) content: String,
+}
+
+#[derive(Clone)]
+pub struct Rag {
+    db: Db,
+    llm: LlmService,
+    access: AccessControl,
+    default_model: String,
+    default_top_k: usize,
+}
+
+impl Rag {
+    pub fn new(
+        db: Db,
+        llm: LlmService,
+        access: AccessControl,
+        default_model: String,
+        default_top_k: usize,
+    ) -> Self {
+        Self {
+            db,
+            llm,
+            access,
+            default_model,
+            default_top_k,
+        }
+    }
+
+    pub fn default_model(&self) -> &str {
+        &self.default_model
+    }
+
+    /// Looks up an embedding model in the catalog, by key or upstream id.
+    pub fn embedding_model(&self, model: &str) -> Result<CatalogEntry, AppError> {
+        let entry = self
+            .access
+            .model_entry(model)
+            .ok_or_else(|| AppError::BadRequest(format!("unknown embedding model: {model}")))?;
+        if entry.kind != ModelKind::Embedding {
+            return Err(AppError::BadRequest(format!(
+                "model {model} is not an embedding model"
+            )));
+        }
+        if let Some(reason) = self.access.disabled_reason(model) {
+            return Err(AppError::Unavailable(reason));
+        }
+        Ok(entry)
+    }
+
+    /// Embeds `inputs` in batches; returns the vectors and the tokens billed.
+    async fn embed(
+        &self,
+        entry: &CatalogEntry,
+        inputs: Vec<String>,
+    ) -> Result<(Vec<Vec<f32>>, u32), AppError> {
+        let provider = provider_from_str(&entry.provider)?;
+        let mut vectors = Vec::with_capacity(inputs.len());
+        let mut tokens = 0;
+        for batch in inputs.chunks(EMBED_BATCH) {
+            let result = self.llm.embed(provider, &entry.id, batch.to_vec()).await?;
+            if result.vectors.len() != batch.len() {
+                return Err(AppError::Upstream(format!(
+                    "embedding provider returned {} vectors for {} inputs",
+                    result.vectors.len(),
+                    batch.len()
+                )));
+            }
+            tokens += result.tokens_input.unwrap_or(0);
+            vectors.extend(result.vectors);
+        }
+        Ok((vectors, tokens))
+    }
+
+    async fn collection(&self, id: &str) -> Result<CollectionRecord, AppError> {
+        self.db
+            .collection(id)
+            .await?
+            .ok_or_else(|| AppError::BadRequest(format!("collection {id} not found")))
+    }
+
+    /// Validates and chunks a document and records it as `processing`. The
+    /// embeddings are computed by [`Rag::index`], inline or in the background.
+    pub async fn prepare(
+        &self,
+        collection_id: &str,
+        upload: DocumentUpload,
+    ) -> Result<PendingDocument, AppError> {
+        let collection = self.collection(collection_id).await?;
+        let entry = self.embedding_model(&collection.embedding_model)?;
+        let options = upload.chunking;
+        if !(MIN_CHUNK_SIZE..=MAX_CHUNK_SIZE).contains(&options.size) {
+            return Err(AppError::BadRequest(format!(
+                "chunk size must be between {MIN_CHUNK_SIZE} and {MAX_CHUNK_SIZE} characters"
+            )));
+        }
+        let chunks = chunk_text(&upload.text, options);
+        if chunks.is_empty() {
+            return Err(AppError::BadRequest("document has no text to index".into()));
+        }
+
+        let document = DocumentRecord {
+            id: Uuid::new_v4().to_string(),
+            collection_id: collection.id,
+            title: upload.title,
+            source: upload.source,
+            content_type: upload.format.as_str().to_string(),
+            chunk_count: chunks.len() as i64,
+            status: "processing".into(),
+            error: None,
+            created_at: Utc::now().to_rfc3339(),
+            updated_at: None,
+        };
+        self.db.insert_document(&document).await?;
+        Ok(PendingDocument {
+            document,
+            entry,
+            chunks,
+        })
+    }
+
+    /// Embeds a prepared document's chunks and marks it ready, or failed with
+    /// the error when embedding or storage fails.
+    pub async fn index(&self, pending: PendingDocument) -> Result<DocumentRecord, AppError> {
+        let PendingDocument {
+            mut document,
+            entry,
+            chunks,
+        } = pending;
+        let result = async {
+            let (vectors, tokens) = self.embed(&entry, chunks.clone()).await?;
+            let rows = chunks
+                .into_iter()
+                .zip(vectors)
+                .enumerate()
+                .map(|(ordinal, (content, vector))| ChunkInsert {
+                    ordinal: ordinal as i64,
+                    content,
+                    embedding: vector::encode(&vector),
+                })
+                .collect();
+            self.db
+                .complete_document(&document.id, &document.collection_id, rows)
+                .await?;
+            Ok::<_, AppError>(tokens)
+        }
+        .await;
+
+        let now = Some(Utc::now().to_rfc3339());
+        match result {
+            Ok(tokens) => {
+                info!(
+                    "indexed document {} into {} ({} chunks, {tokens} embedding tokens)",
+                    document.id, document.collection_id, document.chunk_count
+                );
+                document.status = "ready".into();
+                document.updated_at = now;
+                Ok(document)
+            }
+            Err(e) => {
+                warn!("indexing document {} failed: {e}", document.id);
+                if let Err(db_err) = self.db.fail_document(&document.id, &e.to_string()).await {
+                    warn!("failed to record ingest failure: {db_err}");
+                }
+                Err(e)
+            }
+        }
+    }
+
+    /// Brute-force cosine search over the requested collections. Each collection
+    /// is queried with its own embedding model, so scores stay comparable.
+    /// With `residency` set, a collection whose embedding model is hosted
+    /// elsewhere is refused rather than sent the query.
+    pub(crate) async fn search(
+        &self,
+        query: &str,
+        options: &RetrievalOptions,
+        residency: Option<&str>,
+    ) -> Result<Vec<Hit>, AppError> {
+        let top_k = options
+            .top_k
+            .unwrap_or(self.default_top_k)
+            .clamp(1, MAX_TOP_K);
+        let mut hits = Vec::new();
+        for collection_id in &options.collections {
+            let collection = self.collection(collection_id).await?;
+            let entry = self.embedding_model(&collection.embedding_model)?;
+            if let Some(region) = residency
+                && !entry.satisfies(residency)
+            {
+                return Err(AppError::BadRequest(format!(
+                    "collection {} embeds with {}, which is not hosted in {region} as this account's data residency requires",
+                    collection.id, collection.embedding_model
+                )));
+            }
+            let query_vector = self
+                .embed(&entry, vec![query.to_string()])
+                .await?
+                .0
+                .pop()
+                .unwrap_or_default();
+            for chunk in self.db.collection_chunks(&collection.id).await? {
+                let score = vector::cosine(&query_vector, &vector::decode(&chunk.embedding));
+                if options.min_score.is_some_and(|min| score < min) {
+                    continue;
+                }
+                hits.push(Hit {
+                    citation: Citation {
+                        index: 0,
+                        collection_id: chunk.collection_id,
+                        document_id: chunk.document_id,
+                        document_title: chunk.document_title,
+                        chunk_id: chunk.id,
+                        score,
+                        snippet: chunk.content.chars().take(SNIPPET_CHARS).collect(),
+                    },
+                    content: chunk.content,
+                });
+            }
+        }
+        hits.sort_by(|a, b| b.citation.score.total_cmp(&a.citation.score));
+        hits.truncate(top_k);
+        for (i, hit) in hits.iter_mut().enumerate() {
+            hit.citation.index = i + 1;
+        }
+        Ok(hits)
+    }
+
+    /// Retrieves chunks for the latest user message and inserts them as a system
+    /// message ahead of the conversation. Returns the citations the answer can use.
+    pub async fn augment(
+        &self,
+        messages: &mut Vec<LlmMessage>,
+        options: &RetrievalOptions,
+        residency: Option<&str>,
+    ) -> Result<Vec<Citation>, AppError> {
+        if options.collections.is_empty() {
+            return Ok(Vec::new());
+        }
+        let Some(query) = messages
+            .iter()
+            .rev()
+            .find(|m| m.role == Role::User)
+            .map(|m| m.content.clone())
+        else {
+            return Ok(Vec::new());
+        };
+        let hits = self.search(&query, options, residency).await?;
+        if hits.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let mut content = String::from(
+            "Answer using the numbered sources below when they are relevant, and cite \
+             them inline as [n]. If they don't contain the answer, say so.\n",
+        );
+        for hit in &hits {
+            content.push_str(&format!(
+                "\n[{}] {}\n{}\n",
+                hit.citation.index, hit.citation.document_title, hit.content
+            ));
+        }
+        let at = messages
+            .iter()
+            .position(|m| m.role != Role::System)
+            .unwrap_or(messages.len());
+        messages.insert(
+            at,
+            LlmMessage {
+                role: Role::System,
+                content,
+            },
+        );
+        Ok(hits.into_iter().map(|h| h.citation).collect())
+    }
+}
@@ -1,20 +1,41 @@
 use axum::{
     Json,
-    extract::State,
-    response::sse::{Event, Sse},
+    extract::{Path, State, rejection::JsonRejection},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, Sse},
+    },
 };
 use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
+use std::{collections::HashMap, sync::Arc};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 use tracing::{info, warn};
+use uuid::Uuid;
 
 use crate::{
     AppError, AppState,
+    abuse::{AbuseSettings, AccountRisk, THROTTLED_PER_MINUTE},
+    agent::{self, Budget, Tool},
+    audit_log::AuditEvent,
     auth::validate_token,
-    db::{MessageInsert, UsageStats},
-    governance::{PolicyHitInsert, evaluate_policies},
-    llm::{LlmRequest, LlmResponse, LlmService, Provider},
-    model_router::{AccessControl, RoutedModel},
+    canary::{self, Sighting},
+    config::Config,
+    context::{self, ContextReport},
+    db::{CanaryToken, ExchangeInsert, MessageInsert, MessageRecord, Supersession},
+    dedup::InflightDedup,
+    governance::{Policy, PolicyHitDraft, PolicyHitInsert, evaluate_policies},
+    llm::{
+        LlmMessage, LlmRequest, LlmResponse, LlmService, Provider, Role, ToolRequest, ToolResponse,
+        ToolTurn,
+    },
+    model_router::{AccessControl, CatalogEntry, RoutedModel},
+    notifications::{self, Notice},
     pii::redact,
+    quota::WindowTotals,
+    rag::{Citation, RetrievalOptions},
+    routes::conversations::owned_conversation,
+    translation::{self, TranslationReport, TranslationUsage},
 };
 
 #[derive(Clone, Debug, serde::Serialize)]
@@ -28,260 +49,1504 @@ pub struct RoutingTrace {
 #[derive(serde::Serialize)]
 pub struct ChatResponse {
     pub conversation_id: uuid::Uuid,
+    pub message_id: Option<Uuid>,
     pub message: LlmResponse,
     pub routing: RoutingTrace,
+    pub context: ContextReport,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub citations: Vec<Citation>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translation: Option<TranslationReport>,
+}
+
+type ChatEventStream = Sse<UnboundedReceiverStream<Result<Event, AppError>>>;
+
+/// Whether an exchange adds a new user turn or re-answers the last one.
+enum ExchangeKind {
+    NewTurn,
+    Regenerate {
+        supersedes: Option<String>,
+        /// Language of the turn being re-answered, if it was translated.
+        language: Option<String>,
+    },
+}
+
+/// An inline-translated turn: the prompt's language and the model translating.
+struct PromptTranslation {
+    entry: CatalogEntry,
+    language: String,
+    /// The English prompt sent to the model. Regenerations reuse the stored
+    /// English turn and have none.
+    english: Option<String>,
+    /// Spent translating the prompt; already recorded against the account.
+    usage: TranslationUsage,
+}
+
+/// A request that has passed auth, limits and policy checks and is ready to route.
+struct PreparedChat {
+    body: LlmRequest,
+    user_id: Option<String>,
+    plan: Vec<RoutedModel>,
+    conversation_id: Uuid,
+    user_message: String,
+    policy_hits: Vec<PolicyHitDraft>,
+    context: ContextReport,
+    citations: Vec<Citation>,
+    pii_redacted: bool,
+    kind: ExchangeKind,
+    /// The account's data residency region; summaries and retrieval stay in it too.
+    residency: Option<String>,
+    /// Every canary token, watched for in the reply.
+    canaries: Arc<Vec<CanaryToken>>,
+    translation: Option<PromptTranslation>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RegenerateBody {
+    pub model: Option<String>,
+    pub temperature: Option<f32>,
+    pub max_tokens: Option<u32>,
+    #[serde(default)]
+    pub stream: bool,
+    #[serde(default)]
+    pub retrieval: Option<RetrievalOptions>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct AgentBody {
+    #[serde(flatten)]
+    pub chat: LlmRequest,
+    /// Built-in tools to offer, by name; every one that applies when omitted.
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+    #[serde(default)]
+    pub max_steps: Option<usize>,
+    /// USD of estimated provider cost.
+    #[serde(default)]
+    pub max_cost: Option<f64>,
+}
+
+/// Why an agent run ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+enum AgentStop {
+    Answered,
+    MaxSteps,
+    MaxCost,
+    /// The client went away; the run stops rather than spend more.
+    Disconnected,
+}
+
+/// Where an agent run got to.
+struct AgentRun {
+    /// The model's answer, when it gave one within the budget.
+    answer: Option<LlmResponse>,
+    stop: AgentStop,
+    steps: usize,
+    trace: RoutingTrace,
+    tokens_input: u64,
+    tokens_output: u64,
+    cost: f64,
 }
 
 pub async fn chat(
     State(state): State<AppState>,
     jar: CookieJar,
-    Json(mut body): Json<LlmRequest>,
+    body: Result<Json<LlmRequest>, JsonRejection>,
 ) -> Result<Json<ChatResponse>, AppError> {
-    if body.messages.is_empty() {
-        return Err(AppError::BadRequest("messages cannot be empty".into()));
-    }
-    let claims = validate_token(&state.config, &jar); // stub optional
-    let user_id = claims.as_ref().map(|c| c.sub.clone());
-    let plan = state
-        .access
-        .routing_plan(user_id.as_deref(), &body.model)
-        .await?;
-    let account = state.access.account(user_id.as_deref()).await;
-    if let Some(prompt) = state.access.guardrail_for(user_id.as_deref()).await {
-        body.messages.insert(
-            0,
-            crate::llm::LlmMessage {
-                role: crate::llm::Role::System,
-                content: prompt,
-            },
-        );
-    }
-    enforce_limits(&state.db, account.as_ref(), &plan[0]).await?;
-    let policies = state.db.list_policies().await?;
-    let conversation_id = body.conversation_id.unwrap_or_else(uuid::Uuid::new_v4);
-    state
-        .db
-        .ensure_conversation(conversation_id, Some("Untitled"), user_id.as_deref())
-        .await?;
+    let Json(body) = body?;
+    let prepared = prepare_chat(&state, &jar, body, ExchangeKind::NewTurn).await?;
+    respond_json(state, prepared).await
+}
 
-    let mut policy_hits = Vec::new();
-    if let Some(last) = body.messages.last_mut() {
-        let eval = evaluate_policies(&policies, "user", &last.content);
-        if let Some(blocked) = eval.blocked {
-            return Err(AppError::BadRequest(format!(
-                "Blocked by policy: {}",
-                blocked.policy_name
-            )));
-        }
-        if let Some(red) = eval.redacted {
-            last.content = red;
-        }
-        policy_hits = eval.hits;
+pub async fn chat_stream(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    body: Result<Json<LlmRequest>, JsonRejection>,
+) -> Result<ChatEventStream, AppError> {
+    let Json(body) = body?;
+    let prepared = prepare_chat(&state, &jar, body, ExchangeKind::NewTurn).await?;
+    Ok(respond_stream(state, prepared))
+}
 
-        let (redacted, changed) = redact(&last.content);
-        last.content = redacted;
-        if changed {
-            info!("PII redaction applied");
-        }
-    }
+/// Re-runs the last user turn of a stored conversation, optionally on another
+/// model or temperature. The previous answer stays in the table but is marked
+/// superseded, so it no longer appears in history sent to providers.
+pub async fn regenerate(
+    State(state): State<AppState>,
+    Path(conversation_id): Path<Uuid>,
+    jar: CookieJar,
+    body: Option<Json<RegenerateBody>>,
+) -> Result<Response, AppError> {
+    let Json(opts) = body.unwrap_or_default();
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    owned_conversation(&state, conversation_id, user_id.as_deref()).await?;
 
-    let user_message_id = state
+    let records: Vec<MessageRecord> = state
         .db
-        .insert_message(MessageInsert {
-            id: None,
-            conversation_id,
-            role: "user".into(),
-            content: body
-                .messages
-                .last()
-                .map(|m| m.content.clone())
-                .unwrap_or_default(),
-            provider: None,
-            model: Some(body.model.clone()),
-            tokens_input: None,
-            tokens_output: None,
-            user_id: user_id.clone(),
-        })
-        .await?;
+        .conversation_messages(conversation_id)
+        .await?
+        .into_iter()
+        .filter(|m| m.superseded_by.is_none())
+        .collect();
+    let last_user = records
+        .iter()
+        .rposition(|m| m.role == "user")
+        .ok_or_else(|| {
+            AppError::BadRequest("conversation has no user turn to regenerate".into())
+        })?;
+    let previous = records[last_user + 1..]
+        .iter()
+        .rfind(|m| m.role == "assistant");
+    let model = opts
+        .model
+        .or_else(|| records[last_user].model.clone())
+        .ok_or_else(|| AppError::BadRequest("no model to regenerate with".into()))?;
+    // Routing overwrites the provider per candidate; this is only a placeholder.
+    let provider = previous
+        .and_then(|m| m.provider.as_deref())
+        .and_then(|p| provider_from_str(p).ok())
+        .unwrap_or(Provider::Openai);
+    let supersedes = previous.map(|m| m.id.clone());
+    let language = records[last_user].language.clone();
 
-    if !policy_hits.is_empty() {
-        let inserts: Vec<PolicyHitInsert> = policy_hits
-            .into_iter()
-            .map(|h| PolicyHitInsert {
-                message_id: user_message_id.to_string(),
-                policy_id: h.policy_id,
-                policy_name: h.policy_name,
-                action: h.action,
-            })
-            .collect();
-        let _ = state.db.record_policy_hits(inserts).await;
+    let req = LlmRequest {
+        conversation_id: Some(conversation_id),
+        provider,
+        model,
+        messages: history_from_records(records.into_iter().take(last_user + 1)),
+        max_tokens: opts.max_tokens,
+        temperature: opts.temperature,
+        retrieval: opts.retrieval,
+    };
+    let kind = ExchangeKind::Regenerate {
+        supersedes,
+        language,
+    };
+    let prepared = prepare_chat(&state, &jar, req, kind).await?;
+    if opts.stream {
+        Ok(respond_stream(state, prepared).into_response())
+    } else {
+        Ok(respond_json(state, prepared).await?.into_response())
     }
+}
+
+/// A chat turn the model may answer by calling built-in tools in a loop;
+/// always streamed (see `respond_agent`).
+pub async fn agent(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    body: Result<Json<AgentBody>, JsonRejection>,
+) -> Result<ChatEventStream, AppError> {
+    let Json(body) = body?;
+    let budget = Budget::new(&state.config.load(), body.max_steps, body.max_cost)?;
+    let tools = agent::select_tools(body.tools.as_deref(), body.chat.retrieval.as_ref())?;
+    let prepared = prepare_chat(&state, &jar, body.chat, ExchangeKind::NewTurn).await?;
+    Ok(respond_agent(state, prepared, tools, budget))
+}
 
-    let routed = route_with_fallbacks(&state.llm, &state.access, &body, &plan).await?;
+async fn respond_json(
+    state: AppState,
+    prepared: PreparedChat,
+) -> Result<Json<ChatResponse>, AppError> {
+    let mut routed = match route_deduped(
+        &state,
+        prepared.user_id.as_deref(),
+        &prepared.body,
+        &prepared.plan,
+    )
+    .await
+    {
+        Ok(routed) => routed,
+        Err(e) => {
+            if let Err(db_err) = persist_exchange(&state, &prepared, None, None).await {
+                warn!("failed to persist failed exchange: {db_err}");
+            }
+            return Err(e);
+        }
+    };
 
-    let _ = state
-        .db
-        .insert_message(MessageInsert {
-            id: None,
-            conversation_id,
-            role: "assistant".into(),
-            content: routed.response.content.clone(),
-            provider: Some(routed.response.provider.to_string()),
-            model: Some(routed.response.model.clone()),
-            tokens_input: routed.response.tokens_input,
-            tokens_output: routed.response.tokens_output,
-            user_id: user_id.clone(),
-        })
-        .await?;
+    let (english, reply_usage) = translate_reply(&state, &prepared, &mut routed.response).await;
+    let message_id = persist_exchange(&state, &prepared, Some(&routed), english.as_deref()).await?;
+    schedule_summary_refresh(
+        &state,
+        &prepared,
+        english.as_deref().unwrap_or(&routed.response.content),
+    );
+    let translation = translation_report(&prepared, english.is_some(), reply_usage);
 
     Ok(Json(ChatResponse {
-        conversation_id,
+        conversation_id: prepared.conversation_id,
+        message_id,
         message: routed.response,
         routing: routed.trace,
+        context: prepared.context,
+        citations: prepared.citations,
+        translation,
     }))
 }
 
-pub async fn chat_stream(
-    State(state): State<AppState>,
-    jar: CookieJar,
-    Json(mut body): Json<LlmRequest>,
-) -> Result<Sse<UnboundedReceiverStream<Result<Event, AppError>>>, AppError> {
+fn respond_stream(state: AppState, prepared: PreparedChat) -> ChatEventStream {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let lifecycle = state.lifecycle.clone();
+    lifecycle.spawn(async move {
+        // Send initial comment to establish stream
+        if tx.send(Ok(Event::default().comment("start"))).is_err() {
+            return;
+        }
+        let llm_res = route_deduped(
+            &state,
+            prepared.user_id.as_deref(),
+            &prepared.body,
+            &prepared.plan,
+        )
+        .await;
+        match llm_res {
+            Ok(mut res) => {
+                let (english, reply_usage) =
+                    translate_reply(&state, &prepared, &mut res.response).await;
+                // A client that went away still gets its exchange stored.
+                let chars: Vec<char> = res.response.content.chars().collect();
+                for chunk in chars.chunks(64) {
+                    let text: String = chunk.iter().collect();
+                    if tx.send(Ok(Event::default().data(text))).is_err() {
+                        break;
+                    }
+                }
+                let message_id =
+                    match persist_exchange(&state, &prepared, Some(&res), english.as_deref()).await
+                    {
+                        Ok(id) => id,
+                        Err(e) => {
+                            warn!("failed to persist streamed exchange: {e}");
+                            None
+                        }
+                    };
+                let meta = serde_json::json!({
+                    "conversation_id": prepared.conversation_id,
+                    "message_id": message_id,
+                    "tokens_input": res.response.tokens_input,
+                    "tokens_output": res.response.tokens_output,
+                    "cost": res.response.cost,
+                    "provider": res.response.provider,
+                    "model": res.response.model,
+                    "routing": res.trace,
+                    "context": prepared.context,
+                    "citations": prepared.citations,
+                    "translation": translation_report(&prepared, english.is_some(), reply_usage),
+                });
+                schedule_summary_refresh(
+                    &state,
+                    &prepared,
+                    english.as_deref().unwrap_or(&res.response.content),
+                );
+                let _ = tx.send(Ok(Event::default().event("done").data(meta.to_string())));
+            }
+            Err(e) => {
+                if let Err(db_err) = persist_exchange(&state, &prepared, None, None).await {
+                    warn!("failed to persist failed exchange: {db_err}");
+                }
+                let err_msg = e.to_string();
+                let _ = tx.send(Ok(Event::default().data(format!("Error: {}", err_msg))));
+            }
+        }
+    });
+
+    Sse::new(UnboundedReceiverStream::new(rx)).keep_alive(axum::response::sse::KeepAlive::new())
+}
+
+/// Streams an agent run: a `step` event per model call, a `tool_result` per
+/// tool call and a `policy` event per policy hit on the run's own traffic,
+/// then the answer as data chunks and a `done` event, or an `error` event.
+/// The user's prompt was screened by `prepare_chat`; each tool output is
+/// screened the same way before the model sees it.
+fn respond_agent(
+    state: AppState,
+    mut prepared: PreparedChat,
+    tools: Vec<Tool>,
+    budget: Budget,
+) -> ChatEventStream {
+    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+    let lifecycle = state.lifecycle.clone();
+    lifecycle.spawn(async move {
+        if tx.send(Ok(Event::default().comment("start"))).is_err() {
+            return;
+        }
+        let emit = |event: &str, data: serde_json::Value| {
+            tx.send(Ok(Event::default().event(event).data(data.to_string())))
+                .is_ok()
+        };
+        let run = match run_agent(&state, &mut prepared, &tools, budget, &emit).await {
+            Ok(run) => run,
+            Err(e) => {
+                if let Err(db_err) = persist_exchange(&state, &prepared, None, None).await {
+                    warn!("failed to persist failed agent run: {db_err}");
+                }
+                emit("error", serde_json::json!({ "message": e.to_string() }));
+                return;
+            }
+        };
+        let mut routed = run.answer.map(|response| RoutedResult {
+            response,
+            trace: run.trace.clone(),
+            coalesced: false,
+        });
+        let (mut english, mut reply_usage) = (None, TranslationUsage::default());
+        if let Some(routed) = routed.as_mut() {
+            (english, reply_usage) = translate_reply(&state, &prepared, &mut routed.response).await;
+            let chars: Vec<char> = routed.response.content.chars().collect();
+            for chunk in chars.chunks(64) {
+                let text: String = chunk.iter().collect();
+                // The run is paid for, so it's stored even if the client left.
+                let _ = tx.send(Ok(Event::default().data(text)));
+            }
+        }
+        let message_id =
+            match persist_exchange(&state, &prepared, routed.as_ref(), english.as_deref()).await {
+                Ok(id) => id,
+                Err(e) => {
+                    warn!("failed to persist agent run: {e}");
+                    None
+                }
+            };
+        if let Some(routed) = &routed {
+            record_usage(&state, prepared.user_id.as_deref(), &routed.response).await;
+            schedule_summary_refresh(
+                &state,
+                &prepared,
+                english.as_deref().unwrap_or(&routed.response.content),
+            );
+        }
+        emit(
+            "done",
+            serde_json::json!({
+                "conversation_id": prepared.conversation_id,
+                "message_id": message_id,
+                "stop_reason": run.stop,
+                "steps": run.steps,
+                "budget": budget,
+                "tokens_input": run.tokens_input,
+                "tokens_output": run.tokens_output,
+                "cost": run.cost,
+                "routing": run.trace,
+                "context": prepared.context,
+                "citations": prepared.citations,
+                "translation": translation_report(&prepared, english.is_some(), reply_usage),
+            }),
+        );
+    });
+
+    Sse::new(UnboundedReceiverStream::new(rx)).keep_alive(axum::response::sse::KeepAlive::new())
+}
+
+/// Calls the model with the tools until it answers or the budget runs out.
+/// Steps that end in tool calls count toward the account's usage straight
+/// away, and the account's daily limits are checked again before each further
+/// step; the answering step is recorded with the exchange. Policy hits along
+/// the way join the user turn's.
+async fn run_agent(
+    state: &AppState,
+    prepared: &mut PreparedChat,
+    tools: &[Tool],
+    budget: Budget,
+    emit: &impl Fn(&str, serde_json::Value) -> bool,
+) -> Result<AgentRun, AppError> {
+    let user_id = prepared.user_id.clone();
+    let uid = user_id.as_deref();
+    let conversation_id = prepared.conversation_id;
+    let api_keys = state.provider_keys.for_account(uid).await?;
+    let policies = state.db.list_policies().await?;
+    let base = ToolRequest {
+        provider: prepared.body.provider,
+        model: prepared.body.model.clone(),
+        messages: prepared
+            .body
+            .messages
+            .iter()
+            .cloned()
+            .map(ToolTurn::Message)
+            .collect(),
+        tools: tools.iter().map(|t| t.spec()).collect(),
+        max_tokens: prepared.body.max_tokens,
+        temperature: prepared.body.temperature,
+    };
+    let mut transcript = base.messages.clone();
+    let mut pinned = None;
+    let mut run = AgentRun {
+        answer: None,
+        stop: AgentStop::MaxSteps,
+        steps: 0,
+        trace: RoutingTrace {
+            selected_model: String::new(),
+            provider: String::new(),
+            attempts: Vec::new(),
+            used_fallback: false,
+        },
+        tokens_input: 0,
+        tokens_output: 0,
+        cost: 0.0,
+    };
+
+    while run.steps < budget.max_steps {
+        if run.cost >= budget.max_cost {
+            run.stop = AgentStop::MaxCost;
+            return Ok(run);
+        }
+        if run.steps > 0 {
+            let account = state.access.account(uid).await;
+            enforce_limits(state, account.as_ref(), &prepared.plan[0]).await?;
+        }
+        run.steps += 1;
+        let step = run.steps;
+        let req = ToolRequest {
+            messages: transcript.clone(),
+            ..base.clone()
+        };
+        let mut response = route_agent_step(
+            state,
+            &prepared.plan,
+            &mut pinned,
+            &mut run.trace,
+            req,
+            &api_keys,
+        )
+        .await?;
+        let (tokens_input, tokens_output) = (
+            response.tokens_input.unwrap_or(0),
+            response.tokens_output.unwrap_or(0),
+        );
+        run.tokens_input += tokens_input as u64;
+        run.tokens_output += tokens_output as u64;
+        run.cost += response.cost.unwrap_or(0.0);
+
+        let screened = screen_model_output(state, &policies, prepared, &mut response).await;
+        if screened.is_err() || !response.calls.is_empty() {
+            record_extra_usage(state, uid, tokens_input, tokens_output, "agent step").await;
+        }
+        for hit in screened? {
+            note_agent_hit(prepared, emit, step, "model", hit);
+        }
+        let connected = emit(
+            "step",
+            serde_json::json!({
+                "step": step,
+                "provider": response.provider,
+                "model": response.model,
+                "content": response.content,
+                "tool_calls": response.calls,
+                "tokens_input": response.tokens_input,
+                "tokens_output": response.tokens_output,
+                "cost": response.cost,
+            }),
+        );
+        if response.calls.is_empty() {
+            run.answer = Some(LlmResponse {
+                provider: response.provider,
+                model: response.model,
+                content: response.content,
+                tokens_input: response.tokens_input,
+                tokens_output: response.tokens_output,
+                cost: response.cost,
+            });
+            run.stop = AgentStop::Answered;
+            return Ok(run);
+        }
+        if !connected {
+            run.stop = AgentStop::Disconnected;
+            return Ok(run);
+        }
+
+        transcript.push(ToolTurn::Calls {
+            content: response.content,
+            calls: response.calls.clone(),
+        });
+        for call in response.calls {
+            let output = match Tool::from_name(&call.name).filter(|t| tools.contains(t)) {
+                Some(tool) => {
+                    agent::run_tool(
+                        state,
+                        tool,
+                        &call.arguments,
+                        prepared.body.retrieval.as_ref(),
+                        prepared.residency.as_deref(),
+                    )
+                    .await
+                }
+                None => Err(format!("there is no tool named {}", call.name)),
+            };
+            let is_error = output.is_err();
+            let mut content = output.unwrap_or_else(|e| format!("error: {e}"));
+            canary::watch(
+                state,
+                &prepared.canaries,
+                &content,
+                Sighting::Input,
+                uid,
+                conversation_id,
+            )
+            .await;
+            let (hits, pii_redacted) =
+                screen_prompt(state, &policies, uid, conversation_id, &mut content).await?;
+            for hit in hits {
+                note_agent_hit(prepared, emit, step, "tool_result", hit);
+            }
+            let connected = emit(
+                "tool_result",
+                serde_json::json!({
+                    "step": step,
+                    "call_id": call.id,
+                    "name": call.name,
+                    "content": content,
+                    "is_error": is_error,
+                    "pii_redacted": pii_redacted,
+                }),
+            );
+            if !connected {
+                run.stop = AgentStop::Disconnected;
+                return Ok(run);
+            }
+            transcript.push(ToolTurn::Result {
+                call_id: call.id,
+                content,
+            });
+        }
+    }
+    Ok(run)
+}
+
+/// Checks what the model wrote against `assistant` policies: its text and
+/// every string in its tool arguments, redacted in place. A `block` hit ends
+/// the run. Canaries are watched for too, since a leaked guardrail can be
+/// passed to a tool as well as shown to the user.
+async fn screen_model_output(
+    state: &AppState,
+    policies: &[Policy],
+    prepared: &PreparedChat,
+    response: &mut ToolResponse,
+) -> Result<Vec<PolicyHitDraft>, AppError> {
+    fn strings<'a>(value: &'a mut serde_json::Value, out: &mut Vec<&'a mut String>) {
+        match value {
+            serde_json::Value::String(s) => out.push(s),
+            serde_json::Value::Array(items) => items.iter_mut().for_each(|v| strings(v, out)),
+            serde_json::Value::Object(map) => map.values_mut().for_each(|v| strings(v, out)),
+            _ => {}
+        }
+    }
+    let user_id = prepared.user_id.as_deref();
+    let mut texts = vec![&mut response.content];
+    for call in &mut response.calls {
+        strings(&mut call.arguments, &mut texts);
+    }
+    let mut hits: Vec<PolicyHitDraft> = Vec::new();
+    for text in texts {
+        canary::watch(
+            state,
+            &prepared.canaries,
+            text,
+            Sighting::Output,
+            user_id,
+            prepared.conversation_id,
+        )
+        .await;
+        let eval = evaluate_policies(policies, "assistant", text);
+        if let Some(blocked) = eval.blocked {
+            return Err(policy_block(state, user_id, prepared.conversation_id, &blocked).await);
+        }
+        if let Some(redacted) = eval.redacted {
+            *text = redacted;
+        }
+        for hit in eval.hits {
+            if !hits.iter().any(|h| h.policy_id == hit.policy_id) {
+                hits.push(hit);
+            }
+        }
+    }
+    Ok(hits)
+}
+
+/// Reports a policy hit from an agent run and keeps it for the user turn.
+fn note_agent_hit(
+    prepared: &mut PreparedChat,
+    emit: &impl Fn(&str, serde_json::Value) -> bool,
+    step: usize,
+    source: &str,
+    hit: PolicyHitDraft,
+) {
+    emit(
+        "policy",
+        serde_json::json!({
+            "step": step,
+            "source": source,
+            "policy_id": hit.policy_id,
+            "policy_name": hit.policy_name,
+            "action": hit.action,
+        }),
+    );
+    if !prepared
+        .policy_hits
+        .iter()
+        .any(|h| h.policy_id == hit.policy_id)
+    {
+        prepared.policy_hits.push(hit);
+    }
+}
+
+async fn prepare_chat(
+    state: &AppState,
+    jar: &CookieJar,
+    mut body: LlmRequest,
+    kind: ExchangeKind,
+) -> Result<PreparedChat, AppError> {
+    if state.lifecycle.is_draining() {
+        return Err(AppError::Unavailable("server is shutting down".into()));
+    }
+    if let Some(message) = state.access.maintenance() {
+        return Err(AppError::Unavailable(message));
+    }
     if body.messages.is_empty() {
         return Err(AppError::BadRequest("messages cannot be empty".into()));
     }
-    let claims = validate_token(&state.config, &jar); // stub optional
+    let claims = validate_token(&state.config.load(), jar); // stub optional
     let user_id = claims.as_ref().map(|c| c.sub.clone());
+    // A conversation that is already stored must be the caller's before its
+    // history, summary or messages are touched. An unknown id starts a new
+    // conversation under that id.
+    if let Some(conversation_id) = body.conversation_id
+        && state.db.conversation(conversation_id).await?.is_some()
+    {
+        owned_conversation(state, conversation_id, user_id.as_deref()).await?;
+    }
+    if let ExchangeKind::NewTurn = kind {
+        // Checked before anything scans the text, abuse screening included;
+        // stored history passed this when it was sent.
+        validate_messages(&state.config.load(), &body.messages)?;
+        inject_history(state, &mut body).await?;
+    }
+    let prompt = body.messages.last().map(|m| m.content.as_str());
+    screen_for_abuse(state, user_id.as_deref(), prompt.unwrap_or_default()).await?;
     let plan = state
         .access
         .routing_plan(user_id.as_deref(), &body.model)
         .await?;
     let account = state.access.account(user_id.as_deref()).await;
+    let residency = account.as_ref().and_then(|a| a.data_residency.clone());
+    let canaries = state.canaries.tokens().await?;
     if let Some(prompt) = state.access.guardrail_for(user_id.as_deref()).await {
+        let prompt = match user_id.as_deref() {
+            Some(uid) => canary::inject(prompt, &canaries, uid),
+            None => prompt,
+        };
         body.messages.insert(
             0,
-            crate::llm::LlmMessage {
-                role: crate::llm::Role::System,
+            LlmMessage {
+                role: Role::System,
                 content: prompt,
             },
         );
     }
-    enforce_limits(&state.db, account.as_ref(), &plan[0]).await?;
+    enforce_rate_limit(state, user_id.as_deref()).await?;
+    enforce_limits(state, account.as_ref(), &plan[0]).await?;
     let policies = state.db.list_policies().await?;
-    let conversation_id = body.conversation_id.unwrap_or_else(uuid::Uuid::new_v4);
-    state
-        .db
-        .ensure_conversation(conversation_id, Some("Untitled"), user_id.as_deref())
-        .await?;
+    let conversation_id = body.conversation_id.unwrap_or_else(Uuid::new_v4);
+    if let Some(last) = body.messages.last() {
+        canary::watch(
+            state,
+            &canaries,
+            &last.content,
+            Sighting::Input,
+            user_id.as_deref(),
+            conversation_id,
+        )
+        .await;
+    }
 
-    let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
-    let llm = state.llm.clone();
-    let db = state.db.clone();
-    let plan_clone = plan.clone();
     let mut policy_hits = Vec::new();
+    let mut pii_redacted = false;
     if let Some(last) = body.messages.last_mut() {
-        let eval = evaluate_policies(&policies, "user", &last.content);
-        if let Some(blocked) = eval.blocked {
-            return Err(AppError::BadRequest(format!(
-                "Blocked by policy: {}",
-                blocked.policy_name
-            )));
-        }
-        if let Some(red) = eval.redacted {
-            last.content = red;
-        }
-        policy_hits = eval.hits;
-
-        let (redacted, changed) = redact(&last.content);
-        last.content = redacted;
-        if changed {
-            info!("PII redaction applied");
-        }
+        (policy_hits, pii_redacted) = screen_prompt(
+            state,
+            &policies,
+            user_id.as_deref(),
+            conversation_id,
+            &mut last.content,
+        )
+        .await?;
     }
     let user_message = body
         .messages
         .last()
         .map(|m| m.content.clone())
         .unwrap_or_default();
-    tokio::spawn(async move {
-        // Send initial comment to establish stream
-        if tx.send(Ok(Event::default().comment("start"))).is_err() {
-            return;
-        }
-        let llm_res = route_with_fallbacks(&llm, &state.access, &body, &plan_clone).await;
-        match llm_res {
-            Ok(res) => {
-                let content = res.response.content.clone();
-                for chunk in content.as_bytes().chunks(64) {
-                    let text = String::from_utf8_lossy(chunk).to_string();
-                    if tx.send(Ok(Event::default().data(text))).is_err() {
-                        return;
-                    }
-                }
-                let user_message_id = db
-                    .insert_message(MessageInsert {
-                        id: None,
+    let translation = match account.as_ref().filter(|a| a.inline_translation) {
+        Some(account) => match translation::translation_model(state, residency.as_deref()) {
+            Some(entry) => match &kind {
+                ExchangeKind::NewTurn => {
+                    let translated = translate_prompt(
+                        state,
+                        entry,
+                        &policies,
+                        user_id.as_deref(),
                         conversation_id,
-                        role: "user".into(),
-                        content: user_message.clone(),
-                        provider: None,
-                        model: Some(body.model.clone()),
-                        tokens_input: None,
-                        tokens_output: None,
-                        user_id: user_id.clone(),
+                        &mut body,
+                    )
+                    .await?;
+                    translated.map(|(translation, hits, redacted)| {
+                        for hit in hits {
+                            if !policy_hits.iter().any(|h| h.policy_id == hit.policy_id) {
+                                policy_hits.push(hit);
+                            }
+                        }
+                        pii_redacted |= redacted;
+                        translation
                     })
-                    .await;
-                if let Ok(uid) = user_message_id {
-                    if !policy_hits.is_empty() {
-                        let inserts: Vec<PolicyHitInsert> = policy_hits
-                            .iter()
-                            .map(|h| PolicyHitInsert {
-                                message_id: uid.to_string(),
-                                policy_id: h.policy_id.clone(),
-                                policy_name: h.policy_name.clone(),
-                                action: h.action.clone(),
-                            })
-                            .collect();
-                        let _ = db.record_policy_hits(inserts).await;
-                    }
                 }
-                let meta = serde_json::json!({
-                    "tokens_input": res.response.tokens_input,
-                    "tokens_output": res.response.tokens_output,
-                    "cost": res.response.cost,
-                    "provider": res.response.provider,
-                    "model": res.response.model,
-                    "routing": res.trace
-                });
-                let _ = db
-                    .insert_message(MessageInsert {
-                        id: None,
-                        conversation_id,
-                        role: "assistant".into(),
-                        content: res.response.content.clone(),
-                        provider: Some(res.response.provider.to_string()),
-                        model: Some(res.response.model.clone()),
-                        tokens_input: res.response.tokens_input,
-                        tokens_output: res.response.tokens_output,
-                        user_id: user_id.clone(),
+                ExchangeKind::Regenerate { language, .. } => {
+                    language.clone().map(|language| PromptTranslation {
+                        entry,
+                        language,
+                        english: None,
+                        usage: TranslationUsage::default(),
                     })
-                    .await;
-                let _ = tx.send(Ok(Event::default().event("done").data(meta.to_string())));
+                }
+            },
+            None => {
+                warn!(
+                    "account {} has inline translation on but TRANSLATION_MODEL is unset, unknown or outside its residency",
+                    account.id
+                );
+                None
+            }
+        },
+        None => None,
+    };
+    let citations = match body.retrieval.clone() {
+        Some(retrieval) => {
+            state
+                .rag
+                .augment(&mut body.messages, &retrieval, residency.as_deref())
+                .await?
+        }
+        None => Vec::new(),
+    };
+    let context = fit_context(
+        state,
+        user_id.as_deref(),
+        &mut body,
+        &plan,
+        residency.as_deref(),
+    )
+    .await?;
+
+    Ok(PreparedChat {
+        body,
+        user_id,
+        plan,
+        conversation_id,
+        user_message,
+        policy_hits,
+        context,
+        citations,
+        pii_redacted,
+        kind,
+        residency,
+        canaries,
+        translation,
+    })
+}
+
+/// Applies policies and PII redaction to a user turn in place. Returns the
+/// policy hits and whether PII was redacted.
+async fn screen_prompt(
+    state: &AppState,
+    policies: &[Policy],
+    user_id: Option<&str>,
+    conversation_id: Uuid,
+    text: &mut String,
+) -> Result<(Vec<PolicyHitDraft>, bool), AppError> {
+    let eval = evaluate_policies(policies, "user", text);
+    if let Some(blocked) = eval.blocked {
+        return Err(policy_block(state, user_id, conversation_id, &blocked).await);
+    }
+    if let Some(red) = eval.redacted {
+        *text = red;
+    }
+
+    let mut pii_redacted = false;
+    if state.config.load().pii_redaction {
+        let (redacted, changed) = redact(text);
+        *text = redacted;
+        pii_redacted = changed;
+        if changed {
+            info!("PII redaction applied");
+        }
+    }
+    Ok((eval.hits, pii_redacted))
+}
+
+/// Audits a `block` policy hit, counts it toward the account's abuse score
+/// and returns the error to refuse the request with.
+async fn policy_block(
+    state: &AppState,
+    user_id: Option<&str>,
+    conversation_id: Uuid,
+    blocked: &PolicyHitDraft,
+) -> AppError {
+    note_blocked(state, user_id).await;
+    state
+        .audit
+        .record(AuditEvent::policy_hit(
+            user_id,
+            &blocked.action,
+            serde_json::json!({
+                "policy_id": blocked.policy_id,
+                "policy_name": blocked.policy_name,
+                "conversation_id": conversation_id,
+            }),
+        ))
+        .await;
+    AppError::BadRequest(format!("Blocked by policy: {}", blocked.policy_name))
+}
+
+/// Replaces the last turn with its English translation, screened like the
+/// original so policies written in English also catch other languages. When
+/// translation fails the original goes out as it is. Returns the translation
+/// with the policy hits and PII flag from screening it. What translating used
+/// is recorded against the account straight away, so it counts even if the
+/// request goes no further.
+async fn translate_prompt(
+    state: &AppState,
+    entry: CatalogEntry,
+    policies: &[Policy],
+    user_id: Option<&str>,
+    conversation_id: Uuid,
+    body: &mut LlmRequest,
+) -> Result<Option<(PromptTranslation, Vec<PolicyHitDraft>, bool)>, AppError> {
+    let Some(last) = body.messages.last_mut() else {
+        return Ok(None);
+    };
+    let mut usage = TranslationUsage::default();
+    let translated = translation::to_english(&state.llm, &entry, &last.content, &mut usage).await;
+    record_extra_usage(
+        state,
+        user_id,
+        usage.tokens_input,
+        usage.tokens_output,
+        "translation",
+    )
+    .await;
+    let translated = match translated {
+        Ok(Some(translated)) => translated,
+        Ok(None) => return Ok(None),
+        Err(e) => {
+            warn!(
+                "translating prompt via {} failed, sending it as is: {e}",
+                entry.id
+            );
+            return Ok(None);
+        }
+    };
+    let mut english = translated.english;
+    let (hits, redacted) =
+        screen_prompt(state, policies, user_id, conversation_id, &mut english).await?;
+    last.content = english.clone();
+    let translation = PromptTranslation {
+        entry,
+        language: translated.language,
+        english: Some(english),
+        usage,
+    };
+    Ok(Some((translation, hits, redacted)))
+}
+
+/// Puts a reply to a translated prompt into the prompt's language and returns
+/// the English original, with what translating used (already recorded against
+/// the account). On failure the reply stays in English.
+async fn translate_reply(
+    state: &AppState,
+    prepared: &PreparedChat,
+    response: &mut LlmResponse,
+) -> (Option<String>, TranslationUsage) {
+    let mut usage = TranslationUsage::default();
+    let Some(translation) = prepared.translation.as_ref() else {
+        return (None, usage);
+    };
+    let translated = translation::from_english(
+        &state.llm,
+        &translation.entry,
+        &translation.language,
+        &response.content,
+        &mut usage,
+    )
+    .await;
+    record_extra_usage(
+        state,
+        prepared.user_id.as_deref(),
+        usage.tokens_input,
+        usage.tokens_output,
+        "translation",
+    )
+    .await;
+    match translated {
+        Ok(translated) => (
+            Some(std::mem::replace(&mut response.content, translated)),
+            usage,
+        ),
+        Err(e) => {
+            warn!(
+                "translating reply into {} via {} failed, returning it in English: {e}",
+                translation.language, translation.entry.id
+            );
+            (None, usage)
+        }
+    }
+}
+
+/// Counts tokens from calls made on top of an exchange's reply (translation,
+/// earlier agent steps) towards the account's usage and limits. They are
+/// tokens only: the exchange is already counted as one request.
+async fn record_extra_usage(
+    state: &AppState,
+    user_id: Option<&str>,
+    tokens_input: u32,
+    tokens_output: u32,
+    what: &str,
+) {
+    let Some(uid) = user_id else {
+        return;
+    };
+    let tokens = tokens_input as u64 + tokens_output as u64;
+    if tokens == 0 {
+        return;
+    }
+    state.usage.record(uid, 0, tokens);
+    if let Err(e) = state.store.add_daily_usage(uid, 0, tokens).await {
+        warn!("failed to record shared {what} usage for {uid}: {e}");
+    }
+    if let Err(e) = state
+        .db
+        .record_extra_usage(uid, tokens_input as i64, tokens_output as i64)
+        .await
+    {
+        warn!("failed to record {what} usage for {uid}: {e}");
+    }
+}
+
+fn translation_report(
+    prepared: &PreparedChat,
+    reply_translated: bool,
+    reply_usage: TranslationUsage,
+) -> Option<TranslationReport> {
+    prepared.translation.as_ref().map(|t| {
+        let mut usage = t.usage;
+        usage.add(reply_usage);
+        TranslationReport {
+            language: t.language.clone(),
+            model: t.entry.id.clone(),
+            reply_translated,
+            usage,
+        }
+    })
+}
+
+/// Size limits from the configuration (413) and the shape providers expect
+/// (400): system messages only at the start, no two assistant turns in a row,
+/// and a non-empty user message last. Text is valid UTF-8 once parsed, but
+/// JSON escapes can still smuggle in control characters, which are refused.
+fn validate_messages(config: &Config, messages: &[LlmMessage]) -> Result<(), AppError> {
+    if messages.len() > config.max_messages {
+        return Err(AppError::PayloadTooLarge(format!(
+            "{} messages exceed the limit of {}",
+            messages.len(),
+            config.max_messages
+        )));
+    }
+    let mut seen_turn = false;
+    let mut previous = None;
+    for (i, message) in messages.iter().enumerate() {
+        let chars = message.content.chars().count();
+        if chars > config.max_message_chars {
+            return Err(AppError::PayloadTooLarge(format!(
+                "message {i} is {chars} characters; the limit is {}",
+                config.max_message_chars
+            )));
+        }
+        if let Some(c) = message
+            .content
+            .chars()
+            .find(|c| c.is_control() && !matches!(c, '\n' | '\r' | '\t'))
+        {
+            return Err(AppError::BadRequest(format!(
+                "message {i} contains the control character U+{:04X}",
+                c as u32
+            )));
+        }
+        match message.role {
+            Role::System if seen_turn => {
+                return Err(AppError::BadRequest(format!(
+                    "message {i}: system messages must come before the conversation"
+                )));
+            }
+            Role::Assistant if previous == Some(Role::Assistant) => {
+                return Err(AppError::BadRequest(format!(
+                    "message {i}: two assistant messages in a row"
+                )));
             }
+            Role::System => {}
+            Role::User | Role::Assistant => seen_turn = true,
+        }
+        previous = Some(message.role);
+    }
+    match messages.last() {
+        Some(last) if last.role == Role::User && !last.content.trim().is_empty() => Ok(()),
+        Some(last) if last.role == Role::User => {
+            Err(AppError::BadRequest("the last message is empty".into()))
+        }
+        _ => Err(AppError::BadRequest(
+            "the last message must be from the user".into(),
+        )),
+    }
+}
+
+/// Prepends the stored transcript when the client continues a conversation by
+/// sending only its new turn (a `conversation_id` and no assistant messages).
+/// Stored user turns were redacted on the way in, so earlier PII never goes back
+/// out to the provider. `prepare_chat` has already checked the conversation is
+/// the caller's.
+async fn inject_history(state: &AppState, body: &mut LlmRequest) -> Result<(), AppError> {
+    let Some(conversation_id) = body.conversation_id else {
+        return Ok(());
+    };
+    if body.messages.iter().any(|m| m.role == Role::Assistant) {
+        return Ok(());
+    }
+    let mut history = history_from_records(
+        state
+            .db
+            .conversation_messages(conversation_id)
+            .await?
+            .into_iter()
+            .filter(|m| m.superseded_by.is_none()),
+    );
+    if history.last().is_some_and(|m| m.role == Role::User) {
+        history.pop();
+    }
+    if history.is_empty() {
+        return Ok(());
+    }
+
+    let at = body
+        .messages
+        .iter()
+        .position(|m| m.role != Role::System)
+        .unwrap_or(body.messages.len());
+    body.messages.splice(at..at, history);
+    Ok(())
+}
+
+/// Turns stored messages into provider turns, dropping user turns that never got
+/// an answer because a retry followed them.
+fn history_from_records(records: impl IntoIterator<Item = MessageRecord>) -> Vec<LlmMessage> {
+    let mut history: Vec<LlmMessage> = Vec::new();
+    for record in records {
+        let role = match record.role.as_str() {
+            "user" => Role::User,
+            "assistant" => Role::Assistant,
+            _ => continue,
+        };
+        if role == Role::User && history.last().is_some_and(|m| m.role == Role::User) {
+            history.pop();
+        }
+        // Translated turns go back to the model in the English it saw.
+        history.push(LlmMessage {
+            role,
+            content: record.english_content.unwrap_or(record.content),
+        });
+    }
+    history
+}
+
+/// Keeps the prompt inside the smallest context window in the routing plan so a
+/// fallback never gets a request it would reject. Overflowing turns are dropped
+/// oldest-first and replaced by the conversation's rolling summary, or by a
+/// freshly generated one when `CONTEXT_SUMMARY_MODEL` is set and none exists yet.
+/// Only a summary kept for `user_id` is used.
+async fn fit_context(
+    state: &AppState,
+    user_id: Option<&str>,
+    body: &mut LlmRequest,
+    plan: &[RoutedModel],
+    residency: Option<&str>,
+) -> Result<ContextReport, AppError> {
+    let window = plan.iter().filter_map(|m| m.context_window).min();
+    let mut report = ContextReport {
+        context_window: window,
+        estimated_prompt_tokens: context::prompt_tokens(&body.messages),
+        ..ContextReport::default()
+    };
+    let Some(window) = window else {
+        return Ok(report);
+    };
+    let budget = context::prompt_budget(window, body.max_tokens);
+    if report.estimated_prompt_tokens <= budget {
+        return Ok(report);
+    }
+
+    let stored = match body.conversation_id {
+        Some(id) => state.db.conversation_summary(id, user_id).await?,
+        None => None,
+    };
+    let summarizer = summary_model(state, residency);
+    let summary_reserve = match (&stored, &summarizer) {
+        (Some(summary), _) => context::estimate_tokens(&summary.summary) + SUMMARY_PREAMBLE_TOKENS,
+        (None, Some(_)) => SUMMARY_MAX_TOKENS + SUMMARY_PREAMBLE_TOKENS,
+        (None, None) => 0,
+    };
+    let dropped =
+        context::trim_to_budget(&mut body.messages, budget.saturating_sub(summary_reserve));
+    if context::prompt_tokens(&body.messages) > budget {
+        return Err(AppError::BadRequest(format!(
+            "latest message does not fit the {window}-token context window of {}",
+            plan[0].resolved_model
+        )));
+    }
+    report.dropped_messages = dropped.len();
+    report.dropped_tokens = context::prompt_tokens(&dropped);
+
+    if !dropped.is_empty() {
+        let summary = match (stored, summarizer) {
+            (Some(stored), _) => Some(stored.summary),
+            (None, Some(entry)) => match summarize(&state.llm, &entry, None, &dropped).await {
+                Ok(summary) => Some(summary),
+                Err(e) => {
+                    warn!(
+                        "context summary via {} failed, trimming only: {e}",
+                        entry.id
+                    );
+                    None
+                }
+            },
+            (None, None) => None,
+        };
+        if let Some(summary) = summary {
+            let at = body
+                .messages
+                .iter()
+                .position(|m| m.role != Role::System)
+                .unwrap_or(0);
+            body.messages.insert(
+                at,
+                LlmMessage {
+                    role: Role::System,
+                    content: format!("Summary of the earlier conversation:\n{summary}"),
+                },
+            );
+            report.summarized = true;
+        }
+    }
+    report.estimated_prompt_tokens = context::prompt_tokens(&body.messages);
+    info!(
+        "trimmed {} message(s) (~{} tokens) to fit {window}-token window",
+        report.dropped_messages, report.dropped_tokens
+    );
+    Ok(report)
+}
+
+const SUMMARY_MAX_TOKENS: u32 = 512;
+const SUMMARY_PREAMBLE_TOKENS: u32 = 16;
+
+/// The configured summary model, unless it is hosted outside `residency`.
+fn summary_model(
+    state: &AppState,
+    residency: Option<&str>,
+) -> Option<crate::model_router::CatalogEntry> {
+    let config = state.config.load();
+    config
+        .context_summary_model
+        .as_deref()
+        .and_then(|m| state.access.model_entry(m))
+        .filter(|entry| entry.satisfies(residency))
+}
+
+/// Asks the summary model for a digest of `turns`, folding them into `previous`
+/// when the conversation already has one.
+async fn summarize(
+    llm: &LlmService,
+    entry: &crate::model_router::CatalogEntry,
+    previous: Option<&str>,
+    turns: &[LlmMessage],
+) -> Result<String, AppError> {
+    let input_budget = entry
+        .context_window
+        .map(|w| context::prompt_budget(w, Some(SUMMARY_MAX_TOKENS)))
+        .unwrap_or(8_000)
+        .saturating_sub(256);
+    let input = match previous {
+        Some(previous) => format!(
+            "Current summary:\n{previous}\n\nNew turns:\n{}",
+            context::transcript(
+                turns,
+                input_budget.saturating_sub(context::estimate_tokens(previous))
+            )
+        ),
+        None => context::transcript(turns, input_budget),
+    };
+    let mut req = LlmRequest {
+        conversation_id: None,
+        provider: provider_from_str(&entry.provider)?,
+        model: entry.id.clone(),
+        messages: vec![
+            LlmMessage {
+                role: Role::System,
+                content: "Summarize the conversation below for another assistant that will continue it. If a current summary is given, update it with the new turns. Keep names, numbers, decisions and open questions; be concise.".into(),
+            },
+            LlmMessage {
+                role: Role::User,
+                content: input,
+            },
+        ],
+        max_tokens: Some(SUMMARY_MAX_TOKENS),
+        temperature: Some(0.0),
+        retrieval: None,
+    };
+    clamp_request(&mut req);
+    let res = llm.chat(req).await?;
+    Ok(res.content)
+}
+
+/// Folds the latest exchange into the conversation's rolling summary in the
+/// background, so later turns that overflow the window can lean on it without
+/// waiting for a summarization call.
+fn schedule_summary_refresh(state: &AppState, prepared: &PreparedChat, reply: &str) {
+    // A regenerated answer replaces one the summary already covers.
+    if let ExchangeKind::Regenerate { .. } = prepared.kind {
+        return;
+    }
+    let Some(entry) = summary_model(state, prepared.residency.as_deref()) else {
+        return;
+    };
+    let conversation_id = prepared.conversation_id;
+    let mut turns: Vec<LlmMessage> = prepared
+        .body
+        .messages
+        .iter()
+        .filter(|m| m.role != Role::System)
+        .cloned()
+        .collect();
+    turns.push(LlmMessage {
+        role: Role::Assistant,
+        content: reply.to_string(),
+    });
+    let user_id = prepared.user_id.clone();
+    let db = state.db.clone();
+    let llm = state.llm.clone();
+    state.lifecycle.spawn(async move {
+        let previous = match db
+            .conversation_summary(conversation_id, user_id.as_deref())
+            .await
+        {
+            Ok(previous) => previous,
             Err(e) => {
-                let err_msg = e.to_string();
-                let _ = tx.send(Ok(Event::default().data(format!("Error: {}", err_msg))));
+                warn!("failed to load summary for {conversation_id}: {e}");
+                return;
             }
+        };
+        // With a summary in hand only the newest exchange needs folding in; the
+        // client-sent history before it is already covered.
+        let (previous, new_turns, covered) = match &previous {
+            Some(p) => (
+                Some(p.summary.as_str()),
+                &turns[turns.len().saturating_sub(2)..],
+                p.messages_covered + 2,
+            ),
+            None => (None, &turns[..], turns.len() as i64),
+        };
+        match summarize(&llm, &entry, previous, new_turns).await {
+            Ok(summary) => {
+                if let Err(e) = db
+                    .save_conversation_summary(
+                        conversation_id,
+                        user_id.as_deref(),
+                        &summary,
+                        covered,
+                    )
+                    .await
+                {
+                    warn!("failed to save summary for {conversation_id}: {e}");
+                }
+            }
+            Err(e) => warn!("summary refresh for {conversation_id} failed: {e}"),
         }
     });
+}
+
+/// Writes the user turn, its policy hits and (when routing succeeded) the
+/// assistant reply in one transaction. Regenerations only add the new reply and
+/// mark the one it replaces. Translated turns keep the English text next to
+/// what the user wrote or read; `english_reply` is the reply before it was
+/// translated. A reply shared from an identical in-flight request is stored
+/// without token counts, since its leader already accounted for them, and not at
+/// all when the leader wrote to the same conversation. Returns the id of the
+/// stored reply.
+async fn persist_exchange(
+    state: &AppState,
+    prepared: &PreparedChat,
+    routed: Option<&RoutedResult>,
+    english_reply: Option<&str>,
+) -> Result<Option<Uuid>, AppError> {
+    let coalesced = routed.is_some_and(|r| r.coalesced);
+    if coalesced && prepared.body.conversation_id.is_some() {
+        return Ok(None);
+    }
+    let response = routed.map(|r| &r.response);
+    let mut messages = Vec::new();
+    let mut policy_hits = Vec::new();
+    let language = prepared.translation.as_ref().map(|t| t.language.clone());
+    if let ExchangeKind::NewTurn = prepared.kind {
+        let user_message_id = Uuid::new_v4();
+        messages.push(MessageInsert {
+            id: Some(user_message_id),
+            conversation_id: prepared.conversation_id,
+            role: "user".into(),
+            content: prepared.user_message.clone(),
+            provider: None,
+            model: Some(prepared.body.model.clone()),
+            tokens_input: None,
+            tokens_output: None,
+            user_id: prepared.user_id.clone(),
+            routing_trace: None,
+            pii_redacted: prepared.pii_redacted,
+            language: language.clone(),
+            english_content: prepared
+                .translation
+                .as_ref()
+                .and_then(|t| t.english.clone()),
+        });
+        policy_hits = prepared
+            .policy_hits
+            .iter()
+            .map(|h| PolicyHitInsert {
+                message_id: user_message_id.to_string(),
+                policy_id: h.policy_id.clone(),
+                policy_name: h.policy_name.clone(),
+                action: h.action.clone(),
+            })
+            .collect();
+    }
+
+    let reply_id = response.map(|_| Uuid::new_v4());
+    if let (Some(res), Some(id)) = (response, reply_id) {
+        messages.push(MessageInsert {
+            id: Some(id),
+            conversation_id: prepared.conversation_id,
+            role: "assistant".into(),
+            content: res.content.clone(),
+            provider: Some(res.provider.to_string()),
+            model: Some(res.model.clone()),
+            tokens_input: res.tokens_input.filter(|_| !coalesced),
+            tokens_output: res.tokens_output.filter(|_| !coalesced),
+            user_id: prepared.user_id.clone(),
+            routing_trace: routed.and_then(|r| serde_json::to_string(&r.trace).ok()),
+            pii_redacted: false,
+            language: english_reply.and(language),
+            english_content: english_reply.map(str::to_string),
+        });
+    }
+    let supersedes = match (&prepared.kind, reply_id) {
+        (
+            ExchangeKind::Regenerate {
+                supersedes: Some(previous),
+                ..
+            },
+            Some(id),
+        ) => Some(Supersession {
+            message_id: previous.clone(),
+            superseded_by: id,
+        }),
+        _ => None,
+    };
 
-    Ok(Sse::new(UnboundedReceiverStream::new(rx))
-        .keep_alive(axum::response::sse::KeepAlive::new()))
+    let reviews: Vec<Notice> = policy_hits
+        .iter()
+        .filter(|h| h.action == "flag")
+        .map(|h| Notice::review_pending(&h.policy_name, &h.policy_id, &h.message_id))
+        .collect();
+    let actor = prepared.user_id.as_deref();
+    let mut audit_events: Vec<AuditEvent> = policy_hits
+        .iter()
+        .map(|h| {
+            AuditEvent::policy_hit(
+                actor,
+                &h.action,
+                serde_json::json!({
+                    "policy_id": h.policy_id,
+                    "policy_name": h.policy_name,
+                    "conversation_id": prepared.conversation_id,
+                    "message_id": h.message_id,
+                }),
+            )
+        })
+        .collect();
+    audit_events.push(AuditEvent::routing(
+        actor,
+        routed.is_some(),
+        serde_json::json!({
+            "conversation_id": prepared.conversation_id,
+            "message_id": reply_id,
+            "requested_model": prepared.body.model,
+            "trace": routed.map(|r| &r.trace),
+        }),
+    ));
+    state
+        .db
+        .record_exchange(ExchangeInsert {
+            conversation_id: prepared.conversation_id,
+            title: None,
+            user_id: prepared.user_id.clone(),
+            requests: if coalesced { 0 } else { 1 },
+            messages,
+            policy_hits,
+            supersedes,
+        })
+        .await?;
+    for notice in reviews {
+        notifications::publish(&state.db, notice).await;
+    }
+    if let Some(res) = response {
+        canary::watch(
+            state,
+            &prepared.canaries,
+            english_reply.unwrap_or(&res.content),
+            Sighting::Output,
+            actor,
+            prepared.conversation_id,
+        )
+        .await;
+    }
+    for event in audit_events {
+        state.audit.record(event).await;
+    }
+    // The draft was this turn; once it's answered there is nothing left to restore.
+    if matches!(prepared.kind, ExchangeKind::NewTurn) && response.is_some() {
+        state
+            .db
+            .delete_conversation_draft(prepared.conversation_id)
+            .await?;
+    }
+
+    // Answered requests are counted by `record_usage` when the reply arrives.
+    if let Some(uid) = prepared.user_id.as_deref()
+        && response.is_none()
+    {
+        state.usage.record(uid, 1, 0);
+    }
+    Ok(reply_id)
 }
 
-fn provider_from_str(provider: &str) -> Result<Provider, AppError> {
+pub(crate) fn provider_from_str(provider: &str) -> Result<Provider, AppError> {
     match provider {
         "openai" => Ok(Provider::Openai),
         "anthropic" => Ok(Provider::Anthropic),
+        "mock" => Ok(Provider::Mock),
         other => Err(AppError::BadRequest(format!(
             "unknown provider for model routing: {other}"
         ))),
@@ -292,32 +1557,162 @@ fn should_fallback(err: &AppError) -> bool {
     matches!(err, AppError::Upstream(_) | AppError::Internal(_))
 }
 
-struct RoutedResult {
+#[derive(Clone)]
+pub struct RoutedResult {
     response: LlmResponse,
     trace: RoutingTrace,
+    /// Shared from an identical request already in flight. The call counted
+    /// its usage once, when it answered.
+    coalesced: bool,
 }
 
 fn clamp_request(req: &mut LlmRequest) {
-    let max_tokens_cap = match req.provider {
+    clamp_sampling(req.provider, &mut req.max_tokens, &mut req.temperature);
+}
+
+fn clamp_sampling(provider: Provider, max_tokens: &mut Option<u32>, temperature: &mut Option<f32>) {
+    let max_tokens_cap = match provider {
         Provider::Openai => 8192,
         Provider::Anthropic => 8192,
+        Provider::Mock => 8192,
     };
-    if let Some(max) = req.max_tokens.as_mut() {
-        if *max > max_tokens_cap {
-            *max = max_tokens_cap;
-        }
+    if let Some(max) = max_tokens.as_mut()
+        && *max > max_tokens_cap
+    {
+        *max = max_tokens_cap;
+    }
+    if let Some(temp) = temperature.as_mut() {
+        *temp = temp.clamp(0.0, 2.0);
     }
-    if let Some(temp) = req.temperature.as_mut() {
-        if *temp < 0.0 {
-            *temp = 0.0;
-        } else if *temp > 2.0 {
-            *temp = 2.0;
+}
+
+/// Limit rejection kind for requests turned away by an abuse throttle.
+const ABUSE_THROTTLE: &str = "abuse_throttle";
+
+async fn enforce_rate_limit(state: &AppState, user_id: Option<&str>) -> Result<(), AppError> {
+    let Some(limit) = state.config.load().rate_limit_per_minute else {
+        return Ok(());
+    };
+    let key = user_id.unwrap_or("anonymous");
+    let hits = state
+        .store
+        .hit_window(key, std::time::Duration::from_secs(60))
+        .await?;
+    if hits > limit as u64 {
+        let err = AppError::RateLimited(format!("more than {limit} requests per minute"));
+        return Err(reject(state, user_id, "rate_limit", err).await);
+    }
+    Ok(())
+}
+
+/// Records a limit rejection for the account's usage report and hands the
+/// error back to the caller.
+async fn reject(state: &AppState, user_id: Option<&str>, kind: &str, err: AppError) -> AppError {
+    if let Err(db_err) = state
+        .db
+        .record_limit_rejection(user_id, kind, &err.to_string())
+        .await
+    {
+        warn!("failed to record {kind} rejection: {db_err}");
+    }
+    if kind == ABUSE_THROTTLE {
+        return err;
+    }
+    // Per-minute rate limiting is routine; daily quotas and price caps are
+    // budget decisions an admin may want to revisit.
+    if kind != "rate_limit" {
+        notifications::publish(
+            &state.db,
+            Notice::budget_breach(user_id, kind, &err.to_string()),
+        )
+        .await;
+    }
+    note_blocked(state, user_id).await;
+    err
+}
+
+/// Holds a throttled account to a trickle of requests, then scores this one.
+/// Anonymous traffic is shared by everyone and isn't scored.
+async fn screen_for_abuse(
+    state: &AppState,
+    user_id: Option<&str>,
+    prompt: &str,
+) -> Result<(), AppError> {
+    let (Some(account), Some(settings)) =
+        (user_id, AbuseSettings::from_config(&state.config.load()))
+    else {
+        return Ok(());
+    };
+    if let Some(until) = state.abuse.throttled_until(account) {
+        let hits = state
+            .store
+            .hit_window(
+                &format!("abuse:{account}"),
+                std::time::Duration::from_secs(60),
+            )
+            .await?;
+        if hits > THROTTLED_PER_MINUTE {
+            let err = AppError::RateLimited(format!(
+                "account throttled after unusual activity until {}",
+                until.to_rfc3339()
+            ));
+            return Err(reject(state, user_id, ABUSE_THROTTLE, err).await);
         }
     }
+    if let Some(risk) = state.abuse.record_request(account, prompt, &settings) {
+        raise_abuse_alert(state, &risk).await;
+    }
+    Ok(())
+}
+
+/// Counts a policy block or limit rejection toward the account's abuse score.
+async fn note_blocked(state: &AppState, user_id: Option<&str>) {
+    let (Some(account), Some(settings)) =
+        (user_id, AbuseSettings::from_config(&state.config.load()))
+    else {
+        return;
+    };
+    if let Some(risk) = state.abuse.record_blocked(account, &settings) {
+        raise_abuse_alert(state, &risk).await;
+    }
+}
+
+async fn raise_abuse_alert(state: &AppState, risk: &AccountRisk) {
+    warn!(
+        "throttled account {} for unusual activity (score {})",
+        risk.account_id, risk.score
+    );
+    notifications::publish(&state.db, Notice::abuse_suspected(risk)).await;
+}
+
+/// Requests and tokens the account used in the trailing 24 hours.
+pub(crate) async fn current_usage(state: &AppState, account_id: &str) -> WindowTotals {
+    // Redis tallies are authoritative when replicas share state; otherwise the
+    // in-process 24h window answers without a database round trip.
+    match state.store.daily_usage(account_id).await {
+        Ok(Some(tally)) => WindowTotals {
+            requests: tally.requests,
+            tokens: tally.tokens,
+        },
+        _ => state.usage.totals(account_id),
+    }
+}
+
+/// Counts an answered request and its tokens towards the account's limits.
+async fn record_usage(state: &AppState, user_id: Option<&str>, response: &LlmResponse) {
+    let Some(uid) = user_id else {
+        return;
+    };
+    let tokens =
+        response.tokens_input.unwrap_or(0) as u64 + response.tokens_output.unwrap_or(0) as u64;
+    state.usage.record(uid, 1, tokens);
+    if let Err(e) = state.store.add_daily_usage(uid, 1, tokens).await {
+        warn!("failed to record shared usage for {uid}: {e}");
+    }
 }
 
 async fn enforce_limits(
-    db: &crate::db::Db,
+    state: &AppState,
     account: Option<&crate::model_router::AccountAccess>,
     primary: &RoutedModel,
 ) -> Result<(), AppError> {
@@ -329,53 +1724,84 @@ async fn enforce_limits(
         .model_price_caps
         .iter()
         .find(|c| c.model.eq_ignore_ascii_case(&primary.resolved_model))
+        && primary.estimate_cents > cap.max_cents as f64
     {
-        if primary.estimate_cents > cap.max_cents as f64 {
-            return Err(AppError::BadRequest(
-                "requested model exceeds account price cap".into(),
-            ));
-        }
+        let err = AppError::BadRequest("requested model exceeds account price cap".into());
+        return Err(reject(state, Some(&acct.id), "price_cap", err).await);
     }
 
-    if acct.req_per_day.is_none() && acct.tokens_per_day.is_none() {
+    let (req_per_day, tokens_per_day) = state.access.effective_limits(acct).await;
+    if req_per_day.is_none() && tokens_per_day.is_none() {
         return Ok(());
     }
 
-    let cutoff = chrono::Utc::now() - chrono::Duration::hours(24);
-    let usage = db
-        .usage_since(&acct.id, &cutoff.to_rfc3339())
-        .await
-        .unwrap_or(UsageStats {
-            requests: 0,
-            tokens_input: 0,
-            tokens_output: 0,
-        });
+    let usage = current_usage(state, &acct.id).await;
 
-    if let Some(limit) = acct.req_per_day {
-        if usage.requests >= limit as i64 {
-            return Err(AppError::BadRequest(
-                "account request limit reached for today".into(),
-            ));
-        }
+    if let Some(limit) = req_per_day
+        && usage.requests >= limit as u64
+    {
+        let err = AppError::BadRequest("account request limit reached for today".into());
+        return Err(reject(state, Some(&acct.id), "requests_per_day", err).await);
     }
 
-    if let Some(limit) = acct.tokens_per_day {
-        let total = usage.tokens_input + usage.tokens_output;
-        if total >= limit as i64 {
-            return Err(AppError::BadRequest(
-                "account token limit reached for today".into(),
-            ));
-        }
+    if let Some(limit) = tokens_per_day
+        && usage.tokens >= limit as u64
+    {
+        let err = AppError::BadRequest("account token limit reached for today".into());
+        return Err(reject(state, Some(&acct.id), "tokens_per_day", err).await);
     }
 
     Ok(())
 }
 
+/// Routes the request, sharing the provider call with any identical request from
+/// the same account that is already in flight (double submits, client retries).
+async fn route_deduped(
+    state: &AppState,
+    user_id: Option<&str>,
+    body: &LlmRequest,
+    plan: &[RoutedModel],
+) -> Result<RoutedResult, AppError> {
+    let key = InflightDedup::<RoutedResult>::key(user_id.unwrap_or("anonymous"), body);
+    let api_keys = state.provider_keys.for_account(user_id).await?;
+    let call_state = state.clone();
+    let owner = user_id.map(str::to_owned);
+    let req = body.clone();
+    let plan = plan.to_vec();
+    let (res, coalesced) = state
+        .dedup
+        .run(key, async move {
+            let state = call_state;
+            let routed =
+                route_with_fallbacks(&state.llm, &state.access, &req, &plan, &api_keys).await?;
+            // Counted by the call itself, once, whichever of its requesters
+            // are still around to see the result.
+            record_usage(&state, owner.as_deref(), &routed.response).await;
+            Ok(routed)
+        })
+        .await;
+    let mut routed = res?;
+    if coalesced {
+        let tokens = routed.response.tokens_input.unwrap_or(0) as u64
+            + routed.response.tokens_output.unwrap_or(0) as u64;
+        state
+            .dedup
+            .record_saved(tokens, routed.response.cost.unwrap_or(0.0));
+        info!(
+            "coalesced duplicate in-flight request onto {}",
+            routed.trace.selected_model
+        );
+        routed.coalesced = true;
+    }
+    Ok(routed)
+}
+
 async fn route_with_fallbacks(
     llm: &LlmService,
     router: &AccessControl,
     base: &LlmRequest,
     plan: &[RoutedModel],
+    api_keys: &HashMap<Provider, String>,
 ) -> Result<RoutedResult, AppError> {
     let mut attempts = Vec::new();
     let mut used_fallback = false;
@@ -389,11 +1815,13 @@ async fn route_with_fallbacks(
             attempts.push(format!("{}#{}", candidate.resolved_model, retry + 1));
 
             let start = std::time::Instant::now();
-            let res = llm.chat(req).await;
+            // The account's own key for this provider, if it stored one.
+            let api_key = api_keys.get(&req.provider).map(String::as_str);
+            let res = llm.chat_with_key(req, api_key).await;
             match res {
                 Ok(resp) => {
                     let elapsed = start.elapsed().as_millis();
-                    router.record_health(&candidate.resolved_model, true, elapsed);
+                    router.record_health(&candidate.resolved_model, elapsed, None);
                     info!(
                         "routed model {} via {} ({} ms) after {} attempt(s)",
                         candidate.request_label,
@@ -409,12 +1837,17 @@ async fn route_with_fallbacks(
                             attempts,
                             used_fallback: used_fallback || idx > 0 || retry > 0,
                         },
+                        coalesced: false,
                     });
                 }
                 Err(e) => {
                     let app_err: AppError = e.into();
                     let elapsed = start.elapsed().as_millis();
-                    router.record_health(&candidate.resolved_model, false, elapsed);
+                    router.record_health(
+                        &candidate.resolved_model,
+                        elapsed,
+                        Some(&app_err.to_string()),
+                    );
                     let can_retry = retry == 0 && should_fallback(&app_err);
                     let can_fallback = idx + 1 < plan.len() && should_fallback(&app_err);
                     warn!(
@@ -442,3 +1875,78 @@ async fn route_with_fallbacks(
         "no available model after routing attempts".into(),
     ))
 }
+
+/// One model call of an agent run. The first step may fall back along the
+/// routing plan like a chat request; later steps stay on the model that took
+/// it, since the transcript carries that provider's tool call ids.
+async fn route_agent_step(
+    state: &AppState,
+    plan: &[RoutedModel],
+    pinned: &mut Option<usize>,
+    trace: &mut RoutingTrace,
+    base: ToolRequest,
+    api_keys: &HashMap<Provider, String>,
+) -> Result<ToolResponse, AppError> {
+    let candidates = match *pinned {
+        Some(idx) => idx..idx + 1,
+        None => 0..plan.len(),
+    };
+    for idx in candidates.clone() {
+        let candidate = &plan[idx];
+        for retry in 0..=1 {
+            let mut req = base.clone();
+            req.model = candidate.resolved_model.clone();
+            req.provider = provider_from_str(&candidate.provider)?;
+            clamp_sampling(req.provider, &mut req.max_tokens, &mut req.temperature);
+            trace
+                .attempts
+                .push(format!("{}#{}", candidate.resolved_model, retry + 1));
+
+            let start = std::time::Instant::now();
+            let api_key = api_keys.get(&req.provider).map(String::as_str);
+            match state.llm.chat_tools(req, api_key).await {
+                Ok(resp) => {
+                    let elapsed = start.elapsed().as_millis();
+                    state
+                        .access
+                        .record_health(&candidate.resolved_model, elapsed, None);
+                    *pinned = Some(idx);
+                    trace.selected_model = candidate.resolved_model.clone();
+                    trace.provider = candidate.provider.clone();
+                    trace.used_fallback |= idx > 0 || retry > 0;
+                    return Ok(resp);
+                }
+                Err(e) => {
+                    let app_err: AppError = e.into();
+                    let elapsed = start.elapsed().as_millis();
+                    state.access.record_health(
+                        &candidate.resolved_model,
+                        elapsed,
+                        Some(&app_err.to_string()),
+                    );
+                    let can_retry = retry == 0 && should_fallback(&app_err);
+                    let can_fallback = idx + 1 < candidates.end && should_fallback(&app_err);
+                    warn!(
+                        "agent step on {} attempt {} failed ({}); retry: {}, fallback: {}",
+                        candidate.resolved_model,
+                        retry + 1,
+                        app_err,
+                        can_retry,
+                        can_fallback
+                    );
+                    if can_retry {
+                        continue;
+                    }
+                    if can_fallback {
+                        break;
+                    }
+                    return Err(app_err);
+                }
+            }
+        }
+    }
+
+    Err(AppError::Internal(
+        "no available model after routing attempts".into(),
+    ))
+}
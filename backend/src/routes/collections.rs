@@ -0,0 +1,221 @@
+use axum::{
+    Json,
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{HeaderMap, StatusCode, header},
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    AppError, AppState,
+    db::{CollectionRecord, DocumentRecord},
+    rag,
+    rag::{DocumentUpload, chunking::ChunkOptions, extract::SourceFormat},
+    webhooks,
+};
+
+const MAX_COLLECTION_ID_LEN: usize = 64;
+/// Documents with more chunks than this are indexed in the background.
+const INLINE_CHUNK_LIMIT: usize = 64;
+
+#[derive(Debug, Deserialize)]
+pub struct CollectionBody {
+    pub id: String,
+    pub description: Option<String>,
+    /// Catalog key of an embedding model; defaults to `RAG_EMBEDDING_MODEL`.
+    pub embedding_model: Option<String>,
+}
+
+/// JSON form of a document upload; `text` holds HTML when `format` is `html`.
+#[derive(Debug, Deserialize)]
+pub struct DocumentBody {
+    pub title: String,
+    pub text: String,
+    pub source: Option<String>,
+    #[serde(default)]
+    pub format: SourceFormat,
+    #[serde(default)]
+    pub chunking: Option<ChunkOptions>,
+}
+
+/// Metadata for raw uploads (`text/plain`, `text/html`, `application/pdf`).
+#[derive(Debug, Deserialize)]
+pub struct DocumentQuery {
+    pub title: Option<String>,
+    pub source: Option<String>,
+    pub chunk_size: Option<usize>,
+    pub chunk_overlap: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeletedResponse {
+    pub deleted: bool,
+}
+
+pub async fn list_collections(
+    State(state): State<AppState>,
+) -> Result<Json<Vec<CollectionRecord>>, AppError> {
+    Ok(Json(state.db.list_collections().await?))
+}
+
+pub async fn create_collection(
+    State(state): State<AppState>,
+    Json(body): Json<CollectionBody>,
+) -> Result<Json<CollectionRecord>, AppError> {
+    let id = body.id.trim().to_lowercase();
+    if id.is_empty()
+        || id.len() > MAX_COLLECTION_ID_LEN
+        || !id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_'))
+    {
+        return Err(AppError::BadRequest(format!(
+            "collection id must be 1-{MAX_COLLECTION_ID_LEN} letters, digits, '-' or '_'"
+        )));
+    }
+    let model = body
+        .embedding_model
+        .unwrap_or_else(|| state.rag.default_model().to_string());
+    let entry = state.rag.embedding_model(&model)?;
+
+    let collection = CollectionRecord {
+        id,
+        description: body
+            .description
+            .map(|d| d.trim().to_string())
+            .filter(|d| !d.is_empty()),
+        embedding_model: entry.id,
+        created_at: Utc::now().to_rfc3339(),
+    };
+    state.db.create_collection(&collection).await?;
+    Ok(Json(collection))
+}
+
+/// Removes the collection along with every document and chunk in it.
+pub async fn delete_collection(
+    State(state): State<AppState>,
+    Path(collection_id): Path<String>,
+) -> Result<Json<DeletedResponse>, AppError> {
+    let deleted = state.db.delete_collection(&collection_id).await?;
+    Ok(Json(DeletedResponse { deleted }))
+}
+
+pub async fn list_documents(
+    State(state): State<AppState>,
+    Path(collection_id): Path<String>,
+) -> Result<Json<Vec<DocumentRecord>>, AppError> {
+    Ok(Json(state.db.list_documents(&collection_id).await?))
+}
+
+pub async fn get_document(
+    State(state): State<AppState>,
+    Path((collection_id, document_id)): Path<(String, String)>,
+) -> Result<Json<DocumentRecord>, AppError> {
+    state
+        .db
+        .document(&collection_id, &document_id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::BadRequest(format!("document {document_id} not found")))
+}
+
+/// Extracts, chunks and embeds a document sent either as JSON or as a raw
+/// text, HTML or PDF body. Small documents are searchable when this returns
+/// (200); larger ones come back `processing` (202) and can be polled.
+pub async fn add_document(
+    State(state): State<AppState>,
+    Path(collection_id): Path<String>,
+    Query(query): Query<DocumentQuery>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(StatusCode, Json<DocumentRecord>), AppError> {
+    if let Some(message) = state.access.maintenance() {
+        return Err(AppError::Unavailable(message));
+    }
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("application/json");
+    let upload = if content_type.starts_with("application/json") {
+        let doc: DocumentBody = serde_json::from_slice(&body)
+            .map_err(|e| AppError::BadRequest(format!("invalid document body: {e}")))?;
+        if doc.format == SourceFormat::Pdf {
+            return Err(AppError::BadRequest(
+                "upload PDFs as the raw request body with Content-Type: application/pdf".into(),
+            ));
+        }
+        DocumentUpload {
+            title: doc.title,
+            source: doc.source,
+            format: doc.format,
+            text: rag::extract::extract(doc.format, doc.text.into_bytes()).await?,
+            chunking: doc.chunking.unwrap_or_default(),
+        }
+    } else {
+        let format = SourceFormat::from_content_type(content_type).ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "unsupported document type {content_type}; send text/plain, text/html, application/pdf or JSON"
+            ))
+        })?;
+        let defaults = ChunkOptions::default();
+        DocumentUpload {
+            title: query.title.unwrap_or_default(),
+            source: query.source,
+            format,
+            text: rag::extract::extract(format, body.to_vec()).await?,
+            chunking: ChunkOptions {
+                size: query.chunk_size.unwrap_or(defaults.size),
+                overlap: query.chunk_overlap.unwrap_or(defaults.overlap),
+            },
+        }
+    };
+    let upload = DocumentUpload {
+        title: upload.title.trim().to_string(),
+        source: upload.source.filter(|s| !s.trim().is_empty()),
+        ..upload
+    };
+    if upload.title.is_empty() {
+        return Err(AppError::BadRequest("document title is required".into()));
+    }
+
+    let pending = state.rag.prepare(&collection_id, upload).await?;
+    if pending.document.chunk_count as usize <= INLINE_CHUNK_LIMIT {
+        let document = state.rag.index(pending).await?;
+        return Ok((StatusCode::OK, Json(document)));
+    }
+    let document = pending.document.clone();
+    let rag = state.rag.clone();
+    let db = state.db.clone();
+    let (document_id, collection_id) = (document.id.clone(), document.collection_id.clone());
+    state.lifecycle.spawn(async move {
+        // Failures are recorded on the document by `index`.
+        let data = match rag.index(pending).await {
+            Ok(indexed) => serde_json::json!({
+                "document_id": indexed.id,
+                "collection_id": indexed.collection_id,
+                "status": indexed.status,
+                "chunk_count": indexed.chunk_count,
+            }),
+            Err(e) => serde_json::json!({
+                "document_id": document_id,
+                "collection_id": collection_id,
+                "status": "failed",
+                "error": e.to_string(),
+            }),
+        };
+        webhooks::enqueue(&db, webhooks::DOCUMENT_INGESTED, data).await;
+    });
+    Ok((StatusCode::ACCEPTED, Json(document)))
+}
+
+pub async fn delete_document(
+    State(state): State<AppState>,
+    Path((collection_id, document_id)): Path<(String, String)>,
+) -> Result<Json<DeletedResponse>, AppError> {
+    let deleted = state
+        .db
+        .delete_document(&collection_id, &document_id)
+        .await?;
+    Ok(Json(DeletedResponse { deleted }))
+}
@@ -0,0 +1,412 @@
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+    http::header,
+    response::{IntoResponse, Response},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::{
+    AppError, AppState,
+    auth::validate_token,
+    db::{ConversationDraft, ConversationListRow, ConversationRecord},
+    export::{self, Transcript},
+};
+
+const MAX_TAGS: usize = 20;
+const MAX_TAG_LEN: usize = 32;
+const DEFAULT_LIST_LIMIT: i64 = 50;
+const MAX_DRAFT_CHARS: usize = 100_000;
+
+#[derive(Debug, Deserialize)]
+pub struct ConversationListQuery {
+    pub tag: Option<String>,
+    #[serde(default)]
+    pub starred: bool,
+    pub limit: Option<i64>,
+}
+
+/// Pinned conversations come first, then those with a `sort_order`, then the
+/// rest newest first.
+#[derive(Debug, Serialize)]
+pub struct ConversationListItem {
+    pub id: String,
+    pub title: Option<String>,
+    pub created_at: String,
+    pub pinned: bool,
+    pub starred: bool,
+    pub sort_order: Option<i64>,
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FlagsBody {
+    pub pinned: Option<bool>,
+    pub starred: Option<bool>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct OrderBody {
+    /// The caller's conversations in the order they should be listed.
+    pub ids: Vec<Uuid>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DraftBody {
+    pub content: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct DraftResponse {
+    pub conversation_id: Uuid,
+    pub content: String,
+    pub updated_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TagsBody {
+    pub tags: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TagsResponse {
+    pub conversation_id: Uuid,
+    pub tags: Vec<String>,
+}
+
+pub async fn list_conversations(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Query(query): Query<ConversationListQuery>,
+) -> Result<Json<Vec<ConversationListItem>>, AppError> {
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    let tag = query.tag.as_deref().map(normalize_tag).transpose()?;
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT).clamp(1, 200);
+    let rows = state
+        .db
+        .list_conversations(user_id.as_deref(), tag.as_deref(), query.starred, limit)
+        .await?;
+    Ok(Json(rows.into_iter().map(list_item).collect()))
+}
+
+/// Pins or stars a conversation; omitted flags keep their current value.
+pub async fn set_flags(
+    State(state): State<AppState>,
+    Path(conversation_id): Path<Uuid>,
+    jar: CookieJar,
+    Json(body): Json<FlagsBody>,
+) -> Result<Json<ConversationListItem>, AppError> {
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    owned_conversation(&state, conversation_id, user_id.as_deref()).await?;
+    state
+        .db
+        .set_conversation_flags(conversation_id, body.pinned, body.starred)
+        .await?;
+    let row = state
+        .db
+        .conversation_list_row(conversation_id)
+        .await?
+        .ok_or_else(|| AppError::BadRequest(format!("conversation {conversation_id} not found")))?;
+    Ok(Json(list_item(row)))
+}
+
+/// Sets the manual order of the caller's conversations. Conversations left out
+/// lose their position and sort after the ordered ones.
+pub async fn set_order(
+    State(state): State<AppState>,
+    jar: CookieJar,
+    Json(body): Json<OrderBody>,
+) -> Result<Json<Vec<ConversationListItem>>, AppError> {
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    let mut seen = std::collections::HashSet::new();
+    if !body.ids.iter().all(|id| seen.insert(*id)) {
+        return Err(AppError::BadRequest(
+            "conversation ids must not repeat".into(),
+        ));
+    }
+    for id in &body.ids {
+        owned_conversation(&state, *id, user_id.as_deref()).await?;
+    }
+    state
+        .db
+        .reorder_conversations(user_id.as_deref(), &body.ids)
+        .await?;
+    let rows = state
+        .db
+        .list_conversations(user_id.as_deref(), None, false, DEFAULT_LIST_LIMIT)
+        .await?;
+    Ok(Json(rows.into_iter().map(list_item).collect()))
+}
+
+/// Returns the unsent draft, or empty content when there is none.
+pub async fn get_draft(
+    State(state): State<AppState>,
+    Path(conversation_id): Path<Uuid>,
+    jar: CookieJar,
+) -> Result<Json<DraftResponse>, AppError> {
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    owned_conversation(&state, conversation_id, user_id.as_deref()).await?;
+    let draft = state.db.conversation_draft(conversation_id).await?;
+    Ok(Json(DraftResponse {
+        conversation_id,
+        content: draft
+            .as_ref()
+            .map(|d| d.content.clone())
+            .unwrap_or_default(),
+        updated_at: draft.map(|d| d.updated_at),
+    }))
+}
+
+/// Saves the unsent message for the conversation. Drafts are stored as typed
+/// and are never sent to a provider; an empty draft clears it.
+pub async fn save_draft(
+    State(state): State<AppState>,
+    Path(conversation_id): Path<Uuid>,
+    jar: CookieJar,
+    Json(body): Json<DraftBody>,
+) -> Result<Json<DraftResponse>, AppError> {
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    owned_conversation(&state, conversation_id, user_id.as_deref()).await?;
+    if body.content.chars().count() > MAX_DRAFT_CHARS {
+        return Err(AppError::BadRequest(format!(
+            "drafts must be at most {MAX_DRAFT_CHARS} characters"
+        )));
+    }
+    if body.content.trim().is_empty() {
+        state.db.delete_conversation_draft(conversation_id).await?;
+        return Ok(Json(DraftResponse {
+            conversation_id,
+            content: String::new(),
+            updated_at: None,
+        }));
+    }
+    let draft = ConversationDraft {
+        conversation_id: conversation_id.to_string(),
+        content: body.content,
+        updated_at: Utc::now().to_rfc3339(),
+    };
+    state
+        .db
+        .save_conversation_draft(&draft, user_id.as_deref())
+        .await?;
+    Ok(Json(DraftResponse {
+        conversation_id,
+        content: draft.content,
+        updated_at: Some(draft.updated_at),
+    }))
+}
+
+pub async fn delete_draft(
+    State(state): State<AppState>,
+    Path(conversation_id): Path<Uuid>,
+    jar: CookieJar,
+) -> Result<Json<DraftResponse>, AppError> {
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    owned_conversation(&state, conversation_id, user_id.as_deref()).await?;
+    state.db.delete_conversation_draft(conversation_id).await?;
+    Ok(Json(DraftResponse {
+        conversation_id,
+        content: String::new(),
+        updated_at: None,
+    }))
+}
+
+pub async fn get_tags(
+    State(state): State<AppState>,
+    Path(conversation_id): Path<Uuid>,
+    jar: CookieJar,
+) -> Result<Json<TagsResponse>, AppError> {
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    owned_conversation(&state, conversation_id, user_id.as_deref()).await?;
+    tags_response(&state, conversation_id).await
+}
+
+/// Replaces the conversation's tags with the given set.
+pub async fn set_tags(
+    State(state): State<AppState>,
+    Path(conversation_id): Path<Uuid>,
+    jar: CookieJar,
+    Json(body): Json<TagsBody>,
+) -> Result<Json<TagsResponse>, AppError> {
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    owned_conversation(&state, conversation_id, user_id.as_deref()).await?;
+    let tags = normalize_tags(&body.tags)?;
+    if tags.len() > MAX_TAGS {
+        return Err(AppError::BadRequest(format!(
+            "a conversation can have at most {MAX_TAGS} tags"
+        )));
+    }
+    state
+        .db
+        .replace_conversation_tags(conversation_id, &tags)
+        .await?;
+    tags_response(&state, conversation_id).await
+}
+
+/// Adds tags, keeping the ones already assigned.
+pub async fn add_tags(
+    State(state): State<AppState>,
+    Path(conversation_id): Path<Uuid>,
+    jar: CookieJar,
+    Json(body): Json<TagsBody>,
+) -> Result<Json<TagsResponse>, AppError> {
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    owned_conversation(&state, conversation_id, user_id.as_deref()).await?;
+    let mut tags = state.db.conversation_tags(conversation_id).await?;
+    let new_tags = normalize_tags(&body.tags)?;
+    tags.extend(new_tags.iter().cloned());
+    tags.sort();
+    tags.dedup();
+    if tags.len() > MAX_TAGS {
+        return Err(AppError::BadRequest(format!(
+            "a conversation can have at most {MAX_TAGS} tags"
+        )));
+    }
+    state
+        .db
+        .add_conversation_tags(conversation_id, &new_tags)
+        .await?;
+    tags_response(&state, conversation_id).await
+}
+
+pub async fn remove_tag(
+    State(state): State<AppState>,
+    Path((conversation_id, tag)): Path<(Uuid, String)>,
+    jar: CookieJar,
+) -> Result<Json<TagsResponse>, AppError> {
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    owned_conversation(&state, conversation_id, user_id.as_deref()).await?;
+    let tag = normalize_tag(&tag)?;
+    state
+        .db
+        .remove_conversation_tag(conversation_id, &tag)
+        .await?;
+    tags_response(&state, conversation_id).await
+}
+
+#[derive(Debug, Deserialize, Default, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Markdown,
+    Pdf,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ExportQuery {
+    #[serde(default)]
+    pub format: ExportFormat,
+}
+
+/// Downloads the full transcript, including superseded answers, with model
+/// names, timestamps, policy hits and redaction markers.
+pub async fn export_conversation(
+    State(state): State<AppState>,
+    Path(conversation_id): Path<Uuid>,
+    jar: CookieJar,
+    Query(query): Query<ExportQuery>,
+) -> Result<Response, AppError> {
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    let conversation = owned_conversation(&state, conversation_id, user_id.as_deref()).await?;
+    let transcript = Transcript {
+        conversation,
+        tags: state.db.conversation_tags(conversation_id).await?,
+        messages: state.db.conversation_messages(conversation_id).await?,
+        policy_hits: state.db.conversation_policy_hits(conversation_id).await?,
+    };
+
+    let (content_type, extension, body) = match query.format {
+        ExportFormat::Markdown => (
+            "text/markdown; charset=utf-8",
+            "md",
+            export::to_markdown(&transcript).into_bytes(),
+        ),
+        ExportFormat::Pdf => ("application/pdf", "pdf", export::to_pdf(&transcript)),
+    };
+    let disposition =
+        format!("attachment; filename=\"conversation-{conversation_id}.{extension}\"");
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response())
+}
+
+fn list_item(row: ConversationListRow) -> ConversationListItem {
+    let mut tags: Vec<String> = row
+        .tags
+        .as_deref()
+        .map(|t| t.split(',').map(str::to_string).collect())
+        .unwrap_or_default();
+    tags.sort();
+    ConversationListItem {
+        id: row.id,
+        title: row.title,
+        created_at: row.created_at,
+        pinned: row.pinned,
+        starred: row.starred,
+        sort_order: row.sort_order,
+        tags,
+    }
+}
+
+async fn tags_response(
+    state: &AppState,
+    conversation_id: Uuid,
+) -> Result<Json<TagsResponse>, AppError> {
+    let tags = state.db.conversation_tags(conversation_id).await?;
+    Ok(Json(TagsResponse {
+        conversation_id,
+        tags,
+    }))
+}
+
+/// Loads a conversation if it belongs to the caller. Someone else's conversation
+/// reports as missing rather than forbidden so ids can't be probed.
+pub(crate) async fn owned_conversation(
+    state: &AppState,
+    conversation_id: Uuid,
+    user_id: Option<&str>,
+) -> Result<ConversationRecord, AppError> {
+    state
+        .db
+        .conversation(conversation_id)
+        .await?
+        .filter(|c| c.user_id.as_deref() == user_id)
+        .ok_or_else(|| AppError::BadRequest(format!("conversation {conversation_id} not found")))
+}
+
+/// Tags are lowercased and limited to letters, digits, `-`, `_`, `.` and `:`.
+pub(crate) fn normalize_tag(raw: &str) -> Result<String, AppError> {
+    let tag = raw.trim().to_lowercase();
+    if tag.is_empty() || tag.chars().count() > MAX_TAG_LEN {
+        return Err(AppError::BadRequest(format!(
+            "tags must be 1-{MAX_TAG_LEN} characters"
+        )));
+    }
+    if !tag
+        .chars()
+        .all(|c| c.is_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+    {
+        return Err(AppError::BadRequest(format!(
+            "tag '{tag}' may only contain letters, digits, '-', '_', '.' and ':'"
+        )));
+    }
+    Ok(tag)
+}
+
+fn normalize_tags(raw: &[String]) -> Result<Vec<String>, AppError> {
+    let mut tags = raw
+        .iter()
+        .map(|t| normalize_tag(t))
+        .collect::<Result<Vec<_>, _>>()?;
+    tags.sort();
+    tags.dedup();
+    Ok(tags)
+}
@@ -0,0 +1,81 @@
+use axum::{
+    Json,
+    extract::{Path, State},
+};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::Utc;
+use serde::Deserialize;
+
+use crate::{AppError, AppState, auth::validate_token, db::MessageFeedback};
+
+const MAX_CATEGORY_LEN: usize = 64;
+const MAX_COMMENT_LEN: usize = 2000;
+
+#[derive(Debug, Deserialize)]
+pub struct FeedbackBody {
+    /// 1 (bad) to 5 (great).
+    pub rating: i64,
+    pub category: Option<String>,
+    pub comment: Option<String>,
+}
+
+pub async fn submit_feedback(
+    State(state): State<AppState>,
+    Path(message_id): Path<String>,
+    jar: CookieJar,
+    Json(body): Json<FeedbackBody>,
+) -> Result<Json<MessageFeedback>, AppError> {
+    if !(1..=5).contains(&body.rating) {
+        return Err(AppError::BadRequest(
+            "rating must be between 1 and 5".into(),
+        ));
+    }
+    let category = body
+        .category
+        .map(|c| c.trim().to_lowercase())
+        .filter(|c| !c.is_empty());
+    if category
+        .as_ref()
+        .is_some_and(|c| c.len() > MAX_CATEGORY_LEN)
+    {
+        return Err(AppError::BadRequest(format!(
+            "category must be at most {MAX_CATEGORY_LEN} characters"
+        )));
+    }
+    let comment = body
+        .comment
+        .map(|c| c.trim().to_string())
+        .filter(|c| !c.is_empty());
+    if comment
+        .as_ref()
+        .is_some_and(|c| c.chars().count() > MAX_COMMENT_LEN)
+    {
+        return Err(AppError::BadRequest(format!(
+            "comment must be at most {MAX_COMMENT_LEN} characters"
+        )));
+    }
+
+    let user_id = validate_token(&state.config.load(), &jar).map(|c| c.sub);
+    let message = state
+        .db
+        .message(&message_id)
+        .await?
+        .filter(|m| m.user_id == user_id)
+        .ok_or_else(|| AppError::BadRequest(format!("message {message_id} not found")))?;
+    if message.role != "assistant" {
+        return Err(AppError::BadRequest(
+            "feedback can only be left on assistant messages".into(),
+        ));
+    }
+
+    let feedback = MessageFeedback {
+        message_id: message.id,
+        user_id,
+        rating: body.rating,
+        category,
+        comment,
+        created_at: Utc::now().to_rfc3339(),
+    };
+    state.db.save_feedback(&feedback).await?;
+    Ok(Json(feedback))
+}
@@ -1 +1,4 @@
 pub mod chat;
+pub mod collections;
+pub mod conversations;
+pub mod messages;
@@ -0,0 +1,304 @@
+//! Secrets given as references instead of plaintext values:
+//!
+//! - `file:/run/secrets/openai` reads a mounted file,
+//! - `vault:secret/data/ractochat#openai_api_key` reads a HashiCorp Vault KV
+//!   secret (v1 or v2) field,
+//! - `aws-sm:<secret id or ARN>[#field]` reads an AWS Secrets Manager secret,
+//!   optionally picking one key out of a JSON secret.
+//!
+//! References are resolved when the configuration loads and re-read every
+//! `SECRETS_REFRESH_SECS` so rotations reach the running process.
+
+use crate::error::AppError;
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use reqwest::Url;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fs, time::Duration};
+
+/// Settings that may be references rather than values.
+pub const SECRET_VARS: &[&str] = &[
+    "OPENAI_API_KEY",
+    "OPENAI_API_KEY_SECONDARY",
+    "ANTHROPIC_API_KEY",
+    "ANTHROPIC_API_KEY_SECONDARY",
+    "JWT_SECRET",
+    "AUDIT_HMAC_KEY",
+    "AUDIT_HMAC_KEY_PREVIOUS",
+    "KEY_ENCRYPTION_KEY",
+    "KEY_ENCRYPTION_KEY_PREVIOUS",
+];
+
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where `vault:` and `aws-sm:` references are read from.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SecretBackends {
+    pub vault_addr: Option<String>,
+    pub vault_token: Option<String>,
+    pub vault_namespace: Option<String>,
+    pub aws_region: Option<String>,
+    /// Overrides the regional Secrets Manager endpoint, e.g. for a VPC endpoint.
+    pub aws_endpoint: Option<String>,
+    pub aws_access_key_id: Option<String>,
+    pub aws_secret_access_key: Option<String>,
+    pub aws_session_token: Option<String>,
+}
+
+enum SecretRef<'a> {
+    File(&'a str),
+    Vault { path: &'a str, field: &'a str },
+    Aws { id: &'a str, field: Option<&'a str> },
+}
+
+impl<'a> SecretRef<'a> {
+    fn parse(raw: &'a str) -> Option<Self> {
+        let raw = raw.trim();
+        if let Some(path) = raw.strip_prefix("file:") {
+            return Some(SecretRef::File(path));
+        }
+        if let Some(rest) = raw.strip_prefix("vault:") {
+            let (path, field) = rest.rsplit_once('#').unwrap_or((rest, ""));
+            return Some(SecretRef::Vault { path, field });
+        }
+        if let Some(rest) = raw.strip_prefix("aws-sm:") {
+            let (id, field) = match rest.rsplit_once('#') {
+                Some((id, field)) => (id, Some(field)),
+                None => (rest, None),
+            };
+            return Some(SecretRef::Aws { id, field });
+        }
+        None
+    }
+}
+
+/// Whether a setting's value is a secret reference to resolve.
+pub fn is_reference(value: &str) -> bool {
+    SecretRef::parse(value).is_some()
+}
+
+/// Resolves every reference, keyed like `refs`. Any failure fails the whole
+/// call so a half-rotated set of secrets is never applied.
+pub async fn resolve(
+    refs: &BTreeMap<&'static str, String>,
+    backends: &SecretBackends,
+) -> Result<BTreeMap<&'static str, String>, AppError> {
+    let mut values = BTreeMap::new();
+    if refs.is_empty() {
+        return Ok(values);
+    }
+    let http = reqwest::Client::builder()
+        .timeout(FETCH_TIMEOUT)
+        .build()
+        .map_err(|e| AppError::Internal(format!("failed to build secrets client: {e}")))?;
+    for (&var, raw) in refs {
+        let value = match SecretRef::parse(raw) {
+            Some(SecretRef::File(path)) => read_file(path),
+            Some(SecretRef::Vault { path, field }) => {
+                read_vault(&http, backends, path, field).await
+            }
+            Some(SecretRef::Aws { id, field }) => read_aws(&http, backends, id, field).await,
+            None => Err("not a secret reference".into()),
+        };
+        let value = value.map_err(|e| {
+            AppError::Config(format!("cannot resolve {var} from {}: {e}", raw.trim()))
+        })?;
+        if value.is_empty() {
+            return Err(AppError::Config(format!(
+                "{var} resolved to an empty value from {}",
+                raw.trim()
+            )));
+        }
+        values.insert(var, value);
+    }
+    Ok(values)
+}
+
+fn read_file(path: &str) -> Result<String, String> {
+    fs::read_to_string(path)
+        .map(|s| s.trim_end_matches(['\r', '\n']).to_string())
+        .map_err(|e| e.to_string())
+}
+
+async fn read_vault(
+    http: &reqwest::Client,
+    backends: &SecretBackends,
+    path: &str,
+    field: &str,
+) -> Result<String, String> {
+    if field.is_empty() {
+        return Err("vault references need a field, e.g. vault:secret/data/app#key".into());
+    }
+    let addr = backends
+        .vault_addr
+        .as_deref()
+        .ok_or("VAULT_ADDR is not set")?;
+    let token = backends
+        .vault_token
+        .as_deref()
+        .ok_or("VAULT_TOKEN is not set")?;
+    let url = format!(
+        "{}/v1/{}",
+        addr.trim_end_matches('/'),
+        path.trim_start_matches('/')
+    );
+    let mut req = http.get(url).header("X-Vault-Token", token);
+    if let Some(namespace) = &backends.vault_namespace {
+        req = req.header("X-Vault-Namespace", namespace);
+    }
+    let resp = req.send().await.map_err(|e| e.to_string())?;
+    let status = resp.status();
+    if !status.is_success() {
+        return Err(format!("Vault returned {status}"));
+    }
+    let body: Value = resp.json().await.map_err(|e| e.to_string())?;
+    let data = body.get("data").ok_or("Vault response has no data")?;
+    // KV v2 nests the secret one level further down.
+    let data = data.get("data").filter(|d| d.is_object()).unwrap_or(data);
+    data.get(field)
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .ok_or_else(|| format!("field {field} not found"))
+}
+
+async fn read_aws(
+    http: &reqwest::Client,
+    backends: &SecretBackends,
+    id: &str,
+    field: Option<&str>,
+) -> Result<String, String> {
+    // ARNs carry their region: arn:aws:secretsmanager:<region>:<account>:secret:<name>
+    let region = id
+        .strip_prefix("arn:")
+        .and_then(|arn| arn.split(':').nth(2))
+        .filter(|r| !r.is_empty())
+        .or(backends.aws_region.as_deref())
+        .ok_or("AWS_REGION is not set")?;
+    let access_key = backends
+        .aws_access_key_id
+        .as_deref()
+        .ok_or("AWS_ACCESS_KEY_ID is not set")?;
+    let secret_key = backends
+        .aws_secret_access_key
+        .as_deref()
+        .ok_or("AWS_SECRET_ACCESS_KEY is not set")?;
+    let endpoint = backends
+        .aws_endpoint
+        .clone()
+        .unwrap_or_else(|| format!("https://secretsmanager.{region}.amazonaws.com"));
+    let url = Url::parse(&endpoint).map_err(|e| format!("invalid endpoint {endpoint}: {e}"))?;
+    let host = match (url.host_str(), url.port()) {
+        (Some(host), Some(port)) => format!("{host}:{port}"),
+        (Some(host), None) => host.to_string(),
+        (None, _) => return Err(format!("invalid endpoint {endpoint}: no host")),
+    };
+
+    let body = serde_json::json!({ "SecretId": id }).to_string();
+    let now = Utc::now();
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let mut headers = vec![
+        ("content-type", "application/x-amz-json-1.1".to_string()),
+        ("host", host),
+        ("x-amz-date", amz_date.clone()),
+        ("x-amz-target", "secretsmanager.GetSecretValue".to_string()),
+    ];
+    if let Some(token) = &backends.aws_session_token {
+        headers.push(("x-amz-security-token", token.clone()));
+    }
+    headers.sort_by_key(|(name, _)| *name);
+    let authorization = sigv4_authorization(
+        &SigningInput {
+            access_key,
+            secret_key,
+            region,
+            service: "secretsmanager",
+            amz_date: &amz_date,
+        },
+        &headers,
+        &body,
+    );
+
+    let mut req = http
+        .post(url)
+        .header("Authorization", authorization)
+        .body(body);
+    for (name, value) in &headers {
+        if *name != "host" {
+            req = req.header(*name, value);
+        }
+    }
+    let resp = req.send().await.map_err(|e| e.to_string())?;
+    let status = resp.status();
+    let payload: Value = resp.json().await.unwrap_or(Value::Null);
+    if !status.is_success() {
+        let message = payload
+            .get("message")
+            .or_else(|| payload.get("Message"))
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        return Err(match message {
+            "" => format!("Secrets Manager returned {status}"),
+            message => format!("Secrets Manager returned {status}: {message}"),
+        });
+    }
+    let secret = payload
+        .get("SecretString")
+        .and_then(Value::as_str)
+        .ok_or("secret has no SecretString (binary secrets are not supported)")?;
+    match field {
+        None => Ok(secret.to_string()),
+        Some(field) => serde_json::from_str::<Value>(secret)
+            .ok()
+            .and_then(|json| json.get(field).and_then(Value::as_str).map(str::to_string))
+            .ok_or_else(|| format!("field {field} not found in the JSON secret")),
+    }
+}
+
+struct SigningInput<'a> {
+    access_key: &'a str,
+    secret_key: &'a str,
+    region: &'a str,
+    service: &'a str,
+    amz_date: &'a str,
+}
+
+/// AWS Signature Version 4 for a POST to `/` with no query string. `headers`
+/// must be lowercase and sorted by name.
+fn sigv4_authorization(input: &SigningInput, headers: &[(&str, String)], body: &str) -> String {
+    let date = &input.amz_date[..8];
+    let signed_headers = headers
+        .iter()
+        .map(|(name, _)| *name)
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_headers: String = headers
+        .iter()
+        .map(|(name, value)| format!("{name}:{}\n", value.trim()))
+        .collect();
+    let canonical_request = format!(
+        "POST\n/\n\n{canonical_headers}\n{signed_headers}\n{}",
+        hex::encode(Sha256::digest(body))
+    );
+    let scope = format!("{date}/{}/{}/aws4_request", input.region, input.service);
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{scope}\n{}",
+        input.amz_date,
+        hex::encode(Sha256::digest(&canonical_request))
+    );
+    let key = [input.region, input.service, "aws4_request"].iter().fold(
+        hmac_sha256(format!("AWS4{}", input.secret_key).as_bytes(), date),
+        |key, part| hmac_sha256(&key, part),
+    );
+    format!(
+        "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={}",
+        input.access_key,
+        hex::encode(hmac_sha256(&key, &string_to_sign))
+    )
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
@@ -0,0 +1,319 @@
+use crate::{config::Config, error::AppError};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::warn;
+
+/// State that has to agree across gateway replicas: rate limit windows, usage
+/// tallies over the trailing 24 hours and router health. Backed by Redis when
+/// `REDIS_URL` is set and the `redis` feature is compiled in; otherwise it
+/// stays process-local.
+#[derive(Clone)]
+pub enum SharedStore {
+    Local(LocalStore),
+    #[cfg(feature = "redis")]
+    Redis(RedisStore),
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct DailyTally {
+    pub requests: u64,
+    pub tokens: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SharedHealth {
+    pub last_ok: bool,
+    pub last_latency_ms: Option<u128>,
+    pub successes: u64,
+    pub failures: u64,
+    pub updated_at: Option<SystemTime>,
+}
+
+impl SharedStore {
+    pub async fn connect(config: &Config) -> Self {
+        let Some(url) = config.redis_url.as_deref() else {
+            return Self::Local(LocalStore::default());
+        };
+
+        #[cfg(feature = "redis")]
+        {
+            match RedisStore::connect(url).await {
+                Ok(store) => {
+                    tracing::info!("shared state backed by redis");
+                    return Self::Redis(store);
+                }
+                Err(e) => warn!("redis unavailable ({e}); falling back to in-memory state"),
+            }
+        }
+        #[cfg(not(feature = "redis"))]
+        warn!("REDIS_URL ({url}) set but built without the `redis` feature; using in-memory state");
+
+        Self::Local(LocalStore::default())
+    }
+
+    pub fn is_distributed(&self) -> bool {
+        !matches!(self, Self::Local(_))
+    }
+
+    /// Counts a hit in the current fixed window for `key` and returns the total
+    /// so far, including this one.
+    pub async fn hit_window(&self, key: &str, window: Duration) -> Result<u64, AppError> {
+        match self {
+            Self::Local(store) => Ok(store.hit_window(key, window)),
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.hit_window(key, window).await,
+        }
+    }
+
+    /// Adds `requests` and `tokens` to the account's tally for the current hour.
+    /// The local store keeps no tally; the in-memory counters are the source of
+    /// truth there.
+    #[cfg_attr(not(feature = "redis"), allow(unused_variables))]
+    pub async fn add_daily_usage(
+        &self,
+        account: &str,
+        requests: u64,
+        tokens: u64,
+    ) -> Result<(), AppError> {
+        match self {
+            Self::Local(_) => Ok(()),
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.add_daily_usage(account, requests, tokens).await,
+        }
+    }
+
+    /// Clears the account's tally for the trailing 24 hours.
+    #[cfg_attr(not(feature = "redis"), allow(unused_variables))]
+    pub async fn reset_daily_usage(&self, account: &str) -> Result<(), AppError> {
+        match self {
+            Self::Local(_) => Ok(()),
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.reset_daily_usage(account).await,
+        }
+    }
+
+    /// The account's usage over the trailing 24 hours, summed from hourly
+    /// buckets like the in-memory counters.
+    #[cfg_attr(not(feature = "redis"), allow(unused_variables))]
+    pub async fn daily_usage(&self, account: &str) -> Result<Option<DailyTally>, AppError> {
+        match self {
+            Self::Local(_) => Ok(None),
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.daily_usage(account).await.map(Some),
+        }
+    }
+
+    #[cfg_attr(not(feature = "redis"), allow(unused_variables))]
+    pub async fn record_health(
+        &self,
+        model: &str,
+        ok: bool,
+        latency_ms: u128,
+    ) -> Result<(), AppError> {
+        match self {
+            // The catalog already keeps process-local health.
+            Self::Local(_) => Ok(()),
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.record_health(model, ok, latency_ms).await,
+        }
+    }
+
+    #[cfg_attr(not(feature = "redis"), allow(unused_variables))]
+    pub async fn health(&self, models: &[String]) -> Result<Vec<(String, SharedHealth)>, AppError> {
+        match self {
+            Self::Local(_) => Ok(Vec::new()),
+            #[cfg(feature = "redis")]
+            Self::Redis(store) => store.health(models).await,
+        }
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct LocalStore {
+    windows: Arc<Mutex<HashMap<String, (Instant, u64)>>>,
+}
+
+impl LocalStore {
+    fn hit_window(&self, key: &str, window: Duration) -> u64 {
+        let mut windows = self.windows.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        windows.retain(|_, (started, _)| now.duration_since(*started) < window);
+        let slot = windows.entry(key.to_string()).or_insert((now, 0));
+        slot.1 += 1;
+        slot.1
+    }
+}
+
+#[cfg(feature = "redis")]
+pub use redis_store::RedisStore;
+
+#[cfg(feature = "redis")]
+mod redis_store {
+    use super::{DailyTally, SharedHealth};
+    use crate::{error::AppError, quota::WINDOW_HOURS};
+    use redis::{AsyncCommands, aio::ConnectionManager};
+    use std::{
+        collections::HashMap,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    const PREFIX: &str = "ractochat";
+
+    #[derive(Clone)]
+    pub struct RedisStore {
+        conn: ConnectionManager,
+    }
+
+    impl RedisStore {
+        pub async fn connect(url: &str) -> Result<Self, AppError> {
+            let client = redis::Client::open(url).map_err(map_redis_err)?;
+            let conn = ConnectionManager::new(client)
+                .await
+                .map_err(map_redis_err)?;
+            Ok(Self { conn })
+        }
+
+        pub async fn hit_window(&self, key: &str, window: Duration) -> Result<u64, AppError> {
+            let secs = window.as_secs().max(1);
+            let bucket = unix_secs(SystemTime::now()) / secs;
+            let key = format!("{PREFIX}:rl:{key}:{bucket}");
+            let mut conn = self.conn.clone();
+            let (count,): (u64,) = redis::pipe()
+                .atomic()
+                .incr(&key, 1)
+                .expire(&key, secs as i64)
+                .ignore()
+                .query_async(&mut conn)
+                .await
+                .map_err(map_redis_err)?;
+            Ok(count)
+        }
+
+        pub async fn add_daily_usage(
+            &self,
+            account: &str,
+            requests: u64,
+            tokens: u64,
+        ) -> Result<(), AppError> {
+            let key = usage_key(account, current_hour());
+            let mut conn = self.conn.clone();
+            redis::pipe()
+                .atomic()
+                .hincr(&key, "requests", requests)
+                .ignore()
+                .hincr(&key, "tokens", tokens)
+                .ignore()
+                .expire(&key, (WINDOW_HOURS as i64 + 1) * 3600)
+                .ignore()
+                .query_async::<_, ()>(&mut conn)
+                .await
+                .map_err(map_redis_err)
+        }
+
+        pub async fn reset_daily_usage(&self, account: &str) -> Result<(), AppError> {
+            let mut conn = self.conn.clone();
+            conn.del::<_, ()>(window_keys(account))
+                .await
+                .map_err(map_redis_err)
+        }
+
+        pub async fn daily_usage(&self, account: &str) -> Result<DailyTally, AppError> {
+            let mut pipe = redis::pipe();
+            for key in window_keys(account) {
+                pipe.hgetall(key);
+            }
+            let mut conn = self.conn.clone();
+            let buckets: Vec<HashMap<String, u64>> =
+                pipe.query_async(&mut conn).await.map_err(map_redis_err)?;
+            Ok(buckets
+                .iter()
+                .fold(DailyTally::default(), |acc, fields| DailyTally {
+                    requests: acc.requests + fields.get("requests").copied().unwrap_or(0),
+                    tokens: acc.tokens + fields.get("tokens").copied().unwrap_or(0),
+                }))
+        }
+
+        pub async fn record_health(
+            &self,
+            model: &str,
+            ok: bool,
+            latency_ms: u128,
+        ) -> Result<(), AppError> {
+            let key = format!("{PREFIX}:health:{model}");
+            let counter = if ok { "successes" } else { "failures" };
+            let mut conn = self.conn.clone();
+            redis::pipe()
+                .atomic()
+                .hincr(&key, counter, 1)
+                .ignore()
+                .hset(&key, "last_ok", ok as u8)
+                .ignore()
+                .hset(&key, "last_latency_ms", latency_ms as u64)
+                .ignore()
+                .hset(&key, "updated_at", unix_secs(SystemTime::now()))
+                .ignore()
+                .query_async::<_, ()>(&mut conn)
+                .await
+                .map_err(map_redis_err)
+        }
+
+        pub async fn health(
+            &self,
+            models: &[String],
+        ) -> Result<Vec<(String, SharedHealth)>, AppError> {
+            let mut conn = self.conn.clone();
+            let mut out = Vec::new();
+            for model in models {
+                let fields: HashMap<String, u64> = conn
+                    .hgetall(format!("{PREFIX}:health:{model}"))
+                    .await
+                    .map_err(map_redis_err)?;
+                if fields.is_empty() {
+                    continue;
+                }
+                out.push((
+                    model.clone(),
+                    SharedHealth {
+                        last_ok: fields.get("last_ok").copied().unwrap_or(0) == 1,
+                        last_latency_ms: fields.get("last_latency_ms").map(|v| *v as u128),
+                        successes: fields.get("successes").copied().unwrap_or(0),
+                        failures: fields.get("failures").copied().unwrap_or(0),
+                        updated_at: fields
+                            .get("updated_at")
+                            .map(|s| UNIX_EPOCH + Duration::from_secs(*s)),
+                    },
+                ));
+            }
+            Ok(out)
+        }
+    }
+
+    fn current_hour() -> u64 {
+        unix_secs(SystemTime::now()) / 3600
+    }
+
+    fn usage_key(account: &str, hour: u64) -> String {
+        format!("{PREFIX}:usage:{account}:{hour}")
+    }
+
+    /// The buckets of the trailing 24 hours, the current one first.
+    fn window_keys(account: &str) -> Vec<String> {
+        let now = current_hour();
+        (0..WINDOW_HOURS as u64)
+            .map(|ago| usage_key(account, now - ago))
+            .collect()
+    }
+
+    fn unix_secs(t: SystemTime) -> u64 {
+        t.duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0)
+    }
+
+    fn map_redis_err(e: redis::RedisError) -> AppError {
+        AppError::Internal(format!("redis error: {e}"))
+    }
+}
@@ -0,0 +1,174 @@
+//! Startup progress. The server listens before migrations run, so liveness
+//! checks pass during a long upgrade, but reports ready only once every stage
+//! here has finished.
+
+use crate::llm::LlmService;
+use futures_util::future::join_all;
+use serde::Serialize;
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::{info, warn};
+
+pub const MIGRATIONS: &str = "migrations";
+pub const ROUTER_STATE: &str = "router_state";
+pub const PROVIDERS: &str = "providers";
+
+/// Between rounds of provider checks.
+const PROBE_RETRY: Duration = Duration::from_secs(2);
+/// For a single provider check.
+const PROBE_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StageState {
+    Pending,
+    Running,
+    Done,
+    Skipped,
+    /// Finished with problems the replica can serve through, such as a
+    /// provider that never answered.
+    Degraded,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Stage {
+    pub name: &'static str,
+    pub state: StageState,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub duration_ms: Option<u64>,
+    #[serde(skip)]
+    started: Option<Instant>,
+}
+
+#[derive(Clone)]
+pub struct Startup {
+    stages: Arc<Mutex<Vec<Stage>>>,
+}
+
+impl Startup {
+    pub fn new() -> Self {
+        let stages = [MIGRATIONS, ROUTER_STATE, PROVIDERS]
+            .into_iter()
+            .map(|name| Stage {
+                name,
+                state: StageState::Pending,
+                detail: None,
+                duration_ms: None,
+                started: None,
+            })
+            .collect();
+        Self {
+            stages: Arc::new(Mutex::new(stages)),
+        }
+    }
+
+    pub fn begin(&self, name: &str) {
+        self.update(name, |stage| {
+            stage.state = StageState::Running;
+            stage.started = Some(Instant::now());
+        });
+    }
+
+    /// Progress notes on a running stage, e.g. which provider is not answering.
+    pub fn note(&self, name: &str, detail: String) {
+        self.update(name, |stage| stage.detail = Some(detail));
+    }
+
+    pub fn finish(&self, name: &str, state: StageState, detail: Option<String>) {
+        self.update(name, |stage| {
+            stage.state = state;
+            stage.detail = detail;
+            stage.duration_ms = stage.started.map(|t| t.elapsed().as_millis() as u64);
+        });
+    }
+
+    /// Every stage done, skipped or degraded.
+    pub fn is_complete(&self) -> bool {
+        self.lock().iter().all(|stage| {
+            matches!(
+                stage.state,
+                StageState::Done | StageState::Skipped | StageState::Degraded
+            )
+        })
+    }
+
+    pub fn stages(&self) -> Vec<Stage> {
+        self.lock().clone()
+    }
+
+    fn update(&self, name: &str, apply: impl FnOnce(&mut Stage)) {
+        if let Some(stage) = self.lock().iter_mut().find(|stage| stage.name == name) {
+            apply(stage);
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Stage>> {
+        self.stages.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+impl Default for Startup {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Asks every provider with a key for its model list, retrying the ones that
+/// fail until `within` has passed. Providers still failing then leave the
+/// stage degraded rather than holding the replica back: if an outage kept
+/// every replica unready, nothing would be served at all, not even the
+/// models of the providers that are up.
+pub async fn probe_providers(startup: &Startup, llm: &LlmService, within: Duration) {
+    let mut pending = llm.remote_providers();
+    if within.is_zero() || pending.is_empty() {
+        let why = if within.is_zero() {
+            "disabled by STARTUP_PROBE_SECS=0"
+        } else {
+            "no remote providers configured"
+        };
+        startup.finish(PROVIDERS, StageState::Skipped, Some(why.into()));
+        return;
+    }
+    startup.begin(PROVIDERS);
+    let deadline = Instant::now() + within;
+    loop {
+        let results = join_all(pending.iter().map(|&provider| async move {
+            let result = match tokio::time::timeout(PROBE_TIMEOUT, llm.list_models(provider)).await
+            {
+                Ok(Ok(_)) => Ok(()),
+                Ok(Err(e)) => Err(e.to_string()),
+                Err(_) => Err("timed out".to_string()),
+            };
+            (provider, result)
+        }))
+        .await;
+        let mut failures = Vec::new();
+        pending.clear();
+        for (provider, result) in results {
+            match result {
+                Ok(()) => info!("provider {provider} is reachable"),
+                Err(e) => {
+                    failures.push(format!("{provider}: {e}"));
+                    pending.push(provider);
+                }
+            }
+        }
+        if pending.is_empty() {
+            startup.finish(PROVIDERS, StageState::Done, None);
+            return;
+        }
+        let left = deadline.saturating_duration_since(Instant::now());
+        if left.is_zero() {
+            let failures = failures.join("; ");
+            warn!("starting with unreachable providers: {failures}");
+            startup.finish(PROVIDERS, StageState::Degraded, Some(failures));
+            return;
+        }
+        startup.note(PROVIDERS, failures.join("; "));
+        tokio::time::sleep(PROBE_RETRY.min(left)).await;
+    }
+}
@@ -0,0 +1,361 @@
+//! Declarative export/import of the admin-managed router state (catalog,
+//! aliases, fallbacks, accounts and policies) as a single YAML document.
+
+use crate::{
+    AppState,
+    admin::validate_account,
+    db::{Db, StateWrite},
+    error::AppError,
+    governance::{Policy, PolicyUpsert},
+    model_router::{
+        AccountAccess, AliasTarget, CatalogDefinitions, CatalogEntry, normalize_region,
+    },
+    routes::chat::provider_from_str,
+};
+use axum::{
+    Json,
+    extract::{Query, State},
+    http::header,
+    response::IntoResponse,
+};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use uuid::Uuid;
+
+const STATE_VERSION: u32 = 1;
+
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct StateDocument {
+    pub version: u32,
+    /// Keyed by catalog key, as in the database.
+    #[serde(default)]
+    pub models: BTreeMap<String, CatalogEntry>,
+    #[serde(default)]
+    pub aliases: BTreeMap<String, Vec<AliasTarget>>,
+    #[serde(default)]
+    pub fallbacks: BTreeMap<String, Vec<String>>,
+    #[serde(default)]
+    pub accounts: Vec<AccountAccess>,
+    #[serde(default)]
+    pub policies: Vec<PolicyState>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct PolicyState {
+    /// Required so re-importing the same document updates rather than duplicates.
+    pub id: String,
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    pub match_type: String,
+    pub pattern: String,
+    pub action: String,
+    pub applies_to: String,
+    #[serde(default = "enabled_default")]
+    pub enabled: bool,
+}
+
+fn enabled_default() -> bool {
+    true
+}
+
+impl From<Policy> for PolicyState {
+    fn from(p: Policy) -> Self {
+        Self {
+            id: p.id,
+            name: p.name,
+            description: p.description,
+            match_type: p.match_type,
+            pattern: p.pattern,
+            action: p.action,
+            applies_to: p.applies_to,
+            enabled: p.enabled,
+        }
+    }
+}
+
+/// The stored state, read from the database so it reflects changes other
+/// replicas haven't synced yet.
+async fn current_state(db: &Db) -> Result<StateDocument, AppError> {
+    let defs = db.load_catalog().await?;
+    let mut accounts = db.load_accounts().await?;
+    accounts.sort_by(|a, b| a.id.cmp(&b.id));
+    let mut policies: Vec<PolicyState> = db
+        .list_policies()
+        .await?
+        .into_iter()
+        .map(PolicyState::from)
+        .collect();
+    policies.sort_by(|a, b| a.id.cmp(&b.id));
+    Ok(StateDocument {
+        version: STATE_VERSION,
+        models: defs.models,
+        aliases: defs.aliases,
+        fallbacks: defs.fallbacks,
+        accounts,
+        policies,
+    })
+}
+
+/// The stored state as a YAML state document.
+pub async fn export_yaml(db: &Db) -> Result<String, AppError> {
+    let doc = current_state(db).await?;
+    serde_yaml::to_string(&doc)
+        .map_err(|e| AppError::Internal(format!("failed to encode state: {e}")))
+}
+
+pub async fn export_state(State(state): State<AppState>) -> Result<impl IntoResponse, AppError> {
+    let yaml = export_yaml(&state.db).await?;
+    Ok(([(header::CONTENT_TYPE, "application/yaml")], yaml))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ImportStateQuery {
+    /// Delete models, aliases, fallbacks, accounts and policies missing from
+    /// the document.
+    #[serde(default)]
+    pub prune: bool,
+    /// Report what would change without writing anything.
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct SectionChanges {
+    pub created: Vec<String>,
+    pub updated: Vec<String>,
+    pub deleted: Vec<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ImportStateResponse {
+    pub dry_run: bool,
+    pub models: SectionChanges,
+    pub aliases: SectionChanges,
+    pub fallbacks: SectionChanges,
+    pub accounts: SectionChanges,
+    pub policies: SectionChanges,
+    pub warnings: Vec<String>,
+}
+
+/// Applies a state document in one transaction. The whole document is
+/// validated first; any problem rejects it without changing anything.
+pub async fn import_state(
+    State(state): State<AppState>,
+    Query(query): Query<ImportStateQuery>,
+    body: String,
+) -> Result<Json<ImportStateResponse>, AppError> {
+    let doc: StateDocument = serde_yaml::from_str(&body)
+        .map_err(|e| AppError::BadRequest(format!("invalid state document: {e}")))?;
+    let policies = validate(&doc)?;
+    let warnings = dangling_references(&doc);
+    let current = current_state(&state.db).await?;
+
+    let models = diff(&current.models, &doc.models, query.prune);
+    let aliases = diff(&current.aliases, &doc.aliases, query.prune);
+    let fallbacks = diff(&current.fallbacks, &doc.fallbacks, query.prune);
+    let accounts = diff(
+        &by_key(current.accounts, |a| a.id.clone()),
+        &by_key(doc.accounts.iter(), |a| a.id.clone()),
+        query.prune,
+    );
+    let policy_changes = diff(
+        &by_key(current.policies, |p| p.id.clone()),
+        &by_key(doc.policies.iter(), |p| p.id.clone()),
+        query.prune,
+    );
+
+    // Collections embed with a catalog model; pruning it would strand them.
+    for key in &models.deleted {
+        let entry = &current.models[key];
+        for model in [key, &entry.id] {
+            let collections = state.db.collections_using_model(model).await?;
+            if !collections.is_empty() {
+                return Err(AppError::BadRequest(format!(
+                    "model {key} is used by collection(s) {}; keep it in the document",
+                    collections.join(", ")
+                )));
+            }
+        }
+    }
+
+    if !query.dry_run {
+        state
+            .db
+            .apply_state(StateWrite {
+                accounts: &doc.accounts,
+                catalog: &CatalogDefinitions {
+                    models: doc.models,
+                    aliases: doc.aliases,
+                    fallbacks: doc.fallbacks,
+                },
+                policies: &policies,
+                delete_accounts: &accounts.deleted,
+                delete_models: &models.deleted,
+                delete_aliases: &aliases.deleted,
+                delete_fallbacks: &fallbacks.deleted,
+                delete_policies: &policy_changes.deleted,
+            })
+            .await?;
+        state.access.refresh_if_changed().await?;
+    }
+
+    Ok(Json(ImportStateResponse {
+        dry_run: query.dry_run,
+        models,
+        aliases,
+        fallbacks,
+        accounts,
+        policies: policy_changes,
+        warnings,
+    }))
+}
+
+/// Checks the document as a whole and returns the policies ready to write.
+fn validate(doc: &StateDocument) -> Result<Vec<PolicyUpsert>, AppError> {
+    if doc.version != STATE_VERSION {
+        return Err(AppError::BadRequest(format!(
+            "unsupported state version {}; expected {STATE_VERSION}",
+            doc.version
+        )));
+    }
+
+    for (key, entry) in &doc.models {
+        if key.trim().is_empty() || entry.id.trim().is_empty() {
+            return Err(AppError::BadRequest(
+                "model keys and ids must not be empty".into(),
+            ));
+        }
+        provider_from_str(&entry.provider).map_err(in_item(format!("model {key}")))?;
+        if normalize_region(entry.region.clone())? != entry.region {
+            return Err(AppError::BadRequest(format!(
+                "model {key} region must be lowercase and not blank"
+            )));
+        }
+    }
+    for (alias, targets) in &doc.aliases {
+        if targets.is_empty() || targets.iter().all(|t| t.weight == 0) {
+            return Err(AppError::BadRequest(format!(
+                "alias {alias} needs at least one target with a positive weight"
+            )));
+        }
+    }
+
+    let mut ids = HashSet::new();
+    let mut emails = HashSet::new();
+    for account in &doc.accounts {
+        validate_account(account).map_err(in_item(format!("account {}", account.id)))?;
+        if !ids.insert(account.id.as_str()) {
+            return Err(AppError::BadRequest(format!(
+                "account {} is listed twice",
+                account.id
+            )));
+        }
+        if !emails.insert(account.email.to_lowercase()) {
+            return Err(AppError::BadRequest(format!(
+                "email {} is used by more than one account",
+                account.email
+            )));
+        }
+    }
+
+    let mut policy_ids = HashSet::new();
+    doc.policies
+        .iter()
+        .map(|p| {
+            let id = Uuid::parse_str(&p.id)
+                .map_err(|_| AppError::BadRequest(format!("invalid policy id {}", p.id)))?;
+            if !policy_ids.insert(id) {
+                return Err(AppError::BadRequest(format!("policy {id} is listed twice")));
+            }
+            let upsert = PolicyUpsert {
+                id: Some(id),
+                name: p.name.clone(),
+                description: p.description.clone(),
+                match_type: p.match_type.clone(),
+                pattern: p.pattern.clone(),
+                action: p.action.clone(),
+                applies_to: p.applies_to.clone(),
+                enabled: p.enabled,
+            };
+            upsert.validate().map_err(in_item(format!("policy {id}")))?;
+            Ok(upsert)
+        })
+        .collect()
+}
+
+/// Alias targets and fallback entries that aren't in the catalog. The router
+/// skips them at request time, so they're reported rather than rejected.
+fn dangling_references(doc: &StateDocument) -> Vec<String> {
+    // Aliases and chains may name a model by catalog key or by provider id.
+    let known: HashSet<&str> = doc
+        .models
+        .iter()
+        .flat_map(|(key, entry)| [key.as_str(), entry.id.as_str()])
+        .collect();
+    let mut warnings = Vec::new();
+    for (alias, targets) in &doc.aliases {
+        for target in targets {
+            if !known.contains(target.model.as_str()) {
+                warnings.push(format!(
+                    "alias {alias} targets unknown model {}",
+                    target.model
+                ));
+            }
+        }
+    }
+    for (model, chain) in &doc.fallbacks {
+        for unknown in std::iter::once(model)
+            .chain(chain)
+            .filter(|m| !known.contains(m.as_str()))
+        {
+            warnings.push(format!(
+                "fallback chain for {model} names unknown model {unknown}"
+            ));
+        }
+    }
+    warnings
+}
+
+/// Prefixes a validation error with the item it came from.
+fn in_item(item: String) -> impl FnOnce(AppError) -> AppError {
+    move |e| match e {
+        AppError::BadRequest(msg) => AppError::BadRequest(format!("{item}: {msg}")),
+        other => other,
+    }
+}
+
+fn by_key<T: Serialize>(
+    items: impl IntoIterator<Item = T>,
+    key: impl Fn(&T) -> String,
+) -> BTreeMap<String, T> {
+    items.into_iter().map(|item| (key(&item), item)).collect()
+}
+
+/// Compares entries by their serialized form, so no type needs `PartialEq`.
+fn diff<A: Serialize, B: Serialize>(
+    current: &BTreeMap<String, A>,
+    desired: &BTreeMap<String, B>,
+    prune: bool,
+) -> SectionChanges {
+    let mut changes = SectionChanges::default();
+    for (key, wanted) in desired {
+        match current.get(key) {
+            None => changes.created.push(key.clone()),
+            Some(existing) => {
+                if serde_json::to_value(existing).ok() != serde_json::to_value(wanted).ok() {
+                    changes.updated.push(key.clone());
+                }
+            }
+        }
+    }
+    if prune {
+        changes.deleted = current
+            .keys()
+            .filter(|key| !desired.contains_key(*key))
+            .cloned()
+            .collect();
+    }
+    changes
+}
@@ -0,0 +1,65 @@
+//! Optional HTTPS termination for deployments without a reverse proxy. The
+//! certificate is re-read when its files change, so renewals apply without a
+//! restart.
+
+use crate::{
+    config::{self, SharedConfig},
+    error::AppError,
+};
+use axum_server::tls_rustls::RustlsConfig;
+use std::{path::Path, time::Duration};
+use tracing::{info, warn};
+
+/// How often the certificate and key files are checked for changes.
+const CERT_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// Loads the PEM certificate chain and private key the listener serves.
+pub async fn load(cert: &Path, key: &Path) -> Result<RustlsConfig, AppError> {
+    // Only the ring provider is compiled in; installing it twice is harmless.
+    let _ = rustls::crypto::ring::default_provider().install_default();
+    RustlsConfig::from_pem_file(cert, key).await.map_err(|e| {
+        AppError::Config(format!(
+            "cannot load TLS certificate {} / key {}: {e}",
+            cert.display(),
+            key.display()
+        ))
+    })
+}
+
+/// Swaps in the certificate when its files change or a reload points
+/// `TLS_CERT`/`TLS_KEY` elsewhere. A certificate that fails to load is
+/// reported and the current one stays in use.
+pub fn spawn_cert_reload(config: SharedConfig, tls: RustlsConfig) {
+    let fingerprint = |config: &SharedConfig| {
+        let config = config.load();
+        let cert = config.tls_cert.clone()?;
+        let key = config.tls_key.clone()?;
+        let stamps = (
+            config::file_fingerprint(&cert),
+            config::file_fingerprint(&key),
+        );
+        Some((cert, key, stamps))
+    };
+    tokio::spawn(async move {
+        let mut seen = fingerprint(&config);
+        let mut tick = tokio::time::interval(CERT_CHECK_INTERVAL);
+        tick.tick().await;
+        loop {
+            tick.tick().await;
+            let current = fingerprint(&config);
+            if current == seen {
+                continue;
+            }
+            seen = current;
+            let Some((cert, key, _)) = &seen else {
+                continue;
+            };
+            match tls.reload_from_pem_file(cert, key).await {
+                Ok(()) => info!("reloaded TLS certificate {}", cert.display()),
+                Err(e) => {
+                    warn!("TLS certificate reload failed ({e}); keeping the current certificate")
+                }
+            }
+        }
+    });
+}
@@ -0,0 +1,553 @@
+//! Signed outbound webhooks. Events (new operator notifications, background
+//! document ingests finishing) are queued in the database for every endpoint
+//! subscribed to them and sent by a background worker, so they survive
+//! restarts and are shared across replicas.
+//!
+//! Each request carries `X-Ractochat-Signature: t=<unix seconds>,v1=<hex>`, the
+//! HMAC-SHA256 of `<t>.<body>` keyed by the endpoint's secret. Receivers should
+//! recompute it and reject stale timestamps. Failed attempts (anything but a
+//! 2xx) are retried with exponential backoff; after `MAX_ATTEMPTS` the delivery
+//! is dead-lettered until an admin retries it.
+//!
+//! Secrets are sealed under `KEY_ENCRYPTION_KEY` like provider keys, so
+//! creating an endpoint or rotating its secret needs the master key set.
+
+use crate::{
+    AppState,
+    db::{Db, DueWebhookDelivery, WebhookAttempt, WebhookDelivery, WebhookRecord},
+    error::AppError,
+    notifications,
+    provider_keys::WebhookSecret,
+};
+use axum::{
+    Json,
+    extract::{Path, Query, State},
+};
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Events on top of the notification kinds.
+pub const DOCUMENT_INGESTED: &str = "document_ingested";
+/// Sent by the test endpoint whatever the endpoint subscribes to.
+const PING: &str = "ping";
+
+const MAX_ATTEMPTS: i64 = 8;
+const BACKOFF_BASE_SECS: i64 = 30;
+const BACKOFF_MAX_SECS: i64 = 3600;
+const REQUEST_TIMEOUT_SECS: u64 = 10;
+/// How long a claimed delivery is hidden from other replicas while it's sent.
+const LEASE_SECS: i64 = 60;
+const BATCH: i64 = 20;
+const MIN_SECRET_LEN: usize = 16;
+const ERROR_EXCERPT_CHARS: usize = 500;
+const DEFAULT_LIST_LIMIT: i64 = 100;
+const MAX_LIST_LIMIT: i64 = 500;
+
+/// Every event an endpoint can subscribe to.
+fn events() -> Vec<&'static str> {
+    notifications::KINDS
+        .iter()
+        .copied()
+        .chain([DOCUMENT_INGESTED])
+        .collect()
+}
+
+/// Queues `data` as an `event` for every enabled endpoint subscribed to it.
+/// Best effort: failures are logged and never fail the caller.
+pub async fn enqueue(db: &Db, event: &str, data: Value) {
+    let webhooks = match db.list_webhooks().await {
+        Ok(webhooks) => webhooks,
+        Err(e) => {
+            warn!("failed to load webhooks for {event}: {e}");
+            return;
+        }
+    };
+    for webhook in webhooks.iter().filter(|w| w.enabled) {
+        let events: Vec<String> = serde_json::from_str(&webhook.events).unwrap_or_default();
+        if !events.is_empty() && !events.iter().any(|e| e == event) {
+            continue;
+        }
+        if let Err(e) = queue(db, &webhook.id, event, &data).await {
+            warn!("failed to queue {event} for webhook {}: {e}", webhook.id);
+        }
+    }
+}
+
+async fn queue(
+    db: &Db,
+    webhook_id: &str,
+    event: &str,
+    data: &Value,
+) -> Result<WebhookDelivery, AppError> {
+    let id = Uuid::new_v4().to_string();
+    let now = Utc::now().to_rfc3339();
+    let payload = json!({
+        "id": id,
+        "event": event,
+        "created_at": now,
+        "data": data,
+    });
+    let delivery = WebhookDelivery {
+        id,
+        webhook_id: webhook_id.to_string(),
+        event: event.to_string(),
+        payload: payload.to_string(),
+        status: "pending".into(),
+        attempts: 0,
+        next_attempt_at: now.clone(),
+        last_status: None,
+        last_error: None,
+        created_at: now,
+        delivered_at: None,
+    };
+    db.insert_webhook_delivery(&delivery).await?;
+    Ok(delivery)
+}
+
+/// The HTTP client deliveries are sent with.
+pub fn client() -> reqwest::Client {
+    reqwest::Client::builder()
+        .timeout(std::time::Duration::from_secs(REQUEST_TIMEOUT_SECS))
+        .build()
+        .unwrap_or_default()
+}
+
+/// Sends every delivery that is due and records the outcome. Returns how many
+/// were attempted.
+pub async fn deliver_due(state: &AppState, http: &reqwest::Client) -> Result<usize, AppError> {
+    let db = &state.db;
+    let due = db
+        .due_webhook_deliveries(&Utc::now().to_rfc3339(), BATCH)
+        .await?;
+    let mut attempted = 0;
+    for delivery in due {
+        // Leased from the moment this row is claimed: sends earlier in the
+        // batch can take up to REQUEST_TIMEOUT_SECS each.
+        let lease_until = (Utc::now() + Duration::seconds(LEASE_SECS)).to_rfc3339();
+        if !db
+            .claim_webhook_delivery(&delivery.id, &delivery.next_attempt_at, &lease_until)
+            .await?
+        {
+            continue;
+        }
+        attempted += 1;
+        let secret = state.provider_keys.open_webhook_secret(&WebhookSecret {
+            webhook_id: &delivery.webhook_id,
+            plaintext: &delivery.secret,
+            nonce: delivery.secret_nonce.as_deref(),
+            ciphertext: delivery.secret_ciphertext.as_deref(),
+            key_id: delivery.secret_key_id.as_deref(),
+        });
+        let outcome = match secret {
+            Ok(secret) => send(http, &delivery, &secret).await,
+            Err(e) => Err((None, e.to_string())),
+        };
+        let attempts = delivery.attempts + 1;
+        let now = Utc::now();
+        let finished_at = now.to_rfc3339();
+        let next_attempt_at = (now + backoff(attempts)).to_rfc3339();
+        let attempt = match &outcome {
+            Ok(status) => WebhookAttempt {
+                status: "delivered",
+                attempts,
+                next_attempt_at: &finished_at,
+                last_status: Some(*status),
+                last_error: None,
+                delivered_at: Some(&finished_at),
+            },
+            Err((status, error)) => {
+                let dead = attempts >= MAX_ATTEMPTS;
+                if dead {
+                    warn!(
+                        "webhook delivery {} ({}) dead after {attempts} attempts: {error}",
+                        delivery.id, delivery.event
+                    );
+                }
+                WebhookAttempt {
+                    status: if dead { "dead" } else { "pending" },
+                    attempts,
+                    next_attempt_at: &next_attempt_at,
+                    last_status: *status,
+                    last_error: Some(error),
+                    delivered_at: None,
+                }
+            }
+        };
+        db.record_webhook_attempt(&delivery.id, attempt).await?;
+    }
+    Ok(attempted)
+}
+
+/// 30s after the first failure, doubling up to an hour.
+fn backoff(attempts: i64) -> Duration {
+    let exponent = (attempts - 1).clamp(0, 16) as u32;
+    Duration::seconds((BACKOFF_BASE_SECS << exponent).min(BACKOFF_MAX_SECS))
+}
+
+/// Posts a delivery; the HTTP status on success, or the status (if any) and
+/// error on failure.
+async fn send(
+    http: &reqwest::Client,
+    delivery: &DueWebhookDelivery,
+    secret: &str,
+) -> Result<i64, (Option<i64>, String)> {
+    let timestamp = Utc::now().timestamp();
+    let response = http
+        .post(&delivery.url)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .header("X-Ractochat-Event", &delivery.event)
+        .header("X-Ractochat-Delivery", &delivery.id)
+        .header(
+            "X-Ractochat-Signature",
+            format!(
+                "t={timestamp},v1={}",
+                sign(secret, timestamp, &delivery.payload)
+            ),
+        )
+        .body(delivery.payload.clone())
+        .send()
+        .await
+        .map_err(|e| (None, e.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(status.as_u16() as i64);
+    }
+    let body = response.text().await.unwrap_or_default();
+    let excerpt: String = body.chars().take(ERROR_EXCERPT_CHARS).collect();
+    Err((
+        Some(status.as_u16() as i64),
+        format!("HTTP {status}: {excerpt}"),
+    ))
+}
+
+/// Hex HMAC-SHA256 of `<timestamp>.<payload>`.
+fn sign(secret: &str, timestamp: i64, payload: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(payload.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    format!("whsec_{}", hex::encode(bytes))
+}
+
+/// An endpoint as the API shows it; the secret only in full when it was just
+/// set.
+#[derive(Debug, Serialize)]
+pub struct Webhook {
+    pub id: String,
+    pub url: String,
+    pub events: Vec<String>,
+    pub enabled: bool,
+    pub description: Option<String>,
+    /// `…` and the last four characters.
+    pub masked_secret: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub secret: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl Webhook {
+    fn masked(record: WebhookRecord) -> Self {
+        Self {
+            masked_secret: format!("…{}", record.secret_last4),
+            events: serde_json::from_str(&record.events).unwrap_or_default(),
+            id: record.id,
+            url: record.url,
+            enabled: record.enabled,
+            description: record.description,
+            secret: None,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+        }
+    }
+
+    fn revealed(record: WebhookRecord, secret: String) -> Self {
+        Self {
+            secret: Some(secret),
+            ..Self::masked(record)
+        }
+    }
+}
+
+fn validate_url(state: &AppState, url: &str) -> Result<String, AppError> {
+    let url = url.trim();
+    let parsed = reqwest::Url::parse(url)
+        .map_err(|e| AppError::BadRequest(format!("url {url} is not a valid URL: {e}")))?;
+    match parsed.scheme() {
+        "https" => {}
+        "http" if !state.config.load().production => {}
+        "http" => {
+            return Err(AppError::BadRequest(
+                "webhook urls must use https in production".into(),
+            ));
+        }
+        other => {
+            return Err(AppError::BadRequest(format!(
+                "webhook urls must be http or https, not {other}"
+            )));
+        }
+    }
+    if parsed.host_str().is_none() {
+        return Err(AppError::BadRequest(format!("url {url} has no host")));
+    }
+    Ok(url.to_string())
+}
+
+fn validate_events(events: Vec<String>) -> Result<Vec<String>, AppError> {
+    let known = self::events();
+    let mut normalized: Vec<String> = Vec::new();
+    for event in events {
+        let event = event.trim().to_string();
+        if !known.contains(&event.as_str()) {
+            return Err(AppError::BadRequest(format!(
+                "unknown webhook event {event}; expected one of {}",
+                known.join(", ")
+            )));
+        }
+        if !normalized.contains(&event) {
+            normalized.push(event);
+        }
+    }
+    Ok(normalized)
+}
+
+fn validate_secret(secret: Option<String>) -> Result<String, AppError> {
+    match secret.map(|s| s.trim().to_string()) {
+        None => Ok(generate_secret()),
+        Some(secret) if secret.len() < MIN_SECRET_LEN => Err(AppError::BadRequest(format!(
+            "secret must be at least {MIN_SECRET_LEN} characters"
+        ))),
+        // Receivers key their HMAC with the same bytes, and the masked form
+        // shows the last four characters.
+        Some(secret) if !secret.chars().all(|c| c.is_ascii_graphic()) => Err(AppError::BadRequest(
+            "secret must be printable ASCII without spaces".into(),
+        )),
+        Some(secret) => Ok(secret),
+    }
+}
+
+/// Seals `secret` onto `record`, clearing any legacy plaintext.
+fn set_secret(state: &AppState, record: &mut WebhookRecord, secret: &str) -> Result<(), AppError> {
+    let sealed = state
+        .provider_keys
+        .seal_webhook_secret(&record.id, secret)?;
+    record.secret = String::new();
+    record.secret_nonce = Some(sealed.nonce);
+    record.secret_ciphertext = Some(sealed.ciphertext);
+    record.secret_key_id = Some(sealed.key_id);
+    record.secret_last4 = secret[secret.len().saturating_sub(4)..].to_string();
+    Ok(())
+}
+
+async fn existing(state: &AppState, id: &str) -> Result<WebhookRecord, AppError> {
+    state
+        .db
+        .webhook(id)
+        .await?
+        .ok_or_else(|| AppError::BadRequest(format!("webhook {id} not found")))
+}
+
+pub async fn list_webhooks(State(state): State<AppState>) -> Result<Json<Vec<Webhook>>, AppError> {
+    let webhooks = state.db.list_webhooks().await?;
+    Ok(Json(webhooks.into_iter().map(Webhook::masked).collect()))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateWebhookBody {
+    pub url: String,
+    /// Events to receive; every event when empty.
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub description: Option<String>,
+    /// Generated when omitted.
+    pub secret: Option<String>,
+}
+
+pub async fn create_webhook(
+    State(state): State<AppState>,
+    Json(body): Json<CreateWebhookBody>,
+) -> Result<Json<Webhook>, AppError> {
+    let now = Utc::now().to_rfc3339();
+    let secret = validate_secret(body.secret)?;
+    let mut record = WebhookRecord {
+        id: Uuid::new_v4().to_string(),
+        url: validate_url(&state, &body.url)?,
+        secret: String::new(),
+        secret_nonce: None,
+        secret_ciphertext: None,
+        secret_key_id: None,
+        secret_last4: String::new(),
+        events: serde_json::to_string(&validate_events(body.events)?)
+            .map_err(|e| AppError::Internal(e.to_string()))?,
+        enabled: true,
+        description: body.description.filter(|d| !d.trim().is_empty()),
+        created_at: now.clone(),
+        updated_at: now,
+    };
+    set_secret(&state, &mut record, &secret)?;
+    state.db.save_webhook(&record).await?;
+    Ok(Json(Webhook::revealed(record, secret)))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdateWebhookBody {
+    pub url: Option<String>,
+    pub events: Option<Vec<String>>,
+    pub enabled: Option<bool>,
+    pub description: Option<String>,
+}
+
+pub async fn update_webhook(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    Json(body): Json<UpdateWebhookBody>,
+) -> Result<Json<Webhook>, AppError> {
+    let mut record = existing(&state, &id).await?;
+    if let Some(url) = body.url {
+        record.url = validate_url(&state, &url)?;
+    }
+    if let Some(events) = body.events {
+        record.events = serde_json::to_string(&validate_events(events)?)
+            .map_err(|e| AppError::Internal(e.to_string()))?;
+    }
+    if let Some(enabled) = body.enabled {
+        record.enabled = enabled;
+    }
+    if let Some(description) = body.description {
+        record.description = Some(description).filter(|d| !d.trim().is_empty());
+    }
+    record.updated_at = Utc::now().to_rfc3339();
+    state.db.save_webhook(&record).await?;
+    Ok(Json(Webhook::masked(record)))
+}
+
+#[derive(Debug, Serialize)]
+pub struct DeletedWebhook {
+    pub deleted: bool,
+}
+
+pub async fn delete_webhook(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<DeletedWebhook>, AppError> {
+    let deleted = state.db.delete_webhook(&id).await?;
+    if !deleted {
+        return Err(AppError::BadRequest(format!("webhook {id} not found")));
+    }
+    Ok(Json(DeletedWebhook { deleted }))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub struct RotateSecretBody {
+    /// Generated when omitted.
+    pub secret: Option<String>,
+}
+
+/// Replaces the endpoint's secret. Deliveries still queued are signed with the
+/// new one.
+pub async fn rotate_webhook_secret(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+    body: Option<Json<RotateSecretBody>>,
+) -> Result<Json<Webhook>, AppError> {
+    let mut record = existing(&state, &id).await?;
+    let secret = validate_secret(body.and_then(|Json(b)| b.secret))?;
+    set_secret(&state, &mut record, &secret)?;
+    record.updated_at = Utc::now().to_rfc3339();
+    state.db.save_webhook(&record).await?;
+    info!("rotated secret for webhook {id}");
+    Ok(Json(Webhook::revealed(record, secret)))
+}
+
+/// Queues a `ping` for the endpoint; it goes out with the next delivery run.
+pub async fn test_webhook(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<WebhookDelivery>, AppError> {
+    let record = existing(&state, &id).await?;
+    let delivery = queue(
+        &state.db,
+        &record.id,
+        PING,
+        &json!({ "webhook_id": record.id }),
+    )
+    .await?;
+    Ok(Json(delivery))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct DeliveryQuery {
+    /// `pending`, `delivered` or `dead`.
+    pub status: Option<String>,
+    pub webhook_id: Option<String>,
+    pub limit: Option<i64>,
+}
+
+pub async fn list_webhook_deliveries(
+    State(state): State<AppState>,
+    Query(query): Query<DeliveryQuery>,
+) -> Result<Json<Vec<WebhookDelivery>>, AppError> {
+    let status = query.status.filter(|s| !s.trim().is_empty());
+    if let Some(status) = &status
+        && !["pending", "delivered", "dead"].contains(&status.as_str())
+    {
+        return Err(AppError::BadRequest(format!(
+            "unknown delivery status {status}; expected pending, delivered or dead"
+        )));
+    }
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let deliveries = state
+        .db
+        .list_webhook_deliveries(status.as_deref(), query.webhook_id.as_deref(), limit)
+        .await?;
+    Ok(Json(deliveries))
+}
+
+/// Dead-lettered deliveries, newest first.
+pub async fn list_dead_letters(
+    State(state): State<AppState>,
+    Query(query): Query<DeliveryQuery>,
+) -> Result<Json<Vec<WebhookDelivery>>, AppError> {
+    let limit = query
+        .limit
+        .unwrap_or(DEFAULT_LIST_LIMIT)
+        .clamp(1, MAX_LIST_LIMIT);
+    let deliveries = state
+        .db
+        .list_webhook_deliveries(Some("dead"), query.webhook_id.as_deref(), limit)
+        .await?;
+    Ok(Json(deliveries))
+}
+
+#[derive(Debug, Serialize)]
+pub struct RequeuedDelivery {
+    pub requeued: bool,
+}
+
+pub async fn retry_webhook_delivery(
+    Path(id): Path<String>,
+    State(state): State<AppState>,
+) -> Result<Json<RequeuedDelivery>, AppError> {
+    if !state.db.requeue_webhook_delivery(&id).await? {
+        return Err(AppError::BadRequest(format!(
+            "delivery {id} is not dead-lettered"
+        )));
+    }
+    Ok(Json(RequeuedDelivery { requeued: true }))
+}
@@ -0,0 +1,126 @@
+//! Abuse scoring and throttling.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::test_support::{TestApp, TestClient};
+use serde_json::{Value, json};
+
+async fn block_word(client: &TestClient<'_>, word: &str) {
+    let res = client
+        .post(
+            "/api/v1/admin/policies",
+            json!({
+                "name": format!("no {word}"),
+                "match_type": "contains_any",
+                "pattern": word,
+                "action": "block",
+                "applies_to": "user",
+                "enabled": true,
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+}
+
+async fn risk(client: &TestClient<'_>, account: &str) -> Option<Value> {
+    let list = client.get("/api/v1/admin/abuse").await.json();
+    list.as_array()
+        .unwrap()
+        .iter()
+        .find(|r| r["account_id"] == account)
+        .cloned()
+}
+
+#[tokio::test]
+async fn blocked_attempts_throttle_until_released() {
+    let app = TestApp::with_vars([("ABUSE_SCORE_THRESHOLD", "10")]).await;
+    let client = app.as_user("demo-user");
+    block_word(&client, "forbidden").await;
+
+    for _ in 0..2 {
+        let res = client
+            .post("/api/v1/chat", common::chat("something forbidden"))
+            .await;
+        assert_eq!(res.status, StatusCode::BAD_REQUEST, "{}", res.text());
+    }
+    let scored = risk(&client, "demo-user").await.expect("account is scored");
+    assert_eq!(scored["blocked"], 2);
+    assert!(scored["score"].as_u64().unwrap() >= 10, "{scored}");
+    assert!(scored["throttled_until"].is_string(), "{scored}");
+
+    // A throttled account still gets a trickle of requests.
+    for turn in ["one", "two"] {
+        let res = client.post("/api/v1/chat", common::chat(turn)).await;
+        assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    }
+    let throttled = client.post("/api/v1/chat", common::chat("three")).await;
+    assert_eq!(throttled.status, StatusCode::TOO_MANY_REQUESTS);
+    assert!(
+        throttled.text().contains("throttled"),
+        "{}",
+        throttled.text()
+    );
+
+    let notices = client
+        .get("/api/v1/admin/notifications?kind=abuse_suspected")
+        .await
+        .json();
+    assert_eq!(
+        notices["items"].as_array().map(Vec::len),
+        Some(1),
+        "{notices}"
+    );
+
+    let released = client.delete("/api/v1/admin/abuse/demo-user").await;
+    assert_eq!(released.status, StatusCode::OK, "{}", released.text());
+    assert!(risk(&client, "demo-user").await.is_none());
+    let res = client.post("/api/v1/chat", common::chat("four")).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+}
+
+#[tokio::test]
+async fn repeated_prompts_add_to_the_score() {
+    let app = TestApp::with_vars([("ABUSE_SCORE_THRESHOLD", "100")]).await;
+    let client = app.as_user("demo-user");
+    for _ in 0..4 {
+        let res = client
+            .post("/api/v1/chat", common::chat("same question"))
+            .await;
+        assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    }
+    let scored = risk(&client, "demo-user").await.expect("account is scored");
+    // The first two sends are free; each repeat after that scores 2.
+    assert_eq!(scored["repeated_prompts"], 2);
+    assert_eq!(scored["score"], 4);
+    assert!(scored["throttled_until"].is_null(), "{scored}");
+}
+
+#[tokio::test]
+async fn detection_can_be_turned_off() {
+    let app =
+        TestApp::with_vars([("ABUSE_DETECTION", "false"), ("ABUSE_SCORE_THRESHOLD", "5")]).await;
+    let client = app.as_user("demo-user");
+    block_word(&client, "forbidden").await;
+    for _ in 0..3 {
+        client
+            .post("/api/v1/chat", common::chat("forbidden again"))
+            .await;
+    }
+    let res = client.post("/api/v1/chat", common::chat("allowed")).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    assert!(risk(&client, "demo-user").await.is_none());
+}
+
+#[tokio::test]
+async fn malformed_requests_are_refused_before_scoring() {
+    let app = TestApp::with_vars([("ABUSE_SCORE_THRESHOLD", "100")]).await;
+    let client = app.as_user("demo-user");
+    for _ in 0..4 {
+        let res = client
+            .post("/api/v1/chat", common::chat("same \u{7} question"))
+            .await;
+        assert_eq!(res.status, StatusCode::BAD_REQUEST, "{}", res.text());
+    }
+    assert!(risk(&client, "demo-user").await.is_none());
+}
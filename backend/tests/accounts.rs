@@ -0,0 +1,34 @@
+//! Account changes reach the in-memory router state only once stored.
+
+use axum::http::StatusCode;
+use backend::test_support::TestApp;
+use serde_json::json;
+
+#[tokio::test]
+async fn a_failed_save_leaves_the_account_unchanged() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let res = client
+        .post(
+            "/api/v1/admin/accounts/demo-user/limits",
+            json!({ "req_per_day": 100 }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+
+    app.execute(
+        "CREATE TRIGGER refuse_account_updates BEFORE UPDATE ON accounts \
+         BEGIN SELECT RAISE(ABORT, 'accounts are read-only'); END",
+    )
+    .await;
+    let res = client
+        .post(
+            "/api/v1/admin/accounts/demo-user/limits",
+            json!({ "req_per_day": 5 }),
+        )
+        .await;
+    assert!(!res.status.is_success(), "{}", res.text());
+
+    let account = app.state().access.account(Some("demo-user")).await.unwrap();
+    assert_eq!(account.req_per_day, Some(100));
+}
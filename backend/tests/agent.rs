@@ -0,0 +1,297 @@
+//! Agent runs: model ↔ tool loops with budgets and policies on every step.
+//! The mock provider calls the tools named in the prompt, in order, then
+//! answers with the last tool result.
+
+mod common;
+
+use axum::{Json, Router, http::StatusCode, routing::post};
+use backend::test_support::{TestApp, TestClient};
+use serde_json::{Value, json};
+
+/// The named events of an agent stream, with their data parsed, and the
+/// streamed answer.
+async fn run(client: &TestClient<'_>, body: Value) -> (Vec<(String, Value)>, String) {
+    let res = client.post("/api/v1/chat/agent", body).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let mut events = Vec::new();
+    let mut answer = String::new();
+    for (name, data) in res.events() {
+        match name {
+            Some(name) => events.push((name, serde_json::from_str(&data).unwrap())),
+            None => answer.push_str(&data),
+        }
+    }
+    (events, answer)
+}
+
+fn named<'a>(events: &'a [(String, Value)], name: &str) -> Vec<&'a Value> {
+    events
+        .iter()
+        .filter(|(n, _)| n == name)
+        .map(|(_, data)| data)
+        .collect()
+}
+
+async fn add_policy(client: &TestClient<'_>, pattern: &str, action: &str, applies_to: &str) {
+    let res = client
+        .post(
+            "/api/v1/admin/policies",
+            json!({
+                "name": format!("{action} {pattern}"),
+                "match_type": "contains_any",
+                "pattern": pattern,
+                "action": action,
+                "applies_to": applies_to,
+                "enabled": true,
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+}
+
+#[tokio::test]
+async fn tool_results_feed_the_answer() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let (events, answer) = run(
+        &client,
+        common::chat("calculator: (2 + 3) * 4; current_time"),
+    )
+    .await;
+
+    let steps = named(&events, "step");
+    assert_eq!(steps.len(), 3);
+    assert_eq!(steps[0]["tool_calls"][0]["name"], "calculator");
+    assert_eq!(
+        steps[0]["tool_calls"][0]["arguments"]["expression"],
+        "(2 + 3) * 4"
+    );
+    assert_eq!(steps[1]["tool_calls"][0]["name"], "current_time");
+    let results = named(&events, "tool_result");
+    assert_eq!(results[0]["content"], "20");
+    assert_eq!(results[0]["is_error"], false);
+    let time = results[1]["content"].as_str().unwrap();
+    assert!(chrono::DateTime::parse_from_rfc3339(time).is_ok(), "{time}");
+    assert_eq!(steps[2]["tool_calls"], json!([]));
+    assert!(answer.ends_with(time), "{answer}");
+
+    let done = named(&events, "done")[0];
+    assert_eq!(done["stop_reason"], "answered");
+    assert_eq!(done["steps"], 3);
+    assert!(done["message_id"].is_string(), "{done}");
+
+    // Every step counts toward the account, and the run as one request.
+    let usage = client
+        .get("/api/v1/admin/accounts/demo-user/usage")
+        .await
+        .json();
+    assert_eq!(usage["requests"], 1);
+    assert_eq!(
+        usage["quota"]["tokens_used"],
+        done["tokens_input"].as_u64().unwrap() + done["tokens_output"].as_u64().unwrap()
+    );
+}
+
+#[tokio::test]
+async fn step_budget_ends_the_run_without_an_answer() {
+    let app = TestApp::with_vars([("AGENT_MAX_STEPS", "2")]).await;
+    let client = app.as_user("demo-user");
+    let mut body = common::chat("calculator: 1 + 1; current_time");
+    body["max_steps"] = json!(50);
+    let (events, answer) = run(&client, body).await;
+
+    assert_eq!(named(&events, "step").len(), 2);
+    assert_eq!(named(&events, "tool_result").len(), 2);
+    assert!(answer.is_empty(), "{answer}");
+    let done = named(&events, "done")[0];
+    assert_eq!(done["stop_reason"], "max_steps");
+    assert_eq!(done["budget"]["max_steps"], 2);
+    assert!(done["message_id"].is_null(), "{done}");
+}
+
+#[tokio::test]
+async fn tool_errors_go_back_to_the_model() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let mut body = common::chat("calculator: 2 +");
+    body["tools"] = json!(["calculator"]);
+    let (events, answer) = run(&client, body).await;
+
+    let result = named(&events, "tool_result")[0];
+    assert_eq!(result["is_error"], true);
+    assert!(answer.contains("error:"), "{answer}");
+    assert_eq!(named(&events, "done")[0]["stop_reason"], "answered");
+}
+
+#[tokio::test]
+async fn policies_screen_tool_output() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    add_policy(&client, "42", "redact", "user").await;
+    let (events, answer) = run(&client, common::chat("calculator: 6 * 7")).await;
+
+    assert_eq!(named(&events, "tool_result")[0]["content"], "[REDACTED]");
+    let policy = named(&events, "policy")[0];
+    assert_eq!(policy["source"], "tool_result");
+    assert_eq!(policy["action"], "redact");
+    assert!(answer.ends_with("[REDACTED]"), "{answer}");
+
+    add_policy(&client, "56", "block", "user").await;
+    let (events, answer) = run(&client, common::chat("calculator: 7 * 8")).await;
+    assert_eq!(named(&events, "step").len(), 1);
+    assert!(named(&events, "tool_result").is_empty());
+    assert!(answer.is_empty(), "{answer}");
+    let error = named(&events, "error")[0];
+    assert!(
+        error["message"]
+            .as_str()
+            .unwrap()
+            .contains("Blocked by policy"),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn assistant_policies_screen_tool_arguments() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    // The user's prompt is screened as a user turn and passes; the same text
+    // in the model's tool call does not.
+    add_policy(&client, "9 * 9", "block", "assistant").await;
+    let (events, _) = run(&client, common::chat("calculator: 9 * 9")).await;
+
+    assert!(named(&events, "step").is_empty());
+    assert!(named(&events, "tool_result").is_empty());
+    let error = named(&events, "error")[0];
+    assert!(
+        error["message"]
+            .as_str()
+            .unwrap()
+            .contains("Blocked by policy"),
+        "{error}"
+    );
+    let audit = client
+        .get("/api/v1/admin/audit?kind=policy_hit")
+        .await
+        .json();
+    assert!(
+        audit
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|e| e["action"] == "block"),
+        "{audit}"
+    );
+}
+
+#[tokio::test]
+async fn daily_limits_are_checked_before_each_step() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    client
+        .post(
+            "/api/v1/admin/accounts/demo-user/limits",
+            json!({ "tokens_per_day": 1 }),
+        )
+        .await;
+    let (events, _) = run(&client, common::chat("calculator: 1 + 1")).await;
+
+    assert_eq!(named(&events, "step").len(), 1);
+    let error = named(&events, "error")[0];
+    assert!(
+        error["message"].as_str().unwrap().contains("token limit"),
+        "{error}"
+    );
+}
+
+#[tokio::test]
+async fn unknown_tools_are_refused() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let mut body = common::chat("fetch http://169.254.169.254/");
+    body["tools"] = json!(["http_fetch"]);
+    let res = client.post("/api/v1/chat/agent", body).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert!(res.text().contains("unknown tool"), "{}", res.text());
+
+    let mut body = common::chat("search_documents: refunds");
+    body["tools"] = json!(["search_documents"]);
+    let res = client.post("/api/v1/chat/agent", body).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
+
+/// Stands in for an OpenAI-compatible model that asks for the calculator,
+/// then answers with what the tool returned.
+async fn tool_caller(Json(req): Json<Value>) -> Json<Value> {
+    let messages = req["messages"].as_array().unwrap();
+    let message = match messages.iter().rfind(|m| m["role"] == "tool") {
+        None => {
+            assert_eq!(req["tools"][0]["function"]["name"], "calculator");
+            json!({
+                "role": "assistant",
+                "content": null,
+                "tool_calls": [{
+                    "id": "call_abc",
+                    "type": "function",
+                    "function": { "name": "calculator", "arguments": "{\"expression\":\"2^10\"}" },
+                }],
+            })
+        }
+        Some(result) => {
+            assert_eq!(result["tool_call_id"], "call_abc");
+            let call = &messages[messages.len() - 2];
+            assert_eq!(call["tool_calls"][0]["function"]["name"], "calculator");
+            json!({ "role": "assistant", "content": format!("It is {}.", result["content"].as_str().unwrap()) })
+        }
+    };
+    Json(json!({
+        "id": "agent",
+        "model": req["model"],
+        "choices": [{ "index": 0, "message": message, "finish_reason": "stop" }],
+        "usage": { "prompt_tokens": 10, "completion_tokens": 5, "total_tokens": 15 },
+    }))
+}
+
+#[tokio::test]
+async fn openai_tool_calls_round_trip() {
+    let addr = common::serve(Router::new().route("/v1/chat/completions", post(tool_caller))).await;
+    let base_url = format!("http://{addr}/v1");
+    let app = TestApp::with_vars([
+        ("OPENAI_API_KEY", "sk-test-agent-key"),
+        ("OPENAI_BASE_URL", base_url.as_str()),
+    ])
+    .await;
+    let client = app.as_user("demo-user");
+    let res = client
+        .post(
+            "/api/v1/admin/models",
+            json!({
+                "key": "agent-model",
+                "provider": "openai",
+                "id": "agent-model",
+                "prompt_price_per_1k": 0.1,
+                "completion_price_per_1k": 0.2,
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let res = client
+        .post(
+            "/api/v1/admin/accounts/demo-user/models",
+            json!({ "models": ["agent-model"] }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let mut body = common::chat("what is 2 to the 10th?");
+    body["provider"] = json!("openai");
+    body["model"] = json!("agent-model");
+    body["tools"] = json!(["calculator"]);
+    let (events, answer) = run(&client, body).await;
+
+    assert_eq!(named(&events, "tool_result")[0]["content"], "1024");
+    assert_eq!(answer, "It is 1024.");
+    let done = named(&events, "done")[0];
+    assert_eq!(done["steps"], 2);
+    assert_eq!(done["tokens_input"], 20);
+    assert_eq!(done["routing"]["selected_model"], "agent-model");
+}
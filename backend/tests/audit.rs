@@ -0,0 +1,81 @@
+//! The audit trail's hash chain.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::test_support::TestApp;
+use serde_json::json;
+
+#[tokio::test]
+async fn chain_verifies_and_detects_tampering() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    for limit in [100, 200] {
+        let res = client
+            .post(
+                "/api/v1/admin/accounts/demo-user/limits",
+                json!({ "req_per_day": limit }),
+            )
+            .await;
+        assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    }
+    let res = client.post("/api/v1/chat", common::chat("hello")).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+
+    let verification = client.get("/api/v1/admin/audit/verify").await.json();
+    assert_eq!(verification["valid"], true, "{verification}");
+    let entries = verification["entries"].as_i64().unwrap();
+    assert!(entries >= 3, "{verification}");
+    assert_eq!(verification["head"]["seq"], entries);
+
+    // Rewriting a past entry breaks the chain from that entry on.
+    let rewritten = app
+        .execute("UPDATE audit_log SET detail = '{\"req_per_day\":1}' WHERE seq = 2")
+        .await;
+    assert_eq!(rewritten, 1);
+    let verification = client.get("/api/v1/admin/audit/verify").await.json();
+    assert_eq!(verification["valid"], false, "{verification}");
+    assert_eq!(verification["entries"], 1);
+    assert_eq!(verification["problem"]["seq"], 2);
+}
+
+#[tokio::test]
+async fn deleting_an_entry_breaks_the_chain() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    for limit in [100, 200, 300] {
+        client
+            .post(
+                "/api/v1/admin/accounts/demo-user/limits",
+                json!({ "req_per_day": limit }),
+            )
+            .await;
+    }
+    assert_eq!(app.execute("DELETE FROM audit_log WHERE seq = 2").await, 1);
+    let verification = client.get("/api/v1/admin/audit/verify").await.json();
+    assert_eq!(verification["valid"], false, "{verification}");
+    assert_eq!(verification["problem"]["seq"], 3);
+}
+
+#[tokio::test]
+async fn queued_routing_entries_keep_the_chain_intact() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let chats = (0..8).map(|i| client.post("/api/v1/chat", common::chat(&format!("turn {i}"))));
+    for res in futures_util::future::join_all(chats).await {
+        assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    }
+    client
+        .post(
+            "/api/v1/admin/accounts/demo-user/limits",
+            json!({ "req_per_day": 100 }),
+        )
+        .await;
+
+    // Listing waits for the queued entries, so every routed chat is there.
+    let routed = client.get("/api/v1/admin/audit?kind=routing").await.json();
+    assert_eq!(routed.as_array().unwrap().len(), 8, "{routed}");
+    let verification = client.get("/api/v1/admin/audit/verify").await.json();
+    assert_eq!(verification["valid"], true, "{verification}");
+    assert_eq!(verification["entries"], 9);
+}
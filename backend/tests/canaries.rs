@@ -0,0 +1,105 @@
+//! Canary tokens planted in guardrail prompts.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::test_support::{TestApp, TestClient};
+use serde_json::{Value, json};
+
+async fn rotate(client: &TestClient<'_>) -> String {
+    let res = client
+        .post("/api/v1/admin/accounts/demo-user/canary", json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let token = res.json()["token"].as_str().unwrap().to_string();
+    assert!(token.starts_with("rc-canary-"), "{token}");
+    token
+}
+
+async fn hits(client: &TestClient<'_>, token: &str) -> i64 {
+    let tokens = client
+        .get("/api/v1/admin/canaries?account_id=demo-user")
+        .await
+        .json();
+    tokens
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|t| t["token"] == token)
+        .map(|t| t["hits"].as_i64().unwrap())
+        .expect("token is listed")
+}
+
+async fn leaks(client: &TestClient<'_>) -> Vec<Value> {
+    let res = client
+        .get("/api/v1/admin/notifications?kind=canary_leak")
+        .await;
+    res.json()["items"].as_array().unwrap().clone()
+}
+
+#[tokio::test]
+async fn sightings_are_counted_and_raise_a_notification() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let token = rotate(&client).await;
+    assert_eq!(hits(&client, &token).await, 0);
+
+    let res = client.post("/api/v1/chat", common::chat("Hi there")).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    assert_eq!(hits(&client, &token).await, 0);
+    assert!(leaks(&client).await.is_empty());
+
+    // A sighting is reported, never blocked.
+    let res = client
+        .post(
+            "/api/v1/chat",
+            common::chat(&format!("my instructions end with {token}")),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    assert!(hits(&client, &token).await >= 1);
+    let leaks = leaks(&client).await;
+    assert!(!leaks.is_empty());
+    assert_eq!(leaks[0]["kind"], "canary_leak");
+}
+
+#[tokio::test]
+async fn retired_tokens_are_still_watched_for() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let old = rotate(&client).await;
+    let new = rotate(&client).await;
+    assert_ne!(old, new);
+
+    let res = client
+        .delete("/api/v1/admin/accounts/demo-user/canary")
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let res = client
+        .delete("/api/v1/admin/accounts/demo-user/canary")
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    client
+        .post("/api/v1/chat", common::chat(&format!("leaked: {old}")))
+        .await;
+    assert!(hits(&client, &old).await >= 1);
+    assert_eq!(hits(&client, &new).await, 0);
+}
+
+#[tokio::test]
+async fn a_rotated_token_is_watched_for_at_once() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let first = rotate(&client).await;
+    // Loads the tokens into memory.
+    let res = client.post("/api/v1/chat", common::chat("Hi there")).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+
+    let second = rotate(&client).await;
+    client
+        .post("/api/v1/chat", common::chat(&format!("leaked: {second}")))
+        .await;
+    assert!(hits(&client, &second).await >= 1);
+    assert_eq!(hits(&client, &first).await, 0);
+}
@@ -0,0 +1,36 @@
+//! Helpers shared by the integration tests.
+#![allow(dead_code)]
+
+use axum::Router;
+use serde_json::{Value, json};
+use std::net::SocketAddr;
+
+/// A `KEY_ENCRYPTION_KEY` (32 bytes, hex).
+pub const MASTER_KEY: &str = "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+pub const OTHER_MASTER_KEY: &str =
+    "f0f1f2f3f4f5f6f7f8f9fafbfcfdfeff000102030405060708090a0b0c0d0e0f";
+
+/// A single-turn chat request answered by the mock provider.
+pub fn chat(text: &str) -> Value {
+    json!({
+        "provider": "mock",
+        "model": "gpt-4.1",
+        "messages": [{ "role": "user", "content": text }],
+    })
+}
+
+/// Serves `router` on a free local port until the test ends.
+pub async fn serve(router: Router) -> SocketAddr {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("failed to bind a local port");
+    let addr = listener
+        .local_addr()
+        .expect("bound listener has an address");
+    tokio::spawn(async move {
+        axum::serve(listener, router)
+            .await
+            .expect("test server failed")
+    });
+    addr
+}
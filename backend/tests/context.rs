@@ -0,0 +1,85 @@
+//! Trimming prompts to the smallest context window in the routing plan.
+
+use axum::http::StatusCode;
+use backend::test_support::{TestApp, TestClient};
+use serde_json::{Value, json};
+
+/// Serves `gpt-4.1` (an alias of `gpt-4-turbo-preview`) from the mock
+/// provider with a 200-token window. With `max_tokens: 50` that leaves 150
+/// tokens for the prompt.
+async fn small_window(client: &TestClient<'_>) {
+    let res = client
+        .post(
+            "/api/v1/admin/models",
+            json!({
+                "id": "gpt-4-turbo-preview",
+                "provider": "mock",
+                "prompt_price_per_1k": 0.1,
+                "completion_price_per_1k": 0.1,
+                "context_window": 200,
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+}
+
+fn transcript(latest: &str) -> Value {
+    json!({
+        "provider": "mock",
+        "model": "gpt-4.1",
+        "max_tokens": 50,
+        "messages": [
+            { "role": "system", "content": "Be brief." },
+            { "role": "user", "content": "a".repeat(200) },
+            { "role": "assistant", "content": "b".repeat(200) },
+            { "role": "user", "content": "c".repeat(200) },
+            { "role": "assistant", "content": "d".repeat(200) },
+            { "role": "user", "content": latest },
+        ],
+    })
+}
+
+#[tokio::test]
+async fn oldest_turns_are_dropped_to_fit_the_window() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    small_window(&client).await;
+
+    let res = client.post("/api/v1/chat", transcript("And now?")).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let context = &res.json()["context"];
+    assert_eq!(context["context_window"], 200);
+    assert_eq!(context["summarized"], false);
+    let dropped = context["dropped_messages"].as_u64().unwrap();
+    assert!((2..=4).contains(&dropped), "{context}");
+    assert!(context["dropped_tokens"].as_u64().unwrap() > 0);
+    assert!(context["estimated_prompt_tokens"].as_u64().unwrap() <= 150);
+}
+
+#[tokio::test]
+async fn prompts_within_the_window_are_left_alone() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    small_window(&client).await;
+
+    let mut body = transcript("And now?");
+    body["messages"] = json!([{ "role": "user", "content": "Hello" }]);
+    let res = client.post("/api/v1/chat", body).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let context = &res.json()["context"];
+    assert_eq!(context["dropped_messages"], 0);
+    assert_eq!(context["dropped_tokens"], 0);
+}
+
+#[tokio::test]
+async fn a_latest_message_over_the_window_is_refused() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    small_window(&client).await;
+
+    let res = client
+        .post("/api/v1/chat", transcript(&"e".repeat(1000)))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert!(res.text().contains("does not fit"), "{}", res.text());
+}
@@ -0,0 +1,90 @@
+//! Identical in-flight chat requests share one provider call.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::test_support::TestApp;
+use std::time::Duration;
+
+#[tokio::test]
+async fn concurrent_duplicates_share_one_call_and_bill_once() {
+    let app = TestApp::with_vars([("MOCK_LATENCY_MS", "500")]).await;
+    let client = app.as_user("demo-user");
+    let body = common::chat("what is the capital of France?");
+
+    let (first, second) = tokio::join!(
+        client.post("/api/v1/chat", body.clone()),
+        client.post("/api/v1/chat", body.clone()),
+    );
+    assert_eq!(first.status, StatusCode::OK, "{}", first.text());
+    assert_eq!(second.status, StatusCode::OK, "{}", second.text());
+    assert_eq!(
+        first.json()["message"]["content"],
+        second.json()["message"]["content"]
+    );
+
+    let stats = app.state().dedup.stats();
+    assert_eq!(stats.leaders, 1);
+    assert_eq!(stats.coalesced, 1);
+    assert_eq!(stats.inflight, 0);
+
+    // Only the request that made the call is counted against the account.
+    let usage = client
+        .get("/api/v1/admin/accounts/demo-user/usage")
+        .await
+        .json();
+    assert_eq!(usage["requests"], 1);
+    assert_eq!(usage["quota"]["requests_used"], 1);
+}
+
+#[tokio::test]
+async fn abandoned_call_is_not_shared_with_later_requests() {
+    let app = TestApp::with_vars([("MOCK_LATENCY_MS", "300")]).await;
+    let client = app.as_user("demo-user");
+    let body = common::chat("tell me a story");
+
+    // The client gives up once its call is in flight, before the provider answers.
+    let mut abandoned = Box::pin(client.post("/api/v1/chat", body.clone()));
+    while app.state().dedup.stats().inflight == 0 {
+        tokio::select! {
+            res = &mut abandoned => panic!("answered before it was abandoned: {}", res.text()),
+            _ = tokio::time::sleep(Duration::from_millis(5)) => {}
+        }
+    }
+    drop(abandoned);
+    assert_eq!(app.state().dedup.stats().inflight, 0);
+
+    let retry = client.post("/api/v1/chat", body).await;
+    assert_eq!(retry.status, StatusCode::OK, "{}", retry.text());
+    let stats = app.state().dedup.stats();
+    assert_eq!(stats.leaders, 2);
+    assert_eq!(stats.coalesced, 0);
+}
+
+#[tokio::test]
+async fn shared_call_is_counted_after_its_first_requester_leaves() {
+    let app = TestApp::with_vars([("MOCK_LATENCY_MS", "300")]).await;
+    let client = app.as_user("demo-user");
+    let body = common::chat("what changed this week?");
+
+    let mut first = Box::pin(client.post("/api/v1/chat", body.clone()));
+    let mut second = Box::pin(client.post("/api/v1/chat", body.clone()));
+    while app.state().dedup.stats().coalesced == 0 {
+        tokio::select! {
+            res = &mut first => panic!("answered before a duplicate joined: {}", res.text()),
+            res = &mut second => panic!("answered before a duplicate joined: {}", res.text()),
+            _ = tokio::time::sleep(Duration::from_millis(5)) => {}
+        }
+    }
+    // The request that started the call goes away; the duplicate still waits.
+    drop(first);
+    let res = second.await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+
+    let usage = client
+        .get("/api/v1/admin/accounts/demo-user/usage")
+        .await
+        .json();
+    assert_eq!(usage["quota"]["requests_used"], 1);
+    assert!(usage["quota"]["tokens_used"].as_u64().unwrap() > 0);
+}
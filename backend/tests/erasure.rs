@@ -0,0 +1,62 @@
+//! Erasing a user's data while keeping their quota and usage intact.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::test_support::TestApp;
+
+#[tokio::test]
+async fn erasure_removes_content_and_keeps_usage_under_the_account() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let res = client.post("/api/v1/chat", common::chat("Forget me")).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    app.execute(
+        "INSERT INTO notifications (id, kind, severity, title, detail, dedup_key, created_at, last_seen_at) \
+         VALUES ('n1', 'budget', 'warning', 'demo-user is near its budget', 'demo-user spent 80%', \
+         'budget:demo-user:2026-10', '2026-10-01T00:00:00Z', '2026-10-01T00:00:00Z')",
+    )
+    .await;
+    let before = client
+        .get("/api/v1/admin/accounts/demo-user/usage")
+        .await
+        .json();
+
+    let res = client.delete("/api/v1/admin/users/demo-user/data").await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let body = res.json();
+    let report = &body["report"];
+    assert!(!body["signature"].as_str().unwrap().is_empty());
+    assert_eq!(report["removed"]["conversations"], 1);
+    assert_eq!(report["removed"]["messages"], 2);
+    assert_eq!(report["anonymized"]["notifications"], 1);
+    assert_eq!(report["retained"]["usage_days"], 1);
+    assert_eq!(report["retained"]["usage_hours"], 1);
+
+    let pseudonym = report["pseudonym"].as_str().unwrap();
+    let notifications = client.get("/api/v1/admin/notifications").await.json();
+    let titles: Vec<&str> = notifications["items"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .filter_map(|n| n["title"].as_str())
+        .collect();
+    assert!(
+        titles.contains(&format!("{pseudonym} is near its budget").as_str()),
+        "{titles:?}"
+    );
+    assert!(
+        !titles.iter().any(|t| t.contains("demo-user")),
+        "{titles:?}"
+    );
+
+    let after = client
+        .get("/api/v1/admin/accounts/demo-user/usage")
+        .await
+        .json();
+    assert_eq!(after["requests"], before["requests"]);
+    // Read from the hourly rollups, which is also what a restart rebuilds
+    // the quota counters from.
+    assert_eq!(after["requests"], 1);
+    assert_eq!(after["quota"]["requests_used"], 1);
+}
@@ -0,0 +1,51 @@
+//! The test harness itself: the app boots on its own database and answers
+//! through the mock provider.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::test_support::TestApp;
+
+#[tokio::test]
+async fn health_is_ok() {
+    let app = TestApp::new().await;
+    let res = app.anonymous().get("/health").await;
+    assert_eq!(res.status, StatusCode::OK);
+    assert_eq!(res.text(), "ok");
+}
+
+#[tokio::test]
+async fn chat_is_answered_by_the_mock_provider() {
+    let app = TestApp::new().await;
+    let res = app
+        .as_user("demo-user")
+        .post("/api/v1/chat", common::chat("hello harness"))
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let content = res.json()["message"]["content"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(content.starts_with("[mock "), "{content}");
+    assert!(content.ends_with("hello harness"), "{content}");
+}
+
+#[tokio::test]
+async fn stream_ends_with_a_done_event() {
+    let app = TestApp::new().await;
+    let res = app
+        .as_user("demo-user")
+        .post("/api/v1/chat/stream", common::chat("hello stream"))
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let events = res.events();
+    let text: String = events
+        .iter()
+        .filter(|(name, _)| name.is_none())
+        .map(|(_, data)| data.as_str())
+        .collect();
+    assert!(text.ends_with("hello stream"), "{text}");
+    let (name, done) = events.last().expect("stream has events");
+    assert_eq!(name.as_deref(), Some("done"));
+    assert!(done.contains("message_id"), "{done}");
+}
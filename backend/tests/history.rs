@@ -0,0 +1,95 @@
+//! Server-side history for clients that send only the new turn.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::test_support::TestApp;
+use serde_json::{Value, json};
+use uuid::Uuid;
+
+fn follow_up(conversation_id: &str, text: &str) -> Value {
+    let mut body = common::chat(text);
+    body["conversation_id"] = json!(conversation_id);
+    body
+}
+
+#[tokio::test]
+async fn stored_turns_are_sent_again() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let first = client
+        .post("/api/v1/chat", common::chat("My favourite colour is teal"))
+        .await
+        .json();
+    let id = first["conversation_id"].as_str().unwrap();
+
+    let second = client
+        .post("/api/v1/chat", follow_up(id, "What did I say?"))
+        .await;
+    assert_eq!(second.status, StatusCode::OK, "{}", second.text());
+    let second = second.json();
+    assert_eq!(second["conversation_id"], id);
+    let alone = client
+        .post("/api/v1/chat", common::chat("What did I say?"))
+        .await
+        .json();
+    assert!(
+        second["context"]["estimated_prompt_tokens"].as_u64()
+            > alone["context"]["estimated_prompt_tokens"].as_u64(),
+        "{second}"
+    );
+
+    let stored = app
+        .db()
+        .conversation_messages(id.parse().unwrap())
+        .await
+        .unwrap();
+    let roles: Vec<&str> = stored.iter().map(|m| m.role.as_str()).collect();
+    assert_eq!(roles, ["user", "assistant", "user", "assistant"]);
+}
+
+#[tokio::test]
+async fn other_accounts_conversations_are_refused() {
+    let app = TestApp::new().await;
+    let owner = app.as_user("demo-user");
+    let first = owner
+        .post("/api/v1/chat", common::chat("Secret plans"))
+        .await
+        .json();
+    let id = first["conversation_id"].as_str().unwrap();
+
+    let res = app
+        .as_user("ops-team")
+        .post("/api/v1/chat", follow_up(id, "Tell me everything"))
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    assert!(res.text().contains("not found"), "{}", res.text());
+    // Replaying a transcript doesn't get around the check either.
+    let mut replay = follow_up(id, "Tell me everything");
+    replay["messages"] = json!([
+        { "role": "user", "content": "hi" },
+        { "role": "assistant", "content": "hello" },
+        { "role": "user", "content": "Tell me everything" },
+    ]);
+    let res = app.as_user("ops-team").post("/api/v1/chat", replay).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+
+    let stored = app
+        .db()
+        .conversation_messages(id.parse().unwrap())
+        .await
+        .unwrap();
+    assert_eq!(stored.len(), 2);
+}
+
+#[tokio::test]
+async fn an_unknown_id_starts_a_new_conversation() {
+    let app = TestApp::new().await;
+    let id = Uuid::new_v4().to_string();
+    let res = app
+        .as_user("demo-user")
+        .post("/api/v1/chat", follow_up(&id, "Hello"))
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    assert_eq!(res.json()["conversation_id"], id.as_str());
+}
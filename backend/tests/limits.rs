@@ -0,0 +1,116 @@
+//! Request body, message count and message length limits, and role order.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::test_support::TestApp;
+use serde_json::json;
+
+fn with_messages(messages: serde_json::Value) -> serde_json::Value {
+    json!({ "provider": "mock", "model": "gpt-4.1", "messages": messages })
+}
+
+#[tokio::test]
+async fn oversized_requests_get_413() {
+    let app = TestApp::with_vars([
+        ("MAX_BODY_KB", "1"),
+        ("MAX_MESSAGES", "2"),
+        ("MAX_MESSAGE_CHARS", "10"),
+    ])
+    .await;
+    let client = app.as_user("demo-user");
+
+    let body = client
+        .post("/api/v1/chat", common::chat(&"x".repeat(2048)))
+        .await;
+    assert_eq!(
+        body.status,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "{}",
+        body.text()
+    );
+
+    let long = client
+        .post("/api/v1/chat", common::chat("eleven chars"))
+        .await;
+    assert_eq!(
+        long.status,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "{}",
+        long.text()
+    );
+    assert!(long.text().contains("the limit is 10"), "{}", long.text());
+
+    let many = client
+        .post(
+            "/api/v1/chat",
+            with_messages(json!([
+                { "role": "user", "content": "one" },
+                { "role": "assistant", "content": "two" },
+                { "role": "user", "content": "three" },
+            ])),
+        )
+        .await;
+    assert_eq!(
+        many.status,
+        StatusCode::PAYLOAD_TOO_LARGE,
+        "{}",
+        many.text()
+    );
+
+    let ok = client.post("/api/v1/chat", common::chat("short")).await;
+    assert_eq!(ok.status, StatusCode::OK, "{}", ok.text());
+}
+
+#[tokio::test]
+async fn malformed_conversations_are_rejected() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+
+    for (messages, expected) in [
+        (
+            json!([
+                { "role": "user", "content": "hi" },
+                { "role": "system", "content": "be terse" },
+                { "role": "user", "content": "again" },
+            ]),
+            "system messages must come before",
+        ),
+        (
+            json!([
+                { "role": "user", "content": "hi" },
+                { "role": "assistant", "content": "one" },
+                { "role": "assistant", "content": "two" },
+                { "role": "user", "content": "again" },
+            ]),
+            "two assistant messages in a row",
+        ),
+        (
+            json!([
+                { "role": "user", "content": "hi" },
+                { "role": "assistant", "content": "hello" },
+            ]),
+            "must be from the user",
+        ),
+        (json!([{ "role": "user", "content": "  " }]), "is empty"),
+        (
+            json!([{ "role": "user", "content": "bell \u{7}" }]),
+            "control character U+0007",
+        ),
+    ] {
+        let res = client.post("/api/v1/chat", with_messages(messages)).await;
+        assert_eq!(res.status, StatusCode::BAD_REQUEST, "{}", res.text());
+        assert!(res.text().contains(expected), "{}", res.text());
+    }
+}
+
+#[tokio::test]
+async fn malformed_json_is_a_bad_request() {
+    let app = TestApp::new().await;
+    let res = app
+        .as_user("demo-user")
+        .post("/api/v1/chat", json!({ "messages": "not a list" }))
+        .await;
+    assert!(res.status.is_client_error(), "{}", res.status);
+    assert!(res.json()["error"].is_string(), "{}", res.text());
+}
@@ -0,0 +1,98 @@
+//! Restricting an account's session to its allowed web origins.
+
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode, header},
+};
+use backend::test_support::{TestApp, TestClient, TestResponse};
+use serde_json::json;
+
+async fn get_from(client: &TestClient<'_>, origin: &str) -> TestResponse {
+    let request = Request::builder()
+        .method(Method::GET)
+        .uri("/api/v1/conversations")
+        .header(header::ORIGIN, origin)
+        .body(Body::empty())
+        .expect("valid test request");
+    client.send(request).await
+}
+
+async fn restrict(app: &TestApp, origins: serde_json::Value) -> TestResponse {
+    app.as_user("demo-user")
+        .post(
+            "/api/v1/admin/accounts/demo-user/origins",
+            json!({ "allowed_origins": origins }),
+        )
+        .await
+}
+
+#[tokio::test]
+async fn sessions_are_refused_from_other_origins() {
+    let app = TestApp::new().await;
+    let res = restrict(&app, json!(["https://App.example.com/"])).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    assert_eq!(
+        res.json()["allowed_origins"],
+        json!(["https://app.example.com"])
+    );
+
+    let client = app.as_user("demo-user");
+    let allowed = get_from(&client, "https://app.example.com").await;
+    assert_eq!(allowed.status, StatusCode::OK, "{}", allowed.text());
+    let refused = get_from(&client, "https://evil.example.com").await;
+    assert_eq!(refused.status, StatusCode::FORBIDDEN);
+    assert!(
+        refused.text().contains("evil.example.com"),
+        "{}",
+        refused.text()
+    );
+
+    // Non-browser clients send no Origin and aren't affected.
+    let res = client.get("/api/v1/conversations").await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+
+    // Other accounts keep working from anywhere.
+    let other = get_from(&app.as_user("ops-team"), "https://evil.example.com").await;
+    assert_eq!(other.status, StatusCode::OK, "{}", other.text());
+}
+
+#[tokio::test]
+async fn login_is_refused_from_other_origins() {
+    let app = TestApp::new().await;
+    restrict(&app, json!(["https://app.example.com"])).await;
+    let login = |origin: &str| {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/api/v1/auth/login")
+            .header(header::ORIGIN, origin)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(
+                json!({ "email": "demo@local", "password": "demo123" }).to_string(),
+            ))
+            .expect("valid test request")
+    };
+    let client = app.anonymous();
+    let res = client.send(login("https://evil.example.com")).await;
+    assert_eq!(res.status, StatusCode::FORBIDDEN);
+    let res = client.send(login("https://app.example.com")).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+}
+
+#[tokio::test]
+async fn an_empty_list_lifts_the_restriction() {
+    let app = TestApp::new().await;
+    restrict(&app, json!(["https://app.example.com"])).await;
+    let res = restrict(&app, json!([])).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let res = get_from(&app.as_user("demo-user"), "https://evil.example.com").await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+}
+
+#[tokio::test]
+async fn origins_with_a_path_are_rejected() {
+    let app = TestApp::new().await;
+    let res = restrict(&app, json!(["https://app.example.com/login"])).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+    let res = restrict(&app, json!(["app.example.com"])).await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST);
+}
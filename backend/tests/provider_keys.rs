@@ -0,0 +1,156 @@
+//! Account provider keys sealed under the master key.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::{llm::Provider, test_support::TestApp};
+use serde_json::json;
+
+const API_KEY: &str = "sk-account-key-0123456789wxyz";
+
+#[tokio::test]
+async fn keys_are_sealed_and_only_shown_masked() {
+    let app = TestApp::with_vars([("KEY_ENCRYPTION_KEY", common::MASTER_KEY)]).await;
+    let client = app.as_user("demo-user");
+    let res = client
+        .put(
+            "/api/v1/admin/accounts/demo-user/provider-keys/openai",
+            json!({ "api_key": API_KEY }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    assert!(!res.text().contains(API_KEY));
+
+    let listed = client
+        .get("/api/v1/admin/accounts/demo-user/provider-keys")
+        .await;
+    assert!(!listed.text().contains(API_KEY));
+    assert_eq!(listed.json()[0]["masked_key"], "…wxyz");
+
+    let stored = app.db().provider_keys(Some("demo-user")).await.unwrap();
+    assert_eq!(stored.len(), 1);
+    let needle = API_KEY.as_bytes();
+    assert!(
+        !stored[0]
+            .ciphertext
+            .windows(needle.len())
+            .any(|w| w == needle)
+    );
+
+    let keys = app
+        .state()
+        .provider_keys
+        .for_account(Some("demo-user"))
+        .await
+        .unwrap();
+    assert_eq!(
+        keys.get(&Provider::Openai).map(String::as_str),
+        Some(API_KEY)
+    );
+}
+
+#[tokio::test]
+async fn non_ascii_keys_are_rejected() {
+    let app = TestApp::with_vars([("KEY_ENCRYPTION_KEY", common::MASTER_KEY)]).await;
+    let res = app
+        .as_user("demo-user")
+        .put(
+            "/api/v1/admin/accounts/demo-user/provider-keys/openai",
+            json!({ "api_key": "sk-account-key-01234567€" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST, "{}", res.text());
+}
+
+#[tokio::test]
+async fn keys_need_a_master_key() {
+    let app = TestApp::new().await;
+    let res = app
+        .as_user("demo-user")
+        .put(
+            "/api/v1/admin/accounts/demo-user/provider-keys/openai",
+            json!({ "api_key": API_KEY }),
+        )
+        .await;
+    assert!(res.status.is_server_error(), "{}", res.text());
+    assert!(app.db().provider_keys(None).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn reencrypt_moves_keys_and_webhook_secrets_to_the_new_master_key() {
+    let dir = std::env::temp_dir().join(format!("ractochat-test-{}", uuid::Uuid::new_v4()));
+    let database_url = format!("sqlite://{}", dir.join("keys.db").display());
+
+    let before = TestApp::with_vars([
+        ("DATABASE_URL", database_url.as_str()),
+        ("KEY_ENCRYPTION_KEY", common::MASTER_KEY),
+    ])
+    .await;
+    let res = before
+        .as_user("demo-user")
+        .put(
+            "/api/v1/admin/accounts/demo-user/provider-keys/openai",
+            json!({ "api_key": API_KEY }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    // An endpoint from before webhook secrets were sealed.
+    before
+        .execute(
+            "INSERT INTO webhooks (id, url, secret, secret_last4, events, enabled, created_at, updated_at) \
+             VALUES ('legacy', 'https://example.com/hook', 'whsec_legacy_plaintext', 'text', '[]', 1, '2026-01-01T00:00:00Z', '2026-01-01T00:00:00Z')",
+        )
+        .await;
+    let old_key_id = before.db().provider_keys(None).await.unwrap()[0]
+        .key_id
+        .clone();
+    drop(before);
+
+    let after = TestApp::with_vars([
+        ("DATABASE_URL", database_url.as_str()),
+        ("KEY_ENCRYPTION_KEY", common::OTHER_MASTER_KEY),
+        ("KEY_ENCRYPTION_KEY_PREVIOUS", common::MASTER_KEY),
+    ])
+    .await;
+    let client = after.as_user("demo-user");
+    let res = client
+        .post("/api/v1/admin/provider-keys/reencrypt", json!({}))
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let outcome = res.json();
+    assert_eq!(outcome["reencrypted"], 1, "{outcome}");
+    assert_eq!(outcome["webhook_secrets"], 1, "{outcome}");
+    assert_eq!(outcome["failed"], json!([]));
+
+    let stored = after.db().provider_keys(None).await.unwrap();
+    assert_ne!(stored[0].key_id, old_key_id);
+    assert_eq!(outcome["key_id"], stored[0].key_id);
+    let webhook = after.db().webhook("legacy").await.unwrap().unwrap();
+    assert!(webhook.secret.is_empty());
+    assert_eq!(
+        webhook.secret_key_id.as_deref(),
+        Some(stored[0].key_id.as_str())
+    );
+
+    // A second run has nothing left to do.
+    let again = client
+        .post("/api/v1/admin/provider-keys/reencrypt", json!({}))
+        .await
+        .json();
+    assert_eq!(again["reencrypted"], 0);
+    assert_eq!(again["already_current"], 1);
+    assert_eq!(again["webhook_secrets"], 0);
+
+    let keys = after
+        .state()
+        .provider_keys
+        .for_account(Some("demo-user"))
+        .await
+        .unwrap();
+    assert_eq!(
+        keys.get(&Provider::Openai).map(String::as_str),
+        Some(API_KEY)
+    );
+    drop(after);
+    let _ = std::fs::remove_dir_all(dir);
+}
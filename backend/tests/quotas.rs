@@ -0,0 +1,68 @@
+//! Daily request and token limits.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::test_support::TestApp;
+use serde_json::json;
+
+#[tokio::test]
+async fn request_limit_rejects_until_reset() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let limits = client
+        .post(
+            "/api/v1/admin/accounts/demo-user/limits",
+            json!({ "req_per_day": 2 }),
+        )
+        .await;
+    assert_eq!(limits.status, StatusCode::OK, "{}", limits.text());
+
+    for turn in ["first", "second"] {
+        let res = client.post("/api/v1/chat", common::chat(turn)).await;
+        assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    }
+    let over = client.post("/api/v1/chat", common::chat("third")).await;
+    assert_eq!(over.status, StatusCode::BAD_REQUEST);
+    assert!(over.text().contains("request limit"), "{}", over.text());
+
+    let quota = client
+        .post("/api/v1/admin/accounts/demo-user/quota/reset", json!({}))
+        .await;
+    assert_eq!(quota.status, StatusCode::OK, "{}", quota.text());
+    assert_eq!(quota.json()["requests_used"], 0);
+    let res = client.post("/api/v1/chat", common::chat("fourth")).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+}
+
+#[tokio::test]
+async fn token_limit_counts_prompt_and_reply() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    client
+        .post(
+            "/api/v1/admin/accounts/demo-user/limits",
+            json!({ "tokens_per_day": 1 }),
+        )
+        .await;
+
+    // The limit is checked before each call, so the first one goes through.
+    let res = client
+        .post("/api/v1/chat", common::chat("hello there"))
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let message = &res.json()["message"];
+    let spent =
+        message["tokens_input"].as_u64().unwrap() + message["tokens_output"].as_u64().unwrap();
+
+    let usage = client
+        .get("/api/v1/admin/accounts/demo-user/usage")
+        .await
+        .json();
+    assert_eq!(usage["quota"]["tokens_used"], spent);
+    assert_eq!(usage["quota"]["tokens_remaining"], 0);
+
+    let over = client.post("/api/v1/chat", common::chat("again")).await;
+    assert_eq!(over.status, StatusCode::BAD_REQUEST);
+    assert!(over.text().contains("token limit"), "{}", over.text());
+}
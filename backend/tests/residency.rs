@@ -0,0 +1,121 @@
+//! Per-account data residency against catalog model regions.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::test_support::{TestApp, TestClient};
+use serde_json::{Value, json};
+
+async fn set_residency(client: &TestClient<'_>, region: Value) -> Value {
+    let res = client
+        .post(
+            "/api/v1/admin/accounts/demo-user/residency",
+            json!({ "data_residency": region }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    res.json()
+}
+
+async fn host_model(client: &TestClient<'_>, id: &str, kind: &str, region: &str) {
+    let res = client
+        .post(
+            "/api/v1/admin/models",
+            json!({
+                "id": id,
+                "provider": "mock",
+                "prompt_price_per_1k": 0.1,
+                "completion_price_per_1k": 0.1,
+                "kind": kind,
+                "region": region,
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+}
+
+#[tokio::test]
+async fn only_models_in_the_region_are_routed_to() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let account = set_residency(&client, json!(" EU ")).await;
+    assert_eq!(account["data_residency"], "eu");
+
+    let refused = client.post("/api/v1/chat", common::chat("hello")).await;
+    assert_eq!(refused.status, StatusCode::BAD_REQUEST);
+    assert!(
+        refused.text().contains("data residency"),
+        "{}",
+        refused.text()
+    );
+
+    host_model(&client, "gpt-4-turbo-preview", "chat", "eu").await;
+    let res = client.post("/api/v1/chat", common::chat("hello")).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    assert_eq!(
+        res.json()["routing"]["selected_model"],
+        "gpt-4-turbo-preview"
+    );
+
+    // Models hosted elsewhere don't qualify either.
+    host_model(&client, "gpt-4-turbo-preview", "chat", "us").await;
+    let refused = client.post("/api/v1/chat", common::chat("hello")).await;
+    assert_eq!(
+        refused.status,
+        StatusCode::BAD_REQUEST,
+        "{}",
+        refused.text()
+    );
+
+    set_residency(&client, Value::Null).await;
+    let res = client.post("/api/v1/chat", common::chat("hello")).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+}
+
+#[tokio::test]
+async fn invalid_regions_are_rejected() {
+    let app = TestApp::new().await;
+    let res = app
+        .as_user("demo-user")
+        .post(
+            "/api/v1/admin/accounts/demo-user/residency",
+            json!({ "data_residency": "eu west!" }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::BAD_REQUEST, "{}", res.text());
+}
+
+#[tokio::test]
+async fn retrieval_stays_in_the_region() {
+    let app = TestApp::new().await;
+    let client = app.as_user("demo-user");
+    let created = client
+        .post("/api/v1/collections", json!({ "id": "docs" }))
+        .await;
+    assert_eq!(created.status, StatusCode::OK, "{}", created.text());
+    let added = client
+        .post(
+            "/api/v1/collections/docs/documents",
+            json!({ "title": "rules", "text": "Residency keeps prompts in one region." }),
+        )
+        .await;
+    assert!(added.status.is_success(), "{}", added.text());
+
+    set_residency(&client, json!("eu")).await;
+    host_model(&client, "gpt-4-turbo-preview", "chat", "eu").await;
+    let mut body = common::chat("where do prompts stay?");
+    body["retrieval"] = json!({ "collections": ["docs"] });
+
+    let refused = client.post("/api/v1/chat", body.clone()).await;
+    assert_eq!(refused.status, StatusCode::BAD_REQUEST);
+    assert!(
+        refused.text().contains("collection docs embeds with"),
+        "{}",
+        refused.text()
+    );
+
+    host_model(&client, "text-embedding-3-small", "embedding", "eu").await;
+    let res = client.post("/api/v1/chat", body).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    assert!(!res.json()["citations"].as_array().unwrap().is_empty());
+}
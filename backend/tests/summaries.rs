@@ -0,0 +1,136 @@
+//! Rolling conversation summaries, kept per conversation owner.
+
+mod common;
+
+use axum::http::StatusCode;
+use backend::{db::ConversationSummary, test_support::TestApp};
+use serde_json::json;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// A test app that summarizes with a mock model.
+async fn setup() -> TestApp {
+    let app = TestApp::with_vars([("CONTEXT_SUMMARY_MODEL", "summarizer")]).await;
+    let res = app
+        .as_user("demo-user")
+        .post(
+            "/api/v1/admin/models",
+            json!({
+                "id": "summarizer",
+                "provider": "mock",
+                "prompt_price_per_1k": 0.1,
+                "completion_price_per_1k": 0.1,
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    app
+}
+
+/// Waits for the background refresh to store a summary for `user_id`.
+async fn summary(app: &TestApp, id: Uuid, user_id: &str) -> ConversationSummary {
+    for _ in 0..100 {
+        if let Some(summary) = app
+            .db()
+            .conversation_summary(id, Some(user_id))
+            .await
+            .unwrap()
+        {
+            return summary;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("no summary was stored for {id}");
+}
+
+#[tokio::test]
+async fn each_exchange_refreshes_the_summary() {
+    let app = setup().await;
+    let client = app.as_user("demo-user");
+    let first = client
+        .post("/api/v1/chat", common::chat("Remember the number 42"))
+        .await
+        .json();
+    let id: Uuid = first["conversation_id"].as_str().unwrap().parse().unwrap();
+    let stored = summary(&app, id, "demo-user").await;
+    assert_eq!(stored.messages_covered, 2);
+    assert!(
+        stored.summary.contains("[mock summarizer]"),
+        "{}",
+        stored.summary
+    );
+
+    let mut follow_up = common::chat("And the colour blue");
+    follow_up["conversation_id"] = json!(id);
+    let res = client.post("/api/v1/chat", follow_up).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    for _ in 0..100 {
+        if summary(&app, id, "demo-user").await.messages_covered == 4 {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("the summary was not refreshed after the second exchange");
+}
+
+#[tokio::test]
+async fn summaries_belong_to_the_conversation_owner() {
+    let app = setup().await;
+    let first = app
+        .as_user("demo-user")
+        .post("/api/v1/chat", common::chat("Private notes"))
+        .await
+        .json();
+    let id: Uuid = first["conversation_id"].as_str().unwrap().parse().unwrap();
+    let stored = summary(&app, id, "demo-user").await;
+
+    let db = app.db();
+    assert!(
+        db.conversation_summary(id, Some("ops-team"))
+            .await
+            .unwrap()
+            .is_none()
+    );
+    assert!(db.conversation_summary(id, None).await.unwrap().is_none());
+    // Another owner can't overwrite it either.
+    db.save_conversation_summary(id, Some("ops-team"), "planted", 99)
+        .await
+        .unwrap();
+    let kept = summary(&app, id, "demo-user").await;
+    assert_eq!(kept.summary, stored.summary);
+    assert_eq!(kept.messages_covered, stored.messages_covered);
+}
+
+#[tokio::test]
+async fn the_stored_summary_replaces_trimmed_turns() {
+    let app = setup().await;
+    let client = app.as_user("demo-user");
+    let first = client
+        .post("/api/v1/chat", common::chat(&"a".repeat(400)))
+        .await
+        .json();
+    let id: Uuid = first["conversation_id"].as_str().unwrap().parse().unwrap();
+    summary(&app, id, "demo-user").await;
+
+    let res = client
+        .post(
+            "/api/v1/admin/models",
+            json!({
+                "id": "gpt-4-turbo-preview",
+                "provider": "mock",
+                "prompt_price_per_1k": 0.1,
+                "completion_price_per_1k": 0.1,
+                "context_window": 200,
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let mut follow_up = common::chat("What was that?");
+    follow_up["conversation_id"] = json!(id);
+    follow_up["max_tokens"] = json!(50);
+    let res = client.post("/api/v1/chat", follow_up).await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let context = &res.json()["context"];
+    assert_eq!(context["summarized"], true, "{context}");
+    assert!(context["dropped_messages"].as_u64().unwrap() >= 1);
+}
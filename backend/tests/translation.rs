@@ -0,0 +1,181 @@
+//! Inline translation through a designated translation model.
+
+mod common;
+
+use axum::{Json, Router, http::StatusCode, routing::post};
+use backend::test_support::TestApp;
+use serde_json::{Value, json};
+
+/// Stands in for an OpenAI-compatible translation model. Prompts starting with
+/// "Hola" are Spanish, everything else English; replies are "translated" by
+/// wrapping them. Every call reports 3 input and 2 output tokens.
+async fn translator(Json(req): Json<Value>) -> Json<Value> {
+    let messages = req["messages"].as_array().unwrap();
+    let instructions = messages[0]["content"].as_str().unwrap();
+    let text = messages.last().unwrap()["content"].as_str().unwrap();
+    let output = if instructions.starts_with("Identify") {
+        match text.strip_prefix("Hola") {
+            Some(rest) => format!("es\nHello{rest}"),
+            None => "en".to_string(),
+        }
+    } else {
+        format!("[es] {text}")
+    };
+    Json(json!({
+        "id": "translation",
+        "model": req["model"],
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": output },
+            "finish_reason": "stop",
+        }],
+        "usage": { "prompt_tokens": 3, "completion_tokens": 2, "total_tokens": 5 },
+    }))
+}
+
+async fn setup() -> TestApp {
+    let addr = common::serve(Router::new().route("/v1/chat/completions", post(translator))).await;
+    let base_url = format!("http://{addr}/v1");
+    let app = TestApp::with_vars([
+        ("OPENAI_API_KEY", "sk-test-translation-key"),
+        ("OPENAI_BASE_URL", base_url.as_str()),
+        ("TRANSLATION_MODEL", "translator"),
+    ])
+    .await;
+    let client = app.as_user("demo-user");
+    let res = client
+        .post(
+            "/api/v1/admin/models",
+            json!({
+                "key": "translator",
+                "provider": "openai",
+                "id": "translator",
+                "prompt_price_per_1k": 1.0,
+                "completion_price_per_1k": 2.0,
+            }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let res = client
+        .post(
+            "/api/v1/admin/accounts/demo-user/translation",
+            json!({ "inline_translation": true }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    app
+}
+
+#[tokio::test]
+async fn prompt_and_reply_are_translated_and_billed() {
+    let app = setup().await;
+    let client = app.as_user("demo-user");
+    let res = client
+        .post("/api/v1/chat", common::chat("Hola amigo"))
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let body = res.json();
+
+    let translation = &body["translation"];
+    assert_eq!(translation["language"], "es");
+    assert_eq!(translation["model"], "translator");
+    assert_eq!(translation["reply_translated"], true);
+    // One call each way.
+    assert_eq!(translation["usage"]["tokens_input"], 6);
+    assert_eq!(translation["usage"]["tokens_output"], 4);
+    assert!(translation["usage"]["cost"].as_f64().unwrap() > 0.0);
+
+    // The mock model echoes the English prompt, which comes back "translated".
+    let reply = body["message"]["content"].as_str().unwrap();
+    assert!(reply.starts_with("[es] "), "{reply}");
+    assert!(reply.contains("Hello amigo"), "{reply}");
+
+    let message = &body["message"];
+    let reply_tokens =
+        message["tokens_input"].as_u64().unwrap() + message["tokens_output"].as_u64().unwrap();
+    let usage = client
+        .get("/api/v1/admin/accounts/demo-user/usage")
+        .await
+        .json();
+    assert_eq!(usage["requests"], 1);
+    assert_eq!(usage["quota"]["requests_used"], 1);
+    assert_eq!(usage["quota"]["tokens_used"], reply_tokens + 10);
+    assert_eq!(
+        usage["tokens_input"].as_u64().unwrap() + usage["tokens_output"].as_u64().unwrap(),
+        reply_tokens + 10
+    );
+}
+
+#[tokio::test]
+async fn english_prompts_pass_through() {
+    let app = setup().await;
+    let client = app.as_user("demo-user");
+    let res = client
+        .post("/api/v1/chat", common::chat("Hello friend"))
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let body = res.json();
+    assert!(body["translation"].is_null(), "{body}");
+    assert!(
+        !body["message"]["content"]
+            .as_str()
+            .unwrap()
+            .starts_with("[es]")
+    );
+
+    // Detecting the language still cost a call.
+    let usage = client
+        .get("/api/v1/admin/accounts/demo-user/usage")
+        .await
+        .json();
+    let message = &body["message"];
+    let reply_tokens =
+        message["tokens_input"].as_u64().unwrap() + message["tokens_output"].as_u64().unwrap();
+    assert_eq!(usage["quota"]["tokens_used"], reply_tokens + 5);
+}
+
+#[tokio::test]
+async fn translation_tokens_count_towards_the_token_limit() {
+    let app = setup().await;
+    let client = app.as_user("demo-user");
+    let res = client
+        .post("/api/v1/chat", common::chat("Hola amigo"))
+        .await;
+    let message = &res.json()["message"];
+    let reply_tokens =
+        message["tokens_input"].as_u64().unwrap() + message["tokens_output"].as_u64().unwrap();
+
+    // Enough for the reply alone, not with its translations.
+    client
+        .post(
+            "/api/v1/admin/accounts/demo-user/limits",
+            json!({ "tokens_per_day": reply_tokens + 1 }),
+        )
+        .await;
+    let over = client
+        .post("/api/v1/chat", common::chat("Hola otra vez"))
+        .await;
+    assert_eq!(over.status, StatusCode::BAD_REQUEST);
+    assert!(over.text().contains("token limit"), "{}", over.text());
+}
+
+#[tokio::test]
+async fn streamed_replies_keep_multibyte_characters_whole() {
+    let app = setup().await;
+    // Three-byte characters, so some 64-byte boundary would fall inside one.
+    let prompt = format!("Hola {}", "€".repeat(100));
+    let res = app
+        .as_user("demo-user")
+        .post("/api/v1/chat/stream", common::chat(&prompt))
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let text: String = res
+        .events()
+        .into_iter()
+        .filter(|(name, _)| name.is_none())
+        .map(|(_, data)| data)
+        .collect();
+    assert!(!text.contains('\u{FFFD}'), "{text}");
+    assert!(text.starts_with("[es] "), "{text}");
+    assert!(text.ends_with(&prompt["Hola".len()..]), "{text}");
+}
@@ -0,0 +1,243 @@
+//! Outbound webhook delivery: signing, retries with backoff, leases and
+//! dead-lettering.
+
+mod common;
+
+use axum::{
+    Router,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    routing::post,
+};
+use backend::{test_support::TestApp, webhooks};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde_json::{Value, json};
+use sha2::Sha256;
+use std::sync::{
+    Arc, Mutex,
+    atomic::{AtomicU16, Ordering},
+};
+
+const SECRET: &str = "whsec_test_0123456789abcdef";
+const PAST: &str = "2000-01-01T00:00:00+00:00";
+
+/// An endpoint that answers with `status` and keeps what it received.
+#[derive(Default)]
+struct Receiver {
+    status: AtomicU16,
+    received: Mutex<Vec<(HeaderMap, String)>>,
+}
+
+impl Receiver {
+    fn answer(&self, status: StatusCode) {
+        self.status.store(status.as_u16(), Ordering::SeqCst);
+    }
+
+    fn received(&self) -> Vec<(HeaderMap, String)> {
+        self.received.lock().unwrap().clone()
+    }
+}
+
+async fn receive(
+    State(receiver): State<Arc<Receiver>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    receiver
+        .received
+        .lock()
+        .unwrap()
+        .push((headers, String::from_utf8_lossy(&body).into_owned()));
+    StatusCode::from_u16(receiver.status.load(Ordering::SeqCst)).unwrap()
+}
+
+/// A test app with one webhook pointing at a fresh receiver, and its id.
+async fn setup() -> (TestApp, Arc<Receiver>, String) {
+    let receiver = Arc::new(Receiver::default());
+    receiver.answer(StatusCode::OK);
+    let addr = common::serve(
+        Router::new()
+            .route("/hook", post(receive))
+            .with_state(receiver.clone()),
+    )
+    .await;
+    let app = TestApp::with_vars([("KEY_ENCRYPTION_KEY", common::MASTER_KEY)]).await;
+    let res = app
+        .as_user("demo-user")
+        .post(
+            "/api/v1/admin/webhooks",
+            json!({ "url": format!("http://{addr}/hook"), "secret": SECRET }),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    let id = res.json()["id"].as_str().unwrap().to_string();
+    (app, receiver, id)
+}
+
+async fn deliver(app: &TestApp) -> usize {
+    webhooks::deliver_due(app.state(), &webhooks::client())
+        .await
+        .expect("delivery run failed")
+}
+
+async fn deliveries(app: &TestApp) -> Vec<Value> {
+    let res = app
+        .as_user("demo-user")
+        .get("/api/v1/admin/webhooks/deliveries")
+        .await;
+    res.json().as_array().unwrap().clone()
+}
+
+async fn ping(app: &TestApp, webhook_id: &str) -> String {
+    let res = app
+        .as_user("demo-user")
+        .post(
+            &format!("/api/v1/admin/webhooks/{webhook_id}/test"),
+            json!({}),
+        )
+        .await;
+    assert_eq!(res.status, StatusCode::OK, "{}", res.text());
+    res.json()["id"].as_str().unwrap().to_string()
+}
+
+fn signature_matches(headers: &HeaderMap, body: &str) -> bool {
+    let header = headers["x-ractochat-signature"].to_str().unwrap();
+    let (t, v1) = header
+        .strip_prefix("t=")
+        .and_then(|rest| rest.split_once(",v1="))
+        .unwrap();
+    let mut mac = Hmac::<Sha256>::new_from_slice(SECRET.as_bytes()).unwrap();
+    mac.update(format!("{t}.{body}").as_bytes());
+    hex::encode(mac.finalize().into_bytes()) == v1
+}
+
+#[tokio::test]
+async fn secrets_are_sealed_and_masked() {
+    let (app, _, id) = setup().await;
+    let stored = app.db().webhook(&id).await.unwrap().unwrap();
+    assert!(stored.secret.is_empty());
+    assert!(stored.secret_ciphertext.is_some());
+    assert_eq!(stored.secret_last4, "cdef");
+
+    let listed = app.as_user("demo-user").get("/api/v1/admin/webhooks").await;
+    assert!(!listed.text().contains(SECRET));
+    assert_eq!(listed.json()[0]["masked_secret"], "…cdef");
+
+    let non_ascii = app
+        .as_user("demo-user")
+        .post(
+            &format!("/api/v1/admin/webhooks/{id}/secret"),
+            json!({ "secret": "whsec_test_0123456789abcdé" }),
+        )
+        .await;
+    assert_eq!(non_ascii.status, StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn failed_delivery_backs_off_then_delivers_signed() {
+    let (app, receiver, id) = setup().await;
+    ping(&app, &id).await;
+
+    receiver.answer(StatusCode::INTERNAL_SERVER_ERROR);
+    assert_eq!(deliver(&app).await, 1);
+    let delivery = deliveries(&app).await.remove(0);
+    assert_eq!(delivery["status"], "pending");
+    assert_eq!(delivery["attempts"], 1);
+    assert_eq!(delivery["last_status"], 500);
+    let next: DateTime<Utc> = delivery["next_attempt_at"]
+        .as_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    assert!(next > Utc::now() + chrono::Duration::seconds(25));
+
+    // Not due again until the backoff has passed.
+    assert_eq!(deliver(&app).await, 0);
+    app.execute(&format!(
+        "UPDATE webhook_deliveries SET next_attempt_at = '{PAST}'"
+    ))
+    .await;
+    receiver.answer(StatusCode::OK);
+    assert_eq!(deliver(&app).await, 1);
+    let delivery = deliveries(&app).await.remove(0);
+    assert_eq!(delivery["status"], "delivered");
+    assert_eq!(delivery["attempts"], 2);
+
+    let received = receiver.received();
+    assert_eq!(received.len(), 2);
+    let (headers, body) = &received[1];
+    assert_eq!(headers["x-ractochat-event"], "ping");
+    assert!(signature_matches(headers, body));
+    assert_eq!(
+        serde_json::from_str::<Value>(body).unwrap()["data"]["webhook_id"],
+        id
+    );
+}
+
+#[tokio::test]
+async fn claimed_delivery_is_not_sent_again() {
+    let (app, receiver, id) = setup().await;
+    let delivery_id = ping(&app, &id).await;
+
+    let due = app
+        .db()
+        .due_webhook_deliveries(&Utc::now().to_rfc3339(), 10)
+        .await
+        .unwrap();
+    assert_eq!(due.len(), 1);
+    let lease_until = (Utc::now() + chrono::Duration::seconds(60)).to_rfc3339();
+    let db = app.db();
+    assert!(
+        db.claim_webhook_delivery(&delivery_id, &due[0].next_attempt_at, &lease_until)
+            .await
+            .unwrap()
+    );
+    // Another replica that read the same row loses the race.
+    assert!(
+        !db.claim_webhook_delivery(&delivery_id, &due[0].next_attempt_at, &lease_until)
+            .await
+            .unwrap()
+    );
+    assert_eq!(deliver(&app).await, 0);
+    assert!(receiver.received().is_empty());
+}
+
+#[tokio::test]
+async fn last_failed_attempt_dead_letters_until_retried() {
+    let (app, receiver, id) = setup().await;
+    let delivery_id = ping(&app, &id).await;
+    receiver.answer(StatusCode::SERVICE_UNAVAILABLE);
+    app.execute(&format!(
+        "UPDATE webhook_deliveries SET attempts = 7, next_attempt_at = '{PAST}'"
+    ))
+    .await;
+    assert_eq!(deliver(&app).await, 1);
+
+    let client = app.as_user("demo-user");
+    let dead = client
+        .get("/api/v1/admin/webhooks/dead-letters")
+        .await
+        .json();
+    assert_eq!(dead[0]["id"], delivery_id.as_str());
+    assert_eq!(dead[0]["attempts"], 8);
+    assert_eq!(dead[0]["last_status"], 503);
+    // Dead deliveries aren't retried on their own.
+    app.execute(&format!(
+        "UPDATE webhook_deliveries SET next_attempt_at = '{PAST}'"
+    ))
+    .await;
+    assert_eq!(deliver(&app).await, 0);
+
+    let retried = client
+        .post(
+            &format!("/api/v1/admin/webhooks/deliveries/{delivery_id}/retry"),
+            json!({}),
+        )
+        .await;
+    assert_eq!(retried.status, StatusCode::OK, "{}", retried.text());
+    receiver.answer(StatusCode::OK);
+    assert_eq!(deliver(&app).await, 1);
+    assert_eq!(deliveries(&app).await[0]["status"], "delivered");
+}
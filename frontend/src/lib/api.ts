@@ -1,4 +1,4 @@
-export type ChatProvider = "openai" | "anthropic";
+export type ChatProvider = "openai" | "anthropic" | "mock";
 
 export type ChatRole = "system" | "user" | "assistant";
 